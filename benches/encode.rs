@@ -0,0 +1,74 @@
+//! Encoding time by version and error correction level, the gain from reusing an `Encoder`, and
+//! the mask penalty evaluation that runs eight times per symbol. Run with
+//! `cargo bench --bench encode`.
+
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use qr_tools::capacity::get_unencoded_capacity_in_bytes;
+use qr_tools::generator::{generate_qr_matrix, Encoder};
+use qr_tools::mask::penalty_score;
+use qr_tools::types::{DataMode, ErrorCorrection, QrConfig, Version};
+
+const VERSIONS: [Version; 4] = [Version::V1, Version::V10, Version::V25, Version::V40];
+const LEVELS: [ErrorCorrection; 4] = [ErrorCorrection::L, ErrorCorrection::M, ErrorCorrection::Q, ErrorCorrection::H];
+
+/// As many payload bytes as `version` holds at `error_correction`, so that it is the version
+/// the encoder picks
+fn filling_payload(version: Version, error_correction: ErrorCorrection) -> Vec<u8> {
+    let len = get_unencoded_capacity_in_bytes(version, error_correction, DataMode::Byte);
+    (0..len).map(|i| b'a' + (i * 7 % 26) as u8).collect()
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for version in VERSIONS {
+        for error_correction in LEVELS {
+            let data = filling_payload(version, error_correction);
+            let config = QrConfig { error_correction, ..QrConfig::default() };
+            group.throughput(Throughput::Bytes(data.len() as u64));
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", error_correction), version as u8), &data, |b, data| {
+                b.iter(|| generate_qr_matrix(black_box(data), &config))
+            });
+        }
+    }
+    group.finish();
+}
+
+/// A fresh matrix per call against an [`Encoder`] reusing its buffers, for small symbols where
+/// allocation is a large part of the work
+fn encoder_reuse(c: &mut Criterion) {
+    let payloads: Vec<String> = (0..100).map(|id| format!("https://example.com/item/{}", id)).collect();
+    let config = QrConfig::default();
+    let mut group = c.benchmark_group("encoder_reuse");
+    group.bench_function("generate_qr_matrix", |b| {
+        b.iter(|| {
+            for payload in &payloads {
+                black_box(generate_qr_matrix(black_box(payload.as_bytes()), &config));
+            }
+        })
+    });
+    group.bench_function("Encoder::encode_into", |b| {
+        let mut encoder = Encoder::new(config.clone());
+        let mut matrix = Vec::new();
+        b.iter(|| {
+            for payload in &payloads {
+                encoder.encode_into(black_box(payload.as_bytes()), &mut matrix);
+                black_box(&matrix);
+            }
+        })
+    });
+    group.finish();
+}
+
+fn mask_penalty(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mask_penalty");
+    for version in VERSIONS {
+        let config = QrConfig { skip_mask: true, ..QrConfig::default() };
+        let matrix = generate_qr_matrix(&filling_payload(version, config.error_correction), &config);
+        group.bench_with_input(BenchmarkId::from_parameter(version as u8), &matrix, |b, matrix| b.iter(|| penalty_score(black_box(matrix))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, encoder_reuse, mask_penalty);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Reed-Solomon encoding and decoding throughput. Run with `cargo bench --bench reed_solomon`.
+//!
+//! Version 40-L is the largest symbol: 25 blocks of 30 ECC codewords each.
+
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use qr_tools::capacity::get_block_layout;
+use qr_tools::ecc::{correct_errors, generate_ecc};
+use qr_tools::types::{ErrorCorrection, Version};
+
+const BATCH: usize = 100;
+
+fn reed_solomon(c: &mut Criterion) {
+    let layout = get_block_layout(Version::V40, ErrorCorrection::L);
+    let symbols: Vec<Vec<Vec<u8>>> = (0..BATCH)
+        .map(|symbol| {
+            layout.data_lengths().iter().enumerate().map(|(block, &len)| (0..len).map(|i| (symbol * 31 + block * 7 + i) as u8).collect()).collect()
+        })
+        .collect();
+    let bytes: usize = symbols.iter().flatten().map(Vec::len).sum();
+
+    let mut group = c.benchmark_group("reed_solomon");
+    group.throughput(Throughput::Bytes(bytes as u64));
+    group.bench_function("encode 100 V40-L symbols", |b| {
+        b.iter(|| {
+            for blocks in &symbols {
+                for block in blocks {
+                    black_box(generate_ecc(black_box(block), layout.ecc_per_block));
+                }
+            }
+        })
+    });
+
+    let block: Vec<u8> = (0..118u8).collect();
+    let codeword = [block.clone(), generate_ecc(&block, 30)].concat();
+    let mut damaged = codeword.clone();
+    for i in (0..damaged.len()).step_by(10).take(15) {
+        damaged[i] ^= 0x5a;
+    }
+    group.throughput(Throughput::Bytes(codeword.len() as u64));
+    group.bench_function("encode 118 + 30 codewords", |b| b.iter(|| generate_ecc(black_box(&block), 30)));
+    group.bench_function("check 118 + 30 error-free codewords", |b| b.iter(|| correct_errors(black_box(&codeword), 30)));
+    group.bench_function("correct 15 errors in 118 + 30 codewords", |b| b.iter(|| correct_errors(black_box(&damaged), 30)));
+    group.finish();
+}
+
+criterion_group!(benches, reed_solomon);
+criterion_main!(benches);
@@ -0,0 +1,38 @@
+//! PNG and SVG rendering time for a small and a large symbol. Run with
+//! `cargo bench --bench render`.
+
+use std::hint::black_box;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use qr_tools::capacity::get_unencoded_capacity_in_bytes;
+use qr_tools::generator::generate_qr_matrix;
+use qr_tools::render::{render_png, render_svg, RenderOptions};
+use qr_tools::types::{DataMode, QrConfig, Version};
+
+fn render(c: &mut Criterion) {
+    let options = RenderOptions::default();
+    let mut group = c.benchmark_group("render");
+    for version in [Version::V2, Version::V40] {
+        let config = QrConfig::default();
+        let len = get_unencoded_capacity_in_bytes(version, config.error_correction, DataMode::Byte);
+        let data: Vec<u8> = (0..len).map(|i| b'a' + (i % 26) as u8).collect();
+        let matrix = generate_qr_matrix(&data, &config);
+        let name = format!("V{}", version as u8);
+        let mut output = Vec::new();
+        group.bench_with_input(BenchmarkId::new("png", &name), &matrix, |b, matrix| {
+            b.iter(|| {
+                output.clear();
+                render_png(black_box(matrix), &options, &mut output).unwrap();
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("svg", &name), &matrix, |b, matrix| {
+            b.iter(|| {
+                output.clear();
+                render_svg(black_box(matrix), &options, &mut output).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
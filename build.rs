@@ -1,26 +1,51 @@
 use std::env;
-use std::fs::File;
-use std::io::Write;
+use std::fmt::Write as _;
+use std::fs;
 use std::path::Path;
-use std::process::Command;
 
-fn main() {
-    let out_dir = env::var("OUT_DIR").unwrap();
-    let dest_path = Path::new(&out_dir).join("gf_tables.rs");
+/// Primitive polynomial of GF(256) for QR codes: x^8 + x^4 + x^3 + x^2 + 1
+const PRIMITIVE_POLY: u16 = 0x11D;
 
-    // Run the Python script to generate GF tables
-    let output = Command::new("python3")
-        .arg("generate_gf_tables.py")
-        .output()
-        .expect("Failed to execute generate_gf_tables.py");
+/// Exp and log tables of GF(256), with generator 2
+fn generate_gf_tables() -> ([u8; 256], [u8; 256]) {
+    let mut gf_exp = [0u8; 256];
+    let mut gf_log = [0u8; 256];
+
+    let mut x: u16 = 1;
+    for (i, exp) in gf_exp.iter_mut().enumerate().take(255) {
+        *exp = x as u8;
+        gf_log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= PRIMITIVE_POLY;
+        }
+    }
+    (gf_exp, gf_log)
+}
 
-    if !output.status.success() {
-        panic!("generate_gf_tables.py failed: {}", String::from_utf8_lossy(&output.stderr));
+/// Format a table as a Rust const array, 16 values per line
+fn format_rust_array(name: &str, table: &[u8; 256]) -> String {
+    let mut out = format!("const {}: [u8; 256] = [\n", name);
+    for chunk in table.chunks(16) {
+        let values: Vec<String> = chunk.iter().map(|x| format!("{:3}", x)).collect();
+        writeln!(out, "    {},", values.join(", ")).unwrap();
     }
+    out.push_str("];\n");
+    out
+}
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let dest_path = Path::new(&out_dir).join("gf_tables.rs");
 
-    // Write the generated tables to a Rust file
-    let mut f = File::create(&dest_path).unwrap();
-    f.write_all(&output.stdout).unwrap();
+    let (gf_exp, gf_log) = generate_gf_tables();
+    let mut source = String::new();
+    source.push_str("// Generated Galois Field GF(256) lookup tables\n");
+    source.push_str("// Primitive polynomial: x^8 + x^4 + x^3 + x^2 + 1 (0x11D)\n\n");
+    source.push_str(&format_rust_array("GF_EXP", &gf_exp));
+    source.push('\n');
+    source.push_str(&format_rust_array("GF_LOG", &gf_log));
+    fs::write(&dest_path, source).unwrap();
 
-    println!("cargo:rerun-if-changed=generate_gf_tables.py");
+    println!("cargo:rerun-if-changed=build.rs");
 }
@@ -0,0 +1,23 @@
+//! The analyzer and the image decoder on small bilevel images. The first three bytes give the
+//! width and height in cells and the cell size in pixels; the remaining bytes are the cells,
+//! one bit each, row by row, with missing ones white. Drawing in cells lets the fuzzer build
+//! finder-like structures, which random pixels almost never form.
+#![no_main]
+
+use image::{DynamicImage, GrayImage, Luma};
+use libfuzzer_sys::fuzz_target;
+use qr_tools::cli::analyze::analyze_image;
+use qr_tools::decoder::decode_pixels;
+
+fuzz_target!(|data: &[u8]| {
+    let [width, height, cell, cells @ ..] = data else { return };
+    let (width, height, cell) = (*width as u32 % 48 + 1, *height as u32 % 48 + 1, *cell as u32 % 8 + 1);
+    let dark = |i: u32| cells.get(i as usize / 8).is_some_and(|byte| byte >> (i % 8) & 1 == 1);
+    let pixels = GrayImage::from_fn(width * cell, height * cell, |x, y| {
+        Luma([if dark(y / cell * width + x / cell) { 0 } else { 255 }])
+    });
+    let image = DynamicImage::ImageLuma8(pixels);
+
+    analyze_image(&image);
+    let _ = decode_pixels(&image);
+});
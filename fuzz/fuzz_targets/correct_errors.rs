@@ -0,0 +1,30 @@
+//! Reed-Solomon correction of arbitrary blocks. The first byte is the number of ECC codewords,
+//! the second the number of erasures, whose positions follow; the rest is the received block.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use qr_tools::ecc::{analyze_damage, correct_errors, correct_errors_with_erasures, verify_codewords, Block, CorrectionResult};
+use qr_tools::types::{ErrorCorrection, Version};
+
+fuzz_target!(|data: &[u8]| {
+    let [ecc, erasures, rest @ ..] = data else { return };
+    let ecc = *ecc as usize;
+    let (erasures, received) = rest.split_at((*erasures as usize).min(rest.len()));
+    let erasures: Vec<usize> = erasures.iter().map(|&p| p as usize).collect();
+
+    for result in [correct_errors(received, ecc), correct_errors_with_erasures(received, ecc, &erasures)] {
+        // Whatever the decoder reports as changed must turn the block into a valid codeword
+        if let CorrectionResult::Corrected { error_positions, error_magnitudes, .. } = result {
+            let mut repaired = received.to_vec();
+            for (&position, &magnitude) in error_positions.iter().zip(&error_magnitudes) {
+                repaired[position] ^= magnitude;
+            }
+            assert!(matches!(correct_errors(&repaired, ecc), CorrectionResult::ErrorFree(_)));
+        }
+    }
+
+    verify_codewords(&[Block { codewords: received.to_vec(), num_ecc_codewords: ecc }]);
+    let version = Version::from_u8(ecc as u8 % 40 + 1).unwrap();
+    let level = [ErrorCorrection::L, ErrorCorrection::M, ErrorCorrection::Q, ErrorCorrection::H][erasures.len() % 4];
+    let _ = analyze_damage(received, version, level);
+});
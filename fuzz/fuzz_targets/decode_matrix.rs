@@ -0,0 +1,26 @@
+//! The decoder and its helpers on module matrices filled with arbitrary bits. The first byte
+//! picks a version, or with its top bit set an arbitrary (mostly invalid) width; the remaining
+//! bytes are the modules, row by row, with missing ones light.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use qr_tools::capacity::image_size_to_version;
+use qr_tools::decoder::{decode_matrix, read_format_words, read_version_info, recover_format_info};
+use qr_tools::types::Version;
+
+fuzz_target!(|data: &[u8]| {
+    let [first, modules @ ..] = data else { return };
+    let size = match first & 0x80 {
+        0 => Version::from_u8(first % 40 + 1).unwrap().size(),
+        _ => (first & 0x7F) as usize,
+    };
+    let bit = |i: usize| modules.get(i / 8).map_or(0, |byte| byte >> (i % 8) & 1);
+    let matrix: Vec<Vec<u8>> = (0..size).map(|row| (0..size).map(|col| bit(row * size + col)).collect()).collect();
+
+    let _ = decode_matrix(&matrix);
+    if let Some(version) = image_size_to_version(size) {
+        read_format_words(&matrix);
+        read_version_info(&matrix);
+        recover_format_info(&matrix, version);
+    }
+});
@@ -0,0 +1,22 @@
+//! BCH decoding of format and version information words read from an untrusted symbol
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use qr_tools::decoder::{correct_version_bits, version_bits};
+use qr_tools::format_info;
+
+fuzz_target!(|data: &[u8]| {
+    let [a, b, c, d, ..] = *data else { return };
+
+    let word = u16::from_le_bytes([a, b]) & 0x7FFF;
+    if let Ok((error_correction, mask)) = format_info::decode(word) {
+        assert!(mask < 8);
+        assert!(format_info::distance(word, error_correction, mask) <= 3);
+    }
+
+    let word = u32::from_le_bytes([a, b, c, d]) & 0x3FFFF;
+    if let Some((version, distance)) = correct_version_bits(word) {
+        assert!(distance <= 3);
+        assert_eq!((word ^ version_bits(version)).count_ones(), distance);
+    }
+});
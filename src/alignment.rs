@@ -1,70 +1,118 @@
 use crate::types::Version;
 
-pub fn get_alignment_positions(version: Version) -> Vec<usize> {
-    match version {
-        Version::V1 => vec![],
-        Version::V2 => vec![6, 18],
-        Version::V3 => vec![6, 22],
-        Version::V4 => vec![6, 26],
-        Version::V5 => vec![6, 30],
-        Version::V6 => vec![6, 34],
-        Version::V7 => vec![6, 22, 38],
-        Version::V8 => vec![6, 24, 42],
-        Version::V9 => vec![6, 26, 46],
-        Version::V10 => vec![6, 28, 50],
-        Version::V11 => vec![6, 30, 54],
-        Version::V12 => vec![6, 32, 58],
-        Version::V13 => vec![6, 26, 46, 66],
-        Version::V14 => vec![6, 26, 46, 66],
-        Version::V15 => vec![6, 26, 48, 70],
-        Version::V16 => vec![6, 26, 50, 74],
-        Version::V17 => vec![6, 30, 54, 78],
-        Version::V18 => vec![6, 30, 56, 82],
-        Version::V19 => vec![6, 30, 58, 86],
-        Version::V20 => vec![6, 34, 62, 90],
-        Version::V21 => vec![6, 28, 50, 72, 94],
-        Version::V22 => vec![6, 26, 50, 74, 98],
-        Version::V23 => vec![6, 30, 54, 78, 102],
-        Version::V24 => vec![6, 28, 54, 80, 106],
-        Version::V25 => vec![6, 32, 58, 84, 110],
-        Version::V26 => vec![6, 30, 58, 86, 114],
-        Version::V27 => vec![6, 34, 62, 90, 118],
-        Version::V28 => vec![6, 26, 50, 74, 98, 122],
-        Version::V29 => vec![6, 30, 54, 78, 102, 126],
-        Version::V30 => vec![6, 26, 52, 78, 104, 130],
-        Version::V31 => vec![6, 30, 56, 82, 108, 134],
-        Version::V32 => vec![6, 34, 60, 86, 112, 138],
-        Version::V33 => vec![6, 30, 58, 86, 114, 142],
-        Version::V34 => vec![6, 34, 62, 90, 118, 146],
-        Version::V35 => vec![6, 30, 54, 78, 102, 126, 150],
-        Version::V36 => vec![6, 24, 50, 76, 102, 128, 154],
-        Version::V37 => vec![6, 28, 54, 80, 106, 132, 158],
-        Version::V38 => vec![6, 32, 58, 84, 110, 136, 162],
-        Version::V39 => vec![6, 26, 54, 82, 110, 138, 166],
-        Version::V40 => vec![6, 30, 58, 86, 114, 142, 170],
+/// Most alignment pattern coordinates of any version (seven, from version 35 up)
+const MAX_COORDINATES: usize = 7;
+
+/// Row/column coordinates of the alignment pattern centers of every version, indexed by version
+/// number, with the number of coordinates in use
+static ALIGNMENT_COORDINATES: [([usize; MAX_COORDINATES], usize); 41] = build_alignment_coordinates();
+
+/// ISO/IEC 18004 Annex E: the first coordinate is always 6 and the last is 7 modules in from
+/// the far edge; the ones between are spaced evenly by an even step, any excess taken up by
+/// the first gap. Version 32 is the one exception to the rounding, with a step of 26 where the
+/// formula gives 28.
+const fn build_alignment_coordinates() -> [([usize; MAX_COORDINATES], usize); 41] {
+    let mut table = [([0; MAX_COORDINATES], 0); 41];
+    let mut version = 2;
+    while version <= 40 {
+        let count = version / 7 + 2;
+        let last = 4 * version + 10;
+        let step = if version == 32 { 26 } else { (4 * version + 2 * count + 1) / (2 * count - 2) * 2 };
+        let mut coordinates = [0; MAX_COORDINATES];
+        coordinates[0] = 6;
+        let mut i = 1;
+        while i < count {
+            coordinates[i] = last - (count - 1 - i) * step;
+            i += 1;
+        }
+        table[version] = (coordinates, count);
+        version += 1;
     }
+    table
+}
+
+/// Row/column coordinates of the alignment pattern centers, in increasing order. Patterns sit
+/// at every combination of two of them, except the three that would overlap a finder pattern.
+pub fn get_alignment_positions(version: Version) -> &'static [usize] {
+    let (coordinates, count) = &ALIGNMENT_COORDINATES[version as usize];
+    &coordinates[..*count]
+}
+
+/// Centers of the alignment patterns as (x, y), row by row
+pub fn alignment_centers(version: Version) -> impl Iterator<Item = (usize, usize)> {
+    let coordinates = get_alignment_positions(version);
+    let last = coordinates.len().saturating_sub(1);
+    coordinates.iter().enumerate().flat_map(move |(i, &y)| {
+        coordinates
+            .iter()
+            .enumerate()
+            .filter(move |&(j, _)| ![(0, 0), (0, last), (last, 0)].contains(&(i, j)))
+            .map(move |(_, &x)| (x, y))
+    })
 }
 
 pub fn is_alignment_pattern(x: usize, y: usize, version: Version) -> bool {
-    let positions = get_alignment_positions(version);
-    if positions.is_empty() {
-        return false;
-    }
-    
-    for &center_x in &positions {
-        for &center_y in &positions {
-            // Skip if overlaps with finder patterns
-            if (center_x <= 8 && center_y <= 8) ||
-               (center_x <= 8 && center_y >= version.size() - 9) ||
-               (center_x >= version.size() - 9 && center_y <= 8) {
-                continue;
-            }
-            
-            if x >= center_x.saturating_sub(2) && x <= center_x + 2 &&
-               y >= center_y.saturating_sub(2) && y <= center_y + 2 {
-                return true;
-            }
+    alignment_centers(version).any(|(center_x, center_y)| x.abs_diff(center_x) <= 2 && y.abs_diff(center_y) <= 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// ISO/IEC 18004 table E.1
+    const PUBLISHED: [&[usize]; 40] = [
+        &[],
+        &[6, 18],
+        &[6, 22],
+        &[6, 26],
+        &[6, 30],
+        &[6, 34],
+        &[6, 22, 38],
+        &[6, 24, 42],
+        &[6, 26, 46],
+        &[6, 28, 50],
+        &[6, 30, 54],
+        &[6, 32, 58],
+        &[6, 34, 62],
+        &[6, 26, 46, 66],
+        &[6, 26, 48, 70],
+        &[6, 26, 50, 74],
+        &[6, 30, 54, 78],
+        &[6, 30, 56, 82],
+        &[6, 30, 58, 86],
+        &[6, 34, 62, 90],
+        &[6, 28, 50, 72, 94],
+        &[6, 26, 50, 74, 98],
+        &[6, 30, 54, 78, 102],
+        &[6, 28, 54, 80, 106],
+        &[6, 32, 58, 84, 110],
+        &[6, 30, 58, 86, 114],
+        &[6, 34, 62, 90, 118],
+        &[6, 26, 50, 74, 98, 122],
+        &[6, 30, 54, 78, 102, 126],
+        &[6, 26, 52, 78, 104, 130],
+        &[6, 30, 56, 82, 108, 134],
+        &[6, 34, 60, 86, 112, 138],
+        &[6, 30, 58, 86, 114, 142],
+        &[6, 34, 62, 90, 118, 146],
+        &[6, 30, 54, 78, 102, 126, 150],
+        &[6, 24, 50, 76, 102, 128, 154],
+        &[6, 28, 54, 80, 106, 132, 158],
+        &[6, 32, 58, 84, 110, 136, 162],
+        &[6, 26, 54, 82, 110, 138, 166],
+        &[6, 30, 58, 86, 114, 142, 170],
+    ];
+
+    #[test]
+    fn test_alignment_coordinates_match_the_published_table() {
+        for (i, &published) in PUBLISHED.iter().enumerate() {
+            let version = Version::from_u8(i as u8 + 1).unwrap();
+            assert_eq!(get_alignment_positions(version), published, "{:?}", version);
+            // Every pair of coordinates but the three finder corners
+            let count = published.len();
+            assert_eq!(alignment_centers(version).count(), (count * count).saturating_sub(3), "{:?}", version);
         }
+        assert!(is_alignment_pattern(22, 6, Version::V7));
+        assert!(!is_alignment_pattern(6, 6, Version::V7));
     }
-    false
 }
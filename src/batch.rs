@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use crate::sheet::parse_csv_line;
+use crate::naming::{expand_name_template, NameTokens};
+
+/// One code of a batch: the payload, and optionally its own output filename and caption
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+pub struct BatchItem {
+    pub payload: String,
+    #[serde(default)]
+    pub filename: Option<String>,
+    #[serde(default)]
+    pub caption: Option<String>,
+}
+
+/// Read batch items from CSV text with the columns payload, filename and caption; the last two
+/// may be left out or empty. A first row with a column named `payload` is a header, and then
+/// the columns are matched by name and may come in any order.
+pub fn parse_csv(csv: &str) -> Result<Vec<BatchItem>, Box<dyn std::error::Error>> {
+    let mut lines = csv.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).peekable();
+    let mut columns = HashMap::from([("payload", 0), ("filename", 1), ("caption", 2)]);
+    if let Some((_, header)) = lines.peek() {
+        let fields = parse_csv_line(header);
+        if fields.iter().any(|name| name.trim().eq_ignore_ascii_case("payload")) {
+            columns = fields
+                .iter()
+                .enumerate()
+                .filter_map(|(i, name)| match name.trim().to_lowercase().as_str() {
+                    "payload" => Some(("payload", i)),
+                    "filename" => Some(("filename", i)),
+                    "caption" => Some(("caption", i)),
+                    _ => None,
+                })
+                .collect();
+            lines.next();
+        }
+    }
+
+    let mut items = Vec::new();
+    for (line_no, line) in lines {
+        let fields = parse_csv_line(line);
+        let field = |name: &str| columns.get(name).and_then(|&i| fields.get(i)).filter(|f| !f.is_empty()).cloned();
+        items.push(BatchItem {
+            payload: field("payload").ok_or_else(|| format!("line {}: missing payload", line_no + 1))?,
+            filename: field("filename"),
+            caption: field("caption"),
+        });
+    }
+    Ok(items)
+}
+
+/// Read batch items from JSON Lines: one object per line with a `payload` string and optional
+/// `filename` and `caption` strings
+pub fn parse_jsonl(jsonl: &str) -> Result<Vec<BatchItem>, Box<dyn std::error::Error>> {
+    let mut items = Vec::new();
+    for (line_no, line) in jsonl.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut item: BatchItem = serde_json::from_str(line).map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+        item.filename = item.filename.filter(|f| !f.is_empty());
+        item.caption = item.caption.filter(|c| !c.is_empty());
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// Output filename for an item: its own filename if it has one, otherwise `template` expanded
+/// with [`expand_name_template`]. The name is joined to the output directory, so it must be a
+/// relative path that stays inside it: absolute paths and `..` components are rejected.
+pub fn output_name(item: &BatchItem, template: &str, tokens: &NameTokens) -> Result<String, String> {
+    let name = match &item.filename {
+        Some(filename) => filename.clone(),
+        None => expand_name_template(template, tokens)?,
+    };
+    let inside = Path::new(&name).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !inside || name.is_empty() {
+        return Err(format!("output name {:?} must be a relative path inside the output directory", name));
+    }
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ErrorCorrection, MaskPattern, Version};
+
+    #[test]
+    fn test_csv_and_jsonl_read_the_same_items() {
+        let csv = "caption,payload,filename\nFirst,https://example.com/1,\n,\"a,b\",b.svg\n";
+        let jsonl = "{\"payload\": \"https://example.com/1\", \"caption\": \"First\"}\n\n{\"payload\": \"a,b\", \"filename\": \"b.svg\"}\n";
+        let items = parse_csv(csv).unwrap();
+        assert_eq!(items, parse_jsonl(jsonl).unwrap());
+        let tokens = NameTokens { index: 1, payload: b"", version: Version::V1, error_correction: ErrorCorrection::M, mask: MaskPattern::Pattern0 };
+        assert_eq!(output_name(&items[0], "code-{id}.png", &tokens).unwrap(), "code-1.png");
+        assert_eq!(output_name(&items[1], "code-{id}.png", &tokens).unwrap(), "b.svg");
+
+        // Names may lead into subdirectories but not out of the output directory
+        let named = |filename: &str| BatchItem { filename: Some(filename.to_string()), ..items[0].clone() };
+        assert_eq!(output_name(&named("sub/a.png"), "", &tokens).unwrap(), "sub/a.png");
+        for escaping in ["/tmp/a.png", "../a.png", "sub/../../a.png"] {
+            assert!(output_name(&named(escaping), "", &tokens).is_err(), "{}", escaping);
+        }
+        assert!(output_name(&items[0], "../{id}.png", &tokens).is_err());
+
+        // Without a header the columns are positional
+        assert_eq!(parse_csv("https://example.com/1,,First").unwrap()[0], items[0]);
+    }
+}
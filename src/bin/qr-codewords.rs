@@ -0,0 +1,6 @@
+use qr_tools::cli;
+
+/// Alias of `qr codewords`
+fn main() {
+    cli::run_alias("qr-codewords", "Dump the codewords of a payload at each stage of encoding, in hex and binary", 2, cli::codewords::run)
+}
@@ -0,0 +1,6 @@
+use qr_tools::cli;
+
+/// Alias of `qr decode`
+fn main() {
+    cli::run_alias("qr-decode", "Decode a QR code image and print its payload", 2, cli::decode::run)
+}
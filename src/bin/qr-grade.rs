@@ -0,0 +1,6 @@
+use qr_tools::cli;
+
+/// Alias of `qr grade`
+fn main() {
+    cli::run_alias("qr-grade", "Grade the print quality of a scanned QR code, ISO 15415-style", 2, cli::grade::run)
+}
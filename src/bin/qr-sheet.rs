@@ -0,0 +1,6 @@
+use qr_tools::cli;
+
+/// Alias of `qr sheet`
+fn main() {
+    cli::run_alias("qr-sheet", "Tile QR codes from a CSV file onto printable pages", 2, cli::sheet::run)
+}
@@ -0,0 +1,3 @@
+fn main() {
+    qr_tools::cli::run()
+}
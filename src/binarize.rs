@@ -0,0 +1,151 @@
+use image::{GrayImage, Luma};
+
+/// Local standard deviation below which a window is treated as flat. A flat window lies inside
+/// a uniform area (quiet zone, finder centre, background), where its own mean says nothing
+/// about whether the area is dark or light.
+const MIN_LOCAL_CONTRAST: f64 = 12.0;
+
+/// Summed-area table over a `width` x `height` grid, with a zero row and column in front
+struct Integral {
+    width: usize,
+    height: usize,
+    table: Vec<f64>,
+}
+
+impl Integral {
+    fn new(width: usize, height: usize, value: impl Fn(usize, usize) -> f64) -> Integral {
+        let mut table = vec![0.0; (width + 1) * (height + 1)];
+        for y in 0..height {
+            let mut row = 0.0;
+            for x in 0..width {
+                row += value(x, y);
+                table[(y + 1) * (width + 1) + x + 1] = table[y * (width + 1) + x + 1] + row;
+            }
+        }
+        Integral { width, height, table }
+    }
+
+    /// Sum over the square of `radius` around (x, y) clipped to the grid, and its pixel count
+    fn window(&self, x: usize, y: usize, radius: usize) -> (f64, f64) {
+        let stride = self.width + 1;
+        let (x0, y0) = (x.saturating_sub(radius), y.saturating_sub(radius));
+        let (x1, y1) = ((x + radius + 1).min(self.width), (y + radius + 1).min(self.height));
+        let sum = self.table[y1 * stride + x1] + self.table[y0 * stride + x0] - self.table[y0 * stride + x1] - self.table[y1 * stride + x0];
+        (sum, ((x1 - x0) * (y1 - y0)) as f64)
+    }
+}
+
+/// Adaptive threshold: each pixel is compared with the mean of a window around it, computed
+/// from integral images, so uneven lighting and shadows do not flip whole regions. The window
+/// spans an eighth of the shorter side, wide enough to cover the centre of a finder pattern.
+/// Pixels in flat windows are compared with the midpoint between the darkest and lightest
+/// pixel nearby instead, so a dark quiet zone around a light-on-dark symbol stays dark.
+///
+/// Returns an image of pure black (0) and white (255) pixels.
+pub fn binarize(img: &GrayImage) -> GrayImage {
+    let (width, height) = img.dimensions();
+    let (w, h) = (width as usize, height as usize);
+    if w == 0 || h == 0 {
+        return img.clone();
+    }
+    let value = |x: usize, y: usize| img.get_pixel(x as u32, y as u32)[0] as f64;
+    let radius = (w.min(h) / 16).max(7);
+
+    let sum = Integral::new(w, h, value);
+    let sum_sq = Integral::new(w, h, |x, y| value(x, y).powi(2));
+    let flat_thresholds = flat_thresholds(img, radius);
+
+    GrayImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let (total, n) = sum.window(x, y, radius);
+        let mean = total / n;
+        let deviation = (sum_sq.window(x, y, radius).0 / n - mean * mean).max(0.0).sqrt();
+        let threshold = if deviation >= MIN_LOCAL_CONTRAST {
+            mean
+        } else {
+            flat_thresholds[y / radius][x / radius]
+        };
+        Luma([if value(x, y) < threshold { 0 } else { 255 }])
+    })
+}
+
+/// Threshold for flat areas, per block of `block` pixels: the midpoint between the darkest and
+/// lightest pixel in the nearest surrounding blocks that show real contrast, with the darkest
+/// pixel at most half as bright as the lightest so a lighting gradient alone does not count.
+/// The search widens until it finds some; an image without any contrast comes out all light.
+#[allow(clippy::needless_range_loop)]
+fn flat_thresholds(img: &GrayImage, block: usize) -> Vec<Vec<f64>> {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let (cols, rows) = (w.div_ceil(block), h.div_ceil(block));
+    let mut ranges = vec![vec![(u8::MAX, u8::MIN); cols]; rows];
+    for (x, y, pixel) in img.enumerate_pixels() {
+        let range = &mut ranges[y as usize / block][x as usize / block];
+        *range = (range.0.min(pixel[0]), range.1.max(pixel[0]));
+    }
+
+    (0..rows)
+        .map(|row| {
+            (0..cols)
+                .map(|col| {
+                    (1..=cols.max(rows))
+                        .find_map(|reach| {
+                            let (mut low, mut high) = (u8::MAX, u8::MIN);
+                            for r in row.saturating_sub(reach)..(row + reach + 1).min(rows) {
+                                for c in col.saturating_sub(reach)..(col + reach + 1).min(cols) {
+                                    low = low.min(ranges[r][c].0);
+                                    high = high.max(ranges[r][c].1);
+                                }
+                            }
+                            let (low, high) = (low as f64, high as f64);
+                            let contrast = high - low >= (2.0 * MIN_LOCAL_CONTRAST).max(high / 2.0);
+                            contrast.then_some((low + high) / 2.0)
+                        })
+                        .unwrap_or(0.0)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::tests::reference_matrix;
+    use crate::decoder::{locate_symbol, sample_matrix};
+    use qrcodegen::{QrCode, QrCodeEcc};
+
+    #[test]
+    fn test_uneven_lighting() {
+        let qr = QrCode::encode_text("shadow", QrCodeEcc::Medium).unwrap();
+        let size = qr.size();
+        let scale = 8;
+        let side = ((size + 8) * scale) as u32;
+
+        // Light fades from 240 on the left to 70 on the right; dark modules stay a third as bright
+        let img = GrayImage::from_fn(side, side, |x, y| {
+            let (col, row) = (x as i32 / scale - 4, y as i32 / scale - 4);
+            let light = 240.0 - 170.0 * x as f64 / side as f64;
+            let dark = (0..size).contains(&col) && (0..size).contains(&row) && qr.get_module(col, row);
+            Luma([if dark { (light / 3.0) as u8 } else { light as u8 }])
+        });
+
+        let expected = reference_matrix(&qr);
+        let read = |img: &GrayImage| locate_symbol(img).map(|grid| sample_matrix(img, &grid)).ok();
+        assert_ne!(read(&img), Some(expected.clone()));
+        assert_eq!(read(&binarize(&img)), Some(expected));
+    }
+
+    #[test]
+    fn test_dark_quiet_zone_stays_dark() {
+        // A light-on-dark symbol: the wide dark margin must not be mistaken for a light area
+        let qr = QrCode::encode_text("negative", QrCodeEcc::Low).unwrap();
+        let scale = 6;
+        let side = ((qr.size() + 8) * scale) as u32;
+        let img = GrayImage::from_fn(side, side, |x, y| {
+            let (col, row) = (x as i32 / scale - 4, y as i32 / scale - 4);
+            Luma([if qr.get_module(col, row) { 200 } else { 40 }])
+        });
+        let expected = GrayImage::from_fn(side, side, |x, y| Luma([if img.get_pixel(x, y)[0] > 100 { 255 } else { 0 }]));
+        assert_eq!(binarize(&img), expected);
+    }
+}
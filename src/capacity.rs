@@ -1,60 +1,75 @@
+//! Every capacity figure of ISO/IEC 18004 for versions 1 to 40 at each error correction level:
+//! total, data and ECC codewords (table 9), characters per mode (table 7) and the block
+//! structure. Encoder, decoder and analyzers all read them from here.
+
+use alloc::{vec, vec::Vec};
 use crate::types::{Version, ErrorCorrection, DataMode};
 
+/// Codewords in a symbol, data and ECC together, per version (index 0 = V1)
+const TOTAL_CODEWORDS: [u16; 40] = [26, 44, 70, 100, 134, 172, 196, 242, 292, 346, 404, 466, 532, 581, 655, 733, 815, 901, 991, 1085, 1156, 1258, 1364, 1474, 1588, 1706, 1828, 1921, 2051, 2185, 2323, 2465, 2611, 2761, 2876, 3034, 3196, 3362, 3532, 3706];
+
+/// Data codewords per version (index 0 = V1), for L, M, Q and H
+const DATA_CODEWORDS: [[u16; 40]; 4] = [
+    [19, 34, 55, 80, 108, 136, 156, 194, 232, 274, 324, 370, 428, 461, 523, 589, 647, 721, 795, 861, 932, 1006, 1094, 1174, 1276, 1370, 1468, 1531, 1631, 1735, 1843, 1955, 2071, 2191, 2306, 2434, 2566, 2702, 2812, 2956],
+    [16, 28, 44, 64, 86, 108, 124, 154, 182, 216, 254, 290, 334, 365, 415, 453, 507, 563, 627, 669, 714, 782, 860, 914, 1000, 1062, 1128, 1193, 1267, 1373, 1455, 1541, 1631, 1725, 1812, 1914, 1992, 2102, 2216, 2334],
+    [13, 22, 34, 48, 62, 76, 88, 110, 132, 154, 180, 206, 244, 261, 295, 325, 367, 397, 445, 485, 512, 568, 614, 664, 718, 754, 808, 871, 911, 985, 1033, 1115, 1171, 1231, 1286, 1354, 1426, 1502, 1582, 1666],
+    [9, 16, 26, 36, 46, 60, 66, 86, 100, 122, 140, 158, 180, 197, 223, 253, 283, 313, 341, 385, 406, 442, 464, 514, 538, 596, 628, 661, 701, 745, 793, 845, 901, 961, 986, 1054, 1096, 1142, 1222, 1276],
+];
+
+/// Error correction codewords per version (index 0 = V1), for L, M, Q and H
+const ECC_CODEWORDS: [[u16; 40]; 4] = [
+    [7, 10, 15, 20, 26, 36, 40, 48, 60, 72, 80, 96, 104, 120, 132, 144, 168, 180, 196, 224, 224, 252, 270, 300, 312, 336, 360, 390, 420, 450, 480, 510, 540, 570, 570, 600, 630, 660, 720, 750],
+    [10, 16, 26, 36, 48, 64, 72, 88, 110, 130, 150, 176, 198, 216, 240, 280, 308, 338, 364, 416, 442, 476, 504, 560, 588, 644, 700, 728, 784, 812, 868, 924, 980, 1036, 1064, 1120, 1204, 1260, 1316, 1372],
+    [13, 22, 36, 52, 72, 96, 108, 132, 160, 192, 224, 260, 288, 320, 360, 408, 448, 504, 546, 600, 644, 690, 750, 810, 870, 952, 1020, 1050, 1140, 1200, 1290, 1350, 1440, 1530, 1590, 1680, 1770, 1860, 1950, 2040],
+    [17, 28, 44, 64, 88, 112, 130, 156, 192, 224, 264, 308, 352, 384, 432, 480, 532, 588, 650, 700, 750, 816, 900, 960, 1050, 1110, 1200, 1260, 1350, 1440, 1530, 1620, 1710, 1800, 1890, 1980, 2100, 2220, 2310, 2430],
+];
+
+/// Characters that fit per version (index 0 = V1): digits, alphanumeric characters and bytes,
+/// each for L, M, Q and H
+const CHARACTERS: [[[u16; 40]; 4]; 3] = [
+    [
+        [41, 77, 127, 187, 255, 322, 370, 461, 552, 652, 772, 883, 1022, 1101, 1250, 1408, 1548, 1725, 1903, 2061, 2232, 2409, 2620, 2812, 3057, 3283, 3517, 3669, 3909, 4158, 4417, 4686, 4965, 5253, 5529, 5836, 6153, 6479, 6743, 7089],
+        [34, 63, 101, 149, 202, 255, 293, 365, 432, 513, 604, 691, 796, 871, 991, 1082, 1212, 1346, 1500, 1600, 1708, 1872, 2059, 2188, 2395, 2544, 2701, 2857, 3035, 3289, 3486, 3693, 3909, 4134, 4343, 4588, 4775, 5039, 5313, 5596],
+        [27, 48, 77, 111, 144, 178, 207, 259, 312, 364, 427, 489, 580, 621, 703, 775, 876, 948, 1063, 1159, 1224, 1358, 1468, 1588, 1718, 1804, 1933, 2085, 2181, 2358, 2473, 2670, 2805, 2949, 3081, 3244, 3417, 3599, 3791, 3993],
+        [17, 34, 58, 82, 106, 139, 154, 202, 235, 288, 331, 374, 427, 468, 530, 602, 674, 746, 813, 919, 969, 1056, 1108, 1228, 1286, 1425, 1501, 1581, 1677, 1782, 1897, 2022, 2157, 2301, 2361, 2524, 2625, 2735, 2927, 3057],
+    ],
+    [
+        [25, 47, 77, 114, 154, 195, 224, 279, 335, 395, 468, 535, 619, 667, 758, 854, 938, 1046, 1153, 1249, 1352, 1460, 1588, 1704, 1853, 1990, 2132, 2223, 2369, 2520, 2677, 2840, 3009, 3183, 3351, 3537, 3729, 3927, 4087, 4296],
+        [20, 38, 61, 90, 122, 154, 178, 221, 262, 311, 366, 419, 483, 528, 600, 656, 734, 816, 909, 970, 1035, 1134, 1248, 1326, 1451, 1542, 1637, 1732, 1839, 1994, 2113, 2238, 2369, 2506, 2632, 2780, 2894, 3054, 3220, 3391],
+        [16, 29, 47, 67, 87, 108, 125, 157, 189, 221, 259, 296, 352, 376, 426, 470, 531, 574, 644, 702, 742, 823, 890, 963, 1041, 1094, 1172, 1263, 1322, 1429, 1499, 1618, 1700, 1787, 1867, 1966, 2071, 2181, 2298, 2420],
+        [10, 20, 35, 50, 64, 84, 93, 122, 143, 174, 200, 227, 259, 283, 321, 365, 408, 452, 493, 557, 587, 640, 672, 744, 779, 864, 910, 958, 1016, 1080, 1150, 1226, 1307, 1394, 1431, 1530, 1591, 1658, 1774, 1852],
+    ],
+    [
+        [17, 32, 53, 78, 106, 134, 154, 192, 230, 271, 321, 367, 425, 458, 520, 586, 644, 718, 792, 858, 929, 1003, 1091, 1171, 1273, 1367, 1465, 1528, 1628, 1732, 1840, 1952, 2068, 2188, 2303, 2431, 2563, 2699, 2809, 2953],
+        [14, 26, 42, 62, 84, 106, 122, 152, 180, 213, 251, 287, 331, 362, 412, 450, 504, 560, 624, 666, 711, 779, 857, 911, 997, 1059, 1125, 1190, 1264, 1370, 1452, 1538, 1628, 1722, 1809, 1911, 1989, 2099, 2213, 2331],
+        [11, 20, 32, 46, 60, 74, 86, 108, 130, 151, 177, 203, 241, 258, 292, 322, 364, 394, 442, 482, 509, 565, 611, 661, 715, 751, 805, 868, 908, 982, 1030, 1112, 1168, 1228, 1283, 1351, 1423, 1499, 1579, 1663],
+        [7, 14, 24, 34, 44, 58, 64, 84, 98, 119, 137, 155, 177, 194, 220, 250, 280, 310, 338, 382, 403, 439, 461, 511, 535, 593, 625, 658, 698, 742, 790, 842, 898, 958, 983, 1051, 1093, 1139, 1219, 1273],
+    ],
+];
+
+fn level_index(error_correction: ErrorCorrection) -> usize {
+    match error_correction {
+        ErrorCorrection::L => 0,
+        ErrorCorrection::M => 1,
+        ErrorCorrection::Q => 2,
+        ErrorCorrection::H => 3,
+    }
+}
+
+fn version_index(version: Version) -> usize {
+    version as usize - 1
+}
+
+/// The version of a symbol `size` modules wide, if it is one of the 40 sizes
 pub fn image_size_to_version(size: usize) -> Option<Version> {
-    match size {
-        21 => Some(Version::V1),   // 21x21
-        25 => Some(Version::V2),   // 25x25
-        29 => Some(Version::V3),   // 29x29
-        33 => Some(Version::V4),   // 33x33
-        37 => Some(Version::V5),   // 37x37
-        41 => Some(Version::V6),   // 41x41
-        45 => Some(Version::V7),   // 45x45
-        49 => Some(Version::V8),   // 49x49
-        53 => Some(Version::V9),   // 53x53
-        57 => Some(Version::V10),  // 57x57
-        61 => Some(Version::V11),  // 61x61
-        65 => Some(Version::V12),  // 65x65
-        69 => Some(Version::V13),  // 69x69
-        73 => Some(Version::V14),  // 73x73
-        77 => Some(Version::V15),  // 77x77
-        81 => Some(Version::V16),  // 81x81
-        85 => Some(Version::V17),  // 85x85
-        89 => Some(Version::V18),  // 89x89
-        93 => Some(Version::V19),  // 93x93
-        97 => Some(Version::V20),  // 97x97
-        101 => Some(Version::V21), // 101x101
-        105 => Some(Version::V22), // 105x105
-        109 => Some(Version::V23), // 109x109
-        113 => Some(Version::V24), // 113x113
-        117 => Some(Version::V25), // 117x117
-        121 => Some(Version::V26), // 121x121
-        125 => Some(Version::V27), // 125x125
-        129 => Some(Version::V28), // 129x129
-        133 => Some(Version::V29), // 133x133
-        137 => Some(Version::V30), // 137x137
-        141 => Some(Version::V31), // 141x141
-        145 => Some(Version::V32), // 145x145
-        149 => Some(Version::V33), // 149x149
-        153 => Some(Version::V34), // 153x153
-        157 => Some(Version::V35), // 157x157
-        161 => Some(Version::V36), // 161x161
-        165 => Some(Version::V37), // 165x165
-        169 => Some(Version::V38), // 169x169
-        173 => Some(Version::V39), // 173x173
-        177 => Some(Version::V40), // 177x177
-        _ => None,
+    if size < 21 || !(size - 21).is_multiple_of(4) {
+        return None;
     }
+    u8::try_from((size - 21) / 4 + 1).ok().and_then(Version::from_u8)
 }
 
 pub fn get_total_codewords_in_bytes(version: Version) -> usize {
-    let v = version as u8;
-    match v {
-        1..=9 => [26, 44, 70, 100, 134, 172, 196, 242, 292][v as usize - 1],
-        10..=19 => [346, 404, 466, 532, 581, 655, 733, 815, 901, 991][v as usize - 10],
-        20..=29 => [1085, 1156, 1258, 1364, 1474, 1588, 1706, 1828, 1921, 2051][v as usize - 20],
-        30..=40 => [2185, 2323, 2465, 2611, 2761, 2876, 3034, 3196, 3362, 3532, 3706][v as usize - 30],
-        _ => panic!("Total codewords not supported for version V{}", v),
-    }
+    TOTAL_CODEWORDS[version_index(version)] as usize
 }
 
 pub fn get_total_codewords_in_bits(version: Version) -> usize {
@@ -62,37 +77,7 @@ pub fn get_total_codewords_in_bits(version: Version) -> usize {
 }
 
 pub fn get_ecc_codewords_in_bytes(version: Version, error_correction: ErrorCorrection) -> usize {
-    let v = version as u8;
-    match error_correction {
-        ErrorCorrection::L => match v {
-            1..=10 => [7, 10, 15, 20, 26, 36, 40, 48, 60, 72][v as usize - 1],
-            11..=20 => [80, 96, 104, 120, 132, 144, 168, 180, 196, 224][v as usize - 11],
-            21..=30 => [224, 252, 270, 300, 312, 336, 360, 390, 420, 450][v as usize - 21],
-            31..=40 => [480, 510, 540, 570, 570, 600, 630, 660, 720, 750][v as usize - 31],
-            _ => panic!("ECC L codewords not supported for version V{}", v),
-        },
-        ErrorCorrection::M => match v {
-            1..=10 => [10, 16, 26, 36, 48, 64, 72, 88, 110, 130][v as usize - 1],
-            11..=20 => [150, 176, 198, 216, 240, 280, 308, 338, 364, 416][v as usize - 11],
-            21..=30 => [442, 476, 504, 560, 588, 644, 700, 728, 784, 812][v as usize - 21],
-            31..=40 => [868, 924, 980, 1036, 1064, 1120, 1204, 1260, 1316, 1372][v as usize - 31],
-            _ => panic!("ECC M codewords not supported for version V{}", v),
-        },
-        ErrorCorrection::Q => match v {
-            1..=10 => [13, 22, 36, 52, 72, 96, 108, 132, 160, 192][v as usize - 1],
-            11..=20 => [224, 260, 288, 320, 360, 408, 448, 504, 546, 600][v as usize - 11],
-            21..=30 => [644, 690, 750, 810, 870, 952, 1020, 1050, 1140, 1200][v as usize - 21],
-            31..=40 => [1290, 1350, 1440, 1530, 1590, 1680, 1770, 1860, 1950, 2040][v as usize - 31],
-            _ => panic!("ECC Q codewords not supported for version V{}", v),
-        },
-        ErrorCorrection::H => match v {
-            1..=10 => [17, 28, 44, 64, 88, 112, 130, 156, 192, 224][v as usize - 1],
-            11..=20 => [264, 308, 352, 384, 432, 480, 532, 588, 650, 700][v as usize - 11],
-            21..=30 => [750, 816, 900, 960, 1050, 1110, 1200, 1260, 1350, 1440][v as usize - 21],
-            31..=40 => [1530, 1620, 1710, 1800, 1890, 1980, 2100, 2220, 2310, 2430][v as usize - 31],
-            _ => panic!("ECC H codewords not supported for version V{}", v),
-        },
-    }
+    ECC_CODEWORDS[level_index(error_correction)][version_index(version)] as usize
 }
 
 pub fn get_ecc_codewords_in_bits(version: Version, error_correction: ErrorCorrection) -> usize {
@@ -100,92 +85,269 @@ pub fn get_ecc_codewords_in_bits(version: Version, error_correction: ErrorCorrec
 }
 
 pub fn get_data_capacity_in_bytes(version: Version, error_correction: ErrorCorrection) -> usize {
-    let total = get_total_codewords_in_bytes(version);
-    let ecc = get_ecc_codewords_in_bytes(version, error_correction);
-    total - ecc
+    DATA_CODEWORDS[level_index(error_correction)][version_index(version)] as usize
 }
 
 pub fn get_data_capacity_in_bits(version: Version, error_correction: ErrorCorrection) -> usize {
-    let total = get_total_codewords_in_bits(version);
-    let ecc = get_ecc_codewords_in_bits(version, error_correction);
-    total - ecc
+    get_data_capacity_in_bytes(version, error_correction) * 8
 }
 
 /// Returns the number of _bytes_ that each version can encode for the given data mode and error correction level.
 /// 
 /// This can be used to determine how much actual data can be encoded in a QR code of the specified version and error correction level.
 pub fn get_unencoded_capacity_in_bytes(version: Version, error_correction: ErrorCorrection, data_mode: DataMode) -> usize {
-    let v = version as u8;
-    match (data_mode, error_correction) {
-        (DataMode::Numeric, ErrorCorrection::L) => match v {
-            1..=10 => [41, 77, 127, 187, 255, 322, 370, 461, 552, 652][v as usize - 1],
-            11..=20 => [772, 883, 1022, 1101, 1250, 1408, 1548, 1725, 1903, 2061][v as usize - 11],
-            21..=30 => [2232, 2409, 2620, 2812, 3057, 3283, 3517, 3669, 3909, 4158][v as usize - 21],
-            31..=40 => [4417, 4686, 4965, 5253, 5529, 5836, 6153, 6479, 6743, 7089][v as usize - 31],
-            _ => panic!("Numeric L mode not supported for version V{}", v),
-        },
-        (DataMode::Numeric, ErrorCorrection::M) => match v {
-            1..=10 => [34, 63, 101, 149, 202, 255, 293, 365, 432, 513][v as usize - 1],
-            11..=20 => [604, 691, 796, 871, 991, 1082, 1212, 1346, 1500, 1600][v as usize - 11],
-            21..=30 => [1708, 1872, 2059, 2188, 2395, 2544, 2701, 2857, 3035, 3289][v as usize - 21],
-            31..=40 => [3486, 3693, 3909, 4134, 4343, 4588, 4775, 5039, 5313, 5596][v as usize - 31],
-            _ => panic!("Numeric M mode not supported for version V{}", v),
-        },
-        (DataMode::Numeric, ErrorCorrection::Q) => match v {
-            1..=10 => [27, 48, 77, 111, 144, 178, 207, 259, 312, 364][v as usize - 1],
-            _ => panic!("Numeric Q mode not supported for version V{}", v),
-        },
-        (DataMode::Numeric, ErrorCorrection::H) => match v {
-            1..=10 => [17, 34, 58, 82, 106, 139, 154, 202, 235, 288][v as usize - 1],
-            _ => panic!("Numeric H mode not supported for version V{}", v),
-        },
-        (DataMode::Alphanumeric, ErrorCorrection::L) => match v {
-            1..=10 => [25, 47, 77, 114, 154, 195, 224, 279, 335, 395][v as usize - 1],
-            11..=20 => [468, 535, 619, 667, 758, 854, 938, 1046, 1153, 1249][v as usize - 11],
-            21..=30 => [1352, 1460, 1588, 1704, 1853, 1990, 2132, 2223, 2369, 2520][v as usize - 21],
-            31..=40 => [2677, 2840, 3009, 3183, 3351, 3537, 3729, 3927, 4087, 4296][v as usize - 31],
-            _ => panic!("Alphanumeric L mode not supported for version V{}", v),
-        },
-        (DataMode::Alphanumeric, ErrorCorrection::M) => match v {
-            1..=10 => [20, 38, 61, 90, 122, 154, 178, 221, 262, 311][v as usize - 1],
-            11..=20 => [366, 419, 483, 528, 600, 656, 734, 816, 909, 970][v as usize - 11],
-            21..=30 => [1035, 1134, 1248, 1326, 1451, 1542, 1637, 1732, 1839, 1994][v as usize - 21],
-            31..=40 => [2113, 2238, 2369, 2506, 2632, 2780, 2894, 3054, 3220, 3391][v as usize - 31],
-            _ => panic!("Alphanumeric M mode not supported for version V{}", v),
-        },
-        (DataMode::Alphanumeric, ErrorCorrection::Q) => match v {
-            1..=10 => [16, 29, 47, 67, 87, 108, 125, 157, 189, 221][v as usize - 1],
-            _ => panic!("Alphanumeric Q mode not supported for version V{}", v),
-        },
-        (DataMode::Alphanumeric, ErrorCorrection::H) => match v {
-            1..=10 => [10, 20, 35, 50, 64, 84, 93, 122, 143, 174][v as usize - 1],
-            _ => panic!("Alphanumeric H mode not supported for version V{}", v),
-        },
-        (DataMode::Byte, ErrorCorrection::L) => match v {
-            1..=10 => [17, 32, 53, 78, 106, 134, 154, 192, 230, 271][v as usize - 1],
-            11..=20 => [321, 367, 425, 458, 520, 586, 644, 718, 792, 858][v as usize - 11],
-            21..=30 => [929, 1003, 1091, 1171, 1273, 1367, 1465, 1528, 1628, 1732][v as usize - 21],
-            31..=40 => [1840, 1952, 2068, 2188, 2303, 2431, 2563, 2699, 2809, 2953][v as usize - 31],
-            _ => panic!("Byte L mode not supported for version V{}", v),
-        },
-        (DataMode::Byte, ErrorCorrection::M) => match v {
-            1..=10 => [14, 26, 42, 62, 84, 106, 122, 152, 180, 213][v as usize - 1],
-            11..=20 => [251, 287, 331, 362, 412, 450, 504, 560, 624, 666][v as usize - 11],
-            21..=30 => [711, 779, 857, 911, 997, 1059, 1125, 1190, 1264, 1370][v as usize - 21],
-            31..=40 => [1452, 1538, 1628, 1722, 1809, 1911, 1989, 2099, 2213, 2331][v as usize - 31],
-            _ => panic!("Byte M mode not supported for version V{}", v),
-        },
-        (DataMode::Byte, ErrorCorrection::Q) => match v {
-            1..=10 => [11, 20, 32, 46, 60, 74, 86, 108, 130, 151][v as usize - 1],
-            _ => panic!("Byte Q mode not supported for version V{}", v),
-        },
-        (DataMode::Byte, ErrorCorrection::H) => match v {
-            1..=10 => [7, 14, 24, 34, 44, 58, 64, 84, 98, 119][v as usize - 1],
-            _ => panic!("Byte H mode not supported for version V{}", v),
-        },
-    }
+    let mode = match data_mode {
+        DataMode::Numeric => 0,
+        DataMode::Alphanumeric => 1,
+        DataMode::Byte => 2,
+    };
+    CHARACTERS[mode][level_index(error_correction)][version_index(version)] as usize
+}
+
+/// Characters of `data_mode` that fit in `version` at `error_correction`: digits in numeric
+/// mode, characters of the 45-character set in alphanumeric mode and bytes in byte mode
+pub fn max_characters(version: Version, error_correction: ErrorCorrection, data_mode: DataMode) -> usize {
+    get_unencoded_capacity_in_bytes(version, error_correction, data_mode)
+}
+
+/// The smallest version that holds `len` characters of `data_mode` at `error_correction`, or
+/// `None` when even version 40 is too small
+pub fn smallest_version_for(len: usize, error_correction: ErrorCorrection, data_mode: DataMode) -> Option<Version> {
+    (1..=40).filter_map(Version::from_u8).find(|&version| len <= max_characters(version, error_correction, data_mode))
 }
 
 pub fn get_unencoded_capacity_in_bits(version: Version, error_correction: ErrorCorrection, data_mode: DataMode) -> usize {
     get_unencoded_capacity_in_bytes(version, error_correction, data_mode) * 8
-}
\ No newline at end of file
+}
+
+/// Reed-Solomon block structure of a symbol. Blocks in group 2 hold one data codeword more
+/// than those in group 1; every block has the same number of ECC codewords.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockLayout {
+    pub ecc_per_block: usize,
+    pub group1_blocks: usize,
+    pub group1_data_codewords: usize,
+    pub group2_blocks: usize,
+    pub group2_data_codewords: usize,
+}
+
+impl BlockLayout {
+    pub fn total_blocks(&self) -> usize {
+        self.group1_blocks + self.group2_blocks
+    }
+
+    /// Data codeword count of each block, in block order
+    pub fn data_lengths(&self) -> Vec<usize> {
+        let mut lengths = vec![self.group1_data_codewords; self.group1_blocks];
+        lengths.extend(core::iter::repeat_n(self.group2_data_codewords, self.group2_blocks));
+        lengths
+    }
+
+    /// Where each block's data codewords lie when the blocks are laid end to end, in block order
+    pub fn data_ranges(&self) -> impl Iterator<Item = core::ops::Range<usize>> {
+        core::iter::repeat_n(self.group1_data_codewords, self.group1_blocks)
+            .chain(core::iter::repeat_n(self.group2_data_codewords, self.group2_blocks))
+            .scan(0, |start, len| {
+                *start += len;
+                Some(*start - len..*start)
+            })
+    }
+
+    /// Split an interleaved codeword stream into its blocks, each holding its data codewords
+    /// followed by its ECC codewords. `None` if the stream is too short for this layout.
+    pub fn deinterleave(&self, codewords: &[u8]) -> Option<Vec<Vec<u8>>> {
+        let lengths = self.data_lengths();
+        let mut blocks: Vec<Vec<u8>> = lengths.iter().map(|&len| Vec::with_capacity(len + self.ecc_per_block)).collect();
+        let mut stream = codewords.iter();
+        let longest = lengths.iter().copied().max().unwrap_or(0);
+        for i in 0..longest {
+            for (block, &len) in blocks.iter_mut().zip(&lengths) {
+                if i < len {
+                    block.push(*stream.next()?);
+                }
+            }
+        }
+        for _ in 0..self.ecc_per_block {
+            for block in blocks.iter_mut() {
+                block.push(*stream.next()?);
+            }
+        }
+        Some(blocks)
+    }
+
+    /// Block and index within the block of every codeword of the interleaved stream, in
+    /// stream order
+    pub fn stream_positions(&self) -> Vec<(usize, usize)> {
+        let lengths = self.data_lengths();
+        let longest = lengths.iter().copied().max().unwrap_or(0);
+        let mut positions = Vec::new();
+        for i in 0..longest {
+            for (block, &len) in lengths.iter().enumerate() {
+                if i < len {
+                    positions.push((block, i));
+                }
+            }
+        }
+        for i in 0..self.ecc_per_block {
+            for (block, &len) in lengths.iter().enumerate() {
+                positions.push((block, len + i));
+            }
+        }
+        positions
+    }
+
+    /// Interleave blocks (data codewords followed by ECC codewords) into the order they are
+    /// placed in the symbol; the inverse of `deinterleave`
+    pub fn interleave(&self, blocks: &[Vec<u8>]) -> Vec<u8> {
+        let lengths = self.data_lengths();
+        let longest = lengths.iter().copied().max().unwrap_or(0);
+        let mut stream = Vec::new();
+        for i in 0..longest {
+            for (block, &len) in blocks.iter().zip(&lengths) {
+                if i < len {
+                    stream.push(block[i]);
+                }
+            }
+        }
+        for i in 0..self.ecc_per_block {
+            for (block, &len) in blocks.iter().zip(&lengths) {
+                stream.push(block[len + i]);
+            }
+        }
+        stream
+    }
+}
+
+/// Number of Reed-Solomon blocks per version (index 0 = V1), for L, M, Q and H
+const NUM_BLOCKS: [[u8; 40]; 4] = [
+    [1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25],
+    [1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49],
+    [1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29, 34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68],
+    [1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81],
+];
+
+/// Block structure for the given version and error correction level
+pub fn get_block_layout(version: Version, error_correction: ErrorCorrection) -> BlockLayout {
+    let blocks = NUM_BLOCKS[level_index(error_correction)][version_index(version)] as usize;
+    let ecc_per_block = get_ecc_codewords_in_bytes(version, error_correction) / blocks;
+    let data = get_data_capacity_in_bytes(version, error_correction);
+
+    BlockLayout {
+        ecc_per_block,
+        group1_blocks: blocks - data % blocks,
+        group1_data_codewords: data / blocks,
+        group2_blocks: data % blocks,
+        group2_data_codewords: data / blocks + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_layout_matches_codeword_totals() {
+        for v in 1..=40 {
+            let version = Version::from_u8(v).unwrap();
+            for ecc in LEVELS {
+                let layout = get_block_layout(version, ecc);
+                assert_eq!(layout.ecc_per_block * layout.total_blocks(), get_ecc_codewords_in_bytes(version, ecc), "{:?}-{:?}", version, ecc);
+                assert_eq!(layout.data_lengths().iter().sum::<usize>(), get_data_capacity_in_bytes(version, ecc));
+            }
+        }
+    }
+
+    const LEVELS: [ErrorCorrection; 4] = [ErrorCorrection::L, ErrorCorrection::M, ErrorCorrection::Q, ErrorCorrection::H];
+
+    #[test]
+    fn test_data_and_ecc_add_up_to_total() {
+        for version in (1..=40).filter_map(Version::from_u8) {
+            for ecc in LEVELS {
+                assert_eq!(get_data_capacity_in_bytes(version, ecc) + get_ecc_codewords_in_bytes(version, ecc), get_total_codewords_in_bytes(version), "{:?}-{:?}", version, ecc);
+            }
+        }
+    }
+
+    #[test]
+    fn test_total_codewords_fill_the_symbol() {
+        // Every module outside the function patterns carries a codeword bit, apart from the
+        // 0 to 7 remainder bits
+        for version in (1..=40).filter_map(Version::from_u8) {
+            let size = version.size();
+            let modules = (0..size).flat_map(|row| (0..size).map(move |col| (row, col))).filter(|&(row, col)| !crate::function_modules::is_function_module(row, col, version)).count();
+            assert_eq!(get_total_codewords_in_bytes(version), modules / 8, "{:?}", version);
+        }
+    }
+
+    #[test]
+    fn test_character_capacities_fill_the_data_codewords() {
+        // The longest segment whose mode indicator, character count and data fit in the data bits
+        let segment_bits = |data_mode: DataMode, version: Version, len: usize| {
+            let range = match version as u8 {
+                1..=9 => 0,
+                10..=26 => 1,
+                _ => 2,
+            };
+            let (count_bits, data_bits) = match data_mode {
+                DataMode::Numeric => ([10, 12, 14][range], len / 3 * 10 + [0, 4, 7][len % 3]),
+                DataMode::Alphanumeric => ([9, 11, 13][range], len / 2 * 11 + len % 2 * 6),
+                DataMode::Byte => ([8, 16, 16][range], len * 8),
+            };
+            4 + count_bits + data_bits
+        };
+        for version in (1..=40).filter_map(Version::from_u8) {
+            for ecc in LEVELS {
+                for data_mode in [DataMode::Numeric, DataMode::Alphanumeric, DataMode::Byte] {
+                    let capacity = get_data_capacity_in_bits(version, ecc);
+                    let max = max_characters(version, ecc, data_mode);
+                    assert!(segment_bits(data_mode, version, max) <= capacity, "{:?}-{:?} {:?}", version, ecc, data_mode);
+                    assert!(segment_bits(data_mode, version, max + 1) > capacity, "{:?}-{:?} {:?}", version, ecc, data_mode);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_image_size_to_version() {
+        assert_eq!(image_size_to_version(21), Some(Version::V1));
+        assert_eq!(image_size_to_version(177), Some(Version::V40));
+        for version in (1..=40).filter_map(Version::from_u8) {
+            assert_eq!(image_size_to_version(version.size()), Some(version));
+        }
+        for size in [0, 17, 22, 181] {
+            assert_eq!(image_size_to_version(size), None);
+        }
+    }
+
+    #[test]
+    fn test_smallest_version_for_is_at_the_capacity_boundary() {
+        assert_eq!(max_characters(Version::V1, ErrorCorrection::M, DataMode::Alphanumeric), 20);
+        assert_eq!(smallest_version_for(20, ErrorCorrection::M, DataMode::Alphanumeric), Some(Version::V1));
+        assert_eq!(smallest_version_for(21, ErrorCorrection::M, DataMode::Alphanumeric), Some(Version::V2));
+        assert_eq!(smallest_version_for(0, ErrorCorrection::H, DataMode::Numeric), Some(Version::V1));
+        assert_eq!(smallest_version_for(2953, ErrorCorrection::L, DataMode::Byte), Some(Version::V40));
+        assert_eq!(smallest_version_for(2954, ErrorCorrection::L, DataMode::Byte), None);
+    }
+
+    #[test]
+    fn test_block_layout_v5_q() {
+        // ISO/IEC 18004 table 9: 5-Q is 2 blocks of 15 and 2 blocks of 16 data codewords, 18 ECC each
+        let layout = get_block_layout(Version::V5, ErrorCorrection::Q);
+        assert_eq!(layout, BlockLayout { ecc_per_block: 18, group1_blocks: 2, group1_data_codewords: 15, group2_blocks: 2, group2_data_codewords: 16 });
+    }
+
+    #[test]
+    fn test_interleave_round_trip() {
+        let layout = get_block_layout(Version::V5, ErrorCorrection::Q);
+        let stream: Vec<u8> = (0..get_total_codewords_in_bytes(Version::V5) as u8).collect();
+        let blocks = layout.deinterleave(&stream).unwrap();
+        // The first data codeword of each block comes first, the longer blocks' extra codeword last
+        assert_eq!(blocks.iter().map(|b| b[0]).collect::<Vec<_>>(), [0, 1, 2, 3]);
+        assert_eq!(blocks[3][15], 61);
+        assert_eq!(layout.interleave(&blocks), stream);
+        assert!(layout.deinterleave(&stream[1..]).is_none());
+        for (i, &(block, index)) in layout.stream_positions().iter().enumerate() {
+            assert_eq!(blocks[block][index], stream[i]);
+        }
+    }
+}
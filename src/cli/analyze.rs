@@ -0,0 +1,1896 @@
+use crate::capacity::get_data_capacity_in_bits;
+use crate::capacity::get_total_codewords_in_bits;
+use crate::capacity::image_size_to_version;
+use crate::capacity::get_block_layout;
+use crate::ecc::{analyze_damage, verify_codewords, Block, CorrectionResult, DamageReport};
+use crate::format_info;
+use crate::version_info;
+use crate::alignment::alignment_centers;
+use crate::function_modules::{dark_module, is_function_module};
+use crate::decoder::{read_format_words, decode_byte_text, load_image, decode_shift_jis, kanji_to_shift_jis, locate_symbol, normalize_orientation, read_eci_designator, read_version_words, recover_format_info, error_free_blocks, remask, read_version_info, read_structured_append, sample_matrix, Orientation, SegmentMode, StructuredAppend};
+use crate::detect::{detect_symbol, symbol_region, Region};
+use crate::binarize::binarize;
+use image::{DynamicImage, GrayImage};
+use crate::render::RenderOptions;
+use crate::visualize::render_region_overlay;
+use crate::reference::{golden, Golden, GOLDENS};
+use crate::micro::MicroVersion;
+use super::diff::region_names;
+use super::input::{open_image, FileFormat};
+use super::output::write_file;
+use super::watch::{change, watch};
+use clap::builder::PossibleValuesParser;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::iter::zip;
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+
+mod html;
+mod micro;
+mod reconcile;
+
+use micro::MicroReport;
+use reconcile::{reconcile, Reconciliation, Source};
+
+use crate::types;
+use crate::mask;
+use crate::mask::{penalty_score, Penalty};
+use crate::ecc;
+use types::{Version, ErrorCorrection, MaskPattern};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BorderCheck {
+    has_border: bool,
+    /// Quiet zone width in modules
+    border_width: usize,
+    valid: bool,
+}
+
+/// Everything the analyzer found out about one image, as printed and serialized by `qr analyze`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QrAnalysis {
+    /// Overall outcome, which also decides the exit code
+    verdict: Verdict,
+    /// Problems and notable facts found by the checks, most severe first
+    findings: Vec<Finding>,
+    /// Diagnostics logged while reading and correcting the symbol
+    warnings: Vec<String>,
+    /// Regular QR or Micro QR, told apart by the symbol size
+    symbol_type: SymbolType,
+    version_from_size: Option<Version>,
+    version_from_format: Option<Version>,
+    versions_match: bool,
+    size: usize,
+    error_correction: Option<ErrorCorrection>,
+    mask_pattern: Option<MaskPattern>,
+    data_analysis: DataAnalysis,
+    format_info: FormatInfo,
+    version_info: Option<VersionInfo>,
+    finder_patterns: Vec<FinderPattern>,
+    timing_patterns: TimingPatterns,
+    dark_module: DarkModule,
+    alignment_patterns: Vec<AlignmentPattern>,
+    border_check: BorderCheck,
+    module_size: f64,
+    /// Rotation and mirroring undone before the analysis
+    orientation: Orientation,
+    /// Whether the symbol was read as light modules on a dark background
+    inverted: bool,
+    /// Part of the image the symbol was cropped to, when it was found among other content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cropped: Option<Region>,
+    /// Result of `--expect`, when given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expectation: Option<Expectation>,
+    /// Result of `--reference`, when given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<ReferenceComparison>,
+    /// Mask penalty of the symbol as read and under each of the eight masks
+    penalty: Option<PenaltyReport>,
+    /// Readings tried when the symbol size, version information and format copies disagree
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reconciliation: Option<Reconciliation>,
+    /// Version, mask and format information of a Micro QR symbol
+    #[serde(skip_serializing_if = "Option::is_none")]
+    micro: Option<MicroReport>,
+    /// Modules as read from the image, kept for the overlay output
+    #[serde(skip)]
+    matrix: Vec<Vec<u8>>,
+}
+
+/// Kind of symbol the analyzer found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SymbolType {
+    /// Versions 1-40, with three finder patterns
+    Qr,
+    /// Versions M1-M4, 11 to 17 modules wide, with a single finder pattern
+    Micro,
+}
+
+/// Overall outcome of an analysis. Each verdict has its own exit code so the analyzer can gate
+/// CI pipelines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Verdict {
+    /// Decoded without corrections and every structural check passed (exit code 0)
+    Clean,
+    /// Decoded, but Reed-Solomon correction had to repair codewords (exit code 1)
+    Corrected,
+    /// Decoded despite structural errors such as damaged finders or format information (exit code 2)
+    StructuralErrors,
+    /// The payload could not be recovered (exit code 3)
+    Undecodable,
+}
+
+impl Verdict {
+    fn description(self) -> &'static str {
+        match self {
+            Verdict::Clean => "decoded cleanly",
+            Verdict::Corrected => "decoded with corrections",
+            Verdict::StructuralErrors => "decoded despite structural errors",
+            Verdict::Undecodable => "undecodable",
+        }
+    }
+
+    fn exit_code(self) -> i32 {
+        match self {
+            Verdict::Clean => 0,
+            Verdict::Corrected => 1,
+            Verdict::StructuralErrors => 2,
+            Verdict::Undecodable => 3,
+        }
+    }
+}
+
+/// Exit code for input that cannot be analyzed at all: bad arguments or an unreadable image
+pub const EXIT_USAGE: i32 = 4;
+/// Exit code when the payload does not match `--expect`, whatever the verdict
+const EXIT_UNEXPECTED: i32 = 5;
+/// Exit code when the modules differ from the `--reference` symbol
+const EXIT_REFERENCE_MISMATCH: i32 = 6;
+
+/// Comparison of the decoded payload with the text given to `--expect`
+#[derive(Debug, Serialize, Deserialize)]
+struct Expectation {
+    expected: String,
+    matched: bool,
+    /// Byte offsets where the UTF-8 encodings differ, up to the longer of the two
+    differences: Vec<ByteDifference>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ByteDifference {
+    offset: usize,
+    /// `None` past the end of the expected text
+    expected: Option<u8>,
+    /// `None` past the end of the decoded payload
+    actual: Option<u8>,
+}
+
+fn compare_payload(expected: &str, actual: Option<&str>) -> Expectation {
+    let (expected_bytes, actual_bytes) = (expected.as_bytes(), actual.unwrap_or_default().as_bytes());
+    let differences: Vec<ByteDifference> = (0..expected_bytes.len().max(actual_bytes.len()))
+        .map(|offset| ByteDifference { offset, expected: expected_bytes.get(offset).copied(), actual: actual_bytes.get(offset).copied() })
+        .filter(|d| d.expected != d.actual)
+        .collect();
+    Expectation { expected: expected.to_string(), matched: actual.is_some() && differences.is_empty(), differences }
+}
+
+/// Module-by-module comparison of the symbol with a golden reference vector
+#[derive(Debug, Serialize, Deserialize)]
+struct ReferenceComparison {
+    name: String,
+    source: String,
+    matched: bool,
+    /// Whether the symbol has as many modules per side as the reference; when it does not,
+    /// no modules are compared
+    size_matches: bool,
+    differences: Vec<ModuleDifference>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ModuleDifference {
+    row: usize,
+    col: usize,
+    /// Region of the module, named as by `qr diff`
+    region: String,
+    /// Whether the reference module is dark
+    expected_dark: bool,
+}
+
+fn compare_reference(golden: &Golden, matrix: &[Vec<u8>]) -> ReferenceComparison {
+    let expected = golden.matrix();
+    let size_matches = matrix.len() == expected.len();
+    let mut differences = Vec::new();
+    if size_matches {
+        let regions = region_names(golden.version, matrix, &expected);
+        for (row, (actual, expected)) in zip(matrix, &expected).enumerate() {
+            for (col, (&actual, &expected)) in zip(actual, expected).enumerate() {
+                if actual != expected {
+                    differences.push(ModuleDifference { row, col, region: regions[row][col].to_string(), expected_dark: expected == 1 });
+                }
+            }
+        }
+    }
+    let matched = size_matches && differences.is_empty();
+    ReferenceComparison { name: golden.name.to_string(), source: golden.source.to_string(), matched, size_matches, differences }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Severity {
+    /// Worth knowing, does not affect the verdict
+    Info,
+    /// Out of spec, but readers cope
+    Warning,
+    /// Breaks the structure of the symbol
+    Error,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Finding {
+    severity: Severity,
+    /// Stable identifier of the check, for tools that filter findings
+    code: String,
+    message: String,
+    /// Module the finding refers to, for findings tied to one place in the symbol
+    location: Option<Location>,
+}
+
+/// Module position within the upright symbol
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Location {
+    row: usize,
+    col: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PenaltyReport {
+    /// Penalty of the symbol exactly as read
+    current: Penalty,
+    /// Penalty the same content would score under each mask, format information included
+    masks: Vec<MaskPenalty>,
+    /// Mask with the lowest total; a conforming encoder picks this one (the first on ties)
+    best_mask: u8,
+    /// Whether the symbol uses the best mask, or one that scores the same
+    current_is_best: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MaskPenalty {
+    mask: u8,
+    penalty: Penalty,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FormatInfo {
+    raw_bits_copy1: Option<String>,
+    raw_bits_copy2: Option<String>,
+    copies_match: bool,
+    error_correction: Option<ErrorCorrection>,
+    mask_pattern: Option<MaskPattern>,
+    version: Option<Version>,
+    /// ECC level and mask were not read from the format information but found by trying
+    /// every combination against the Reed-Solomon blocks
+    recovered_by_search: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VersionInfo {
+    raw_bits_copy1: Option<String>,
+    raw_bits_copy2: Option<String>,
+    copies_match: bool,
+    /// Bits corrected in each copy, `None` when a copy is beyond the 3-bit correction limit
+    errors_copy1: Option<u32>,
+    errors_copy2: Option<u32>,
+    version: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FinderPattern {
+    position: String,
+    /// Top-left module of the pattern
+    row: usize,
+    col: usize,
+    valid: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TimingPatterns {
+    valid: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DarkModule {
+    present: bool,
+    position: (usize, usize),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DataAnalysis {
+    decoded_bit_string: Option<String>,
+    unmasked_bit_string: Option<String>,
+    unmasked_bytes: Option<String>,
+    corrected_bit_string: Option<String>,
+    corrected_bytes: Option<String>,
+    expected_bit_string_size: Option<usize>,
+    actual_bit_string_size: Option<usize>,
+    expected_data_bit_string_size: Option<usize>,
+    expected_ecc_bit_string_size: Option<usize>,
+    encoding_info_bit_string: Option<String>,
+    encoding_name: Option<String>,
+    /// Character set designator from the last ECI header
+    eci: Option<u32>,
+    /// Index, total and parity of the Structured Append header, when the symbol is one of a
+    /// sequence
+    structured_append: Option<StructuredAppend>,
+    /// Segments in the order they appear in the data codewords
+    segments: Vec<Segment>,
+    read_data_bytes: Option<String>,
+    read_ecc_bytes: Option<String>,
+    data_length: Option<usize>,
+    extracted_data: Option<String>,
+    corrected_data: Option<String>,
+    message_bytes: Option<String>,
+    reconstructed_ecc_bytes: Option<String>,
+    data_error_positions: Option<Vec<usize>>,
+    corrupted_bytes_percentage: Option<f64>,
+    /// Correction capacity used and left in each block
+    damage: Option<DamageReport>,
+    padding_bits: Option<String>,
+    data_ecc_valid: bool,
+    block_structure: Option<BlockStructure>,
+    data_corrupted: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Segment {
+    mode: SegmentMode,
+    /// Position of the mode indicator within the data bits
+    bit_offset: usize,
+    /// Character count (ECI headers have none)
+    char_count: Option<usize>,
+    /// Character set in effect for the segment
+    eci: Option<u32>,
+    content: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BlockStructure {
+    detected: bool,
+    group1_blocks: Option<usize>,
+    group1_data_codewords: Option<usize>,
+    group2_blocks: Option<usize>,
+    group2_data_codewords: Option<usize>,
+    ecc_codewords_per_block: Option<usize>,
+    total_data_blocks: Option<usize>,
+    total_ecc_blocks: Option<usize>,
+    /// Reed-Solomon result of each block, in block order
+    blocks: Vec<BlockReport>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BlockReport {
+    index: usize,
+    data_codewords: usize,
+    ecc_codewords: usize,
+    /// Codewords corrected in this block; `None` when the block is beyond repair
+    errors: Option<usize>,
+    correctable: bool,
+    /// The codewords that were repaired, by position within the block
+    repaired: Vec<RepairedCodeword>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RepairedCodeword {
+    /// Index within the block, data codewords first
+    position: usize,
+    /// Bits that were wrong, as the XOR of the read and the corrected value
+    magnitude: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AlignmentPattern {
+    x: usize,
+    y: usize,
+    valid: bool,
+}
+
+/// Log sink that keeps messages for the `warnings` array instead of printing them, so stdout
+/// carries nothing but the report
+struct Diagnostics {
+    messages: Mutex<Vec<String>>,
+}
+
+impl Diagnostics {
+    fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.messages.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+impl log::Log for Diagnostics {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Info
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            self.messages.lock().unwrap_or_else(|e| e.into_inner()).push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static DIAGNOSTICS: Diagnostics = Diagnostics { messages: Mutex::new(Vec::new()) };
+
+/// Exit codes: 0 decoded cleanly, 1 decoded with corrections, 2 structural errors but
+/// decodable, 3 undecodable, 4 bad arguments or unreadable image, 5 payload differs from --expect,
+/// 6 modules differ from --reference
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// QR code image to analyze: PNG, JPEG, BMP, GIF, TIFF, SVG or any other format the
+    /// image crate reads
+    image: String,
+    /// Format of the image, when neither its contents nor its extension tell
+    #[arg(long, value_enum)]
+    format: Option<FileFormat>,
+    /// Write an image with the regions of the symbol highlighted
+    #[arg(long, value_name = "FILE")]
+    overlay: Option<String>,
+    /// Output format
+    #[arg(long, value_enum, default_value = "json")]
+    report: Report,
+    /// Payload the symbol should hold; exit code 5 if it differs
+    #[arg(long, value_name = "TEXT")]
+    expect: Option<String>,
+    /// Golden symbol the modules should match bit for bit; exit code 6 if any differ
+    #[arg(long, value_name = "NAME", value_parser = PossibleValuesParser::new(GOLDENS.iter().map(|golden| golden.name)))]
+    reference: Option<String>,
+    /// Write the report to FILE instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    output: Option<String>,
+    /// Overwrite an existing overlay or report file
+    #[arg(long)]
+    force: bool,
+    /// Keep running, and analyze the image again each time it changes, printing what changed
+    /// since the previous run
+    #[arg(long)]
+    watch: bool,
+}
+
+pub fn run(args: Args) -> i32 {
+    if log::set_logger(&DIAGNOSTICS).is_ok() {
+        log::set_max_level(log::LevelFilter::Info);
+    }
+    if args.watch {
+        // The full report comes once; after that, only what changed. Files written by the
+        // first run are rewritten without --force.
+        let mut previous: Option<QrAnalysis> = None;
+        let mut force = args.force;
+        watch(&[&args.image], || match analyze_and_write(&args, force, previous.is_none()) {
+            Ok(analysis) => {
+                if let Some(previous) = &previous {
+                    let changes = describe_changes(previous, &analysis);
+                    if changes.is_empty() {
+                        println!("  no change");
+                    }
+                    for change in changes {
+                        println!("  {}", change);
+                    }
+                }
+                force = true;
+                previous = Some(analysis);
+            }
+            Err(error) => eprintln!("Error: {}", error),
+        });
+    }
+
+    match analyze_and_write(&args, args.force, true) {
+        Ok(analysis) => exit_code(&analysis),
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            EXIT_USAGE
+        }
+    }
+}
+
+/// Analyze the image and write the overlay and the report: to `--output` when given, otherwise
+/// to stdout if `print` is set
+fn analyze_and_write(args: &Args, force: bool, print: bool) -> Result<QrAnalysis, Box<dyn std::error::Error>> {
+    let filename = &args.image;
+    let mut analysis = analyze_qr_code(filename, args.format)?;
+    if let Some(expected) = &args.expect {
+        analysis.expectation = Some(compare_payload(expected, analysis.data_analysis.extracted_data.as_deref()));
+    }
+    if let Some(golden) = args.reference.as_deref().and_then(golden) {
+        analysis.reference = Some(compare_reference(golden, &analysis.matrix));
+    }
+
+    if let (Some(path), Some(version)) = (&args.overlay, analysis.version_from_size) {
+        write_file(path, force, |file| {
+            render_region_overlay(&analysis.matrix, version, analysis.error_correction, &RenderOptions::default(), file)
+        })?;
+    }
+    
+    match &args.output {
+        Some(path) => write_file(path, force, |file| write_report(file, args.report, filename, &analysis, false))?,
+        None if print => {
+            let color = std::io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+            write_report(&mut std::io::stdout().lock(), args.report, filename, &analysis, color)?
+        }
+        None => {}
+    }
+    Ok(analysis)
+}
+
+fn exit_code(analysis: &QrAnalysis) -> i32 {
+    if analysis.expectation.as_ref().is_some_and(|e| !e.matched) {
+        return EXIT_UNEXPECTED;
+    }
+    if analysis.reference.as_ref().is_some_and(|r| !r.matched) {
+        return EXIT_REFERENCE_MISMATCH;
+    }
+    analysis.verdict.exit_code()
+}
+
+/// What changed between two analyses of the same file, one line each: the verdict, the
+/// symbol parameters, the payload, the modules that flipped by region, the codewords repaired
+/// and the findings that appeared or went away. Empty when nothing did.
+fn describe_changes(previous: &QrAnalysis, current: &QrAnalysis) -> Vec<String> {
+    let known = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+    let mut changes: Vec<String> = [
+        change("verdict", previous.verdict, current.verdict, |v| v.description().to_string()),
+        change("version", previous.version_from_size, current.version_from_size, |v| known(v.map(|v| format!("{:?}", v)))),
+        change("error level", previous.error_correction, current.error_correction, |e| known(e.map(|e| format!("{:?}", e)))),
+        change("mask", previous.mask_pattern, current.mask_pattern, |m| known(m.map(|m| (m as u8).to_string()))),
+        change("payload", &previous.data_analysis.extracted_data, &current.data_analysis.extracted_data, |p| known(p.as_ref().map(|p| format!("{:?}", p)))),
+        change("codewords repaired", repaired_codewords(previous), repaired_codewords(current), |n| known(n.map(|n| n.to_string()))),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    let (before, after) = (&previous.matrix, &current.matrix);
+    if before.len() != after.len() {
+        changes.push(format!("size: {0}x{0} -> {1}x{1} modules", before.len(), after.len()));
+    } else if let Some(version) = current.version_from_size {
+        let regions = region_names(version, before, after);
+        let mut flipped: BTreeMap<&str, usize> = BTreeMap::new();
+        for (row, (before, after)) in zip(before, after).enumerate() {
+            for col in (0..before.len()).filter(|&col| before[col] != after[col]) {
+                *flipped.entry(regions[row][col]).or_default() += 1;
+            }
+        }
+        if !flipped.is_empty() {
+            let counts: Vec<String> = flipped.iter().map(|(region, count)| format!("{} {}", region, count)).collect();
+            changes.push(format!("{} modules flipped ({})", flipped.values().sum::<usize>(), counts.join(", ")));
+        }
+    }
+
+    let key = |finding: &Finding| (finding.code.clone(), finding.message.clone());
+    for finding in previous.findings.iter().filter(|f| !current.findings.iter().any(|g| key(g) == key(f))) {
+        changes.push(format!("- {}: {}", finding.code, finding.message));
+    }
+    for finding in current.findings.iter().filter(|f| !previous.findings.iter().any(|g| key(g) == key(f))) {
+        changes.push(format!("+ {}: {}", finding.code, finding.message));
+    }
+    changes
+}
+
+/// Codewords Reed-Solomon correction repaired over all blocks; `None` when the blocks were not
+/// read or one is beyond repair
+fn repaired_codewords(analysis: &QrAnalysis) -> Option<usize> {
+    let blocks = &analysis.data_analysis.block_structure.as_ref()?.blocks;
+    blocks.iter().map(|block| block.errors).sum()
+}
+
+/// Output format of the analysis
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Report {
+    /// Every field, pretty-printed for machines
+    Json,
+    /// Summary of the checks for people, colorized on a terminal
+    Text,
+    /// Self-contained page with a zoomable module grid, a tooltip per module and the decode trace
+    Html,
+}
+
+fn write_report(out: &mut dyn Write, report: Report, filename: &str, analysis: &QrAnalysis, color: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match report {
+        Report::Json => writeln!(out, "{}", serde_json::to_string_pretty(analysis)?)?,
+        Report::Text => write_text_report(out, filename, analysis, color)?,
+        Report::Html => out.write_all(html::render(filename, analysis)?.as_bytes())?,
+    }
+    Ok(())
+}
+
+/// Comma-separated in-block positions of the codewords repaired in `block`
+fn repaired_positions(block: &BlockReport) -> String {
+    block.repaired.iter().map(|r| r.position.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+/// Write a summary of the analysis: symbol parameters, a ✓ or ✗ per structural check, the
+/// decoded text and the error statistics, colorized when `color` is set
+fn write_text_report(out: &mut dyn Write, filename: &str, analysis: &QrAnalysis, color: bool) -> io::Result<()> {
+    let paint = |code: &str, text: &str| if color { format!("\x1b[{}m{}\x1b[0m", code, text) } else { text.to_string() };
+    let check = |out: &mut dyn Write, ok: bool, label: &str| {
+        let mark = if ok { paint("32", "✓") } else { paint("31", "✗") };
+        writeln!(out, "  {} {}", mark, label)
+    };
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| paint("33", "unknown"));
+
+    writeln!(out, "{}", paint("1", &format!("QR analysis of {}", filename)))?;
+    let verdict_color = match analysis.verdict {
+        Verdict::Clean => "32",
+        Verdict::Corrected | Verdict::StructuralErrors => "33",
+        Verdict::Undecodable => "31",
+    };
+    writeln!(out, "  Verdict       {}", paint(verdict_color, analysis.verdict.description()))?;
+    let micro = analysis.micro.as_ref();
+    match micro {
+        Some(micro) => writeln!(out, "  Version       {} ({}x{} modules, Micro QR)", micro.version, analysis.size, analysis.size)?,
+        None => writeln!(out, "  Version       {} ({}x{} modules)", or_unknown(analysis.version_from_size.map(|v| format!("{:?}", v))), analysis.size, analysis.size)?,
+    }
+    let detection_only = micro.is_some_and(|micro| micro.version_from_format == Some(MicroVersion::M1));
+    let level = analysis.error_correction.map(|e| format!("{:?}", e)).or(detection_only.then(|| "none (M1 detects errors only)".to_string()));
+    writeln!(out, "  Error level   {}", or_unknown(level))?;
+    let recovered = if analysis.format_info.recovered_by_search { " (recovered by search)" } else { "" };
+    let mask = match micro {
+        Some(micro) => micro.mask,
+        None => analysis.mask_pattern.map(|m| m as u8),
+    };
+    writeln!(out, "  Mask          {}{}", or_unknown(mask.map(|m| m.to_string())), recovered)?;
+    if analysis.orientation != Orientation::default() {
+        let mirrored = if analysis.orientation.mirrored { ", mirrored" } else { "" };
+        writeln!(out, "  Orientation   rotated {}°{}", analysis.orientation.rotation, mirrored)?;
+    }
+    if analysis.inverted {
+        writeln!(out, "  Polarity      light on dark")?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "{}", paint("1", "Structure"))?;
+    for finder in &analysis.finder_patterns {
+        check(out, finder.valid, &format!("Finder pattern {}", finder.position))?;
+    }
+    check(out, analysis.timing_patterns.valid, "Timing patterns")?;
+    if micro.is_none() {
+        check(out, analysis.dark_module.present, "Dark module")?;
+    }
+    for pattern in &analysis.alignment_patterns {
+        check(out, pattern.valid, &format!("Alignment pattern at ({}, {})", pattern.x, pattern.y))?;
+    }
+    match micro {
+        Some(micro) => check(out, micro.mask.is_some(), "Format information")?,
+        None => check(out, analysis.format_info.copies_match, "Format information copies match")?,
+    }
+    if let Some(info) = &analysis.version_info {
+        check(out, info.version.is_some(), &format!("Version information ({})", or_unknown(info.version.clone())))?;
+    }
+    check(out, analysis.versions_match, "Version agrees with symbol size")?;
+    if let Some(reconciliation) = &analysis.reconciliation {
+        for (index, hypothesis) in reconciliation.hypotheses.iter().enumerate() {
+            let outcome = match hypothesis.error_free_blocks {
+                _ if !hypothesis.tested => "not sampled at this width".to_string(),
+                Some(error_free) => format!("{} of {} blocks error-free", error_free, hypothesis.blocks),
+                None => "blocks beyond correction".to_string(),
+            };
+            let marker = if reconciliation.trusted == Some(index) { paint("32", "→") } else { " ".to_string() };
+            writeln!(
+                out,
+                "      {} {:?} ({}), ECC {:?} mask {} ({}): {}",
+                marker,
+                hypothesis.version,
+                hypothesis.version_source,
+                hypothesis.error_correction,
+                hypothesis.mask_pattern as u8,
+                hypothesis.format_source,
+                outcome
+            )?;
+        }
+    }
+    check(out, analysis.border_check.valid, &format!("Quiet zone ({} modules)", analysis.border_check.border_width))?;
+
+    let data = &analysis.data_analysis;
+    writeln!(out)?;
+    writeln!(out, "{}", paint("1", "Data"))?;
+    writeln!(out, "  Encoding      {}", or_unknown(data.encoding_name.clone()))?;
+    match &data.extracted_data {
+        Some(text) => writeln!(out, "  Text          {:?}", text)?,
+        None => writeln!(out, "  Text          {}", paint("31", "not decoded"))?,
+    }
+    if let Some(header) = data.structured_append {
+        writeln!(out, "  Sequence      symbol {} of {}, parity {:#04x}", header.index + 1, header.total, header.parity)?;
+    }
+    if let Some(structure) = &data.block_structure {
+        let errors: usize = structure.blocks.iter().filter_map(|b| b.errors).sum();
+        let failed = structure.blocks.iter().filter(|b| !b.correctable).count();
+        let summary = match (failed, errors) {
+            (0, 0) => paint("32", "no errors"),
+            (0, _) => paint("33", &format!("{} codewords corrected", errors)),
+            _ => paint("31", &format!("{} of {} blocks beyond repair", failed, structure.blocks.len())),
+        };
+        writeln!(out, "  Errors        {}", summary)?;
+        for block in &structure.blocks {
+            let state = match block.errors {
+                Some(0) => "0 errors".to_string(),
+                Some(1) => format!("1 error at codeword {}", block.repaired[0].position),
+                Some(errors) => format!("{} errors at codewords {}", errors, repaired_positions(block)),
+                None => paint("31", "uncorrectable"),
+            };
+            writeln!(out, "    Block {:<3}   {} data + {} ECC codewords, {}", block.index + 1, block.data_codewords, block.ecc_codewords, state)?;
+        }
+    }
+    if let Some(percentage) = data.corrupted_bytes_percentage {
+        writeln!(out, "  Corrupted     {:.1}% of codewords", percentage)?;
+    }
+    if let Some(damage) = &data.damage {
+        let worst = damage.blocks.iter().filter_map(|b| b.margin.map(|m| (m, b.capacity))).min();
+        match worst {
+            Some((margin, capacity)) if damage.correctable => writeln!(out, "  Health        {:.0}% ({} of {} corrections left in the worst block)", damage.health, margin, capacity)?,
+            _ => writeln!(out, "  Health        {}", paint("31", "0% (a block is beyond repair)"))?,
+        }
+    }
+
+    if let Some(expectation) = &analysis.expectation {
+        check(out, expectation.matched, &format!("Payload matches {:?}", expectation.expected))?;
+        for difference in &expectation.differences {
+            let byte = |b: Option<u8>| b.map_or("--".to_string(), |b| format!("{:02X}", b));
+            writeln!(out, "      byte {:<5} expected {} got {}", difference.offset, byte(difference.expected), byte(difference.actual))?;
+        }
+    }
+    if let Some(reference) = &analysis.reference {
+        check(out, reference.matched, &format!("Modules match reference {} ({})", reference.name, reference.source))?;
+        if !reference.size_matches {
+            writeln!(out, "      symbol size differs from the reference")?;
+        }
+        for difference in &reference.differences {
+            let expected = if difference.expected_dark { "dark" } else { "light" };
+            writeln!(out, "      row {:<3} col {:<3} {:<11} expected {}", difference.row, difference.col, difference.region, expected)?;
+        }
+    }
+
+    if !analysis.findings.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "{}", paint("1", "Findings"))?;
+        for finding in &analysis.findings {
+            let label = match finding.severity {
+                Severity::Error => paint("31", "error  "),
+                Severity::Warning => paint("33", "warning"),
+                Severity::Info => paint("36", "info   "),
+            };
+            match finding.location {
+                Some(Location { row, col }) => writeln!(out, "  {} {} (row {}, col {})", label, finding.message, row, col)?,
+                None => writeln!(out, "  {} {}", label, finding.message)?,
+            }
+        }
+    }
+
+    if let Some(penalty) = &analysis.penalty {
+        writeln!(out)?;
+        writeln!(out, "{}", paint("1", "Mask penalty"))?;
+        writeln!(out, "  Total         {} (rule 1: {}, rule 2: {}, rule 3: {}, rule 4: {})", penalty.current.total, penalty.current.runs, penalty.current.blocks, penalty.current.finder_like, penalty.current.balance)?;
+        check(out, penalty.current_is_best, &format!("Lowest-penalty mask is {}", penalty.best_mask))?;
+    }
+    Ok(())
+}
+
+fn analyze_qr_code(filename: &str, format: Option<FileFormat>) -> Result<QrAnalysis, Box<dyn std::error::Error>> {
+    Ok(analyze_image(&open_image(filename, format)?))
+}
+
+/// [`analyze_image`] for the contents of an image file already in memory, in any format
+/// [`load_image`] reads. Fails only when they are not an image.
+pub fn analyze_bytes(bytes: &[u8]) -> Result<QrAnalysis, Box<dyn std::error::Error>> {
+    Ok(analyze_image(&load_image(bytes, None)?))
+}
+
+/// Run every check on an image already in memory. A symbol that cannot be found or read is
+/// reported through the findings, so any image, however malformed, yields an analysis.
+pub fn analyze_image(img: &DynamicImage) -> QrAnalysis {
+    let (luma, cropped) = crop_to_symbol(binarize(&img.to_luma8()));
+    let (width, height) = luma.dimensions();
+    
+    let mut analysis = empty_analysis();
+    analysis.cropped = cropped;
+    let (SampledSymbol { matrix, resampled, module_size, border_check }, inverted) = match read_any_polarity(luma, width, height) {
+        Ok(read) => read,
+        Err(error) => {
+            let finding = Finding { severity: Severity::Error, code: "symbol_not_found".to_string(), message: error.to_string(), location: None };
+            return finish(analysis, vec![finding]);
+        }
+    };
+    if let Some(version) = MicroVersion::from_size(matrix.len()) {
+        let (matrix, orientation) = crate::micro::normalize_orientation(&matrix);
+        analysis.symbol_type = SymbolType::Micro;
+        analysis.size = matrix.len();
+        analysis.module_size = module_size;
+        analysis.border_check = border_check;
+        analysis.orientation = orientation;
+        analysis.inverted = inverted;
+        micro::analyze(&mut analysis, &matrix, version);
+        analysis.matrix = matrix;
+        let findings = collect_findings(&analysis);
+        return finish(analysis, findings);
+    }
+    let (matrix, orientation) = normalize_orientation(&matrix);
+    let resampled = resampled.map(|resampled| normalize_orientation(&resampled).0);
+
+    // When the size, version information and format copies disagree, go on with the reading
+    // whose Reed-Solomon blocks check out
+    let reconciliation = reconcile(&matrix, resampled.as_deref());
+    let trusted = reconciliation.as_ref().and_then(Reconciliation::trusted).cloned();
+    let matrix = match (&trusted, resampled) {
+        (Some(trusted), Some(resampled)) if trusted.version.size() == resampled.len() && resampled.len() != matrix.len() => resampled,
+        _ => matrix,
+    };
+    analysis.reconciliation = reconciliation;
+    analysis.size = matrix.len();
+    analysis.module_size = module_size;
+    analysis.border_check = border_check;
+    analysis.orientation = orientation;
+    analysis.inverted = inverted;
+    
+    // Determine version from size
+    analysis.version_from_size = image_size_to_version(analysis.size);
+    let Some(version) = analysis.version_from_size else {
+        let message = format!("symbol is {0}x{0} modules, which is not a QR or Micro QR version size", analysis.size);
+        let finding = Finding { severity: Severity::Error, code: "unsupported_size".to_string(), message, location: None };
+        analysis.matrix = matrix;
+        return finish(analysis, vec![finding]);
+    };
+    
+    // Analyze finder patterns
+    analysis.finder_patterns = analyze_finder_patterns(&matrix);
+    
+    // Analyze timing patterns
+    analysis.timing_patterns = analyze_timing_patterns(&matrix);
+    
+    // Analyze dark module
+    analysis.dark_module = analyze_dark_module(&matrix, version);
+    
+    // Analyze format information
+    if let Some(mut format_info) = analyze_format_info(&matrix) {
+        // For V1-V6, version is implicit from size, so use size-based version
+        format_info.version = analysis.version_from_size;
+        analysis.format_info = format_info;
+        analysis.error_correction = analysis.format_info.error_correction;
+        analysis.mask_pattern = analysis.format_info.mask_pattern;
+        analysis.version_from_format = analysis.format_info.version;
+    }
+    
+    // Analyze version information (V7+), which overrides the size-derived version
+    if let Some((version_info, version)) = analyze_version_info(&matrix) {
+        analysis.version_from_format = version;
+        analysis.version_info = Some(version_info);
+    }
+    
+    if let Some(trusted) = &trusted {
+        analysis.error_correction = Some(trusted.error_correction);
+        analysis.mask_pattern = Some(trusted.mask_pattern);
+        analysis.format_info.error_correction = analysis.error_correction;
+        analysis.format_info.mask_pattern = analysis.mask_pattern;
+        analysis.format_info.recovered_by_search = trusted.format_source == Source::Search;
+    }
+    
+    // Check if versions match
+    analysis.versions_match = analysis.version_from_size == analysis.version_from_format;
+    
+    // Analyze alignment patterns (for V2+)
+    if version != Version::V1 {
+        analysis.alignment_patterns = analyze_alignment_patterns(&matrix, version);
+    }
+    
+    // Try to decode data
+    if let Some(mask) = analysis.mask_pattern {
+        analysis.data_analysis = decode_data_comprehensive(&matrix, mask, version, analysis.error_correction);
+    }
+    
+    // Score the symbol against the mask penalty rules
+    if let (Some(ecc), Some(mask)) = (analysis.error_correction, analysis.mask_pattern) {
+        analysis.penalty = Some(analyze_penalty(&matrix, version, ecc, mask as u8));
+    }
+    
+    analysis.matrix = matrix;
+    let findings = collect_findings(&analysis);
+    finish(analysis, findings)
+}
+
+/// Attach the findings, most severe first, and the logged diagnostics, then settle the verdict
+fn finish(mut analysis: QrAnalysis, mut findings: Vec<Finding>) -> QrAnalysis {
+    findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+    analysis.findings = findings;
+    analysis.warnings = DIAGNOSTICS.take();
+    analysis.verdict = verdict(&analysis);
+    analysis
+}
+
+/// Analysis with nothing checked yet, filled in as far as the symbol can be read
+fn empty_analysis() -> QrAnalysis {
+    QrAnalysis {
+        verdict: Verdict::Undecodable,
+        findings: Vec::new(),
+        warnings: Vec::new(),
+        version_from_size: None,
+        version_from_format: None,
+        symbol_type: SymbolType::Qr,
+        versions_match: false,
+        size: 0,
+        error_correction: None,
+        mask_pattern: None,
+        format_info: FormatInfo {
+            raw_bits_copy1: None,
+            raw_bits_copy2: None,
+            copies_match: false,
+            error_correction: None,
+            mask_pattern: None,
+            version: None,
+            recovered_by_search: false,
+        },
+        version_info: None,
+        data_analysis: DataAnalysis {
+            decoded_bit_string: None,
+            unmasked_bit_string: None,
+            unmasked_bytes: None,
+            corrected_bit_string: None,
+            corrected_bytes: None,
+            expected_bit_string_size: None,
+            actual_bit_string_size: None,
+            expected_data_bit_string_size: None,
+            expected_ecc_bit_string_size: None,
+            encoding_info_bit_string: None,
+            eci: None,
+            structured_append: None,
+            segments: Vec::new(),
+            encoding_name: None,
+            data_length: None,
+            message_bytes: None,
+            reconstructed_ecc_bytes: None,
+            read_data_bytes: None,
+            read_ecc_bytes: None,
+            extracted_data: None,
+            corrected_data: None,
+            data_error_positions: None,
+            corrupted_bytes_percentage: None,
+            damage: None,
+            padding_bits: None,
+            data_ecc_valid: false,
+            block_structure: None,
+            data_corrupted: false,
+        },
+        finder_patterns: Vec::new(),
+        timing_patterns: TimingPatterns { valid: false },
+        dark_module: DarkModule { present: false, position: (0, 0) },
+        alignment_patterns: Vec::new(),
+        border_check: border_from_width(0),
+        module_size: 0.0,
+        orientation: Orientation::default(),
+        inverted: false,
+        cropped: None,
+        expectation: None,
+        reference: None,
+        penalty: None,
+        reconciliation: None,
+        micro: None,
+        matrix: Vec::new(),
+    }
+}
+
+
+/// Turn the individual checks into findings, most severe first
+fn collect_findings(analysis: &QrAnalysis) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut add = |severity: Severity, code: &'static str, message: String, location: Option<(usize, usize)>| {
+        let location = location.map(|(row, col)| Location { row, col });
+        findings.push(Finding { severity, code: code.to_string(), message, location });
+    };
+
+    for finder in analysis.finder_patterns.iter().filter(|f| !f.valid) {
+        add(Severity::Error, "finder_damaged", format!("{} finder pattern is damaged", finder.position), Some((finder.row, finder.col)));
+    }
+    let timing_location = match analysis.symbol_type {
+        SymbolType::Qr => (6, 8),
+        SymbolType::Micro => (0, 8),
+    };
+    if !analysis.timing_patterns.valid {
+        add(Severity::Error, "timing_broken", "timing patterns do not alternate".to_string(), Some(timing_location));
+    }
+    if analysis.symbol_type == SymbolType::Qr && !analysis.dark_module.present {
+        add(Severity::Warning, "dark_module_missing", "dark module is light".to_string(), Some(analysis.dark_module.position));
+    }
+    for pattern in analysis.alignment_patterns.iter().filter(|p| !p.valid) {
+        add(Severity::Warning, "alignment_damaged", format!("alignment pattern at ({}, {}) is damaged", pattern.x, pattern.y), Some((pattern.y, pattern.x)));
+    }
+
+    let format = &analysis.format_info;
+    let (format_read, format_location) = match &analysis.micro {
+        Some(micro) => (micro.mask.is_some(), Some((8, 1))),
+        None => (analysis.mask_pattern.is_some(), Some((8, 0))),
+    };
+    if !format_read {
+        add(Severity::Error, "format_unreadable", "format information is unreadable".to_string(), format_location);
+    } else if format.recovered_by_search {
+        add(Severity::Error, "format_recovered_by_search", "format information is beyond repair; ECC level and mask were found by search".to_string(), format_location);
+    } else if !format.copies_match {
+        add(Severity::Warning, "format_copies_differ", "format information copies differ".to_string(), format_location);
+    }
+    let version_info_location = Some((analysis.size.saturating_sub(11), 0));
+    if let Some(info) = &analysis.version_info {
+        match info.version {
+            None => add(Severity::Error, "version_info_unreadable", "version information is unreadable".to_string(), version_info_location),
+            Some(_) if !info.copies_match => add(Severity::Warning, "version_info_copies_differ", "version information copies differ".to_string(), version_info_location),
+            Some(_) => {}
+        }
+    }
+    if analysis.version_from_format.is_some() && !analysis.versions_match {
+        let message = format!("symbol size says {:?} but the symbol information says {:?}", analysis.version_from_size, analysis.version_from_format);
+        add(Severity::Error, "version_mismatch", message, None);
+    }
+    if let Some(micro) = &analysis.micro && let Some(version) = micro.version_from_format.filter(|&version| version != micro.version) {
+        let message = format!("symbol size says {} but the format information says {}", micro.version, version);
+        add(Severity::Error, "version_mismatch", message, None);
+    }
+
+    if let Some(region) = analysis.cropped {
+        let message = format!("symbol found among other content and cropped to {}x{} px at ({}, {})", region.width, region.height, region.x, region.y);
+        add(Severity::Info, "cropped_to_symbol", message, None);
+    }
+    if let Some(reconciliation) = &analysis.reconciliation {
+        let severity = if reconciliation.trusted.is_some() { Severity::Info } else { Severity::Warning };
+        add(severity, "sources_reconciled", reconciliation.reason.clone(), None);
+    }
+
+    // Micro QR symbols need half the quiet zone of regular ones
+    let border = &analysis.border_check;
+    let required = match analysis.symbol_type {
+        SymbolType::Qr => 4,
+        SymbolType::Micro => 2,
+    };
+    if !border.has_border {
+        add(Severity::Error, "quiet_zone_missing", "symbol has no quiet zone".to_string(), None);
+    } else if border.border_width < required {
+        add(Severity::Warning, "quiet_zone_narrow", format!("quiet zone is {} modules wide, {} are required", border.border_width, required), None);
+    }
+
+    let data = &analysis.data_analysis;
+    if let Some(structure) = &data.block_structure {
+        for block in &structure.blocks {
+            match block.errors {
+                None => add(Severity::Error, "block_uncorrectable", format!("block {} has too many errors to correct", block.index + 1), None),
+                Some(0) => {}
+                Some(errors) => {
+                    let message = format!("block {}: {} codeword{} corrected ({})", block.index + 1, errors, if errors == 1 { "" } else { "s" }, repaired_positions(block));
+                    add(Severity::Info, "block_corrected", message, None);
+                }
+            }
+        }
+    }
+    if data.extracted_data.is_none() {
+        add(Severity::Error, "payload_undecoded", "payload could not be decoded".to_string(), None);
+    }
+
+    if let Some(penalty) = analysis.penalty.as_ref().filter(|p| !p.current_is_best) {
+        add(Severity::Info, "mask_not_optimal", format!("mask {} would have a lower penalty than the mask used", penalty.best_mask), None);
+    }
+
+    findings
+}
+
+fn verdict(analysis: &QrAnalysis) -> Verdict {
+    let data = &analysis.data_analysis;
+    let corrected = data.block_structure.iter().flat_map(|s| &s.blocks).any(|b| b.errors != Some(0));
+    if data.extracted_data.is_none() {
+        Verdict::Undecodable
+    } else if analysis.findings.iter().any(|f| f.severity == Severity::Error) {
+        Verdict::StructuralErrors
+    } else if corrected {
+        Verdict::Corrected
+    } else {
+        Verdict::Clean
+    }
+}
+
+struct SampledSymbol {
+    matrix: Vec<Vec<u8>>,
+    /// The symbol sampled again at the width its version information gives, when that differs
+    resampled: Option<Vec<Vec<u8>>>,
+    module_size: f64,
+    border_check: BorderCheck,
+}
+
+/// Locate the symbol and sample one value per module. Finder-pattern detection handles photos
+/// and rotated scans; when no finder patterns are recognizable (e.g. they are damaged), fall
+/// back to the dark bounding box of an upright symbol.
+fn read_modules(luma: &GrayImage, width: u32, height: u32) -> Result<SampledSymbol, Box<dyn std::error::Error>> {
+    if let Ok(detection) = detect_symbol(luma) {
+        let module = (detection.top_left.module_size + detection.top_right.module_size + detection.bottom_left.module_size) / 3.0;
+        let (tl, tr, bl) = (detection.top_left, detection.top_right, detection.bottom_left);
+        let margin = tl.x.min(tl.y).min(width as f64 - tr.x).min(height as f64 - bl.y);
+        let border_width = (margin / module - 3.5).max(0.0).round() as usize;
+        let matrix = detection.sample(luma);
+        let resampled = read_version_info(&matrix)
+            .filter(|version| version.size() != matrix.len() && version.size().abs_diff(matrix.len()) <= 8)
+            .and_then(|version| detection.with_version(luma, version).ok())
+            .map(|detection| detection.sample(luma));
+        return Ok(SampledSymbol { matrix, resampled, module_size: module, border_check: border_from_width(border_width) });
+    }
+
+    let grid = locate_symbol(luma)?;
+    Ok(SampledSymbol {
+        matrix: sample_matrix(luma, &grid),
+        resampled: None,
+        module_size: grid.module_width,
+        border_check: border_from_width(grid.quiet_zone(width, height)),
+    })
+}
+
+/// Quiet zone kept around a symbol cropped out of a larger image, in modules
+const CROP_MARGIN: f64 = 4.0;
+
+/// Cut a symbol out of a screenshot or a page: text and graphics around it would otherwise
+/// widen the dark bounding box and count against the quiet zone. The symbol is found by its
+/// finder patterns in either polarity; an image holding nothing else is left whole.
+fn crop_to_symbol(luma: GrayImage) -> (GrayImage, Option<Region>) {
+    let mut negative = luma.clone();
+    image::imageops::invert(&mut negative);
+    match symbol_region(&luma, CROP_MARGIN).or_else(|| symbol_region(&negative, CROP_MARGIN)) {
+        Some(region) => (image::imageops::crop_imm(&luma, region.x, region.y, region.width, region.height).to_image(), Some(region)),
+        None => (luma, None),
+    }
+}
+
+/// Sample the symbol in both polarities and keep the reading whose finder patterns check out,
+/// so light-on-dark symbols are analyzed too. A light quiet zone breaks ties (read in the wrong
+/// polarity, the dark background swallows it); remaining ties go to the normal reading.
+fn read_any_polarity(luma: GrayImage, width: u32, height: u32) -> Result<(SampledSymbol, bool), Box<dyn std::error::Error>> {
+    let valid_finders = |symbol: &SampledSymbol| {
+        let (matrix, _) = normalize_orientation(&symbol.matrix);
+        let finders = analyze_finder_patterns(&matrix).iter().filter(|p| p.valid).count();
+        (finders, symbol.border_check.valid)
+    };
+
+    let normal = read_modules(&luma, width, height);
+    let mut negative = luma;
+    image::imageops::invert(&mut negative);
+    let inverse = read_modules(&negative, width, height);
+
+    match (normal, inverse) {
+        (Ok(normal), Ok(inverse)) if valid_finders(&inverse) > valid_finders(&normal) => Ok((inverse, true)),
+        (Ok(normal), _) => Ok((normal, false)),
+        (Err(_), Ok(inverse)) => Ok((inverse, true)),
+        (Err(error), Err(_)) => Err(error),
+    }
+}
+
+fn border_from_width(border_width: usize) -> BorderCheck {
+    BorderCheck {
+        has_border: border_width > 0,
+        border_width,
+        valid: border_width > 0,
+    }
+}
+
+fn analyze_finder_patterns(matrix: &[Vec<u8>]) -> Vec<FinderPattern> {
+    let mut patterns = Vec::new();
+    let size = matrix.len();
+    
+    // Check top-left
+    patterns.push(FinderPattern {
+        position: "top-left".to_string(),
+        row: 0,
+        col: 0,
+        valid: check_finder_pattern(matrix, 0, 0),
+    });
+    
+    // Check top-right
+    patterns.push(FinderPattern {
+        position: "top-right".to_string(),
+        row: 0,
+        col: size - 7,
+        valid: check_finder_pattern(matrix, size - 7, 0),
+    });
+    
+    // Check bottom-left
+    patterns.push(FinderPattern {
+        position: "bottom-left".to_string(),
+        row: size - 7,
+        col: 0,
+        valid: check_finder_pattern(matrix, 0, size - 7),
+    });
+    
+    patterns
+}
+
+fn check_finder_pattern(matrix: &[Vec<u8>], start_x: usize, start_y: usize) -> bool {
+    let expected = [
+        [1,1,1,1,1,1,1],
+        [1,0,0,0,0,0,1],
+        [1,0,1,1,1,0,1],
+        [1,0,1,1,1,0,1],
+        [1,0,1,1,1,0,1],
+        [1,0,0,0,0,0,1],
+        [1,1,1,1,1,1,1],
+    ];
+    
+    for y in 0..7 {
+        for x in 0..7 {
+            if matrix[start_y + y][start_x + x] != expected[y][x] {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn analyze_timing_patterns(matrix: &[Vec<u8>]) -> TimingPatterns {
+    let size = matrix.len();
+    let expected = |i: usize| ((i + 1) % 2) as u8;
+    
+    // Check the horizontal timing pattern, then the vertical one
+    let valid = (8..(size - 8)).all(|i| matrix[6][i] == expected(i)) && (8..(size - 8)).all(|i| matrix[i][6] == expected(i));
+    
+    TimingPatterns { valid }
+}
+
+fn analyze_dark_module(matrix: &[Vec<u8>], version: Version) -> DarkModule {
+    let (row, col) = dark_module(version);
+    let present = matrix[row][col] == 1;
+    
+    DarkModule {
+        present,
+        position: (row, col),
+    }
+}
+
+fn analyze_format_info(matrix: &[Vec<u8>]) -> Option<FormatInfo> {
+    let size = matrix.len();
+    let [word1, word2] = read_format_words(matrix);
+    let raw_bits1 = format!("{:015b}", word1);
+    let raw_bits2 = format!("{:015b}", word2);
+    let copies_match = word1 == word2;
+    log::debug!("Format bits (copy 1): {}", raw_bits1);
+    log::debug!("Format bits (copy 2): {}", raw_bits2);
+
+    // A copy damaged beyond repair can also land near the wrong word, so the corrected
+    // combination has to produce valid Reed-Solomon blocks before it is trusted
+    let version = image_size_to_version(size);
+    let corrected = format_info::decode(word1).or_else(|_| format_info::decode(word2)).ok();
+    let verified = corrected.filter(|&(ec, mask_idx)| version.is_none_or(|v| error_free_blocks(matrix, v, ec, mask_idx).is_some()));
+    let mut recovered_by_search = false;
+    let (ecc, mask) = if let Some((ec, mask_idx)) = verified {
+        log::debug!("Corrected format info: ECC {:?}, Mask {:?}", ec, mask_idx);
+        (Some(ec), Some(MaskPattern::from_index(mask_idx)))
+    } else if let Some((ec, mask_idx)) = version.and_then(|v| recover_format_info(matrix, v)) {
+        // Both copies are beyond repair: take the combination whose Reed-Solomon blocks check out
+        log::warn!("Recovered format info by trying every mask and ECC level: ECC {:?}, Mask {:?}", ec, mask_idx);
+        recovered_by_search = true;
+        (Some(ec), Some(MaskPattern::from_index(mask_idx)))
+    } else if let Some((ec, mask_idx)) = corrected {
+        log::warn!("Corrected format info: ECC {:?}, Mask {:?} (data blocks do not check out)", ec, mask_idx);
+        (Some(ec), Some(MaskPattern::from_index(mask_idx)))
+    } else {
+        log::warn!("Failed to correct format info");
+        (None, None)
+    };
+    
+    Some(FormatInfo {
+        raw_bits_copy1: Some(raw_bits1),
+        raw_bits_copy2: Some(raw_bits2),
+        copies_match,
+        error_correction: ecc,
+        mask_pattern: mask,
+        version: None,
+        recovered_by_search,
+    })
+}
+
+fn analyze_penalty(matrix: &[Vec<u8>], version: Version, ecc: ErrorCorrection, current_mask: u8) -> PenaltyReport {
+    let masks: Vec<MaskPenalty> = (0..8)
+        .map(|mask| MaskPenalty { mask, penalty: penalty_score(&remask(matrix, version, ecc, current_mask, mask)) })
+        .collect();
+    let best = masks.iter().min_by_key(|m| m.penalty.total).expect("eight masks");
+    let current = penalty_score(matrix);
+    PenaltyReport {
+        current,
+        best_mask: best.mask,
+        current_is_best: masks[current_mask as usize].penalty.total == best.penalty.total,
+        masks,
+    }
+}
+
+fn analyze_alignment_patterns(matrix: &[Vec<u8>], version: Version) -> Vec<AlignmentPattern> {
+    alignment_centers(version)
+        .map(|(x, y)| AlignmentPattern { x, y, valid: check_alignment_pattern(matrix, x, y) })
+        .collect()
+}
+
+fn check_alignment_pattern(matrix: &[Vec<u8>], center_x: usize, center_y: usize) -> bool {
+    let expected = [
+        [1,1,1,1,1],
+        [1,0,0,0,1],
+        [1,0,1,0,1],
+        [1,0,0,0,1],
+        [1,1,1,1,1],
+    ];
+    
+    for (y, expected_row) in expected.iter().enumerate() {
+        for (x, &expected_module) in expected_row.iter().enumerate() {
+            let matrix_x = center_x - 2 + x;
+            let matrix_y = center_y - 2 + y;
+            if matrix[matrix_y][matrix_x] != expected_module {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+fn decode_data_comprehensive(matrix: &[Vec<u8>], mask: MaskPattern, version: Version, ecc_level: Option<ErrorCorrection>) -> DataAnalysis {
+    let size = matrix.len();
+
+    let mut analysis_result = DataAnalysis {
+        decoded_bit_string: None,
+        unmasked_bit_string: None,
+        unmasked_bytes: None,
+        corrected_bytes: None,
+        corrected_bit_string: None,
+        expected_bit_string_size: None,
+        actual_bit_string_size: None,
+        expected_data_bit_string_size: None,
+        expected_ecc_bit_string_size: None,
+        encoding_info_bit_string: None,
+        eci: None,
+        structured_append: None,
+        segments: Vec::new(),
+        reconstructed_ecc_bytes: None,
+        encoding_name: None,
+        data_length: None,
+        message_bytes: None,
+        read_data_bytes: None,
+        read_ecc_bytes: None,
+        extracted_data: None,
+        corrected_data: None,
+        data_error_positions: None,
+        corrupted_bytes_percentage: None,
+        damage: None,
+        padding_bits: None,
+        data_ecc_valid: false,
+        block_structure: None,
+        data_corrupted: true,
+    };
+    
+    // Step 1: Read raw bit string from matrix
+    let decoded_bits = read_data_bits(matrix, size);
+    let decoded_bit_string = decoded_bits.iter().map(|&b| if b == 1 { '1' } else { '0' }).collect::<String>();
+    analysis_result.decoded_bit_string = Some(decoded_bit_string);
+    
+    // Step 2: Apply mask to matrix and read unmasked bits
+    let mut unmasked_matrix = matrix.to_vec();
+    mask::apply_mask(&mut unmasked_matrix, mask);
+    let unmasked_bits = read_data_bits(&unmasked_matrix, size);
+    let unmasked_bit_string = unmasked_bits.iter().map(|&b| if b == 1 { '1' } else { '0' }).collect::<String>();
+    analysis_result.unmasked_bit_string = Some(unmasked_bit_string.clone());
+    
+    if unmasked_bits.len() < 8 {
+        return analysis_result;
+    }
+    let unmasked_bytes = bits_to_bytes(&unmasked_bits);
+    analysis_result.unmasked_bytes = Some(unmasked_bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" "));
+
+    let Some(ecc_level) = ecc_level else {
+        return analysis_result;
+    };
+    
+    // Step 2.5: Attempt error correction or fallback to original data
+    let total_capacity_bits = get_total_codewords_in_bits(version);
+    analysis_result.expected_bit_string_size = Some(total_capacity_bits);
+    analysis_result.actual_bit_string_size = Some(unmasked_bits.len());
+    
+    let data_capacity_bits = get_data_capacity_in_bits(version, ecc_level);
+    analysis_result.expected_data_bit_string_size = Some(data_capacity_bits);
+    
+    // Calculate actual boundaries based on unmasked_bits length
+    if data_capacity_bits > unmasked_bits.len() {
+        log::warn!("Not enough bits read. Expected {}, got {}", data_capacity_bits, unmasked_bits.len());
+        return analysis_result; // Not enough bits read
+    }
+    if !data_capacity_bits.is_multiple_of(8) {
+        log::warn!("Number of bits read is not byte-aligned: {}", data_capacity_bits);
+        return analysis_result; // Data capacity not byte-aligned
+    }
+    let ecc_bits_expected = total_capacity_bits - data_capacity_bits;
+    analysis_result.expected_ecc_bit_string_size = Some(ecc_bits_expected);
+
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" ");
+    let layout = get_block_layout(version, ecc_level);
+    let Some(blocks) = layout.deinterleave(&unmasked_bytes) else {
+        log::warn!("Not enough codewords read for {} blocks", layout.total_blocks());
+        return analysis_result;
+    };
+    let lengths = layout.data_lengths();
+    let read_data: Vec<u8> = blocks.iter().zip(&lengths).flat_map(|(block, &len)| block[..len].to_vec()).collect();
+    let read_ecc: Vec<u8> = blocks.iter().zip(&lengths).flat_map(|(block, &len)| block[len..].to_vec()).collect();
+    analysis_result.read_data_bytes = Some(hex(&read_data));
+    analysis_result.read_ecc_bytes = Some(hex(&read_ecc));
+    let as_read: Vec<Block> = blocks.iter().map(|block| Block { codewords: block.clone(), num_ecc_codewords: layout.ecc_per_block }).collect();
+    analysis_result.data_ecc_valid = verify_codewords(&as_read).is_valid();
+    analysis_result.damage = analyze_damage(&unmasked_bytes, version, ecc_level).ok();
+
+    // Correct every block on its own, then put the data codewords back in message order
+    let mut block_reports = Vec::with_capacity(blocks.len());
+    let mut corrected_blocks = Vec::with_capacity(blocks.len());
+    for (index, (block, &len)) in blocks.iter().zip(&lengths).enumerate() {
+        let (corrected, repaired) = match ecc::correct_errors(block, layout.ecc_per_block) {
+            CorrectionResult::ErrorFree(_) => (Some(block.clone()), Vec::new()),
+            CorrectionResult::Corrected { error_positions, error_magnitudes, .. } => {
+                let mut codewords = block.clone();
+                for (&position, &magnitude) in zip(&error_positions, &error_magnitudes) {
+                    codewords[position] ^= magnitude;
+                }
+                let repaired = zip(error_positions, error_magnitudes).map(|(position, magnitude)| RepairedCodeword { position, magnitude }).collect();
+                (Some(codewords), repaired)
+            }
+            CorrectionResult::Uncorrectable => (None, Vec::new()),
+        };
+        let errors = corrected.as_ref().map(|_| repaired.len());
+        block_reports.push(BlockReport { index, data_codewords: len, ecc_codewords: layout.ecc_per_block, errors, correctable: corrected.is_some(), repaired });
+        corrected_blocks.push(corrected);
+    }
+    analysis_result.block_structure = Some(BlockStructure {
+        detected: true,
+        group1_blocks: Some(layout.group1_blocks),
+        group1_data_codewords: Some(layout.group1_data_codewords),
+        group2_blocks: Some(layout.group2_blocks),
+        group2_data_codewords: Some(layout.group2_data_codewords),
+        ecc_codewords_per_block: Some(layout.ecc_per_block),
+        total_data_blocks: Some(layout.total_blocks()),
+        total_ecc_blocks: Some(layout.total_blocks()),
+        blocks: block_reports,
+    });
+
+    let Some(corrected_blocks) = corrected_blocks.into_iter().collect::<Option<Vec<Vec<u8>>>>() else {
+        log::warn!("Uncorrectable errors detected in data.");
+        return analysis_result; // Correction failed, return without corrected data
+    };
+    let corrected_data: Vec<u8> = corrected_blocks.iter().zip(&lengths).flat_map(|(block, &len)| block[..len].to_vec()).collect();
+    let corrected_stream = layout.interleave(&corrected_blocks);
+    let data_error_positions: Vec<usize> = zip(&unmasked_bytes, &corrected_stream).enumerate().filter(|(_, (a, b))| a != b).map(|(i, _)| i).collect();
+    let corrected_bit_string = bytes_to_bit_string(&corrected_data);
+    if !data_error_positions.is_empty() {
+        let corrected_ecc: Vec<u8> = corrected_blocks.iter().zip(&lengths).flat_map(|(block, &len)| block[len..].to_vec()).collect();
+        analysis_result.corrected_bit_string = Some(corrected_bit_string.clone());
+        analysis_result.corrected_bytes = Some(hex(&corrected_data));
+        analysis_result.corrected_data = Some(hex(&corrected_stream));
+        analysis_result.reconstructed_ecc_bytes = Some(hex(&corrected_ecc));
+        analysis_result.corrupted_bytes_percentage = Some((data_error_positions.len() as f64 / (corrected_stream.len() as f64)) * 100.0);
+        analysis_result.data_error_positions = Some(data_error_positions);
+    }
+
+    // Step 3: Walk the segments up to the terminator or the end of the data capacity
+    let bit_at = |position: usize, count: usize| -> Option<u32> {
+        if position + count > data_capacity_bits {
+            return None;
+        }
+        corrected_bit_string.get(position..position + count).and_then(|bits| u32::from_str_radix(bits, 2).ok())
+    };
+    let mut position = 0;
+    let mut message_bits = String::new();
+    let mut segments = Vec::new();
+    while let Some(mode_bits) = bit_at(position, 4) {
+        if analysis_result.encoding_info_bit_string.is_none() {
+            analysis_result.encoding_info_bit_string = Some(format!("{:04b}", mode_bits));
+        }
+        if mode_bits == 0 {
+            position += 4;
+            break;
+        }
+        let Some(mode) = SegmentMode::from_bits(mode_bits) else {
+            log::warn!("Unsupported segment mode {:04b} at bit {}", mode_bits, position);
+            analysis_result.encoding_name = Some("Unknown".to_string());
+            return analysis_result;
+        };
+
+        if mode == SegmentMode::Eci {
+            let mut cursor = position + 4;
+            let eci = read_eci_designator(|count| {
+                let value = bit_at(cursor, count);
+                cursor += count;
+                value
+            });
+            let Some(eci) = eci else {
+                log::warn!("Invalid ECI designator at bit {}", position);
+                return analysis_result;
+            };
+            analysis_result.eci = Some(eci);
+            segments.push(Segment { mode, bit_offset: position, char_count: None, eci: Some(eci), content: None });
+            position = cursor;
+            continue;
+        }
+        if mode == SegmentMode::StructuredAppend {
+            let mut cursor = position + 4;
+            let Some(header) = read_structured_append(|count| {
+                let value = bit_at(cursor, count);
+                cursor += count;
+                value
+            }) else {
+                log::warn!("Structured Append header at bit {} runs past the data capacity", position);
+                return analysis_result;
+            };
+            analysis_result.structured_append = Some(header);
+            segments.push(Segment { mode, bit_offset: position, char_count: None, eci: analysis_result.eci, content: None });
+            position = cursor;
+            continue;
+        }
+
+        let Some(char_count) = bit_at(position + 4, mode.char_count_bits(version)).map(|n| n as usize) else {
+            log::warn!("{} segment at bit {} is missing its character count", mode, position);
+            return analysis_result;
+        };
+        let data_start = position + 4 + mode.char_count_bits(version);
+        let data_bits = segment_data_bits(mode, char_count);
+        let Some(content) = decode_segment(mode, char_count, data_start, analysis_result.eci, &bit_at) else {
+            log::warn!("{} segment of {} characters runs past the data capacity", mode, char_count);
+            return analysis_result;
+        };
+        message_bits.push_str(&corrected_bit_string[data_start..data_start + data_bits]);
+        segments.push(Segment { mode, bit_offset: position, char_count: Some(char_count), eci: analysis_result.eci, content: Some(content) });
+        position = data_start + data_bits;
+    }
+
+    let data_segments: Vec<&Segment> = segments.iter().filter(|s| !matches!(s.mode, SegmentMode::Eci | SegmentMode::StructuredAppend)).collect();
+    if data_segments.is_empty() {
+        analysis_result.encoding_name = Some("Unknown".to_string());
+        return analysis_result;
+    }
+    analysis_result.encoding_name = Some(data_segments.iter().map(|s| s.mode.to_string()).collect::<Vec<_>>().join("+"));
+    analysis_result.data_length = Some(data_segments.iter().filter_map(|s| s.char_count).sum());
+    analysis_result.extracted_data = Some(data_segments.iter().filter_map(|s| s.content.as_deref()).collect());
+    let message_bits: Vec<u8> = message_bits.bytes().map(|b| b - b'0').collect();
+    analysis_result.message_bytes = Some(bits_to_bytes(&message_bits).iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" "));
+    analysis_result.padding_bits = Some(corrected_bit_string[position.min(data_capacity_bits)..data_capacity_bits].to_string());
+    analysis_result.segments = segments;
+
+    analysis_result
+}
+
+/// Number of data bits a segment of `char_count` characters occupies after its header
+fn segment_data_bits(mode: SegmentMode, char_count: usize) -> usize {
+    match mode {
+        SegmentMode::Numeric => char_count / 3 * 10 + [0, 4, 7][char_count % 3],
+        SegmentMode::Alphanumeric => char_count / 2 * 11 + [0, 6][char_count % 2],
+        SegmentMode::Kanji => char_count * 13,
+        SegmentMode::Byte => char_count * 8,
+        SegmentMode::Eci | SegmentMode::StructuredAppend => 0,
+    }
+}
+
+/// Text of a segment whose data starts at bit `start`; `None` if it runs past the data capacity
+fn decode_segment(mode: SegmentMode, char_count: usize, start: usize, eci: Option<u32>, bit_at: &dyn Fn(usize, usize) -> Option<u32>) -> Option<String> {
+    const ALPHANUMERIC_CHARS: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+    let mut position = start;
+    let mut read = |count: usize| {
+        let value = bit_at(position, count);
+        position += count;
+        value
+    };
+
+    match mode {
+        SegmentMode::Numeric => {
+            let mut digits = String::new();
+            let mut left = char_count;
+            while left > 0 {
+                let group = left.min(3);
+                let value = read([0, 4, 7, 10][group])?;
+                digits.push_str(&format!("{:0width$}", value, width = group));
+                left -= group;
+            }
+            Some(digits)
+        }
+        SegmentMode::Alphanumeric => {
+            let char_of = |value: u32| ALPHANUMERIC_CHARS.get(value as usize).map_or(' ', |&c| c as char);
+            let mut chars = String::new();
+            for _ in 0..char_count / 2 {
+                let pair = read(11)?;
+                chars.push(char_of(pair / 45));
+                chars.push(char_of(pair % 45));
+            }
+            if char_count % 2 == 1 {
+                chars.push(char_of(read(6)?));
+            }
+            Some(chars)
+        }
+        SegmentMode::Kanji => {
+            let mut shift_jis = Vec::new();
+            for _ in 0..char_count {
+                shift_jis.extend(kanji_to_shift_jis(read(13)? as u16));
+            }
+            Some(decode_shift_jis(&shift_jis))
+        }
+        SegmentMode::Byte => {
+            let bytes = (0..char_count).map(|_| read(8).map(|b| b as u8)).collect::<Option<Vec<u8>>>()?;
+            if eci.is_some() || std::str::from_utf8(&bytes).is_ok() {
+                Some(decode_byte_text(&bytes, eci))
+            } else {
+                Some(format!("{:?}", bytes))
+            }
+        }
+        SegmentMode::Eci | SegmentMode::StructuredAppend => Some(String::new()),
+    }
+}
+
+fn bytes_to_bit_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:08b}", byte)).collect::<Vec<String>>().join("")
+}
+
+#[allow(clippy::needless_range_loop)]
+fn read_data_bits(matrix: &[Vec<u8>], size: usize) -> Vec<u8> {
+    let mut bits = Vec::new();
+    let mut col = size - 1;
+    let mut going_up = true;
+    
+    // Function modules depend on the version, so a matrix of any other size has no data to read
+    let Some(version) = image_size_to_version(size) else {
+        return bits;
+    };
+    let max_bits = get_total_codewords_in_bits(version);
+    
+    while col > 0 && bits.len() < max_bits {
+        if col == 6 { col -= 1; } // Skip timing column
+        
+        if going_up {
+            // Read from bottom to top
+            for row in (0..size).rev() {
+                if bits.len() >= max_bits { break; }
+                // Read right column first, then left column
+                for offset in [0, 1] {
+                    if bits.len() >= max_bits { break; }
+                    if col >= offset {
+                        let c = col - offset;
+                        if !is_function_module(row, c, version) {
+                            bits.push(matrix[row][c]);
+                        }
+                    }
+                }
+            }
+        } else {
+            // Read from top to bottom
+            for row in 0..size {
+                if bits.len() >= max_bits { break; }
+                // Read right column first, then left column
+                for offset in [0, 1] {
+                    if bits.len() >= max_bits { break; }
+                    if col >= offset {
+                        let c = col - offset;
+                        if !is_function_module(row, c, version) {
+                            bits.push(matrix[row][c]);
+                        }
+                    }
+                }
+            }
+        }
+        
+        going_up = !going_up;
+        col = col.saturating_sub(2);
+    }
+    
+    bits
+}
+
+#[allow(dead_code)]
+fn apply_mask_to_bits(bits: &[u8], mask: MaskPattern, version: Version) -> Vec<u8> {
+    let size = version.size();
+    let mut unmasked_bits = Vec::new();
+    let mut bit_index = 0;
+    let mut col = size - 1;
+    let mut going_up = true;
+    
+    while col > 0 && bit_index < bits.len() {
+        if col == 6 { col -= 1; }
+        
+        for c in [col, col - 1] {
+            let mut row = if going_up { size - 1 } else { 0 };
+            
+            loop {
+                if !is_function_module(row, c, version) && bit_index < bits.len() {
+                    let unmasked_bit = apply_mask_to_bit(bits[bit_index], row, c, mask);
+                    unmasked_bits.push(unmasked_bit);
+                    bit_index += 1;
+                }
+                
+                if going_up {
+                    if row == 0 { break; }
+                    row -= 1;
+                } else {
+                    if row == size - 1 { break; }
+                    row += 1;
+                }
+            }
+        }
+        
+        going_up = !going_up;
+        col = col.saturating_sub(2);
+    }
+    
+    unmasked_bits
+}
+
+#[allow(dead_code)]
+fn apply_mask_to_bit(bit: u8, row: usize, col: usize, mask: MaskPattern) -> u8 {
+    let mask_value = match mask {
+        MaskPattern::Pattern0 => (row + col).is_multiple_of(2),
+        MaskPattern::Pattern1 => row.is_multiple_of(2),
+        MaskPattern::Pattern2 => col.is_multiple_of(3),
+        MaskPattern::Pattern3 => (row + col).is_multiple_of(3),
+        MaskPattern::Pattern4 => (row / 2 + col / 3).is_multiple_of(2),
+        MaskPattern::Pattern5 => (row * col) % 2 + (row * col) % 3 == 0,
+        MaskPattern::Pattern6 => ((row * col) % 2 + (row * col) % 3).is_multiple_of(2),
+        MaskPattern::Pattern7 => ((row + col) % 2 + (row * col) % 3).is_multiple_of(2),
+    };
+    
+    if mask_value { 1 - bit } else { bit }
+}
+
+fn bits_to_bytes(bits: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for chunk in bits.chunks(8) {
+        let mut byte = 0u8;
+        for (i, &bit) in chunk.iter().enumerate() {
+            byte |= bit << (7 - i);
+        }
+        bytes.push(byte);
+    }
+    bytes
+}
+
+fn analyze_version_info(matrix: &[Vec<u8>]) -> Option<(VersionInfo, Option<Version>)> {
+    if matrix.len() < Version::V7.size() { // Only V7+ have version info
+        return None;
+    }
+    
+    // Bottom-left (6x3) and top-right (3x6) copies, corrected separately with BCH(18,6)
+    let (word1, word2) = read_version_words(matrix);
+    let (copy1, copy2) = (version_info::decode(word1), version_info::decode(word2));
+    
+    // Trust whichever copy needed fewer corrections
+    let version = [copy1, copy2].into_iter().flatten().min_by_key(|&(_, errors)| errors).map(|(version, _)| version);
+    
+    Some((VersionInfo {
+        raw_bits_copy1: Some(format!("{:018b}", word1)),
+        raw_bits_copy2: Some(format!("{:018b}", word2)),
+        copies_match: word1 == word2,
+        errors_copy1: copy1.map(|(_, errors)| errors),
+        errors_copy2: copy2.map(|(_, errors)| errors),
+        version: version.map(|v| format!("{:?}", v)),
+    }, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::tests::reference_matrix;
+
+    #[test]
+    fn test_compare_payload() {
+        assert!(compare_payload("HELLO", Some("HELLO")).matched);
+        let expectation = compare_payload("HELLO!", Some("HELPO"));
+        assert!(!expectation.matched);
+        let offsets: Vec<_> = expectation.differences.iter().map(|d| (d.offset, d.expected, d.actual)).collect();
+        assert_eq!(offsets, [(3, Some(b'L'), Some(b'P')), (5, Some(b'!'), None)]);
+        assert!(!compare_payload("", None).matched);
+    }
+
+    #[test]
+    fn test_compare_reference_lists_differing_modules() {
+        let golden = golden("hello-world-1q").unwrap();
+        let mut matrix = golden.matrix();
+        assert!(compare_reference(golden, &matrix).matched);
+
+        matrix[0][0] ^= 1;
+        matrix[20][20] ^= 1;
+        let comparison = compare_reference(golden, &matrix);
+        assert!(!comparison.matched);
+        let differences: Vec<_> = comparison.differences.iter().map(|d| (d.row, d.col, d.region.as_str(), d.expected_dark)).collect();
+        assert_eq!(differences, [(0, 0, "finder", true), (20, 20, "data", false)]);
+
+        let comparison = compare_reference(golden, &matrix[1..]);
+        assert!(!comparison.matched && !comparison.size_matches && comparison.differences.is_empty());
+    }
+
+    #[test]
+    fn test_crops_a_symbol_out_of_a_screenshot() {
+        let symbol = crate::render::render_image(&golden("hello-world-1q").unwrap().matrix(), &RenderOptions::default()).unwrap();
+        let mut page = image::RgbImage::from_pixel(symbol.width() + 400, symbol.height() + 200, image::Rgb([255, 255, 255]));
+        image::imageops::overlay(&mut page, &symbol, 300, 100);
+        // Lines of "text" to the left of the symbol and a rule across the page above it
+        for line in 0..10u32 {
+            for y in 120 + line * 24..130 + line * 24 {
+                for x in 30..150 + line * 37 % 100 {
+                    page.put_pixel(x, y, image::Rgb([30, 30, 30]));
+                }
+            }
+        }
+        for x in 10..page.width() - 10 {
+            for y in 40..44 {
+                page.put_pixel(x, y, image::Rgb([0, 0, 0]));
+            }
+        }
+
+        let analysis = analyze_image(&DynamicImage::from(page));
+        assert_eq!(analysis.verdict, Verdict::Clean);
+        assert!(analysis.border_check.valid);
+        let region = analysis.cropped.unwrap();
+        assert_eq!((region.x, region.y), (300, 100));
+        assert!(analysis.findings.iter().any(|f| f.code == "cropped_to_symbol"));
+        assert_eq!(analyze_image(&DynamicImage::from(symbol)).cropped, None);
+    }
+
+    #[test]
+    fn test_describe_changes_between_runs() {
+        let analyze = |matrix: &[Vec<u8>]| analyze_image(&DynamicImage::from(crate::render::render_image(matrix, &RenderOptions::default()).unwrap()));
+        let mut matrix = golden("hello-world-1q").unwrap().matrix();
+        let clean = analyze(&matrix);
+        assert!(describe_changes(&clean, &analyze(&matrix)).is_empty());
+
+        // Two modules of the first data codeword, in the bottom-right corner
+        matrix[20][20] ^= 1;
+        matrix[20][19] ^= 1;
+        let damaged = analyze(&matrix);
+        assert_eq!(
+            describe_changes(&clean, &damaged)[..3],
+            ["verdict: decoded cleanly -> decoded with corrections", "codewords repaired: 0 -> 1", "2 modules flipped (data 2)"]
+        );
+        assert_eq!(describe_changes(&damaged, &clean)[0], "verdict: decoded with corrections -> decoded cleanly");
+    }
+
+    #[test]
+    fn test_analyzes_micro_qr_symbols() {
+        use crate::micro::tests::{field, micro_symbol};
+        use crate::micro::MicroFormat;
+        // "HELLO" in M3-L: alphanumeric mode, then two pairs and a single character
+        let bits: Vec<u8> = field(1, 2).chain(field(5, 4)).chain(field(17 * 45 + 14, 11)).chain(field(21 * 45 + 21, 11)).chain(field(24, 6)).collect();
+        let format = MicroFormat { version: MicroVersion::M3, error_correction: Some(ErrorCorrection::L), mask: 1 };
+        let mut matrix = micro_symbol(format, &bits);
+        let render = |matrix: &[Vec<u8>]| DynamicImage::from(crate::render::render_image(matrix, &RenderOptions::default()).unwrap());
+
+        let analysis = analyze_image(&render(&matrix));
+        assert_eq!((analysis.symbol_type, analysis.verdict), (SymbolType::Micro, Verdict::Clean), "{:?}", analysis.findings);
+        assert_eq!(analysis.data_analysis.extracted_data.as_deref(), Some("HELLO"));
+        assert_eq!(analysis.error_correction, Some(ErrorCorrection::L));
+        let micro = analysis.micro.as_ref().unwrap();
+        assert_eq!((micro.version, micro.mask, micro.format_bit_errors), (MicroVersion::M3, Some(1), Some(0)));
+        let json: serde_json::Value = serde_json::to_value(&analysis).unwrap();
+        assert_eq!(json["symbol_type"], "micro");
+        let mut text = Vec::new();
+        write_text_report(&mut text, "micro.png", &analysis, false).unwrap();
+        assert!(String::from_utf8(text).unwrap().contains("Version       M3 (15x15 modules, Micro QR)"));
+
+        // Upside down, with a data codeword damaged
+        matrix[14][14] ^= 1;
+        matrix[14][13] ^= 1;
+        let turned: Vec<Vec<u8>> = matrix.iter().rev().map(|row| row.iter().rev().copied().collect()).collect();
+        let analysis = analyze_image(&render(&turned));
+        assert_eq!(analysis.verdict, Verdict::Corrected, "{:?}", analysis.findings);
+        assert_eq!(analysis.orientation.rotation, 180);
+        assert_eq!(analysis.data_analysis.extracted_data.as_deref(), Some("HELLO"));
+        assert_eq!(repaired_codewords(&analysis), Some(1));
+    }
+
+    #[test]
+    fn test_report_survives_a_json_round_trip() {
+        let qr = qrcodegen::QrCode::encode_text("HELLO WORLD", qrcodegen::QrCodeEcc::Quartile).unwrap();
+        let matrix = reference_matrix(&qr);
+        let mut png = Vec::new();
+        crate::render::render_png(&matrix, &crate::render::RenderOptions::default(), &mut png).unwrap();
+        let analysis = analyze_bytes(&png).unwrap();
+        assert!(analyze_bytes(b"not an image").is_err());
+
+        let json = serde_json::to_string(&analysis).unwrap();
+        let reloaded: QrAnalysis = serde_json::from_str(&json).unwrap();
+        assert_eq!(reloaded.verdict, Verdict::Clean);
+        assert_eq!(serde_json::to_string(&reloaded).unwrap(), json);
+    }
+}
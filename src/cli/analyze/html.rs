@@ -0,0 +1,329 @@
+//! `qr analyze --report html`: one self-contained page with the symbol as a zoomable module
+//! grid, a tooltip for every module and the steps the decoder went through, for debugging a
+//! symbol or showing how one is read.
+
+use serde::Serialize;
+use crate::decoder::mask_bit;
+use crate::function_modules::is_function_module;
+use crate::pixel_mapping::{trace, ModuleSource};
+use super::super::diff::region_names;
+use super::{Location, QrAnalysis, Severity, Verdict};
+
+/// What the tooltip of one module shows, serialized as an array to keep large symbols small:
+/// region, dark as read, codeword in the stream, bit (7 = most significant), block, what the
+/// codeword holds, and the bit after unmasking
+#[derive(Serialize)]
+struct ModuleInfo(&'static str, bool, Option<usize>, Option<u8>, Option<usize>, Option<String>, Option<bool>);
+
+const STYLE: &str = r#"
+body { font: 14px/1.4 system-ui, sans-serif; margin: 2em; color: #222; max-width: 72em; }
+h1 { font-size: 1.4em; } h2 { font-size: 1.1em; margin-top: 2em; border-bottom: 1px solid #ddd; }
+dl { display: grid; grid-template-columns: max-content auto; gap: .2em 1.5em; }
+dt { color: #666; } dd { margin: 0; font-family: ui-monospace, monospace; overflow-wrap: anywhere; }
+table { border-collapse: collapse; } td, th { padding: .2em .8em; border-bottom: 1px solid #eee; text-align: left; }
+.grid { overflow: auto; max-height: 80vh; border: 1px solid #ddd; background: #fff; }
+.controls { margin: .5em 0; display: flex; gap: 1.5em; align-items: center; }
+#tooltip { position: fixed; pointer-events: none; background: #222; color: #fff; padding: .4em .6em;
+  border-radius: 4px; font: 12px/1.4 ui-monospace, monospace; white-space: pre; display: none; }
+.error { color: #b00; } .warning { color: #a60; } .info { color: #06c; }
+.clean { color: #080; } .corrected, .structural_errors { color: #a60; } .undecodable { color: #b00; }
+pre { background: #f6f6f6; padding: 1em; overflow: auto; }
+"#;
+
+/// Draws the region tints and codeword highlight, and fills the tooltip, from `modules`
+const SCRIPT: &str = r#"
+const svg = document.getElementById('symbol');
+const tooltip = document.getElementById('tooltip');
+const highlight = document.getElementById('highlight');
+const colors = { finder: '#e53935', timing: '#fb8c00', alignment: '#8e24aa', format: '#1e88e5',
+  version: '#00897b', data: '#43a047', ecc: '#fdd835', remainder: '#757575', data_or_ecc: '#7cb342' };
+const square = (row, col) => `M${col} ${row}h1v1h-1z`;
+const tints = {};
+modules.forEach((info, i) => {
+  tints[info[0]] = (tints[info[0]] || '') + square(Math.floor(i / size), i % size);
+});
+const layer = document.getElementById('regions');
+for (const [region, d] of Object.entries(tints)) {
+  const path = document.createElementNS('http://www.w3.org/2000/svg', 'path');
+  path.setAttribute('d', d);
+  path.setAttribute('fill', colors[region] || '#999');
+  layer.appendChild(path);
+}
+document.getElementById('show-regions').addEventListener('change', event => {
+  layer.style.display = event.target.checked ? '' : 'none';
+});
+const zoom = document.getElementById('zoom');
+const resize = () => { svg.style.width = svg.style.height = (size + 2) * zoom.value + 'px'; };
+zoom.addEventListener('input', resize);
+resize();
+svg.addEventListener('mousemove', event => {
+  const point = new DOMPoint(event.clientX, event.clientY).matrixTransform(svg.getScreenCTM().inverse());
+  const row = Math.floor(point.y), col = Math.floor(point.x);
+  if (row < 0 || col < 0 || row >= size || col >= size) { svg.dispatchEvent(new Event('mouseleave')); return; }
+  const [region, dark, codeword, bit, block, source, unmasked] = modules[row * size + col];
+  const lines = [`row ${row}, col ${col}`, `region     ${region}`, `as read    ${dark ? 'dark (1)' : 'light (0)'}`];
+  if (unmasked !== null) lines.push(`unmasked   ${unmasked ? 1 : 0}`);
+  if (codeword !== null) lines.push(`codeword   ${codeword}, bit ${bit}`, `block      ${block + 1}`);
+  if (source !== null) lines.push(`holds      ${source}`);
+  tooltip.textContent = lines.join('\n');
+  tooltip.style.display = 'block';
+  tooltip.style.left = event.clientX + 14 + 'px';
+  tooltip.style.top = event.clientY + 14 + 'px';
+  let d = square(row, col);
+  if (codeword !== null) {
+    modules.forEach((info, i) => { if (info[2] === codeword) d += square(Math.floor(i / size), i % size); });
+  }
+  highlight.setAttribute('d', d);
+});
+svg.addEventListener('mouseleave', () => { tooltip.style.display = 'none'; highlight.setAttribute('d', ''); });
+"#;
+
+/// The whole page for `analysis` of the image `filename`
+pub(super) fn render(filename: &str, analysis: &QrAnalysis) -> Result<String, serde_json::Error> {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>QR analysis of {0}</title>\n<style>{1}</style>\n</head>\n<body>\n<h1>QR analysis of {0}</h1>\n",
+        escape(filename),
+        STYLE
+    );
+    let verdict = serde_json::to_value(analysis.verdict)?;
+    let verdict = verdict.as_str().unwrap_or_default();
+    html += &format!("<p>Verdict: <strong class=\"{}\">{}</strong></p>\n", verdict, verdict_label(analysis.verdict));
+
+    if analysis.matrix.is_empty() {
+        html += "<p>No symbol was found, so there is no module grid.</p>\n";
+    } else {
+        let modules = serde_json::to_string(&module_infos(analysis))?.replace("</", "<\\/");
+        html += &symbol_svg(&analysis.matrix);
+        html += &format!("<div id=\"tooltip\"></div>\n<script>\nconst size = {};\nconst modules = {};\n{}</script>\n", analysis.matrix.len(), modules, SCRIPT);
+    }
+
+    html += &decode_trace(analysis);
+    html += &format!(
+        "<h2>Full analysis</h2>\n<details><summary>JSON</summary>\n<pre>{}</pre>\n</details>\n</body>\n</html>\n",
+        escape(&serde_json::to_string_pretty(analysis)?)
+    );
+    Ok(html)
+}
+
+fn verdict_label(verdict: Verdict) -> &'static str {
+    match verdict {
+        Verdict::Clean => "decoded cleanly",
+        Verdict::Corrected => "decoded with corrections",
+        Verdict::StructuralErrors => "decoded despite structural errors",
+        Verdict::Undecodable => "undecodable",
+    }
+}
+
+/// The modules as read, one unit per module with a one-module margin, under the region tints
+/// and the highlight the script draws
+fn symbol_svg(matrix: &[Vec<u8>]) -> String {
+    let size = matrix.len();
+    let mut dark = String::new();
+    for (row, modules) in matrix.iter().enumerate() {
+        for (col, _) in modules.iter().enumerate().filter(|&(_, &module)| module == 1) {
+            dark += &format!("M{} {}h1v1h-1z", col, row);
+        }
+    }
+    format!(
+        "<h2>Modules</h2>\n<div class=\"controls\">\n<label>Zoom <input id=\"zoom\" type=\"range\" min=\"2\" max=\"40\" value=\"{zoom}\"></label>\n\
+         <label><input id=\"show-regions\" type=\"checkbox\" checked> Color regions</label>\n</div>\n\
+         <div class=\"grid\">\n<svg id=\"symbol\" xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"-1 -1 {side} {side}\" shape-rendering=\"crispEdges\">\n\
+         <rect x=\"-1\" y=\"-1\" width=\"{side}\" height=\"{side}\" fill=\"#fff\"/>\n<path d=\"{dark}\" fill=\"#000\"/>\n\
+         <g id=\"regions\" opacity=\"0.35\"></g>\n<path id=\"highlight\" fill=\"none\" stroke=\"#d00\" stroke-width=\"0.15\"/>\n</svg>\n</div>\n",
+        zoom = (600 / (size + 2)).clamp(2, 40),
+        side = size + 2,
+    )
+}
+
+/// Region, codeword and unmasked bit of every module, row by row. Codewords need the ECC
+/// level and unmasking the mask, so either may be missing when the format information was
+/// unreadable.
+fn module_infos(analysis: &QrAnalysis) -> Vec<ModuleInfo> {
+    let matrix = &analysis.matrix;
+    let size = matrix.len();
+    let Some(version) = analysis.version_from_size else {
+        return matrix.iter().flatten().map(|&module| ModuleInfo("unknown", module == 1, None, None, None, None, None)).collect();
+    };
+    let regions = region_names(version, matrix, matrix);
+    let mut origins = vec![None; size * size];
+    if let Some(error_correction) = analysis.error_correction {
+        for origin in trace(version, error_correction) {
+            origins[origin.row * size + origin.col] = Some(origin);
+        }
+    }
+
+    let mut infos = Vec::with_capacity(size * size);
+    for (row, modules) in matrix.iter().enumerate() {
+        for (col, &module) in modules.iter().enumerate() {
+            let origin = origins[row * size + col];
+            let location = origin.and_then(|origin| origin.codeword);
+            let source = origin.map(|origin| match origin.source {
+                ModuleSource::Data(k) | ModuleSource::Padding(k) => format!("data codeword {} of the message", k),
+                ModuleSource::Ecc(j) => format!("ECC codeword {} of its block", j),
+                ModuleSource::Remainder => "remainder bit".to_string(),
+            });
+            let unmasked = analysis
+                .mask_pattern
+                .filter(|_| !is_function_module(row, col, version))
+                .map(|mask| (module == 1) ^ mask_bit(mask as u8, row, col));
+            infos.push(ModuleInfo(
+                regions[row][col],
+                module == 1,
+                location.map(|location| location.codeword),
+                location.map(|location| location.bit),
+                location.map(|location| location.block),
+                source,
+                unmasked,
+            ));
+        }
+    }
+    infos
+}
+
+/// The decoder's steps in order, each with what it read
+fn decode_trace(analysis: &QrAnalysis) -> String {
+    let mut html = String::from("<h2>Decode trace</h2>\n<ol>\n");
+    let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+
+    let orientation = &analysis.orientation;
+    let mut located = vec![
+        ("Size", format!("{0}x{0} modules", analysis.size)),
+        ("Module size", format!("{:.1} px", analysis.module_size)),
+        ("Quiet zone", format!("{} modules", analysis.border_check.border_width)),
+        ("Orientation", format!("rotated {}°{}", orientation.rotation, if orientation.mirrored { ", mirrored" } else { "" })),
+        ("Polarity", if analysis.inverted { "light on dark" } else { "dark on light" }.to_string()),
+    ];
+    if let Some(region) = analysis.cropped {
+        located.push(("Cropped to", format!("{}x{} px at ({}, {})", region.width, region.height, region.x, region.y)));
+    }
+    html += &step("Locate the symbol", &located);
+
+    let format = &analysis.format_info;
+    html += &step(
+        "Read the format information",
+        &[
+            ("Copy 1", or_unknown(format.raw_bits_copy1.clone())),
+            ("Copy 2", or_unknown(format.raw_bits_copy2.clone())),
+            ("Copies match", format.copies_match.to_string()),
+            ("Error correction", or_unknown(analysis.error_correction.map(|level| format!("{:?}", level)))),
+            ("Mask", or_unknown(analysis.mask_pattern.map(|mask| mask as u8).or(analysis.micro.as_ref().and_then(|micro| micro.mask)).map(|mask| mask.to_string()))),
+            ("Recovered by search", format.recovered_by_search.to_string()),
+        ],
+    );
+
+    let mut version = match &analysis.micro {
+        Some(micro) => vec![
+            ("From size", format!("{} (Micro QR)", micro.version)),
+            ("From format information", or_unknown(micro.version_from_format.map(|v| v.to_string()))),
+        ],
+        None => vec![("From size", or_unknown(analysis.version_from_size.map(|v| format!("{:?}", v))))],
+    };
+    if let Some(info) = &analysis.version_info {
+        version.push(("Copy 1", or_unknown(info.raw_bits_copy1.clone())));
+        version.push(("Copy 2", or_unknown(info.raw_bits_copy2.clone())));
+        version.push(("Decoded", or_unknown(info.version.clone())));
+    }
+    version.push(("Agrees with size", analysis.versions_match.to_string()));
+    if let Some(reconciliation) = &analysis.reconciliation {
+        version.push(("Reconciled", reconciliation.reason.clone()));
+    }
+    html += &step("Determine the version", &version);
+
+    let data = &analysis.data_analysis;
+    html += &step(
+        "Unmask and read the codewords",
+        &[
+            ("Bits expected", or_unknown(data.expected_bit_string_size.map(|n| n.to_string()))),
+            ("Bits read", or_unknown(data.actual_bit_string_size.map(|n| n.to_string()))),
+            ("Codewords", or_unknown(data.unmasked_bytes.clone())),
+        ],
+    );
+
+    let mut blocks = vec![("Data codewords", or_unknown(data.read_data_bytes.clone())), ("ECC codewords", or_unknown(data.read_ecc_bytes.clone()))];
+    for block in data.block_structure.iter().flat_map(|structure| &structure.blocks) {
+        let state = match block.errors {
+            Some(0) => "no errors".to_string(),
+            Some(errors) => format!("{} corrected at codewords {}", errors, super::repaired_positions(block)),
+            None => "beyond repair".to_string(),
+        };
+        blocks.push(("Block", format!("{}: {} data + {} ECC codewords, {}", block.index + 1, block.data_codewords, block.ecc_codewords, state)));
+    }
+    html += &step("Deinterleave and correct the blocks", &blocks);
+
+    let segments: Vec<(&str, String)> = data
+        .segments
+        .iter()
+        .map(|segment| {
+            let count = segment.char_count.map_or(String::new(), |count| format!(", {} characters", count));
+            let content = segment.content.as_ref().map_or(String::new(), |content| format!(": {:?}", content));
+            ("Segment", format!("{:?} at bit {}{}{}", segment.mode, segment.bit_offset, count, content))
+        })
+        .collect();
+    html += &step("Parse the segments", &segments);
+
+    let mut payload = vec![("Encoding", or_unknown(data.encoding_name.clone())), ("Text", or_unknown(data.extracted_data.as_ref().map(|text| format!("{:?}", text))))];
+    if let Some(header) = data.structured_append {
+        payload.push(("Sequence", format!("symbol {} of {}, parity {:#04x}", header.index + 1, header.total, header.parity)));
+    }
+    html += &step("Assemble the payload", &payload);
+    html += "</ol>\n";
+
+    if !analysis.findings.is_empty() {
+        html += "<h2>Findings</h2>\n<table>\n";
+        for finding in &analysis.findings {
+            let severity = match finding.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+                Severity::Info => "info",
+            };
+            let location = finding.location.map_or(String::new(), |Location { row, col }| format!("row {}, col {}", row, col));
+            html += &format!("<tr><td class=\"{0}\">{0}</td><td>{1}</td><td>{2}</td></tr>\n", severity, escape(&finding.message), location);
+        }
+        html += "</table>\n";
+    }
+    if !analysis.warnings.is_empty() {
+        html += "<h2>Decoder log</h2>\n<pre>";
+        html += &escape(&analysis.warnings.join("\n"));
+        html += "</pre>\n";
+    }
+    html
+}
+
+fn step(title: &str, facts: &[(&str, String)]) -> String {
+    let mut html = format!("<li><strong>{}</strong>\n<dl>\n", escape(title));
+    for (label, value) in facts {
+        html += &format!("<dt>{}</dt><dd>{}</dd>\n", escape(label), escape(value));
+    }
+    html + "</dl>\n</li>\n"
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::analyze_image;
+    use crate::reference::golden;
+    use crate::render::{render_image, RenderOptions};
+
+    #[test]
+    fn test_html_report_embeds_every_module() {
+        let matrix = golden("hello-world-1q").unwrap().matrix();
+        let image = render_image(&matrix, &RenderOptions::default()).unwrap();
+        let analysis = analyze_image(&image.into());
+        let html = render("a<b>.png", &analysis).unwrap();
+
+        assert!(html.contains("<title>QR analysis of a&lt;b&gt;.png</title>"));
+        assert!(html.contains("<dt>Text</dt><dd>&quot;HELLO WORLD&quot;</dd>"));
+        assert_eq!(html.matches("<li><strong>").count(), 7);
+        let modules = html.split("const modules = ").nth(1).and_then(|rest| rest.split(";\n").next()).unwrap();
+        let modules: Vec<serde_json::Value> = serde_json::from_str(modules).unwrap();
+        assert_eq!(modules.len(), 21 * 21);
+        // A finder module is never unmasked; the bottom-right module carries the top bit of
+        // the first data codeword, 0x20, which mask 6 leaves alone there
+        assert_eq!(modules[0], serde_json::json!(["finder", true, null, null, null, null, null]));
+        assert_eq!(modules[21 * 21 - 1], serde_json::json!(["data", false, 0, 7, 0, "data codeword 0 of the message", false]));
+    }
+}
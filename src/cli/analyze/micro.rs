@@ -0,0 +1,159 @@
+//! Micro QR symbols in the analyzer: one finder pattern, timing patterns along the top row
+//! and left column, a single format information copy, one Reed-Solomon block and a reduced
+//! set of modes, each read and checked in place of its regular QR counterpart.
+
+use std::iter::zip;
+use serde::{Deserialize, Serialize};
+use crate::ecc::CorrectionResult;
+use crate::micro::{self, MicroFormat, MicroVersion};
+use super::{bits_to_bytes, check_finder_pattern, BlockReport, BlockStructure, DataAnalysis, FinderPattern, FormatInfo, QrAnalysis, RepairedCodeword, Segment, TimingPatterns};
+
+/// Version, mask and format information of a Micro QR symbol
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct MicroReport {
+    /// Version from the symbol width
+    pub(super) version: MicroVersion,
+    /// Version from the format information, `None` when it is unreadable
+    pub(super) version_from_format: Option<MicroVersion>,
+    /// One of the four Micro QR masks, 0-3
+    pub(super) mask: Option<u8>,
+    /// Bits corrected in the format information, which has no second copy to fall back on
+    pub(super) format_bit_errors: Option<u32>,
+}
+
+/// Run the Micro QR checks on an upright symbol of `version` and read its data
+pub(super) fn analyze(analysis: &mut QrAnalysis, matrix: &[Vec<u8>], version: MicroVersion) {
+    analysis.finder_patterns = vec![FinderPattern { position: "top-left".to_string(), row: 0, col: 0, valid: check_finder_pattern(matrix, 0, 0) }];
+    let timing = |i: usize| i.is_multiple_of(2) as u8;
+    analysis.timing_patterns = TimingPatterns { valid: (8..matrix.len()).all(|i| matrix[0][i] == timing(i) && matrix[i][0] == timing(i)) };
+
+    let word = micro::read_format_word(matrix);
+    let format = micro::decode_format(word);
+    match format {
+        Some((format, bit_errors)) => log::debug!("Micro QR format info: {}, ECC {:?}, mask {} ({} bits corrected)", format.version, format.error_correction, format.mask, bit_errors),
+        None => log::warn!("Failed to correct Micro QR format info"),
+    }
+    analysis.format_info = FormatInfo {
+        raw_bits_copy1: Some(format!("{:015b}", word)),
+        raw_bits_copy2: None,
+        // There is a single copy, so there is nothing to disagree with
+        copies_match: true,
+        error_correction: format.and_then(|(format, _)| format.error_correction),
+        mask_pattern: None,
+        version: None,
+        recovered_by_search: false,
+    };
+    analysis.error_correction = analysis.format_info.error_correction;
+    analysis.versions_match = format.is_some_and(|(format, _)| format.version == version);
+    analysis.micro = Some(MicroReport {
+        version,
+        version_from_format: format.map(|(format, _)| format.version),
+        mask: format.map(|(format, _)| format.mask),
+        format_bit_errors: format.map(|(_, bit_errors)| bit_errors),
+    });
+
+    if let Some((format, _)) = format.filter(|_| analysis.versions_match) {
+        analysis.data_analysis = decode_data(matrix, format);
+    }
+}
+
+/// Read, correct and parse the data of an upright symbol whose format information matches
+/// its size, recording every step as the regular QR path does
+fn decode_data(matrix: &[Vec<u8>], format: MicroFormat) -> DataAnalysis {
+    let mut result = DataAnalysis { data_corrupted: true, ..DataAnalysis::default() };
+    let capacity = format.capacity().expect("decoded formats have a capacity");
+    let bit_string = |bits: &[u8]| bits.iter().map(|&b| if b == 1 { '1' } else { '0' }).collect::<String>();
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<String>>().join(" ");
+
+    let placement = micro::placement(format.version);
+    let read: Vec<u8> = placement.iter().map(|&(row, col)| matrix[row][col]).collect();
+    let unmasked = micro::read_bits(matrix, format.version, format.mask);
+    result.decoded_bit_string = Some(bit_string(&read));
+    result.unmasked_bit_string = Some(bit_string(&unmasked));
+    result.unmasked_bytes = Some(hex(&bits_to_bytes(&unmasked)));
+    result.expected_bit_string_size = Some(placement.len());
+    result.actual_bit_string_size = Some(unmasked.len());
+    result.expected_data_bit_string_size = Some(capacity.data_bits);
+    result.expected_ecc_bit_string_size = Some(8 * capacity.ecc_codewords);
+
+    let codewords = micro::codewords(&unmasked, capacity);
+    let data_codewords = capacity.data_codewords();
+    result.read_data_bytes = Some(hex(&codewords[..data_codewords]));
+    result.read_ecc_bytes = Some(hex(&codewords[data_codewords..]));
+
+    let (corrected, repaired) = match micro::correct(&codewords, capacity) {
+        CorrectionResult::ErrorFree(_) => (Some(codewords.clone()), Vec::new()),
+        CorrectionResult::Corrected { error_positions, error_magnitudes, .. } => {
+            let mut corrected = codewords.clone();
+            for (&position, &magnitude) in zip(&error_positions, &error_magnitudes) {
+                corrected[position] ^= magnitude;
+            }
+            let repaired = zip(error_positions, error_magnitudes).map(|(position, magnitude)| RepairedCodeword { position, magnitude }).collect();
+            (Some(corrected), repaired)
+        }
+        CorrectionResult::Uncorrectable => (None, Vec::new()),
+    };
+    result.data_ecc_valid = corrected.is_some() && repaired.is_empty();
+    result.data_corrupted = !result.data_ecc_valid;
+    let block = BlockReport {
+        index: 0,
+        data_codewords,
+        ecc_codewords: capacity.ecc_codewords,
+        errors: corrected.as_ref().map(|_| repaired.len()),
+        correctable: corrected.is_some(),
+        repaired,
+    };
+    result.block_structure = Some(BlockStructure {
+        detected: true,
+        group1_blocks: Some(1),
+        group1_data_codewords: Some(data_codewords),
+        group2_blocks: Some(0),
+        group2_data_codewords: Some(0),
+        ecc_codewords_per_block: Some(capacity.ecc_codewords),
+        total_data_blocks: Some(1),
+        total_ecc_blocks: Some(1),
+        blocks: vec![block],
+    });
+    let Some(corrected) = corrected else {
+        log::warn!("Uncorrectable errors detected in data.");
+        return result;
+    };
+
+    let data_bits = micro::data_bits(&corrected, capacity);
+    let data_error_positions: Vec<usize> = zip(&codewords, &corrected).enumerate().filter(|(_, (a, b))| a != b).map(|(i, _)| i).collect();
+    if !data_error_positions.is_empty() {
+        result.corrected_bit_string = Some(bit_string(&data_bits));
+        result.corrected_bytes = Some(hex(&corrected[..data_codewords]));
+        result.corrected_data = Some(hex(&corrected));
+        result.reconstructed_ecc_bytes = Some(hex(&corrected[data_codewords..]));
+        result.corrupted_bytes_percentage = Some(data_error_positions.len() as f64 / corrected.len() as f64 * 100.0);
+        result.data_error_positions = Some(data_error_positions);
+    }
+
+    let mode_bits = format.version.mode_indicator_bits();
+    if mode_bits > 0 {
+        result.encoding_info_bit_string = Some(bit_string(&data_bits[..mode_bits]));
+    }
+    let payload = match micro::parse_segments(&data_bits, format.version) {
+        Ok(payload) if !payload.segments.is_empty() => payload,
+        Ok(_) => {
+            result.encoding_name = Some("Unknown".to_string());
+            return result;
+        }
+        Err(error) => {
+            log::warn!("Micro QR data does not parse: {}", error);
+            result.encoding_name = Some("Unknown".to_string());
+            return result;
+        }
+    };
+    result.encoding_name = Some(payload.segments.iter().map(|s| s.mode.to_string()).collect::<Vec<_>>().join("+"));
+    result.data_length = Some(payload.segments.iter().map(|s| s.char_count).sum());
+    result.extracted_data = Some(payload.text);
+    result.padding_bits = Some(bit_string(&data_bits[payload.end.min(data_bits.len())..]));
+    result.segments = payload
+        .segments
+        .into_iter()
+        .map(|segment| Segment { mode: segment.mode, bit_offset: segment.bit_offset, char_count: Some(segment.char_count), eci: None, content: Some(segment.text) })
+        .collect();
+    result
+}
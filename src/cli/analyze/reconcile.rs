@@ -0,0 +1,176 @@
+//! Settling which version, ECC level and mask to read a symbol with when its sources disagree:
+//! the symbol size against the version information, and the two format information copies
+//! against each other. Every combination is tried against the Reed-Solomon blocks, since a
+//! wrong one leaves syndromes that almost never check out.
+
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::capacity::{get_block_layout, image_size_to_version};
+use crate::decoder::{error_free_blocks, read_format_words, read_version_info, recover_format_info};
+use crate::format_info;
+use crate::types::{ErrorCorrection, MaskPattern, Version};
+
+/// Where a hypothesis took its version, or its ECC level and mask, from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum Source {
+    /// The width of the symbol in modules, as sampled
+    SymbolSize,
+    /// The version information blocks of a version 7+ symbol
+    VersionInfo,
+    /// The format information copy around the top-left finder
+    FormatCopy1,
+    /// The format information copy split between the other two finders
+    FormatCopy2,
+    /// Every ECC level and mask, tried because neither format copy decodes
+    Search,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::SymbolSize => "symbol size",
+            Source::VersionInfo => "version information",
+            Source::FormatCopy1 => "format copy 1",
+            Source::FormatCopy2 => "format copy 2",
+            Source::Search => "search",
+        })
+    }
+}
+
+/// One way of reading the symbol, scored against its Reed-Solomon blocks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Hypothesis {
+    pub version_source: Source,
+    pub format_source: Source,
+    pub version: Version,
+    pub error_correction: ErrorCorrection,
+    pub mask_pattern: MaskPattern,
+    /// Whether the symbol was sampled at this version's width, so the blocks could be read
+    pub tested: bool,
+    pub blocks: usize,
+    /// Blocks whose syndromes all vanish as read, `None` when a block is beyond correction
+    pub error_free_blocks: Option<usize>,
+}
+
+impl Hypothesis {
+    pub fn decodes(&self) -> bool {
+        self.error_free_blocks.is_some()
+    }
+}
+
+/// The readings tried when the sources disagree, and which one the analysis went on with
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct Reconciliation {
+    pub hypotheses: Vec<Hypothesis>,
+    /// Index into `hypotheses`; `None` when none of them decodes
+    pub trusted: Option<usize>,
+    pub reason: String,
+}
+
+impl Reconciliation {
+    pub fn trusted(&self) -> Option<&Hypothesis> {
+        self.trusted.map(|index| &self.hypotheses[index])
+    }
+}
+
+/// Score every combination of the version claims and the format claims. `matrix` is the
+/// symbol as sampled and `resampled` the same symbol sampled again at the width its version
+/// information gives, when that differs. `None` when all sources agree.
+pub(super) fn reconcile(matrix: &[Vec<u8>], resampled: Option<&[Vec<u8>]>) -> Option<Reconciliation> {
+    let mut claims = Vec::new();
+    if let Some(version) = image_size_to_version(matrix.len()) {
+        claims.push((Source::SymbolSize, version));
+    }
+    if let Some(version) = read_version_info(matrix).filter(|&version| version.size() != matrix.len()) {
+        claims.push((Source::VersionInfo, version));
+    }
+
+    let mut hypotheses = Vec::new();
+    for (version_source, version) in claims {
+        let sampled = [Some(matrix), resampled].into_iter().flatten().find(|m| m.len() == version.size());
+        for (format_source, error_correction, mask) in format_claims(sampled.unwrap_or(matrix), version) {
+            let blocks = get_block_layout(version, error_correction).total_blocks();
+            hypotheses.push(Hypothesis {
+                version_source,
+                format_source,
+                version,
+                error_correction,
+                mask_pattern: MaskPattern::from_index(mask),
+                tested: sampled.is_some(),
+                blocks,
+                error_free_blocks: sampled.and_then(|m| error_free_blocks(m, version, error_correction, mask)),
+            });
+        }
+    }
+    if hypotheses.len() < 2 {
+        return None;
+    }
+
+    // Most error-free blocks first; ties go to the earlier claim, the symbol size and copy 1
+    let trusted = (0..hypotheses.len())
+        .filter(|&i| hypotheses[i].decodes())
+        .max_by_key(|&i| (hypotheses[i].error_free_blocks, std::cmp::Reverse(i)));
+    let reason = reason(&hypotheses, trusted);
+    Some(Reconciliation { hypotheses, trusted, reason })
+}
+
+/// ECC level and mask from each format copy that decodes, once per distinct pair, or from the
+/// search when neither does
+fn format_claims(matrix: &[Vec<u8>], version: Version) -> Vec<(Source, ErrorCorrection, u8)> {
+    let mut claims: Vec<(Source, ErrorCorrection, u8)> = Vec::new();
+    for (source, word) in [Source::FormatCopy1, Source::FormatCopy2].into_iter().zip(read_format_words(matrix)) {
+        if let Ok((error_correction, mask)) = format_info::decode(word) && !claims.iter().any(|&(_, e, m)| (e, m) == (error_correction, mask)) {
+            claims.push((source, error_correction, mask));
+        }
+    }
+    if claims.is_empty() {
+        claims.extend(recover_format_info(matrix, version).map(|(error_correction, mask)| (Source::Search, error_correction, mask)));
+    }
+    claims
+}
+
+fn reason(hypotheses: &[Hypothesis], trusted: Option<usize>) -> String {
+    let Some(trusted) = trusted.map(|index| &hypotheses[index]) else {
+        return "no reading decodes, so the symbol is analyzed as sampled".to_string();
+    };
+    let reading = format!(
+        "{:?} from the {} with ECC {:?} and mask {} from {}",
+        trusted.version, trusted.version_source, trusted.error_correction, trusted.mask_pattern as u8, trusted.format_source
+    );
+    let decoding = hypotheses.iter().filter(|h| h.decodes()).count();
+    let error_free = trusted.error_free_blocks.unwrap_or(0);
+    if decoding == 1 {
+        format!("{} is the only reading whose Reed-Solomon blocks check out", reading)
+    } else if error_free == trusted.blocks {
+        format!("{} leaves every syndrome zero, ahead of {} other readings that decode", reading, decoding - 1)
+    } else {
+        format!("{} leaves the most blocks error-free ({} of {}) of the {} readings that decode", reading, error_free, trusted.blocks, decoding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::golden;
+
+    #[test]
+    fn test_trusts_the_format_copy_whose_blocks_check_out() {
+        // Copy 1 rewritten to a valid word for another level and mask: it decodes cleanly, so
+        // only the Reed-Solomon blocks can tell the copies apart
+        let mut matrix = golden("hello-world-1q").unwrap().matrix();
+        let word = format_info::encode(ErrorCorrection::L, 2);
+        for (i, &(row, col)) in format_info::positions(matrix.len())[0].iter().enumerate() {
+            matrix[row][col] = (word >> i & 1) as u8;
+        }
+
+        let reconciliation = reconcile(&matrix, None).unwrap();
+        let readings: Vec<_> = reconciliation.hypotheses.iter().map(|h| (h.format_source, h.error_correction, h.decodes())).collect();
+        assert_eq!(readings, [(Source::FormatCopy1, ErrorCorrection::L, false), (Source::FormatCopy2, ErrorCorrection::Q, true)]);
+        let trusted = reconciliation.trusted().unwrap();
+        assert_eq!((trusted.mask_pattern, trusted.error_free_blocks), (MaskPattern::Pattern6, Some(1)));
+        assert_eq!(reconciliation.reason, "V1 from the symbol size with ECC Q and mask 6 from format copy 2 is the only reading whose Reed-Solomon blocks check out");
+
+        assert!(reconcile(&golden("hello-world-1q").unwrap().matrix(), None).is_none());
+    }
+}
@@ -0,0 +1,257 @@
+//! `qr codewords`: the codeword pipeline of a payload, stage by stage, as a hex and binary
+//! dump. Every stage is taken from what the encoder recorded while building the symbol, so the
+//! dump shows what ends up in the matrix rather than what the spec says should.
+
+use crate::capacity::get_block_layout;
+use crate::diagnostics::{Diagnostics, Stage};
+use crate::encoding::encode_segment;
+use crate::fit::{check_fit, check_mode};
+use crate::generator::{calculate_version, generate_qr_matrix, Segment};
+use crate::pixel_mapping::get_data_ecc_positions;
+use crate::types::{DataMode, ErrorCorrection, QrConfig, Version};
+use super::generate::{parse_data_mode, read_input};
+use super::{parse_error_correction, report};
+
+const EXAMPLES: &str = "\
+Examples:
+  qr codewords \"Hello, World!\"
+  qr codewords -e Q -d alphanumeric \"HELLO WORLD\"
+  qr codewords --stage ecc --input payload.bin
+  diff <(qr codewords --stage stream \"order 1\") <(qr codewords --stage stream \"order 2\")";
+
+/// Every stage is printed under a `== <stage> ==` heading, one row of eight codewords per
+/// line with its offset, so the output of two payloads or two stages diffs line by line.
+#[derive(Debug, clap::Args)]
+#[command(after_help = EXAMPLES)]
+pub struct Args {
+    /// Text to encode
+    #[arg(required_unless_present = "input", conflicts_with = "input")]
+    text: Option<String>,
+    /// Read the payload from a file, or - for stdin, as raw bytes
+    #[arg(short, long, value_name = "FILE")]
+    input: Option<String>,
+    /// Error correction level (L, M, Q, H)
+    #[arg(short, long, default_value = "M", value_parser = parse_error_correction)]
+    error_correction: ErrorCorrection,
+    /// Data mode (byte, numeric, alphanumeric)
+    #[arg(short, long, default_value = "byte", value_parser = parse_data_mode)]
+    data_mode: DataMode,
+    /// Print only this stage
+    #[arg(long, value_enum)]
+    stage: Option<DumpStage>,
+}
+
+/// Stages of the dump, in pipeline order
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+enum DumpStage {
+    /// Mode indicator, character count and data bits of each segment
+    Segments,
+    /// Segments, terminator and padding as codewords
+    Data,
+    /// Data codewords split into Reed-Solomon blocks
+    Blocks,
+    /// ECC codewords of each block
+    Ecc,
+    /// Data and ECC codewords in the order they are placed
+    Stream,
+    /// The placed stream as bits, remainder bits included
+    Bits,
+}
+
+/// The codewords of one payload at each step from segments to the placed bit stream
+struct Pipeline {
+    version: Version,
+    error_correction: ErrorCorrection,
+    segments: Vec<Segment>,
+    /// Bits of each segment, mode indicator and character count included
+    segment_bits: Vec<Vec<u8>>,
+    data_blocks: Vec<Vec<u8>>,
+    ecc_blocks: Vec<Vec<u8>>,
+    /// Codewords in placement order
+    stream: Vec<u8>,
+    /// Modules after the last codeword, left at zero
+    remainder_bits: usize,
+}
+
+impl Pipeline {
+    /// Encode `data` with `config` and collect the stages the encoder recorded
+    fn encode(data: &[u8], config: &QrConfig) -> Result<Pipeline, Box<dyn std::error::Error>> {
+        let diagnostics = Diagnostics::new();
+        generate_qr_matrix(data, &QrConfig { diagnostics: Some(diagnostics.clone()), ..config.clone() });
+        let version = calculate_version(data, config.error_correction, config.data_mode);
+        let mut pipeline = Pipeline {
+            version,
+            error_correction: config.error_correction,
+            segments: Vec::new(),
+            segment_bits: Vec::new(),
+            data_blocks: Vec::new(),
+            ecc_blocks: Vec::new(),
+            stream: Vec::new(),
+            remainder_bits: 0,
+        };
+        for stage in diagnostics.stages() {
+            match stage {
+                Stage::Segments { segments } => {
+                    // The encoder writes the whole payload as one segment
+                    pipeline.segment_bits = vec![encode_segment(data, version, config.data_mode)];
+                    pipeline.segments = segments;
+                }
+                Stage::Blocks { data, ecc } => {
+                    pipeline.data_blocks = data;
+                    pipeline.ecc_blocks = ecc;
+                }
+                Stage::Codewords { codewords } => pipeline.stream = codewords,
+                Stage::MaskScores { .. } => {}
+            }
+        }
+        if pipeline.stream.is_empty() {
+            return Err("the encoder recorded no codewords".into());
+        }
+        pipeline.remainder_bits = get_data_ecc_positions(version).len().saturating_sub(8 * pipeline.stream.len());
+        Ok(pipeline)
+    }
+
+    fn data_codewords(&self) -> Vec<u8> {
+        self.data_blocks.concat()
+    }
+
+    fn bits(&self) -> Vec<u8> {
+        let mut bits: Vec<u8> = self.stream.iter().flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1)).collect();
+        bits.resize(bits.len() + self.remainder_bits, 0);
+        bits
+    }
+}
+
+pub fn run(args: Args) -> i32 {
+    report(dump(args))
+}
+
+fn dump(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let data = match (&args.text, &args.input) {
+        (_, Some(path)) => read_input(path)?,
+        (Some(text), None) => text.as_bytes().to_vec(),
+        (None, None) => unreachable!("clap requires the text or --input"),
+    };
+    let config = QrConfig { error_correction: args.error_correction, data_mode: args.data_mode, ..QrConfig::default() };
+    check_mode(&data, config.data_mode)?;
+    check_fit(&data, config.error_correction, config.data_mode)?;
+    let pipeline = Pipeline::encode(&data, &config)?;
+    print!("{}", format_pipeline(&pipeline, args.stage));
+    Ok(())
+}
+
+/// The dump of every stage, or of `only` that one
+fn format_pipeline(pipeline: &Pipeline, only: Option<DumpStage>) -> String {
+    let mut lines = Vec::new();
+    if only.is_none() {
+        let layout = get_block_layout(pipeline.version, pipeline.error_correction);
+        lines.push(format!(
+            "Version {}-{:?}: {} data and {} ECC codewords in {} block{}",
+            pipeline.version as u8,
+            pipeline.error_correction,
+            pipeline.data_blocks.iter().map(Vec::len).sum::<usize>(),
+            layout.total_blocks() * layout.ecc_per_block,
+            layout.total_blocks(),
+            if layout.total_blocks() == 1 { "" } else { "s" }
+        ));
+    }
+    let stages = [DumpStage::Segments, DumpStage::Data, DumpStage::Blocks, DumpStage::Ecc, DumpStage::Stream, DumpStage::Bits];
+    for stage in stages.into_iter().filter(|&stage| only.is_none_or(|only| only == stage)) {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        match stage {
+            DumpStage::Segments => {
+                lines.push("== Segments ==".to_string());
+                for (i, (segment, bits)) in pipeline.segments.iter().zip(&pipeline.segment_bits).enumerate() {
+                    lines.push(format!("Segment {}: {}, {} characters, {} bits", i + 1, segment.mode, segment.characters, segment.bits));
+                    lines.extend(bit_rows(bits));
+                }
+            }
+            DumpStage::Data => {
+                let data = pipeline.data_codewords();
+                let segment_bits: usize = pipeline.segments.iter().map(|segment| segment.bits).sum();
+                lines.push(format!("== Data codewords: {}, {} segment bits then terminator and padding ==", data.len(), segment_bits));
+                lines.extend(codeword_rows(&data));
+            }
+            DumpStage::Blocks => {
+                lines.push("== Blocks ==".to_string());
+                for group in pipeline.data_blocks.chunk_by(|a, b| a.len() == b.len()) {
+                    lines.push(format!("{} block{} of {} data codewords", group.len(), if group.len() == 1 { "" } else { "s" }, group[0].len()));
+                }
+                for (i, block) in pipeline.data_blocks.iter().enumerate() {
+                    lines.push(format!("Block {} data", i + 1));
+                    lines.extend(codeword_rows(block));
+                }
+            }
+            DumpStage::Ecc => {
+                lines.push(format!("== ECC codewords: {} per block ==", pipeline.ecc_blocks.first().map_or(0, Vec::len)));
+                for (i, block) in pipeline.ecc_blocks.iter().enumerate() {
+                    lines.push(format!("Block {} ECC", i + 1));
+                    lines.extend(codeword_rows(block));
+                }
+            }
+            DumpStage::Stream => {
+                lines.push(format!("== Interleaved stream: {} codewords in placement order ==", pipeline.stream.len()));
+                lines.extend(codeword_rows(&pipeline.stream));
+            }
+            DumpStage::Bits => {
+                lines.push(format!("== Bit stream: {} bits, {} of them remainder bits ==", 8 * pipeline.stream.len() + pipeline.remainder_bits, pipeline.remainder_bits));
+                lines.extend(bit_rows(&pipeline.bits()));
+            }
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+/// Eight codewords per row: offset of the first, then each in hex and in binary
+fn codeword_rows(codewords: &[u8]) -> Vec<String> {
+    codewords
+        .chunks(8)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex = chunk.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+            let binary = chunk.iter().map(|b| format!("{:08b}", b)).collect::<Vec<_>>().join(" ");
+            format!("  {:04}  {:<23}  {}", row * 8, hex, binary)
+        })
+        .collect()
+}
+
+/// Sixty-four bits per row in groups of eight, with the bit offset of the row
+fn bit_rows(bits: &[u8]) -> Vec<String> {
+    bits.chunks(64)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let groups = chunk.chunks(8).map(|group| group.iter().map(|&bit| if bit == 1 { '1' } else { '0' }).collect::<String>());
+            format!("  {:05}  {}", row * 64, groups.collect::<Vec<_>>().join(" "))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dumps_each_stage_of_the_pipeline() {
+        let config = QrConfig { error_correction: ErrorCorrection::Q, data_mode: DataMode::Alphanumeric, ..QrConfig::default() };
+        let pipeline = Pipeline::encode(b"HELLO WORLD", &config).unwrap();
+        assert_eq!(pipeline.version, Version::V1);
+        assert_eq!(pipeline.segment_bits[0].len(), pipeline.segments[0].bits);
+        assert_eq!(pipeline.data_codewords(), [0x20, 0x5B, 0x0B, 0x78, 0xD1, 0x72, 0xDC, 0x4D, 0x43, 0x40, 0xEC, 0x11, 0xEC]);
+        // A single block is placed as its data followed by its ECC
+        assert_eq!(pipeline.stream, [pipeline.data_blocks[0].clone(), pipeline.ecc_blocks[0].clone()].concat());
+        assert_eq!((pipeline.remainder_bits, pipeline.bits().len()), (0, 208));
+
+        let dump = format_pipeline(&pipeline, None);
+        assert!(dump.starts_with("Version 1-Q: 13 data and 13 ECC codewords in 1 block\n"));
+        assert!(dump.contains("== Data codewords: 13, 74 segment bits then terminator and padding ==\n  0000  20 5B 0B 78 D1 72 DC 4D  00100000 01011011"));
+        assert_eq!(format_pipeline(&pipeline, Some(DumpStage::Stream)).lines().count(), 1 + 4);
+
+        let version_2 = Pipeline::encode(&[b'a'; 20], &QrConfig::default()).unwrap();
+        assert_eq!(version_2.version, Version::V2);
+        assert_eq!(version_2.remainder_bits, 7);
+        assert!(format_pipeline(&version_2, Some(DumpStage::Bits)).starts_with("== Bit stream: 359 bits, 7 of them remainder bits ==\n"));
+    }
+}
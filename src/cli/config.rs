@@ -0,0 +1,122 @@
+//! Default options for `qr generate` from a TOML file. Keys are the long option names, so
+//!
+//! ```toml
+//! error-correction = "Q"
+//! scale = 8
+//! quiet-zone = 2
+//! format = "svg"
+//! eye-color = "#0b5394"
+//! frame = true
+//! ```
+//!
+//! behaves like passing those flags, except that flags given on the command line still win.
+
+use clap::{Arg, Command};
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// The config file used when `--config` is not given, if it exists:
+/// `$XDG_CONFIG_HOME/qr-tools/config.toml`, by default `~/.config/qr-tools/config.toml`
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("qr-tools").join("config.toml"))
+}
+
+/// Add `--config` to `command` and turn the keys of the config file into its defaults. The
+/// file is the one named by `--config` in `args`, else the default one if it exists.
+pub fn with_defaults(command: Command, args: &[OsString]) -> Result<Command, Box<dyn std::error::Error>> {
+    let command = command.arg(
+        Arg::new("config")
+            .long("config")
+            .value_name("FILE")
+            .help("TOML file with default options [default: ~/.config/qr-tools/config.toml]"),
+    );
+    let path = match config_arg(args) {
+        Some(path) => PathBuf::from(path),
+        None => match default_path().filter(|path| path.is_file()) {
+            Some(path) => path,
+            None => return Ok(command),
+        },
+    };
+    let text = std::fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let table: toml::Table = text.parse().map_err(|e| format!("{}: {}", path.display(), e))?;
+    apply(command, &table).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// The value of `--config` before any `--`. The file has to be read before clap parses the
+/// arguments, since its contents change how they parse.
+fn config_arg(args: &[OsString]) -> Option<OsString> {
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().cloned();
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(path.into());
+        }
+    }
+    None
+}
+
+/// Set the default of each option named in `table`, checking the value with the option's own parser
+fn apply(mut command: Command, table: &toml::Table) -> Result<Command, String> {
+    for (key, value) in table {
+        let long = key.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()) && arg.get_id() != "config")
+            .ok_or_else(|| format!("unknown option {:?}", key))?;
+        let value = match value {
+            toml::Value::String(s) if arg.get_action().takes_values() => s.clone(),
+            toml::Value::Integer(i) if arg.get_action().takes_values() => i.to_string(),
+            toml::Value::Float(f) if arg.get_action().takes_values() => f.to_string(),
+            toml::Value::Boolean(b) if !arg.get_action().takes_values() => b.to_string(),
+            _ if arg.get_action().takes_values() => return Err(format!("{}: expected a string or number", key)),
+            _ => return Err(format!("{}: expected true or false", key)),
+        };
+        if arg.get_action().takes_values() {
+            check_value(arg, &value).map_err(|e| format!("{}: invalid value {:?}: {}", key, value, e))?;
+        }
+        let id = arg.get_id().clone();
+        command = command.mut_arg(id, |arg| arg.default_value(value));
+    }
+    Ok(command)
+}
+
+/// Run `value` through the option's value parser, as clap would when the default is used
+fn check_value(arg: &Arg, value: &str) -> Result<(), String> {
+    let check = Arg::new("value").long("value").value_parser(arg.get_value_parser().clone());
+    Command::new("config")
+        .no_binary_name(true)
+        .arg(check)
+        .try_get_matches_from([format!("--value={}", value)])
+        .map(|_| ())
+        .map_err(|e| std::error::Error::source(&e).map_or_else(|| e.kind().to_string(), |source| source.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::{Args, FromArgMatches};
+    use crate::cli::generate;
+    use crate::types::ErrorCorrection;
+
+    #[test]
+    fn test_config_sets_defaults_that_flags_override() {
+        let table: toml::Table = "scale = 3\nerror_correction = \"h\"\nframe = true".parse().unwrap();
+        let command = apply(generate::Args::augment_args(Command::new("generate")), &table).unwrap();
+        let matches = command.clone().try_get_matches_from(["generate", "--scale", "5", "text"]).unwrap();
+        assert!(generate::Args::from_arg_matches(&matches).is_ok());
+        assert_eq!(matches.get_one::<usize>("scale"), Some(&5));
+        assert_eq!(matches.get_one::<ErrorCorrection>("error_correction"), Some(&ErrorCorrection::H));
+        assert!(matches.get_flag("frame"));
+
+        for bad in ["colour = \"red\"", "scale = \"big\"", "frame = [true]"] {
+            assert!(apply(command.clone(), &bad.parse().unwrap()).is_err(), "{}", bad);
+        }
+        assert_eq!(config_arg(&["generate".into(), "--config=team.toml".into()]), Some("team.toml".into()));
+    }
+}
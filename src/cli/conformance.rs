@@ -0,0 +1,298 @@
+//! `qr generate --compare-with`: hand the same payload and parameters to another QR code
+//! generator and compare its symbol with ours module by module, or hand our symbol to another
+//! decoder and check that it reads the payload back. Every module that differs from a
+//! generator known to follow the spec points at an encoder bug.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use rand::{thread_rng, Rng};
+use crate::capacity::image_size_to_version;
+use crate::decoder::{image_to_matrix, read_format_words, remask};
+use crate::format_info;
+use crate::generator::calculate_version;
+use crate::render::{Png, RenderOptions, Renderer};
+use crate::types::{ErrorCorrection, QrConfig};
+use super::diff::{diff_matrices, print_summary, ModuleDiff};
+use super::input::open_image;
+
+/// Tokens replaced in the words of the reference command
+const TOKENS: &str = "{text}, {payload}, {version}, {ecc}, {mask}, {output}, {image}";
+
+/// An external tool to compare with, as the words of its command line
+#[derive(Debug, Clone)]
+pub enum Reference {
+    /// Writes a symbol for the payload to `{output}`
+    Generator(Vec<String>),
+    /// Reads the symbol at `{image}` and prints its payload
+    Decoder(Vec<String>),
+}
+
+/// Value parser for --compare-with. The command is split into words at whitespace, with
+/// single or double quotes around words that contain it; no shell is involved.
+pub fn parse_reference(value: &str) -> Result<Reference, String> {
+    let words = split_command(value)?;
+    let uses = |token: &str| words.iter().any(|word| word.contains(token));
+    match (uses("{output}"), uses("{image}")) {
+        (true, false) => Ok(Reference::Generator(words)),
+        (false, true) => Ok(Reference::Decoder(words)),
+        (true, true) => Err("use {output} for a generator or {image} for a decoder, not both".to_string()),
+        (false, false) => Err(format!("the command needs {{output}} for a generator or {{image}} for a decoder; tokens: {}", TOKENS)),
+    }
+}
+
+fn split_command(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unclosed quote in the command".to_string());
+    }
+    words.extend(word);
+    if words.is_empty() {
+        return Err("empty command".to_string());
+    }
+    Ok(words)
+}
+
+/// Values of the command tokens for one payload
+struct Tokens {
+    text: Option<String>,
+    payload: PathBuf,
+    version: u8,
+    error_correction: ErrorCorrection,
+    mask: u8,
+    output: PathBuf,
+    image: PathBuf,
+}
+
+impl Tokens {
+    /// `word` with every token replaced in one pass, so a value is never searched for
+    /// tokens itself. Braces around anything else are kept as they are.
+    fn fill(&self, word: &str) -> Result<String, String> {
+        let mut filled = String::new();
+        let mut rest = word;
+        while let Some(start) = rest.find('{') {
+            filled.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match rest.find('}').and_then(|end| Some((end, self.value(&rest[1..end])?))) {
+                Some((end, value)) => {
+                    filled.push_str(&value?);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    filled.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        filled.push_str(rest);
+        Ok(filled)
+    }
+
+    /// Value of the token `{name}`, `None` when there is no such token
+    fn value(&self, name: &str) -> Option<Result<String, String>> {
+        let value = match name {
+            "text" => return Some(self.text.clone().ok_or_else(|| "{text} needs a UTF-8 payload; use {payload} for binary data".to_string())),
+            "payload" => self.payload.to_string_lossy().into_owned(),
+            "version" => self.version.to_string(),
+            "ecc" => format!("{:?}", self.error_correction),
+            "mask" => self.mask.to_string(),
+            "output" => self.output.to_string_lossy().into_owned(),
+            "image" => self.image.to_string_lossy().into_owned(),
+            _ => return None,
+        };
+        Some(Ok(value))
+    }
+}
+
+/// Run the reference on `data` encoded with `config` as `matrix`, and print where it
+/// disagrees. Returns whether it agrees. The files passed to the command live in a
+/// directory of their own, removed afterwards.
+pub fn compare_with(reference: &Reference, data: &[u8], config: &QrConfig, matrix: &[Vec<u8>], options: &RenderOptions) -> Result<bool, Box<dyn std::error::Error>> {
+    let dir = create_private_dir()?;
+    let result = run_reference(reference, data, config, matrix, options, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// A new directory under the system temp directory with a random name, readable by this user
+/// only. Creating it fails rather than reuse a directory that already exists, which someone
+/// else may have planted there.
+fn create_private_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("qr-compare-{}-{:016x}", std::process::id(), thread_rng().r#gen::<u64>()));
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+fn run_reference(reference: &Reference, data: &[u8], config: &QrConfig, matrix: &[Vec<u8>], options: &RenderOptions, dir: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let tokens = Tokens {
+        text: String::from_utf8(data.to_vec()).ok(),
+        payload: dir.join("payload.bin"),
+        version: calculate_version(data, config.error_correction, config.data_mode) as u8,
+        error_correction: config.error_correction,
+        mask: config.mask_pattern as u8,
+        output: dir.join("reference.png"),
+        image: dir.join("ours.png"),
+    };
+    fs::write(&tokens.payload, data)?;
+    let words = match reference {
+        Reference::Generator(words) => words,
+        Reference::Decoder(words) => {
+            Png(fs::File::create(&tokens.image)?).render(matrix, options)?;
+            words
+        }
+    };
+    let words = words.iter().map(|word| tokens.fill(word)).collect::<Result<Vec<_>, _>>()?;
+    println!("Reference: {}", words.join(" "));
+    let output = Command::new(&words[0]).args(&words[1..]).output().map_err(|e| format!("cannot run {}: {}", words[0], e))?;
+    if !output.status.success() {
+        return Err(format!("the reference command failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+
+    match reference {
+        Reference::Generator(_) => {
+            let theirs = image_to_matrix(&open_image(&tokens.output, None)?).map_err(|e| format!("cannot read the reference symbol: {}", e))?;
+            let comparison = compare_symbols(matrix, &theirs)?;
+            print_comparison(&comparison);
+            Ok(comparison.diffs.as_ref().is_some_and(Vec::is_empty))
+        }
+        Reference::Decoder(_) => {
+            let stdout = output.stdout.strip_suffix(b"\n").unwrap_or(&output.stdout);
+            let read = stdout.strip_suffix(b"\r").unwrap_or(stdout);
+            if read == data {
+                println!("Payload read back unchanged");
+                return Ok(true);
+            }
+            println!("Payload read back as {:?}, expected {:?}", String::from_utf8_lossy(read), String::from_utf8_lossy(data));
+            Ok(false)
+        }
+    }
+}
+
+/// ECC level and mask from a symbol's format information
+type Format = (ErrorCorrection, u8);
+
+/// Our symbol against a reference generator's
+struct SymbolComparison {
+    /// Width in modules of ours and theirs
+    sizes: (usize, usize),
+    /// ECC level and mask in the format information of ours and theirs, where it decodes
+    formats: (Option<Format>, Option<Format>),
+    /// Differing modules, after bringing the reference to our mask; `None` when the sizes
+    /// differ and no module compares
+    diffs: Option<Vec<ModuleDiff>>,
+}
+
+/// Compare the two symbols module by module. A generator is free to pick another mask, so a
+/// reference with a different mask is remasked to ours first, leaving only the differences
+/// that are not down to the choice of mask.
+fn compare_symbols(ours: &[Vec<u8>], theirs: &[Vec<u8>]) -> Result<SymbolComparison, Box<dyn std::error::Error>> {
+    let format = |matrix: &[Vec<u8>]| read_format_words(matrix).into_iter().find_map(|word| format_info::decode(word).ok());
+    let formats = (format(ours), format(theirs));
+    let sizes = (ours.len(), theirs.len());
+    if sizes.0 != sizes.1 {
+        return Ok(SymbolComparison { sizes, formats, diffs: None });
+    }
+    let version = image_size_to_version(sizes.1).ok_or("the reference symbol has no QR code size")?;
+    let theirs = match formats {
+        (Some((_, our_mask)), Some((level, their_mask))) if our_mask != their_mask => remask(theirs, version, level, their_mask, our_mask),
+        _ => theirs.to_vec(),
+    };
+    Ok(SymbolComparison { sizes, formats, diffs: Some(diff_matrices(ours, &theirs)?) })
+}
+
+fn print_comparison(comparison: &SymbolComparison) {
+    let version = |size: usize| image_size_to_version(size).map_or(format!("{} modules", size), |version| (version as u8).to_string());
+    let level = |format: Option<Format>| format.map_or("unreadable".to_string(), |(level, _)| format!("{:?}", level));
+    let mask = |format: Option<Format>| format.map_or("unreadable".to_string(), |(_, mask)| mask.to_string());
+    let (ours, theirs) = comparison.formats;
+    println!("{:<10} {:<11} reference", "", "ours");
+    println!("{:<10} {:<11} {}", "Version", version(comparison.sizes.0), version(comparison.sizes.1));
+    println!("{:<10} {:<11} {}", "ECC level", level(ours), level(theirs));
+    println!("{:<10} {:<11} {}", "Mask", mask(ours), mask(theirs));
+    match &comparison.diffs {
+        None => println!("Symbols differ in size; modules not compared"),
+        Some(diffs) => {
+            if let (Some((_, our_mask)), Some((_, their_mask))) = (ours, theirs) && our_mask != their_mask {
+                println!("Reference remasked from mask {} to {} for the comparison", their_mask, our_mask);
+            }
+            print_summary(diffs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_and_fills_the_command() {
+        let Reference::Generator(words) = parse_reference("qrencode -l {ecc} -o '{output}' \"{text}\"").unwrap() else {
+            panic!("a command with {{output}} is a generator");
+        };
+        assert_eq!(words, ["qrencode", "-l", "{ecc}", "-o", "{output}", "{text}"]);
+        assert!(matches!(parse_reference("zbarimg -q --raw {image}"), Ok(Reference::Decoder(_))));
+        assert!(parse_reference("qrencode {text}").is_err());
+        assert!(parse_reference("qrencode -o {output} 'open").is_err());
+
+        let tokens = Tokens {
+            text: Some("Hello, World!".to_string()),
+            payload: PathBuf::from("payload.bin"),
+            version: 1,
+            error_correction: ErrorCorrection::Q,
+            mask: 3,
+            output: PathBuf::from("reference.png"),
+            image: PathBuf::from("ours.png"),
+        };
+        let filled: Vec<String> = words.iter().map(|word| tokens.fill(word).unwrap()).collect();
+        assert_eq!(filled, ["qrencode", "-l", "Q", "-o", "reference.png", "Hello, World!"]);
+        assert_eq!(tokens.fill("-l{ecc}{mask}{x}{").unwrap(), "-lQ3{x}{");
+
+        // A payload that looks like tokens is passed on as it is
+        let tricky = Tokens { text: Some("{mask} {output}".to_string()), ..tokens };
+        assert_eq!(tricky.fill("{text}:{mask}").unwrap(), "{mask} {output}:3");
+        assert!(Tokens { text: None, ..tricky }.fill("{text}").is_err());
+    }
+
+    #[test]
+    fn test_each_comparison_gets_a_new_private_directory() {
+        let (first, second) = (create_private_dir().unwrap(), create_private_dir().unwrap());
+        assert_ne!(first, second);
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&first).unwrap().permissions()) & 0o777, 0o700);
+        for dir in [first, second] {
+            fs::remove_dir(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compares_modules_whatever_the_mask() {
+        let ours = crate::reference::golden("hello-world-1q").unwrap().matrix();
+        let (level, mask) = read_format_words(&ours).into_iter().find_map(|word| format_info::decode(word).ok()).unwrap();
+        let mut theirs = remask(&ours, crate::types::Version::V1, level, mask, (mask + 1) % 8);
+        let comparison = compare_symbols(&ours, &theirs).unwrap();
+        assert_eq!(comparison.formats.1, Some((level, (mask + 1) % 8)));
+        assert!(comparison.diffs.unwrap().is_empty());
+
+        // A data module the encoder got wrong shows through the remasking
+        theirs[10][10] ^= 1;
+        assert_eq!(compare_symbols(&ours, &theirs).unwrap().diffs.unwrap().len(), 1);
+        assert!(compare_symbols(&ours, &vec![vec![0; 25]; 25]).unwrap().diffs.is_none());
+    }
+}
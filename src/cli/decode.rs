@@ -0,0 +1,175 @@
+use crate::decoder::{decode_pixels, join_structured_append, sampling_grid, Decoded, Grade};
+use crate::visualize::draw_sampling_grid;
+use super::input::open_image;
+use super::output::{save_image, write_file};
+use std::io::Write;
+
+/// Exits with status 1 if the image cannot be read or holds no decodable symbol, and 2 if it
+/// decodes but grades below --min-grade.
+/// Binary payloads round-trip with: qr-decode --raw-bytes code.png | xxd -r -p > payload.bin
+/// A photo that fails to decode can be diagnosed with: qr-decode photo.jpg --grid-image grid.png
+/// A payload split over several symbols is read with: qr-decode --join a.png b.png c.png
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Image to decode
+    image: String,
+    /// The other images of the sequence, with --join
+    #[arg(requires = "join")]
+    more: Vec<String>,
+    /// Decode the images as one Structured Append sequence, given in any order: check that
+    /// every symbol is there and the parity holds, and print the payloads joined in sequence
+    /// order
+    #[arg(long, conflicts_with_all = ["grid_json", "grid_image"])]
+    join: bool,
+    /// Print the payload bytes as hex instead of text
+    #[arg(long)]
+    raw_bytes: bool,
+    /// Print version, ECC level, mask and quality metrics to stderr
+    #[arg(short, long)]
+    verbose: bool,
+    /// Lowest acceptable quality grade (A, B, C, D); a symbol graded below it exits with 2
+    #[arg(long, value_name = "GRADE")]
+    min_grade: Option<Grade>,
+    /// Write the sampling grid (module centers in pixels and the modules read there) as JSON,
+    /// whether or not the symbol decodes
+    #[arg(long, value_name = "FILE")]
+    grid_json: Option<String>,
+    /// Draw the sampling grid onto a copy of the image: red dots where modules read dark, blue
+    /// where they read light, yellow crosses on the finder and alignment patterns
+    #[arg(long, value_name = "FILE")]
+    grid_image: Option<String>,
+    /// Overwrite existing grid files
+    #[arg(long)]
+    force: bool,
+}
+
+pub fn run(args: Args) -> i32 {
+    if args.join {
+        return join(&args);
+    }
+    let image = match open_image(&args.image, None) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    if let Err(e) = export_grid(&image, &args) {
+        eprintln!("Error: {}", e);
+        return 1;
+    }
+    match decode_pixels(&image) {
+        Ok(decoded) => {
+            if args.verbose {
+                eprintln!("{}", describe(&decoded));
+                let quality = &decoded.quality;
+                eprintln!(
+                    "Grade {}: {:.0}% of error correction unused, {} format bits corrected, timing {:.0}% regular",
+                    quality.grade,
+                    quality.unused_error_correction * 100.0,
+                    quality.format_bit_errors,
+                    quality.timing_regularity * 100.0
+                );
+                if quality.corrected_per_block.iter().any(|&percent| percent > 0.0) {
+                    let blocks: Vec<String> = quality.corrected_per_block.iter().map(|percent| format!("{:.0}%", percent)).collect();
+                    eprintln!("Codewords corrected per block: {}", blocks.join(", "));
+                }
+                if decoded.orientation.rotation != 0 || decoded.orientation.mirrored {
+                    eprintln!(
+                        "Symbol was rotated {} degrees{}",
+                        decoded.orientation.rotation,
+                        if decoded.orientation.mirrored { " and mirrored" } else { "" }
+                    );
+                }
+            }
+            print_payload(&decoded.text, &decoded.bytes, args.raw_bytes);
+            check_grade(decoded.quality.grade, args.min_grade)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+/// Decode every image of a Structured Append sequence and print the joined payload. With
+/// --min-grade, the lowest grade of the symbols counts.
+fn join(args: &Args) -> i32 {
+    let mut symbols = Vec::new();
+    for path in std::iter::once(&args.image).chain(&args.more) {
+        match open_image(path, None).and_then(|image| decode_pixels(&image).map_err(|e| format!("cannot decode {}: {}", path, e).into())) {
+            Ok(decoded) => {
+                if args.verbose {
+                    eprintln!("{}: {}", path, describe(&decoded));
+                }
+                symbols.push(decoded);
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return 1;
+            }
+        }
+    }
+    let ordered = match join_structured_append(&symbols) {
+        Ok(ordered) => ordered,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    let text: String = ordered.iter().map(|symbol| symbol.text.as_str()).collect();
+    let bytes: Vec<u8> = ordered.iter().flat_map(|symbol| symbol.bytes.iter().copied()).collect();
+    print_payload(&text, &bytes, args.raw_bytes);
+    let grade = symbols.iter().map(|symbol| symbol.quality.grade).min().expect("at least one image");
+    check_grade(grade, args.min_grade)
+}
+
+/// Version, ECC level, mask and place in a Structured Append sequence, for --verbose
+fn describe(decoded: &Decoded) -> String {
+    let mut description = format!(
+        "Version {}, ECC {:?}, mask {:?}{}",
+        decoded.version as u8,
+        decoded.error_correction,
+        decoded.mask_pattern,
+        if decoded.corrected { ", errors corrected" } else { "" }
+    );
+    if let Some(header) = decoded.structured_append {
+        description += &format!(", symbol {} of {} (parity {:#04x})", header.index + 1, header.total, header.parity);
+    }
+    description
+}
+
+fn print_payload(text: &str, bytes: &[u8], raw_bytes: bool) {
+    if raw_bytes {
+        println!("{}", bytes.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+    } else {
+        println!("{}", text);
+    }
+}
+
+/// Exit code for a symbol of `grade`: 2 if it is below `min_grade`
+fn check_grade(grade: Grade, min_grade: Option<Grade>) -> i32 {
+    match min_grade {
+        Some(minimum) if grade < minimum => {
+            eprintln!("Error: grade {} is below the minimum of {}", grade, minimum);
+            2
+        }
+        _ => 0,
+    }
+}
+
+/// Write the grid files asked for with --grid-json and --grid-image
+fn export_grid(image: &image::DynamicImage, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if args.grid_json.is_none() && args.grid_image.is_none() {
+        return Ok(());
+    }
+    let grid = sampling_grid(image).map_err(|e| format!("no sampling grid: {}", e))?;
+    if let Some(path) = &args.grid_json {
+        let json = serde_json::to_string_pretty(&grid)?;
+        write_file(path, args.force, |file| Ok(writeln!(file, "{}", json)?))?;
+    }
+    if let Some(path) = &args.grid_image {
+        save_image(draw_sampling_grid(image, &grid), path, args.force)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,592 @@
+use image::{Rgb, RgbImage};
+use crate::decoder::{decode_pixels, image_to_matrix, read_format_words};
+use crate::format_info;
+use crate::render::{draw_caption, Caption, CaptionPosition};
+use crate::function_modules::{module_regions, ModuleRegion};
+use crate::capacity::image_size_to_version;
+use crate::micro::{self, MicroVersion};
+use crate::pixel_mapping::module_codewords;
+use crate::types::{ErrorCorrection, Version};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use super::input::{open_image, FileFormat};
+use super::output::save_image_as;
+use super::watch::{change, watch};
+
+/// Pixels per module and quiet zone width in modules of the diff image
+const DIFF_SCALE: u32 = 10;
+const DIFF_BORDER: u32 = 4;
+
+/// Composite layout: label strip height, font size and spacing between panels, in pixels
+const LABEL_HEIGHT: u32 = 40;
+const LABEL_SIZE: f32 = 20.0;
+const PANEL_GAP: u32 = 20;
+
+/// Exit codes: the inputs match, they differ, or they could not be compared
+const EXIT_IDENTICAL: i32 = 0;
+const EXIT_DIFFERS: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
+
+/// Module matrices of the two inputs and where they differ
+struct Comparison {
+    matrix1: Vec<Vec<u8>>,
+    matrix2: Vec<Vec<u8>>,
+    /// Set when the symbols differ in size, and no module compares
+    sizes: Option<SizeChange>,
+    diffs: Vec<ModuleDiff>,
+}
+
+impl Comparison {
+    fn identical(&self) -> bool {
+        self.sizes.is_none() && self.diffs.is_empty()
+    }
+}
+
+/// Width in modules of one symbol, and its version when the width is a QR code's
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct SymbolSize {
+    modules: usize,
+    version: Option<u8>,
+}
+
+impl SymbolSize {
+    fn of(matrix: &[Vec<u8>]) -> SymbolSize {
+        SymbolSize { modules: matrix.len(), version: image_size_to_version(matrix.len()).map(|version| version as u8) }
+    }
+}
+
+impl std::fmt::Display for SymbolSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.modules, self.modules)?;
+        match self.version {
+            Some(version) => write!(f, " (version {})", version),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Two symbols of different sizes, say from a payload that outgrew its version
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct SizeChange {
+    first: SymbolSize,
+    second: SymbolSize,
+}
+
+/// A module whose color differs between the two inputs
+#[derive(Debug, Clone, Serialize)]
+pub(super) struct ModuleDiff {
+    row: usize,
+    col: usize,
+    region: &'static str,
+    /// "dark->light" or "light->dark", from the first input to the second
+    change: &'static str,
+}
+
+/// Report printed with --json
+#[derive(Debug, Serialize)]
+struct DiffReport<'a> {
+    identical: bool,
+    /// Sizes of the two symbols when they differ; no module is compared then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<SizeChange>,
+    differing_modules: &'a [ModuleDiff],
+    /// Number of differing modules per region
+    differing_regions: BTreeMap<&'static str, usize>,
+}
+
+/// One property of the two decoded symbols
+#[derive(Debug, Serialize)]
+struct FieldComparison {
+    first: String,
+    second: String,
+    matches: bool,
+}
+
+impl FieldComparison {
+    fn new(first: String, second: String) -> FieldComparison {
+        let matches = first == second;
+        FieldComparison { first, second, matches }
+    }
+}
+
+/// Report of --semantic. The symbols count as identical when their payloads match, whatever
+/// version, ECC level or mask each generator picked.
+#[derive(Debug, Serialize)]
+struct SemanticReport {
+    identical: bool,
+    payload: FieldComparison,
+    version: FieldComparison,
+    error_correction: FieldComparison,
+    mask: FieldComparison,
+}
+
+impl SemanticReport {
+    fn fields(&self) -> [(&'static str, &FieldComparison); 4] {
+        [("Payload", &self.payload), ("Version", &self.version), ("ECC level", &self.error_correction), ("Mask", &self.mask)]
+    }
+}
+
+/// Modules are compared after detecting each input's module size, so the two may use
+/// different scales. The summary lists every differing module as: row col region change.
+///
+/// Diff image colors: black/white same in both, green white in first and black in second,
+/// red black in first and white in second.
+///
+/// Symbols of different sizes, such as two versions, count as different without comparing
+/// modules.
+///
+/// Exit codes: 0 identical, 1 modules or sizes differ, 2 error
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    input1: String,
+    input2: String,
+    /// Diff image to write; not written when omitted
+    output: Option<String>,
+    /// Print {identical, size, differing_modules, differing_regions} as JSON; size is only
+    /// present when the sizes differ
+    #[arg(long)]
+    json: bool,
+    /// Also write the two inputs as read and the diff side by side, labelled, as one image
+    #[arg(long, value_name = "FILE", conflicts_with = "semantic")]
+    composite: Option<String>,
+    /// TrueType font for the composite labels [default: system sans-serif]
+    #[arg(long, value_name = "FILE")]
+    font: Option<String>,
+    /// Decode both symbols and compare payload, version, ECC level and mask; they count as
+    /// identical when the payloads match
+    #[arg(long, conflicts_with = "output")]
+    semantic: bool,
+    /// Format of the diff and composite images, for names without a known extension
+    #[arg(long, value_enum)]
+    format: Option<FileFormat>,
+    /// Overwrite existing output files
+    #[arg(long)]
+    force: bool,
+    /// Keep running, and compare again each time either input changes, printing what changed
+    /// since the previous run
+    #[arg(long)]
+    watch: bool,
+}
+
+pub fn run(args: Args) -> i32 {
+    if args.watch {
+        // The full report comes once; after that, only what changed. Images written by the
+        // first run are rewritten without --force.
+        let mut previous: Option<Outcome> = None;
+        let mut force = args.force;
+        watch(&[&args.input1, &args.input2], || match compare(&args, force, previous.is_none()) {
+            Ok(outcome) => {
+                if let Some(previous) = &previous {
+                    let changes = describe_changes(previous, &outcome);
+                    if changes.is_empty() {
+                        println!("  no change");
+                    }
+                    for change in changes {
+                        println!("  {}", change);
+                    }
+                }
+                force = true;
+                previous = Some(outcome);
+            }
+            Err(e) => eprintln!("Error: {}", e),
+        });
+    }
+
+    match compare(&args, args.force, true) {
+        Ok(outcome) if outcome.identical() => EXIT_IDENTICAL,
+        Ok(_) => EXIT_DIFFERS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Result of one comparison, kept by --watch to report what changed on the next
+enum Outcome {
+    Semantic(Box<SemanticReport>),
+    Modules { sizes: Option<SizeChange>, diffs: Vec<ModuleDiff> },
+}
+
+impl Outcome {
+    fn identical(&self) -> bool {
+        match self {
+            Outcome::Semantic(report) => report.identical,
+            Outcome::Modules { sizes, diffs } => sizes.is_none() && diffs.is_empty(),
+        }
+    }
+}
+
+/// Compare the inputs and write the diff and composite images; the report goes to stdout if
+/// `print` is set
+fn compare(args: &Args, force: bool, print: bool) -> Result<Outcome, Box<dyn std::error::Error>> {
+    let files = [&args.input1, &args.input2];
+    let (output, composite) = (&args.output, &args.composite);
+
+    if args.semantic {
+        let report = compare_decoded(files[0], files[1])?;
+        if print && args.json {
+            print_json(&mut io::stdout().lock(), &report)?;
+        } else if print {
+            print_semantic(&report);
+        }
+        return Ok(Outcome::Semantic(Box::new(report)));
+    }
+
+    let comparison = create_diff(files[0], files[1])?;
+    // Symbols of different sizes have no module grid in common to draw
+    let drawable = comparison.sizes.is_none();
+    if let Some(output) = output.as_ref().filter(|_| drawable) {
+        save_image_as(render_diff(&comparison), output, args.format, force)?;
+    }
+    if let Some(path) = composite.as_ref().filter(|_| drawable) {
+        save_image_as(render_composite(&comparison, files[0], files[1], args.font.clone()), path, args.format, force)?;
+    }
+    if !drawable && (output.is_some() || composite.is_some()) {
+        eprintln!("Warning: the symbols differ in size; no diff image written");
+    }
+
+    if print && args.json {
+        let report = DiffReport {
+            identical: comparison.identical(),
+            size: comparison.sizes,
+            differing_modules: &comparison.diffs,
+            differing_regions: count_regions(&comparison.diffs),
+        };
+        print_json(&mut io::stdout().lock(), &report)?;
+    } else if print {
+        if let Some(output) = output.as_ref().filter(|_| drawable) {
+            println!("Diff created: {} vs {} -> {}", files[0], files[1], output);
+        }
+        if let Some(composite) = composite.as_ref().filter(|_| drawable) {
+            println!("Composite created: {}", composite);
+        }
+        match &comparison.sizes {
+            Some(sizes) => println!("Symbols differ in size: {} vs {}", sizes.first, sizes.second),
+            None => print_summary(&comparison.diffs),
+        }
+    }
+    Ok(Outcome::Modules { sizes: comparison.sizes, diffs: comparison.diffs })
+}
+
+/// What changed between two comparisons of the same inputs, one line each: whether they are
+/// identical, then the decoded fields that changed or the modules that started or stopped
+/// differing, by region. Empty when nothing did.
+fn describe_changes(previous: &Outcome, current: &Outcome) -> Vec<String> {
+    let same = |identical: bool| if identical { "identical" } else { "different" }.to_string();
+    let mut changes: Vec<String> = change("inputs", previous.identical(), current.identical(), same).into_iter().collect();
+    match (previous, current) {
+        (Outcome::Semantic(before), Outcome::Semantic(after)) => {
+            for ((name, before), (_, after)) in before.fields().into_iter().zip(after.fields()) {
+                let show = |value: &String| if name == "Payload" { format!("{:?}", value) } else { value.clone() };
+                let pair = |field: &FieldComparison| if field.matches { show(&field.first) } else { format!("{} vs {}", show(&field.first), show(&field.second)) };
+                changes.extend(change(name, pair(before), pair(after), |pair| pair));
+            }
+        }
+        (Outcome::Modules { sizes: sizes_before, diffs: before }, Outcome::Modules { sizes: sizes_after, diffs: after }) => {
+            let sizes = |sizes: &Option<SizeChange>| sizes.map_or("same".to_string(), |sizes| format!("{} vs {}", sizes.first, sizes.second));
+            changes.extend(change("sizes", sizes(sizes_before), sizes(sizes_after), |sizes| sizes));
+            let key = |diff: &ModuleDiff| (diff.row, diff.col, diff.change);
+            let differing = |diffs: &[ModuleDiff], others: &[ModuleDiff]| -> Vec<ModuleDiff> {
+                diffs.iter().filter(|d| !others.iter().any(|o| key(o) == key(d))).cloned().collect()
+            };
+            let counts = |diffs: &[ModuleDiff]| count_regions(diffs).iter().map(|(region, count)| format!("{} {}", region, count)).collect::<Vec<_>>().join(", ");
+            let (started, stopped) = (differing(after, before), differing(before, after));
+            if !started.is_empty() {
+                changes.push(format!("+ {} modules now differ ({})", started.len(), counts(&started)));
+            }
+            if !stopped.is_empty() {
+                changes.push(format!("- {} modules match again ({})", stopped.len(), counts(&stopped)));
+            }
+        }
+        _ => {}
+    }
+    changes
+}
+
+/// Read the module matrices of two images and find the modules that differ
+fn create_diff(input1: &str, input2: &str) -> Result<Comparison, Box<dyn std::error::Error>> {
+    let matrix1 = image_to_matrix(&open_image(input1, None)?)?;
+    let matrix2 = image_to_matrix(&open_image(input2, None)?)?;
+    compare_matrices(matrix1, matrix2)
+}
+
+/// Compare two symbols module by module, or only by size when their sizes differ
+fn compare_matrices(matrix1: Vec<Vec<u8>>, matrix2: Vec<Vec<u8>>) -> Result<Comparison, Box<dyn std::error::Error>> {
+    if matrix1.len() != matrix2.len() {
+        let sizes = SizeChange { first: SymbolSize::of(&matrix1), second: SymbolSize::of(&matrix2) };
+        return Ok(Comparison { matrix1, matrix2, sizes: Some(sizes), diffs: Vec::new() });
+    }
+    let diffs = diff_matrices(&matrix1, &matrix2)?;
+    Ok(Comparison { matrix1, matrix2, sizes: None, diffs })
+}
+
+/// The modules that differ between two symbols of the same size
+pub(super) fn diff_matrices(matrix1: &[Vec<u8>], matrix2: &[Vec<u8>]) -> Result<Vec<ModuleDiff>, Box<dyn std::error::Error>> {
+    let size = matrix1.len();
+    if size != matrix2.len() {
+        return Err(format!("Symbols have different sizes: {}x{} vs {}x{} modules",
+                          size, size, matrix2.len(), matrix2.len()).into());
+    }
+    let regions = match image_size_to_version(size) {
+        Some(version) => region_names(version, matrix1, matrix2),
+        None if MicroVersion::from_size(size).is_some() => micro_region_names(size),
+        None => return Err(format!("{}x{} modules is neither a QR nor a Micro QR symbol", size, size).into()),
+    };
+
+    let mut diffs = Vec::new();
+    for row in 0..size {
+        for col in 0..size {
+            let is_black1 = matrix1[row][col] == 1;
+            let is_black2 = matrix2[row][col] == 1;
+            if is_black1 != is_black2 {
+                let change = if is_black1 { "dark->light" } else { "light->dark" };
+                diffs.push(ModuleDiff { row, col, region: regions[row][col], change });
+            }
+        }
+    }
+    Ok(diffs)
+}
+
+/// Draw a symbol-sized panel at DIFF_SCALE with a DIFF_BORDER quiet zone, coloring each module
+fn render_panel(size: usize, color: impl Fn(usize, usize) -> Rgb<u8>) -> RgbImage {
+    let image_size = (size as u32 + 2 * DIFF_BORDER) * DIFF_SCALE;
+    let mut panel = RgbImage::from_pixel(image_size, image_size, Rgb([255, 255, 255]));
+    for row in 0..size {
+        for col in 0..size {
+            let pixel = color(row, col);
+            let left = (col as u32 + DIFF_BORDER) * DIFF_SCALE;
+            let top = (row as u32 + DIFF_BORDER) * DIFF_SCALE;
+            for y in top..top + DIFF_SCALE {
+                for x in left..left + DIFF_SCALE {
+                    panel.put_pixel(x, y, pixel);
+                }
+            }
+        }
+    }
+    panel
+}
+
+fn render_matrix(matrix: &[Vec<u8>]) -> RgbImage {
+    render_panel(matrix.len(), |row, col| if matrix[row][col] == 1 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) })
+}
+
+fn render_diff(comparison: &Comparison) -> RgbImage {
+    let (matrix1, matrix2) = (&comparison.matrix1, &comparison.matrix2);
+    render_panel(matrix1.len(), |row, col| match (matrix1[row][col] == 1, matrix2[row][col] == 1) {
+        (true, true) => Rgb([0, 0, 0]),       // Both black -> black
+        (false, false) => Rgb([255, 255, 255]), // Both white -> white
+        (false, true) => Rgb([0, 255, 0]),     // White->Black -> green
+        (true, false) => Rgb([255, 0, 0]),     // Black->White -> red
+    })
+}
+
+/// First input, second input and diff side by side, each labelled above. Without a usable
+/// font the panels are still drawn, unlabelled, so a CI job always gets its artifact.
+fn render_composite(comparison: &Comparison, input1: &str, input2: &str, font: Option<String>) -> RgbImage {
+    let panels = [
+        (render_matrix(&comparison.matrix1), file_name(input1)),
+        (render_matrix(&comparison.matrix2), file_name(input2)),
+        (render_diff(comparison), format!("diff: {} modules", comparison.diffs.len())),
+    ];
+    let panel_size = panels[0].0.width();
+    let width = 3 * panel_size + 4 * PANEL_GAP;
+    let height = LABEL_HEIGHT + panel_size + PANEL_GAP;
+    let mut canvas = RgbImage::from_pixel(width, height, Rgb([255, 255, 255]));
+
+    for (i, (panel, label)) in panels.into_iter().enumerate() {
+        let left = PANEL_GAP + i as u32 * (panel_size + PANEL_GAP);
+        image::imageops::replace(&mut canvas, &panel, left as i64, LABEL_HEIGHT as i64);
+        let caption = Caption { text: label, size: LABEL_SIZE, position: CaptionPosition::Top, font_path: font.clone() };
+        let center_x = left as f32 + panel_size as f32 / 2.0;
+        if let Err(e) = draw_caption(&mut canvas, &caption, center_x, LABEL_HEIGHT as f32 / 2.0) {
+            eprintln!("Warning: composite labels skipped: {}", e);
+            break;
+        }
+    }
+    canvas
+}
+
+fn file_name(path: &str) -> String {
+    std::path::Path::new(path).file_name().map_or(path.to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// Name the region of every module. Data modules are split into data, ecc and remainder when
+/// the format information of either input gives the ECC level.
+pub(super) fn region_names(version: Version, matrix1: &[Vec<u8>], matrix2: &[Vec<u8>]) -> Vec<Vec<&'static str>> {
+    let error_correction: Option<ErrorCorrection> = read_format_words(matrix1)
+        .into_iter()
+        .chain(read_format_words(matrix2))
+        .find_map(|word| format_info::decode(word).ok())
+        .map(|(level, _)| level);
+    let codewords = error_correction.map(|level| module_codewords(version, level));
+
+    module_regions(version)
+        .into_iter()
+        .enumerate()
+        .map(|(row, line)| {
+            line.into_iter()
+                .enumerate()
+                .map(|(col, region)| match region {
+                    ModuleRegion::Finder => "finder",
+                    ModuleRegion::Timing => "timing",
+                    ModuleRegion::Alignment => "alignment",
+                    ModuleRegion::Format => "format",
+                    ModuleRegion::Version => "version",
+                    ModuleRegion::Data => match &codewords {
+                        None => "data_or_ecc",
+                        Some(codewords) => match codewords[row][col] {
+                            Some(location) if location.is_ecc => "ecc",
+                            Some(_) => "data",
+                            None => "remainder",
+                        },
+                    },
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Name the region of every module of a Micro QR symbol `size` modules wide. Its one timing
+/// pattern of each direction runs along the outer row and column.
+fn micro_region_names(size: usize) -> Vec<Vec<&'static str>> {
+    let format = micro::format_positions();
+    (0..size)
+        .map(|row| {
+            (0..size)
+                .map(|col| match (row, col) {
+                    _ if format.contains(&(row, col)) => "format",
+                    (0..=7, 0..=7) => "finder",
+                    _ if micro::is_function_module(row, col) => "timing",
+                    _ => "data_or_ecc",
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Write a JSON report. A reader that stops early, like `head -1`, closes the pipe; the rest
+/// of the report is dropped then rather than reported as an error.
+fn print_json(out: &mut impl Write, report: &impl Serialize) -> Result<(), Box<dyn std::error::Error>> {
+    let written = serde_json::to_writer_pretty(&mut *out, report).map_err(io::Error::from).and_then(|()| writeln!(out)).and_then(|()| out.flush());
+    match written {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => Ok(written?),
+    }
+}
+
+pub(super) fn print_summary(diffs: &[ModuleDiff]) {
+    if diffs.is_empty() {
+        println!("Modules identical");
+        return;
+    }
+
+    let counts: Vec<String> = count_regions(diffs).iter().map(|(region, count)| format!("{} {}", region, count)).collect();
+    println!("{} modules differ ({})", diffs.len(), counts.join(", "));
+    for diff in diffs {
+        println!("{} {} {} {}", diff.row, diff.col, diff.region, diff.change);
+    }
+}
+
+/// Decode both images and compare what they hold
+fn compare_decoded(input1: &str, input2: &str) -> Result<SemanticReport, Box<dyn std::error::Error>> {
+    let first = decode_pixels(&open_image(input1, None)?).map_err(|e| format!("cannot decode {}: {}", input1, e))?;
+    let second = decode_pixels(&open_image(input2, None)?).map_err(|e| format!("cannot decode {}: {}", input2, e))?;
+
+    Ok(SemanticReport {
+        identical: first.bytes == second.bytes,
+        payload: FieldComparison {
+            first: first.text.clone(),
+            second: second.text.clone(),
+            matches: first.bytes == second.bytes,
+        },
+        version: FieldComparison::new(format!("{:?}", first.version), format!("{:?}", second.version)),
+        error_correction: FieldComparison::new(format!("{:?}", first.error_correction), format!("{:?}", second.error_correction)),
+        mask: FieldComparison::new((first.mask_pattern as u8).to_string(), (second.mask_pattern as u8).to_string()),
+    })
+}
+
+fn print_semantic(report: &SemanticReport) {
+    for (name, field) in report.fields() {
+        let (first, second) = if name == "Payload" {
+            (format!("{:?}", field.first), format!("{:?}", field.second))
+        } else {
+            (field.first.clone(), field.second.clone())
+        };
+        if field.matches {
+            println!("{:<10} same     {}", name, first);
+        } else {
+            println!("{:<10} differs  {} vs {}", name, first, second);
+        }
+    }
+}
+
+fn count_regions(diffs: &[ModuleDiff]) -> BTreeMap<&'static str, usize> {
+    let mut by_region = BTreeMap::new();
+    for diff in diffs {
+        *by_region.entry(diff.region).or_default() += 1;
+    }
+    by_region
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols_of_different_sizes_differ_without_erroring() {
+        let version_1 = crate::reference::golden("hello-world-1q").unwrap().matrix();
+        let mut changed = version_1.clone();
+        changed[10][10] ^= 1;
+        let same_size = compare_matrices(version_1.clone(), changed).unwrap();
+        assert_eq!((same_size.sizes, same_size.diffs.len()), (None, 1));
+
+        let comparison = compare_matrices(version_1, vec![vec![0; 25]; 25]).unwrap();
+        assert!(!comparison.identical());
+        let sizes = comparison.sizes.unwrap();
+        assert_eq!(sizes.second, SymbolSize { modules: 25, version: Some(2) });
+        assert_eq!(format!("{} vs {}", sizes.first, sizes.second), "21x21 (version 1) vs 25x25 (version 2)");
+        let report = DiffReport { identical: false, size: Some(sizes), differing_modules: &[], differing_regions: BTreeMap::new() };
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["size"]["first"]["version"], 1);
+        assert_eq!(json["identical"], false);
+    }
+
+    #[test]
+    fn test_micro_symbols_compare_module_by_module() {
+        use crate::micro::tests::{field, micro_symbol};
+        use crate::micro::MicroFormat;
+        let bits: Vec<u8> = field(5, 3).chain(field(12, 10)).chain(field(34, 7)).collect();
+        let first = micro_symbol(MicroFormat { version: MicroVersion::M1, error_correction: None, mask: 3 }, &bits);
+        let mut second = first.clone();
+        for (row, col) in [(0, 10), (3, 8), (10, 10)] {
+            second[row][col] ^= 1;
+        }
+        let comparison = compare_matrices(first.clone(), second).unwrap();
+        let regions: Vec<&str> = comparison.diffs.iter().map(|diff| diff.region).collect();
+        assert_eq!(regions, ["timing", "format", "data_or_ecc"]);
+        assert!(compare_matrices(first.clone(), first).unwrap().identical());
+
+        let error = diff_matrices(&vec![vec![0; 19]; 19], &vec![vec![0; 19]; 19]).unwrap_err();
+        assert_eq!(error.to_string(), "19x19 modules is neither a QR nor a Micro QR symbol");
+    }
+
+    #[test]
+    fn test_json_report_stops_quietly_at_a_closed_pipe() {
+        struct ClosedPipe;
+        impl Write for ClosedPipe {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let report = DiffReport { identical: true, size: None, differing_modules: &[], differing_regions: BTreeMap::new() };
+        assert!(print_json(&mut ClosedPipe, &report).is_ok());
+        let mut json = Vec::new();
+        print_json(&mut json, &report).unwrap();
+        assert!(json.ends_with(b"}\n"));
+    }
+}
@@ -0,0 +1,829 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use rayon::prelude::*;
+use crate::types::{QrConfig, OutputFormat, ErrorCorrection, DataMode, MaskPattern, Version};
+use crate::capacity::max_characters;
+use crate::fit::{check_fit, check_mode};
+use crate::matrix::decode_base64;
+use crate::generator::{generate_qr_matrix, generate_qr_stages, calculate_version, boost_error_correction, lowest_penalty_mask, mask_candidates, symbol_metadata, MaskCandidate};
+use crate::sheet::{render_page_png, SheetLayout};
+use crate::visualize::render_placement_order;
+use crate::decoder::decode_image;
+use crate::logo::{max_logo_size, MIN_LOGO_MODULES};
+use crate::batch::{output_name, parse_csv, parse_jsonl};
+use crate::naming::{expand_name_template, uses_index, NameTokens};
+use crate::diagnostics::{format_stages, Diagnostics};
+use crate::url::{looks_like_url, normalize_url, uppercase_url, url_warnings};
+use super::{parse_error_correction, parse_positive, report};
+use super::conformance::{compare_with, parse_reference, Reference};
+use super::output::write_file;
+use super::payload::Payload;
+use crate::render::{Caption, CaptionPosition, EyeStyle, Gradient, Logo, ModuleStyle, RenderOptions, Renderer, Pbm, Pgm, Png, Svg, Terminal, parse_hex_color, render_gif_stages};
+
+/// Write the image to the output file, or to stdout for `-`
+fn write_output(config: &QrConfig, force: bool, write: impl FnOnce(&mut dyn Write) -> Result<(), Box<dyn std::error::Error>>) -> Result<(), Box<dyn std::error::Error>> {
+    if config.output_filename == "-" {
+        let mut stdout = io::stdout().lock();
+        write(&mut stdout)?;
+        Ok(stdout.flush()?)
+    } else {
+        write_file(&config.output_filename, force, |file| write(file))
+    }
+}
+
+fn save_matrix(matrix: &[Vec<u8>], config: &QrConfig, options: &RenderOptions, writer: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+    let mut renderer: Box<dyn Renderer + '_> = match config.output_format {
+        OutputFormat::Png => Box::new(Png(writer)),
+        OutputFormat::Svg => Box::new(Svg(writer)),
+        OutputFormat::Pbm => Box::new(Pbm { writer, plain: false }),
+        OutputFormat::PbmPlain => Box::new(Pbm { writer, plain: true }),
+        OutputFormat::Pgm => Box::new(Pgm(writer)),
+        OutputFormat::Terminal => Box::new(Terminal(writer)),
+        OutputFormat::GifStages | OutputFormat::Placement => unreachable!("debug formats are rendered from the input text"),
+    };
+    renderer.render(matrix, options)
+}
+
+fn save_stages(data: &[u8], config: &QrConfig, options: &RenderOptions, writer: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+    let stages = generate_qr_stages(data, config);
+    render_gif_stages(&stages, options, writer)
+}
+
+/// Size the logo to the largest square the error correction can absorb, raising the
+/// error correction level to H if the current level leaves too little room.
+fn prepare_logo(data: &[u8], config: &mut QrConfig, path: &str) -> Result<Option<Logo>, Box<dyn std::error::Error>> {
+    if !matches!(config.output_format, OutputFormat::Png) {
+        eprintln!("Warning: --logo is only supported for PNG output; ignoring it");
+        return Ok(None);
+    }
+
+    let (error_correction, size_modules) = logo_room(data, config);
+    if error_correction != config.error_correction {
+        eprintln!("Note: raising error correction from {:?} to H to make room for the logo", config.error_correction);
+        config.error_correction = error_correction;
+    }
+
+    if size_modules < MIN_LOGO_MODULES {
+        eprintln!("Warning: not enough error correction capacity to cover {} modules; omitting logo", MIN_LOGO_MODULES);
+        return Ok(None);
+    }
+
+    Ok(Some(Logo {
+        image: image::open(path)?,
+        size_modules,
+    }))
+}
+
+/// The error correction level a logo needs, H if the current level leaves too little room,
+/// and the logo size in modules it allows
+fn logo_room(data: &[u8], config: &QrConfig) -> (ErrorCorrection, usize) {
+    let version = calculate_version(data, config.error_correction, config.data_mode);
+    let size_modules = max_logo_size(version, config.error_correction);
+    if size_modules < MIN_LOGO_MODULES && config.error_correction != ErrorCorrection::H {
+        let version = calculate_version(data, ErrorCorrection::H, config.data_mode);
+        return (ErrorCorrection::H, max_logo_size(version, ErrorCorrection::H));
+    }
+    (config.error_correction, size_modules)
+}
+
+fn parse_mask(value: &str) -> Result<MaskPattern, String> {
+    match value {
+        "0" => Ok(MaskPattern::Pattern0),
+        "1" => Ok(MaskPattern::Pattern1),
+        "2" => Ok(MaskPattern::Pattern2),
+        "3" => Ok(MaskPattern::Pattern3),
+        "4" => Ok(MaskPattern::Pattern4),
+        "5" => Ok(MaskPattern::Pattern5),
+        "6" => Ok(MaskPattern::Pattern6),
+        "7" => Ok(MaskPattern::Pattern7),
+        _ => Err("mask pattern must be 0-7".to_string()),
+    }
+}
+
+/// Payload bytes given on the command line by --input-hex or --input-base64
+#[derive(Debug, Clone)]
+struct InlineBytes(Vec<u8>);
+
+/// Pairs of hex digits; whitespace and colons between them are ignored
+fn parse_hex_payload(value: &str) -> Result<InlineBytes, String> {
+    let digits: Vec<u8> = value.bytes().filter(|&b| !b.is_ascii_whitespace() && b != b':').collect();
+    if !digits.iter().all(u8::is_ascii_hexdigit) || !digits.len().is_multiple_of(2) {
+        return Err("use pairs of hex digits, e.g. 00ff10".to_string());
+    }
+    let nibble = |digit: u8| (digit as char).to_digit(16).unwrap() as u8;
+    Ok(InlineBytes(digits.chunks(2).map(|pair| nibble(pair[0]) << 4 | nibble(pair[1])).collect()))
+}
+
+fn parse_base64_payload(value: &str) -> Result<InlineBytes, String> {
+    decode_base64(value.trim()).map(InlineBytes).ok_or_else(|| "use standard base64 with padding".to_string())
+}
+
+pub(super) fn parse_data_mode(value: &str) -> Result<DataMode, String> {
+    match value.to_lowercase().as_str() {
+        "byte" => Ok(DataMode::Byte),
+        "numeric" => Ok(DataMode::Numeric),
+        "alphanumeric" => Ok(DataMode::Alphanumeric),
+        _ => Err("use byte, numeric or alphanumeric".to_string()),
+    }
+}
+
+fn parse_format(value: &str) -> Result<OutputFormat, String> {
+    match value.to_lowercase().as_str() {
+        "png" => Ok(OutputFormat::Png),
+        "svg" => Ok(OutputFormat::Svg),
+        "pbm" => Ok(OutputFormat::Pbm),
+        "pbm-plain" => Ok(OutputFormat::PbmPlain),
+        "pgm" => Ok(OutputFormat::Pgm),
+        "terminal" => Ok(OutputFormat::Terminal),
+        "gif-stages" => Ok(OutputFormat::GifStages),
+        "placement" => Ok(OutputFormat::Placement),
+        _ => Err("use png, svg, pbm, pbm-plain, pgm, terminal, gif-stages or placement".to_string()),
+    }
+}
+
+fn parse_style(value: &str) -> Result<ModuleStyle, String> {
+    match value.to_lowercase().as_str() {
+        "square" => Ok(ModuleStyle::Square),
+        "dot" => Ok(ModuleStyle::Dot),
+        "rounded" => Ok(ModuleStyle::Rounded),
+        _ => Err("use square, dot or rounded".to_string()),
+    }
+}
+
+fn parse_eye_style(value: &str) -> Result<EyeStyle, String> {
+    match value.to_lowercase().as_str() {
+        "square" => Ok(EyeStyle::Square),
+        "rounded" => Ok(EyeStyle::Rounded),
+        "circle" => Ok(EyeStyle::Circle),
+        _ => Err("use square, rounded or circle".to_string()),
+    }
+}
+
+fn parse_caption_position(value: &str) -> Result<CaptionPosition, String> {
+    match value.to_lowercase().as_str() {
+        "top" => Ok(CaptionPosition::Top),
+        "bottom" => Ok(CaptionPosition::Bottom),
+        _ => Err("use top or bottom".to_string()),
+    }
+}
+
+const EXAMPLES: &str = "\
+Examples:
+  qr generate \"Hello, World!\"
+  qr generate --input notes.txt
+  head -c 64 /dev/urandom | qr generate --input - -o random.png
+  qr generate --input-hex 00ff7f10 -o binary.png
+  qr generate --batch items.csv --output-dir out/ --name-template \"item-{id}.svg\" -f svg
+  qr generate -o \"qr-{hash8}-{version}{ecc}.png\" \"https://example.com\"
+  qr generate --dry-run -e Q \"https://example.com/orders/20240001\"
+  qr generate --normalize-url --uppercase-url \"https://Example.com/MENU\"
+  qr generate -e H -m 3 -o my-qr.svg -f svg \"Hello, World!\"
+  qr generate -f pbm -o my-qr.pbm \"Hello, World!\"
+  qr generate -f gif-stages -o stages.gif \"Hello, World!\"
+  qr generate -o - \"Hello, World!\" > my-qr.png
+  qr generate -e H --logo logo.png \"https://example.com\"
+  qr generate --dpi 300 --module-size-mm 0.5 -o label.png \"https://example.com\"
+  qr generate --frame --caption \"Scan to pay\" \"https://example.com/pay\"
+  qr generate -o jane.png contact --name \"Jane Doe\" --phone \"+1 555 0100\" --email jane@example.com
+  qr generate email jane@example.com --subject \"Order 1234\"
+  qr generate geo 48.8584 2.2945
+  qr generate sepa --iban DE33100205000001194700 --name \"Example e.V.\" --amount 25.00 --text \"Donation\"
+  qr generate totp --issuer Example --account jane@example.com --secret JBSWY3DPEHPK3PXP";
+
+#[derive(Debug, clap::Args)]
+#[command(after_help = EXAMPLES, subcommand_negates_reqs = true)]
+pub struct Args {
+    /// Text to encode
+    #[arg(required_unless_present_any = ["input", "input_hex", "input_base64", "batch"], conflicts_with_all = ["input", "input_hex", "input_base64"])]
+    text: Option<String>,
+    /// Read the payload from a file, or - for stdin, as raw bytes
+    #[arg(short, long, value_name = "FILE", conflicts_with_all = ["input_hex", "input_base64"])]
+    input: Option<String>,
+    /// Payload bytes as hex, for binary data that cannot be passed as text
+    #[arg(long, value_name = "HEX", value_parser = parse_hex_payload, conflicts_with = "input_base64")]
+    input_hex: Option<InlineBytes>,
+    /// Payload bytes as base64
+    #[arg(long, value_name = "BASE64", value_parser = parse_base64_payload)]
+    input_base64: Option<InlineBytes>,
+    /// Generate one code per row of a CSV or JSON Lines file (.jsonl), or - for stdin.
+    /// Columns: payload, optional filename, whose extension (png, svg, pbm, pgm) overrides --format, and optional caption
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["text", "input", "input_hex", "input_base64", "output"])]
+    batch: Option<String>,
+    /// Directory the --batch codes are written to
+    #[arg(long, value_name = "DIR", default_value = ".", conflicts_with_all = ["text", "input", "input_hex", "input_base64"])]
+    output_dir: String,
+    /// Filename for --batch rows without one, with the tokens of --output; {index} or {id} is
+    /// the row number [default: {index}.<format>]. Names must stay inside --output-dir
+    #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["text", "input", "input_hex", "input_base64"])]
+    name_template: Option<String>,
+    /// Codes generated in parallel by --batch [default: number of CPUs]
+    #[arg(short, long, value_parser = parse_positive::<usize>, conflicts_with_all = ["text", "input", "input_hex", "input_base64"])]
+    jobs: Option<usize>,
+    /// Lowercase the scheme of a URL payload and convert its host to punycode
+    #[arg(long, conflicts_with = "batch")]
+    normalize_url: bool,
+    /// Uppercase a URL payload to encode it in the denser alphanumeric mode; only for URLs
+    /// whose path is case-insensitive
+    #[arg(long, conflicts_with = "batch")]
+    uppercase_url: bool,
+    /// Error correction level (L, M, Q, H) [default: M; payload subcommands raise it as far as
+    /// the symbol size allows, or use the level their format requires]
+    #[arg(short, long, value_parser = parse_error_correction)]
+    error_correction: Option<ErrorCorrection>,
+    /// Mask pattern (0-7)
+    #[arg(short, long, visible_alias = "mask-pattern", default_value = "0", value_parser = parse_mask)]
+    mask: MaskPattern,
+    /// Data mode (byte, numeric, alphanumeric)
+    #[arg(short, long, default_value = "byte", value_parser = parse_data_mode)]
+    data_mode: DataMode,
+    /// Output filename, or - for stdout. Tokens: {hash}/{hash8} (SHA-256 of the payload),
+    /// {version}, {ecc}, {mask}, and {index}, the first number not taken by an existing file
+    #[arg(short, long, default_value = "qr-code.png")]
+    output: String,
+    /// Output format (png, svg, pbm, pbm-plain, pgm, terminal, gif-stages, placement)
+    #[arg(short, long, default_value = "png", value_parser = parse_format)]
+    format: OutputFormat,
+    /// Pixels per module. PNGs over 4096x4096 pixels are written row by row to save memory,
+    /// unless they have a logo or caption
+    #[arg(long, value_name = "PX", default_value_t = 10, value_parser = parse_positive::<usize>)]
+    scale: usize,
+    /// Light border around the symbol, in modules
+    #[arg(long, value_name = "MODULES", default_value_t = 4)]
+    quiet_zone: usize,
+    /// Skip mask application
+    #[arg(short, long)]
+    skip_mask: bool,
+    /// Print the segments, block structure, codewords and mask penalties to stderr
+    #[arg(short, long)]
+    verbose: bool,
+    /// Data module shape (square, dot, rounded)
+    #[arg(long, default_value = "square", value_parser = parse_style)]
+    style: ModuleStyle,
+    /// Finder pattern shape (square, rounded, circle)
+    #[arg(long, default_value = "square", value_parser = parse_eye_style)]
+    eye_style: EyeStyle,
+    /// Finder pattern color as #rrggbb
+    #[arg(long, default_value = "#000000", value_parser = |value: &str| parse_hex_color(value).ok_or("use #rrggbb"))]
+    eye_color: [u8; 3],
+    /// SVG gradient fill: "#from,#to,<angle>deg" or "#from,#to,radial"
+    #[arg(long, value_parser = |value: &str| Gradient::parse(value).ok_or("use \"#rrggbb,#rrggbb,45deg\" or \"#rrggbb,#rrggbb,radial\""))]
+    gradient: Option<Gradient>,
+    /// Physical resolution stored in PNG metadata / SVG dimensions
+    #[arg(long, value_parser = parse_positive::<f64>)]
+    dpi: Option<f64>,
+    /// Printed size of one module; sets the scale (with --dpi) or the DPI
+    #[arg(long, value_name = "MM", value_parser = parse_positive::<f64>)]
+    module_size_mm: Option<f64>,
+    /// Outline codewords in the placement debug output
+    #[arg(long)]
+    codeword_boundaries: bool,
+    /// Fail rather than fall back to a system caption font, so the same inputs and build
+    /// always write the same bytes
+    #[arg(long)]
+    deterministic: bool,
+    /// Light modules on a dark background
+    #[arg(long)]
+    invert: bool,
+    /// Decode the written file and check it holds the input
+    #[arg(long)]
+    verify: bool,
+    /// Overwrite existing output files
+    #[arg(long)]
+    force: bool,
+    /// Print the version, ECC level, mask, segments, codeword counts and mask penalty as JSON,
+    /// or write them to FILE. With --batch, written next to each code as <name>.json
+    #[arg(long, value_name = "FILE", num_args = 0..=1, require_equals = true, default_missing_value = "-")]
+    meta_json: Option<String>,
+    /// Print the version, ECC level and mode the payload would get and the room left in that
+    /// version, without writing anything
+    #[arg(long, conflicts_with_all = ["batch", "verify", "meta_json"])]
+    dry_run: bool,
+    /// Render the code under each of the eight mask patterns to a contact sheet PNG and print
+    /// their penalties rule by rule, without writing the code
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["batch", "verify", "meta_json", "dry_run", "skip_mask"])]
+    explain_mask: Option<String>,
+    /// Encode the payload with another generator, or read the code with another decoder, and
+    /// report where it disagrees, without writing the code. The command runs without a shell;
+    /// tokens: {text}, {payload} (a file of the payload bytes), {version}, {ecc}, {mask}, and
+    /// either {output}, where a generator writes its image, or {image}, the code for a decoder
+    /// that prints the payload
+    #[arg(long, value_name = "COMMAND", value_parser = parse_reference, conflicts_with_all = ["batch", "verify", "meta_json", "dry_run", "explain_mask"])]
+    compare_with: Option<Reference>,
+    /// Draw a frame around the code (PNG, SVG)
+    #[arg(long)]
+    frame: bool,
+    /// Caption printed with the code (PNG, SVG)
+    #[arg(long)]
+    caption: Option<String>,
+    /// Caption font size in pixels
+    #[arg(long, value_name = "PX", default_value_t = 24.0, value_parser = parse_positive::<f32>)]
+    caption_size: f32,
+    /// Caption placement (top, bottom)
+    #[arg(long, default_value = "bottom", value_parser = parse_caption_position)]
+    caption_position: CaptionPosition,
+    /// TrueType font for PNG captions [default: system sans-serif]
+    #[arg(long, value_name = "FILE")]
+    caption_font: Option<String>,
+    /// Overlay an image in the center (PNG only); may raise ECC to H
+    #[arg(short, long, value_name = "FILE")]
+    logo: Option<String>,
+    #[command(subcommand)]
+    payload: Option<Payload>,
+}
+
+pub fn run(args: Args) -> i32 {
+    report(generate(args))
+}
+
+fn generate(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let verify = args.verify;
+    let mut config = QrConfig {
+        error_correction: args.error_correction.unwrap_or(ErrorCorrection::M),
+        mask_pattern: args.mask,
+        data_mode: args.data_mode,
+        output_filename: args.output,
+        output_format: args.format,
+        skip_mask: args.skip_mask,
+        verbose: args.verbose,
+        logo: args.logo,
+        ..QrConfig::default()
+    };
+    let mut options = RenderOptions {
+        scale: args.scale,
+        quiet_zone: args.quiet_zone,
+        style: args.style,
+        eye_style: args.eye_style,
+        eye_color: args.eye_color,
+        gradient: args.gradient,
+        dpi: args.dpi,
+        deterministic: args.deterministic,
+        invert: args.invert,
+        frame: args.frame,
+        ..RenderOptions::default()
+    };
+    let caption_style = Caption {
+        text: String::new(),
+        size: args.caption_size,
+        position: args.caption_position,
+        font_path: args.caption_font,
+    };
+    let caption = args.caption.map(|text| Caption { text, ..caption_style.clone() });
+    let module_size_mm = args.module_size_mm;
+    let codeword_boundaries = args.codeword_boundaries;
+    
+    if caption.as_ref().is_some_and(|caption| !caption.text.is_empty()) {
+        options.caption = caption;
+    }
+    if let Some(mm) = module_size_mm {
+        // With a fixed resolution, pick the pixel scale that comes closest to the requested
+        // module size; otherwise keep the scale and derive the resolution from it.
+        match options.dpi {
+            Some(dpi) => options.scale = ((dpi * mm / 25.4).round() as usize).max(1),
+            None => options.dpi = Some(options.scale as f64 * 25.4 / mm),
+        }
+    }
+    if options.dpi.is_some() && !matches!(config.output_format, OutputFormat::Png | OutputFormat::Svg) {
+        eprintln!("Warning: --dpi and --module-size-mm only affect PNG and SVG output");
+    }
+    if options.gradient.is_some() && !matches!(config.output_format, OutputFormat::Svg) {
+        eprintln!("Warning: --gradient is only supported for SVG output; ignoring it");
+    }
+    if verify && (config.output_filename == "-" || !matches!(config.output_format, OutputFormat::Png | OutputFormat::Pbm | OutputFormat::PbmPlain | OutputFormat::Pgm)) {
+        return Err("--verify needs a PNG, PBM or PGM output file to read back".into());
+    }
+
+    if let Some(path) = args.batch {
+        if args.payload.is_some() {
+            return Err("--batch reads its payloads from the file and takes no payload subcommand".into());
+        }
+        if args.meta_json.as_deref().is_some_and(|meta| meta != "-") {
+            return Err("--meta-json takes no file with --batch; each code gets its own".into());
+        }
+        let name_template = args.name_template.unwrap_or_else(|| format!("{{index}}.{}", extension(config.output_format)));
+        let jobs = args.jobs.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+        let batch = Batch { config: &config, options: &options, caption_style, codeword_boundaries, verify, metadata: args.meta_json.is_some(), force: args.force };
+        return generate_batch(&path, &args.output_dir, &name_template, jobs, &batch);
+    }
+
+    if args.payload.is_some() && (args.normalize_url || args.uppercase_url) {
+        return Err("--normalize-url and --uppercase-url apply to the text or --input".into());
+    }
+    let inline = args.input_hex.or(args.input_base64).map(|InlineBytes(bytes)| bytes);
+    let data = match (args.text, args.input, inline, args.payload) {
+        (Some(text), None, None, None) => prepare_url(text.into_bytes(), args.normalize_url, args.uppercase_url, &mut config)?,
+        (None, Some(path), None, None) => prepare_url(read_input(&path)?, args.normalize_url, args.uppercase_url, &mut config)?,
+        (None, None, Some(bytes), None) => prepare_url(bytes, args.normalize_url, args.uppercase_url, &mut config)?,
+        (None, None, None, Some(payload)) => {
+            if config.data_mode != DataMode::Byte {
+                return Err("payload subcommands are encoded in byte mode".into());
+            }
+            let required = payload.required_error_correction();
+            let data = payload.build()?.into_bytes();
+            match (required, args.error_correction) {
+                (Some(level), Some(given)) if given != level => return Err(format!("this payload requires error correction {:?}", level).into()),
+                (Some(_), _) if config.logo.is_some() => return Err("--logo may raise the error correction level, which this payload fixes".into()),
+                (Some(level), _) => config.error_correction = level,
+                (None, None) => config.error_correction = boost_error_correction(&data, config.error_correction, config.data_mode),
+                (None, Some(_)) => {}
+            }
+            data
+        }
+        (None, None, None, None) => unreachable!("clap requires the text, an --input option, --batch or a payload subcommand"),
+        _ => return Err("a payload subcommand replaces the text and the --input options".into()),
+    };
+    check_mode(&data, config.data_mode)?;
+    if args.dry_run {
+        if config.logo.is_some() && matches!(config.output_format, OutputFormat::Png) {
+            config.error_correction = logo_room(&data, &config).0;
+        }
+        let (report, fits) = describe_fit(&data, &config);
+        println!("{}", report);
+        return if fits { Ok(()) } else { Err("the payload is too large".into()) };
+    }
+    check_fit(&data, config.error_correction, config.data_mode)?;
+    if let Some(path) = args.explain_mask {
+        let candidates = mask_candidates(&data, &config);
+        write_mask_sheet(&candidates, config.mask_pattern, &path, args.force)?;
+        println!("{}", explain_masks(&candidates, config.mask_pattern));
+        println!("Mask candidates written to {}", path);
+        return Ok(());
+    }
+    if let Some(reference) = args.compare_with {
+        let matrix = generate_qr_matrix(&data, &config);
+        return if compare_with(&reference, &data, &config, &matrix, &options)? { Ok(()) } else { Err("the reference disagrees".into()) };
+    }
+    let meta_to_stdout = args.meta_json.as_deref() == Some("-");
+    if meta_to_stdout && config.output_filename == "-" {
+        return Err("the image and --meta-json cannot both be written to stdout".into());
+    }
+    if config.output_filename != "-" {
+        config.output_filename = expand_output_name(&config.output_filename, &data, &config)?;
+    }
+    write_code(&data, &mut config, &mut options, codeword_boundaries, args.force)?;
+
+    // Keep stdout clean when the image or the metadata is being streamed there
+    let status = |message: String| if meta_to_stdout { eprintln!("{}", message) } else { println!("{}", message) };
+    if config.output_filename != "-" {
+        status(format!("QR code generated: {}", config.output_filename));
+    }
+    if verify {
+        status(format!("Verified: payload matches ({})", verify_output(&config.output_filename, &data)?));
+    }
+    if let Some(path) = args.meta_json {
+        write_metadata(&data, &config, &path, args.force)?;
+    }
+    Ok(())
+}
+
+/// What `--dry-run` prints: the symbol `data` would be encoded in and the room left in it, and
+/// whether it fits at all
+fn describe_fit(data: &[u8], config: &QrConfig) -> (String, bool) {
+    let (error_correction, mode) = (config.error_correction, config.data_mode);
+    let mode_name = format!("{:?}", mode).to_lowercase();
+    let version = match check_fit(data, error_correction, mode) {
+        Ok(version) => version,
+        Err(too_large) => return (too_large.to_string(), false),
+    };
+    let capacity = max_characters(version, error_correction, mode);
+    let mut report = format!(
+        "Version {} ({}x{} modules), error correction {:?}, {} mode\n{} of {} characters used, {} to spare",
+        version as u8, version.size(), version.size(), error_correction, mode_name, data.len(), capacity, capacity - data.len()
+    );
+    if version != Version::V40 {
+        report += &format!(" before version {}", version as u8 + 1);
+    }
+    let boosted = boost_error_correction(data, error_correction, mode);
+    if boosted != error_correction {
+        report += &format!("\nError correction {:?} fits in the same size", boosted);
+    }
+    (report, true)
+}
+
+/// What `--explain-mask` prints: each mask's penalty under the four rules of ISO/IEC 18004
+/// section 7.8.3, and the mask those rules select next to the one applied
+fn explain_masks(candidates: &[MaskCandidate], applied: MaskPattern) -> String {
+    let lowest = lowest_penalty_mask(candidates);
+    let mut report = String::from(
+        "Rule 1: runs of five or more modules of one color\n\
+         Rule 2: 2x2 blocks of one color\n\
+         Rule 3: finder-like 1:1:3:1:1 patterns\n\
+         Rule 4: imbalance of dark and light modules\n\n\
+         Mask  Rule 1  Rule 2  Rule 3  Rule 4  Total",
+    );
+    for candidate in candidates {
+        let penalty = &candidate.penalty;
+        let notes: Vec<&str> = [(Some(candidate.mask) == lowest, "lowest"), (candidate.mask == applied, "applied")]
+            .into_iter()
+            .filter_map(|(holds, note)| holds.then_some(note))
+            .collect();
+        let line = format!(
+            "{:>4}  {:>6}  {:>6}  {:>6}  {:>6}  {:>5}  {}",
+            candidate.mask as u8, penalty.runs, penalty.blocks, penalty.finder_like, penalty.balance, penalty.total, notes.join(", ")
+        );
+        report += "\n";
+        report += line.trim_end();
+    }
+    if let Some(lowest) = lowest.filter(|&lowest| lowest != applied) {
+        report += &format!("\n\nThe rules select mask {}; pass -m {} to use it", lowest as u8, lowest as u8);
+    }
+    report
+}
+
+/// Resolution of the `--explain-mask` contact sheet
+const MASK_SHEET_DPI: f64 = 150.0;
+
+/// Tile the eight candidates onto an A4 contact sheet, captioned with their penalties
+fn write_mask_sheet(candidates: &[MaskCandidate], applied: MaskPattern, path: &str, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let lowest = lowest_penalty_mask(candidates);
+    let codes: Vec<(Vec<Vec<u8>>, Option<String>)> = candidates
+        .iter()
+        .map(|candidate| {
+            let mut caption = format!("Mask {}: penalty {}", candidate.mask as u8, candidate.penalty.total);
+            if Some(candidate.mask) == lowest {
+                caption += ", lowest";
+            }
+            if candidate.mask == applied {
+                caption += ", applied";
+            }
+            (candidate.matrix.clone(), Some(caption))
+        })
+        .collect();
+    let layout = SheetLayout { rows: 4, columns: 2, ..SheetLayout::default() };
+    write_file(path, force, |file| render_page_png(&codes, &layout, MASK_SHEET_DPI, file))
+}
+
+/// Warn about characters that may keep a URL payload from scanning, after applying
+/// --normalize-url and --uppercase-url, which need one. Uppercasing switches to alphanumeric
+/// mode. Other payloads pass through unchanged.
+fn prepare_url(data: Vec<u8>, normalize: bool, uppercase: bool, config: &mut QrConfig) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let Some(mut url) = std::str::from_utf8(&data).ok().filter(|text| looks_like_url(text)).map(str::to_string) else {
+        if normalize || uppercase {
+            return Err("--normalize-url and --uppercase-url need a payload like https://example.com".into());
+        }
+        return Ok(data);
+    };
+    if normalize {
+        url = normalize_url(&url)?;
+    }
+    for warning in url_warnings(&url) {
+        eprintln!("Warning: {}", warning);
+    }
+    if uppercase {
+        url = uppercase_url(&url)?;
+        if url.split_once("://").is_some_and(|(_, rest)| rest.trim_end_matches('/').contains('/')) {
+            eprintln!("Note: the path is uppercased too; check that the server ignores its case");
+        }
+        config.data_mode = DataMode::Alphanumeric;
+    }
+    Ok(url.into_bytes())
+}
+
+/// Write [`symbol_metadata`] as JSON to `path`, or stdout for `-`
+fn write_metadata(data: &[u8], config: &QrConfig, path: &str, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let json = serde_json::to_string_pretty(&symbol_metadata(data, config))?;
+    if path == "-" {
+        println!("{}", json);
+    } else {
+        write_file(path, force, |file| Ok(writeln!(file, "{}", json)?))?;
+    }
+    Ok(())
+}
+
+/// Encode `data` and write it to the configured output in the configured format. With
+/// `--verbose`, the encoder's diagnostics go to stderr, since stdout may carry the image.
+fn write_code(data: &[u8], config: &mut QrConfig, options: &mut RenderOptions, codeword_boundaries: bool, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(path) = config.logo.clone() {
+        options.logo = prepare_logo(data, config, &path)?;
+    }
+
+    let diagnostics = config.verbose.then(Diagnostics::new);
+    let config = &QrConfig { diagnostics: diagnostics.clone(), ..config.clone() };
+    write_output(config, force, |writer| match config.output_format {
+        OutputFormat::GifStages => save_stages(data, config, options, writer),
+        OutputFormat::Placement => {
+            let version = calculate_version(data, config.error_correction, config.data_mode);
+            render_placement_order(version, options, codeword_boundaries, writer)
+        }
+        _ => save_matrix(&generate_qr_matrix(data, config), config, options, writer),
+    })?;
+    if let Some(diagnostics) = diagnostics.filter(|diagnostics| !diagnostics.stages().is_empty()) {
+        eprintln!("{}", format_stages(&diagnostics.stages()));
+    }
+    Ok(())
+}
+
+/// Template tokens for `data` encoded with `config`. The version and ECC level are the ones
+/// requested, before a logo may raise the level.
+fn name_tokens<'a>(data: &'a [u8], index: usize, config: &QrConfig) -> NameTokens<'a> {
+    NameTokens {
+        index,
+        payload: data,
+        version: calculate_version(data, config.error_correction, config.data_mode),
+        error_correction: config.error_correction,
+        mask: config.mask_pattern,
+    }
+}
+
+/// Expand the --output template. `{index}` becomes the first number whose file does not
+/// exist yet, so repeated runs never collide.
+fn expand_output_name(template: &str, data: &[u8], config: &QrConfig) -> Result<String, String> {
+    let name = |index| expand_name_template(template, &name_tokens(data, index, config));
+    if !uses_index(template) {
+        return name(1);
+    }
+    let mut index = 1;
+    while Path::new(&name(index)?).exists() {
+        index += 1;
+    }
+    name(index)
+}
+
+/// File extension of an output format, for the default --name-template
+fn extension(format: OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Png | OutputFormat::Placement => "png",
+        OutputFormat::Svg => "svg",
+        OutputFormat::Pbm | OutputFormat::PbmPlain => "pbm",
+        OutputFormat::Pgm => "pgm",
+        OutputFormat::Terminal => "txt",
+        OutputFormat::GifStages => "gif",
+    }
+}
+
+/// Format of a `--batch` output: the one its extension names, so a row's `b.svg` is an SVG
+/// whatever --format says. Names with another extension, or none, keep `format`.
+fn batch_format(output: &Path, format: OutputFormat) -> OutputFormat {
+    match output.extension().and_then(|extension| extension.to_str()).map(str::to_ascii_lowercase).as_deref() {
+        Some(name) if name == extension(format) => format,
+        Some("png") => OutputFormat::Png,
+        Some("svg") => OutputFormat::Svg,
+        Some("pbm") => OutputFormat::Pbm,
+        Some("pgm") => OutputFormat::Pgm,
+        _ => format,
+    }
+}
+
+/// Settings shared by every code of a `--batch` run
+struct Batch<'a> {
+    config: &'a QrConfig,
+    options: &'a RenderOptions,
+    /// Size, position and font of the per-row captions
+    caption_style: Caption,
+    codeword_boundaries: bool,
+    verify: bool,
+    /// Write the --meta-json metadata next to each code
+    metadata: bool,
+    force: bool,
+}
+
+/// Generate one code per row of a CSV or JSON Lines file into `output_dir`, `jobs` at a time.
+/// A failing row does not stop the others; the failures are listed at the end.
+fn generate_batch(path: &str, output_dir: &str, name_template: &str, jobs: usize, batch: &Batch) -> Result<(), Box<dyn std::error::Error>> {
+    let input = String::from_utf8(read_input(path)?).map_err(|_| format!("{} is not UTF-8 text", path))?;
+    let jsonl = path.ends_with(".jsonl") || path.ends_with(".ndjson") || (path == "-" && input.trim_start().starts_with('{'));
+    let items = if jsonl { parse_jsonl(&input)? } else { parse_csv(&input)? };
+    if items.is_empty() {
+        return Err(format!("No rows found in {}", path).into());
+    }
+
+    let outputs = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let tokens = name_tokens(item.payload.as_bytes(), i + 1, batch.config);
+            output_name(item, name_template, &tokens).map(|name| Path::new(output_dir).join(name))
+        })
+        .collect::<Result<Vec<PathBuf>, String>>()?;
+    let mut rows_by_output = HashMap::new();
+    for (i, output) in outputs.iter().enumerate() {
+        if let Some(first) = rows_by_output.insert(output, i) {
+            return Err(format!("rows {} and {} would both be written to {}", first + 1, i + 1, output.display()).into());
+        }
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    let failures: Vec<(usize, String)> = pool.install(|| {
+        items
+            .par_iter()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                generate_batch_item(item.payload.as_bytes(), item.caption.as_deref(), &outputs[i], batch).err().map(|e| (i, e.to_string()))
+            })
+            .collect()
+    });
+
+    for (i, error) in &failures {
+        eprintln!("Row {}: {}", i + 1, error);
+    }
+    println!("{} of {} codes generated in {}", items.len() - failures.len(), items.len(), output_dir);
+    if !failures.is_empty() {
+        return Err(format!("{} rows failed", failures.len()).into());
+    }
+    Ok(())
+}
+
+fn generate_batch_item(data: &[u8], caption: Option<&str>, output: &Path, batch: &Batch) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    check_mode(data, batch.config.data_mode)?;
+    check_fit(data, batch.config.error_correction, batch.config.data_mode)?;
+    let output_format = batch_format(output, batch.config.output_format);
+    if batch.verify && !matches!(output_format, OutputFormat::Png | OutputFormat::Pbm | OutputFormat::PbmPlain | OutputFormat::Pgm) {
+        return Err(format!("--verify needs a PNG, PBM or PGM output file to read back, not {}", output.display()).into());
+    }
+    let mut config = QrConfig { output_filename: output.to_string_lossy().into_owned(), output_format, ..batch.config.clone() };
+    let mut options = batch.options.clone();
+    if let Some(text) = caption {
+        options.caption = Some(Caption { text: text.to_string(), ..batch.caption_style.clone() });
+    }
+    write_code(data, &mut config, &mut options, batch.codeword_boundaries, batch.force)?;
+    if batch.verify {
+        verify_output(&config.output_filename, data)?;
+    }
+    if batch.metadata {
+        write_metadata(data, &config, &output.with_extension("json").to_string_lossy(), batch.force)?;
+    }
+    Ok(())
+}
+
+/// Read the payload for `--input`: the whole file, or stdin for `-`, byte for byte
+pub(super) fn read_input(path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if path == "-" {
+        let mut data = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path, e).into())
+    }
+}
+
+/// Read the written image back with the decoder and check that it holds `data`. Returns the
+/// version, ECC level and mask the decoder actually found.
+fn verify_output(path: &str, data: &[u8]) -> Result<String, Box<dyn std::error::Error>> {
+    let decoded = decode_image(path).map_err(|e| format!("verification failed: {} does not decode: {}", path, e))?;
+    let detected = format!(
+        "version {}, ECC {:?}, mask {}{}",
+        decoded.version as u8,
+        decoded.error_correction,
+        decoded.mask_pattern as u8,
+        if decoded.corrected { ", errors corrected" } else { "" }
+    );
+    if decoded.bytes != data {
+        return Err(format!("verification failed: {} decodes to {:?} ({})", path, String::from_utf8_lossy(&decoded.bytes), detected).into());
+    }
+    Ok(detected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_payloads_decode_to_the_same_bytes() {
+        let InlineBytes(hex) = parse_hex_payload("00:ff 7F10").unwrap();
+        let InlineBytes(base64) = parse_base64_payload("AP9/EA==\n").unwrap();
+        assert_eq!(hex, [0x00, 0xFF, 0x7F, 0x10]);
+        assert_eq!(base64, hex);
+        assert!(parse_hex_payload("0ff").is_err());
+        assert!(parse_hex_payload("0g").is_err());
+    }
+
+    #[test]
+    fn test_batch_rows_are_written_in_the_format_their_name_gives() {
+        assert!(matches!(batch_format(Path::new("b.svg"), OutputFormat::Png), OutputFormat::Svg));
+        assert!(matches!(batch_format(Path::new("c.PGM"), OutputFormat::Svg), OutputFormat::Pgm));
+        assert!(matches!(batch_format(Path::new("d.pbm"), OutputFormat::PbmPlain), OutputFormat::PbmPlain));
+        assert!(matches!(batch_format(Path::new("e.code"), OutputFormat::Pgm), OutputFormat::Pgm));
+
+        let dir = std::env::temp_dir().join(format!("qr-tools-batch-format-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rows = dir.join("rows.csv");
+        std::fs::write(&rows, "payload,filename\nfirst,a.png\nsecond,b.svg\n").unwrap();
+        let (config, options) = (QrConfig::default(), RenderOptions::default());
+        let batch = Batch { config: &config, options: &options, caption_style: Caption { text: String::new(), size: 16.0, position: CaptionPosition::Bottom, font_path: None }, codeword_boundaries: false, verify: false, metadata: false, force: false };
+        let out = dir.join("out");
+        generate_batch(&rows.to_string_lossy(), &out.to_string_lossy(), "{index}.png", 1, &batch).unwrap();
+        assert!(std::fs::read(out.join("a.png")).unwrap().starts_with(b"\x89PNG"));
+        assert!(String::from_utf8(std::fs::read(out.join("b.svg")).unwrap()).unwrap().contains("<svg"));
+
+        let verify = Batch { verify: true, force: true, ..batch };
+        assert!(generate_batch(&rows.to_string_lossy(), &out.to_string_lossy(), "{index}.png", 1, &verify).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_explain_masks_marks_the_lowest_and_applied_masks() {
+        let candidates = mask_candidates(b"explain", &QrConfig::default());
+        let lowest = lowest_penalty_mask(&candidates).unwrap();
+        let applied = MaskPattern::from_index((lowest as u8 + 1) % 8);
+        let report = explain_masks(&candidates, applied);
+        let rows: Vec<&str> = report.lines().filter(|line| line.starts_with("   ")).collect();
+        assert_eq!(rows.len(), 8);
+        assert!(rows[lowest as usize].ends_with(&format!("{}  lowest", candidates[lowest as usize].penalty.total)));
+        assert!(rows[applied as usize].ends_with("applied"));
+        assert!(report.ends_with(&format!("The rules select mask {}; pass -m {} to use it", lowest as u8, lowest as u8)));
+    }
+}
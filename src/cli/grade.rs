@@ -0,0 +1,94 @@
+use crate::decoder::Grade;
+use crate::grade::{assess, Measure, PrintQuality};
+use super::input::open_image;
+
+/// Grades are relative to the scan: reflectance is the grey level of the image, not a
+/// calibrated measurement, so scan with a fixed exposure to compare prints.
+/// Exits with status 1 if the image cannot be read or holds no symbol, and 2 if the overall
+/// grade is below --min-grade.
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// Scanned image of a printed QR code
+    image: String,
+    /// Output format
+    #[arg(long, value_enum, default_value = "text")]
+    report: Report,
+    /// Lowest acceptable overall grade (A, B, C, D); a symbol graded below it exits with 2
+    #[arg(long, value_name = "GRADE")]
+    min_grade: Option<Grade>,
+}
+
+/// Output format of the grades
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Report {
+    /// One line per parameter
+    Text,
+    /// Every measure with its grade
+    Json,
+}
+
+pub fn run(args: Args) -> i32 {
+    let quality = match open_image(&args.image, None).and_then(|image| assess(&image)) {
+        Ok(quality) => quality,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    match args.report {
+        Report::Text => print!("{}", text_report(&quality)),
+        Report::Json => println!("{}", serde_json::to_string_pretty(&quality).expect("grades serialize")),
+    }
+    match args.min_grade {
+        Some(minimum) if quality.overall < minimum => {
+            eprintln!("Error: grade {} is below the minimum of {}", quality.overall, minimum);
+            2
+        }
+        _ => 0,
+    }
+}
+
+fn text_report(quality: &PrintQuality) -> String {
+    let line = |name: &str, measure: Option<Measure>, value: &dyn Fn(f64) -> String| match measure {
+        Some(measure) => format!("{:<26}{}  {}\n", name, measure.grade, value(measure.value)),
+        None => format!("{:<26}F  not measured, the symbol does not decode\n", name),
+    };
+    let mut report = String::new();
+    report += &line("Symbol contrast", Some(quality.symbol_contrast), &|v| format!("{:.0}%", v));
+    report += &line("Modulation", quality.modulation, &|v| format!("{:.2} lowest", v));
+    report += &line("Axial non-uniformity", Some(quality.axial_nonuniformity), &|v| format!("{:.3}", v));
+    report += &line("Fixed pattern damage", Some(quality.fixed_pattern_damage), &|v| format!("{} modules wrong", v));
+    report += &line("Unused error correction", quality.unused_error_correction, &|v| format!("{:.0}%", v * 100.0));
+    report += &format!("{:<26}{}\n", "Decode", quality.decode);
+    report += &format!("{:<26}{}\n", "Overall", quality.overall);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_report_lists_every_parameter() {
+        let measure = |value, grade| Measure { value, grade };
+        let quality = PrintQuality {
+            symbol_contrast: measure(81.0, Grade::A),
+            modulation: None,
+            axial_nonuniformity: measure(0.0125, Grade::A),
+            fixed_pattern_damage: measure(3.0, Grade::C),
+            unused_error_correction: None,
+            decode: Grade::F,
+            overall: Grade::F,
+        };
+        assert_eq!(
+            text_report(&quality),
+            "Symbol contrast           A  81%\n\
+             Modulation                F  not measured, the symbol does not decode\n\
+             Axial non-uniformity      A  0.013\n\
+             Fixed pattern damage      C  3 modules wrong\n\
+             Unused error correction   F  not measured, the symbol does not decode\n\
+             Decode                    F\n\
+             Overall                   F\n"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Images given on the command line are read in any format the `image` crate supports, and
+//! SVG. The format is recognized from the file contents, then from the extension; `--format`
+//! settles the cases neither tells, such as an output name without an extension.
+
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use image::{DynamicImage, ImageFormat};
+use crate::svg;
+
+/// Image file formats for `--format`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FileFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Gif,
+    Tiff,
+}
+
+impl From<FileFormat> for ImageFormat {
+    fn from(format: FileFormat) -> Self {
+        match format {
+            FileFormat::Png => ImageFormat::Png,
+            FileFormat::Jpeg => ImageFormat::Jpeg,
+            FileFormat::Bmp => ImageFormat::Bmp,
+            FileFormat::Gif => ImageFormat::Gif,
+            FileFormat::Tiff => ImageFormat::Tiff,
+        }
+    }
+}
+
+/// Read an image as `format`, or in whatever format its contents or extension show. SVG
+/// documents are rasterized.
+pub fn open_image(path: impl AsRef<Path>, format: Option<FileFormat>) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let cannot_read = |e: &dyn std::fmt::Display| format!("cannot read {}: {}", path.display(), e);
+    let bytes = fs::read(path).map_err(|e| cannot_read(&e))?;
+    let svg_extension = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("svg"));
+    if format.is_none() && image::guess_format(&bytes).is_err() && (svg_extension || svg::is_svg(&bytes)) {
+        let text = std::str::from_utf8(&bytes).map_err(|e| cannot_read(&e))?;
+        return Ok(DynamicImage::ImageRgb8(svg::rasterize(text).map_err(|e| cannot_read(&e))?));
+    }
+
+    let mut reader = image::io::Reader::new(Cursor::new(bytes));
+    match format {
+        Some(format) => reader.set_format(format.into()),
+        None => {
+            reader = reader.with_guessed_format().map_err(|e| cannot_read(&e))?;
+            if reader.format().is_none() {
+                let format = ImageFormat::from_path(path).map_err(|_| format!("cannot tell the image format of {}; pass --format", path.display()))?;
+                reader.set_format(format);
+            }
+        }
+    }
+    Ok(reader.decode().map_err(|e| cannot_read(&e))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_every_format_whatever_the_name() {
+        let dir = std::env::temp_dir().join(format!("qr-tools-input-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image = DynamicImage::ImageRgb8(image::RgbImage::from_fn(8, 8, |x, y| image::Rgb(if (x + y) % 2 == 0 { [0; 3] } else { [255; 3] })));
+        for format in [FileFormat::Png, FileFormat::Jpeg, FileFormat::Bmp, FileFormat::Gif, FileFormat::Tiff] {
+            // No extension: the contents tell the format
+            let path = dir.join(format!("{:?}", format));
+            image.save_with_format(&path, format.into()).unwrap();
+            assert_eq!(open_image(&path, None).unwrap().width(), 8, "{:?}", format);
+            assert_eq!(open_image(&path, Some(format)).unwrap().width(), 8, "{:?}", format);
+        }
+        let mut svg = Vec::new();
+        crate::render::render_svg(&[vec![1]], &crate::render::RenderOptions::default(), &mut svg).unwrap();
+        std::fs::write(dir.join("code"), svg).unwrap();
+        assert!(open_image(dir.join("code"), None).unwrap().width() >= 512);
+        std::fs::write(dir.join("noise"), b"not an image").unwrap();
+        assert!(open_image(dir.join("noise"), None).unwrap_err().to_string().contains("pass --format"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! Command-line front end. Every tool is a subcommand of the `qr` binary; the older
+//! `qr-generator`, `qr-analyzer`, ... binaries parse the same arguments through [`run_alias`].
+
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::ffi::OsString;
+use std::process;
+
+use crate::types::ErrorCorrection;
+
+pub mod analyze;
+pub mod codewords;
+pub mod config;
+pub mod conformance;
+pub mod decode;
+pub mod diff;
+pub mod generate;
+pub mod grade;
+pub mod input;
+pub mod noise;
+pub mod output;
+pub mod payload;
+pub mod sheet;
+pub mod watch;
+
+/// QR code generation, analysis and testing tools
+#[derive(Debug, Parser)]
+#[command(name = "qr", version, propagate_version = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Generate a QR code from text
+    Generate(Box<generate::Args>),
+    /// Check a QR code image for structural and data errors
+    Analyze(analyze::Args),
+    /// Decode a QR code image and print its payload
+    Decode(decode::Args),
+    /// Grade the print quality of a scanned QR code, ISO 15415-style
+    Grade(grade::Args),
+    /// Compare the modules of two QR code images
+    Diff(diff::Args),
+    /// Dump the codewords of a payload at each stage of encoding, in hex and binary
+    Codewords(codewords::Args),
+    /// Add controlled noise and damage to a QR code image
+    Noise(noise::Args),
+    /// Tile QR codes from a CSV file onto printable pages
+    Sheet(sheet::Args),
+    /// Print a shell completion script for `qr`
+    Completions {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+/// Entry point of the `qr` binary
+pub fn run() -> ! {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let subcommand = args.get(1).and_then(|arg| arg.to_str());
+    // The analyzer documents its own exit code for bad arguments
+    let usage_code = if subcommand == Some("analyze") { analyze::EXIT_USAGE } else { 2 };
+    let mut command = Cli::command();
+    if subcommand == Some("generate") {
+        let generate = command.find_subcommand("generate").cloned().expect("generate subcommand");
+        let generate = config::with_defaults(generate, &args).unwrap_or_else(|e| exit_on_config_error(e, usage_code));
+        command = command.mut_subcommand("generate", |_| generate);
+    }
+    let cli = command
+        .try_get_matches_from(&args)
+        .and_then(|matches| Cli::from_arg_matches(&matches))
+        .unwrap_or_else(|error| exit_on_parse_error(error, usage_code));
+    let code = match cli.command {
+        Command::Generate(args) => generate::run(*args),
+        Command::Analyze(args) => analyze::run(args),
+        Command::Decode(args) => decode::run(args),
+        Command::Grade(args) => grade::run(args),
+        Command::Diff(args) => diff::run(args),
+        Command::Codewords(args) => codewords::run(args),
+        Command::Noise(args) => noise::run(args),
+        Command::Sheet(args) => sheet::run(args),
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "qr", &mut std::io::stdout());
+            0
+        }
+    };
+    process::exit(code)
+}
+
+/// Entry point of a single-tool binary such as `qr-decode`: the subcommand's arguments parsed
+/// under the binary's own name. `usage_code` is the exit status for invalid arguments.
+pub fn run_alias<A: clap::Args>(name: &'static str, about: &'static str, usage_code: i32, run: fn(A) -> i32) -> ! {
+    run_alias_with(name, about, usage_code, |command, _| Ok(command), run)
+}
+
+/// Adjusts a command before parsing, given the raw arguments
+pub type Configure = fn(clap::Command, &[OsString]) -> Result<clap::Command, Box<dyn std::error::Error>>;
+
+/// [`run_alias`] for a tool whose command line depends on more than its arguments, such as
+/// `qr-generator` reading defaults from [`config::with_defaults`]
+pub fn run_alias_with<A: clap::Args>(
+    name: &'static str,
+    about: &'static str,
+    usage_code: i32,
+    configure: Configure,
+    run: fn(A) -> i32,
+) -> ! {
+    let args: Vec<OsString> = std::env::args_os().collect();
+    let command = A::augment_args(clap::Command::new(name).about(about).version(env!("CARGO_PKG_VERSION")));
+    let command = configure(command, &args).unwrap_or_else(|e| exit_on_config_error(e, usage_code));
+    let args = command
+        .try_get_matches_from(&args)
+        .and_then(|matches| A::from_arg_matches(&matches))
+        .unwrap_or_else(|error| exit_on_parse_error(error, usage_code));
+    process::exit(run(args))
+}
+
+/// Print a parse error, or the help or version text clap reports the same way
+fn exit_on_parse_error(error: clap::Error, usage_code: i32) -> ! {
+    let code = if error.use_stderr() { usage_code } else { 0 };
+    let _ = error.print();
+    process::exit(code)
+}
+
+fn exit_on_config_error(error: Box<dyn std::error::Error>, usage_code: i32) -> ! {
+    eprintln!("Error: {}", error);
+    process::exit(usage_code)
+}
+
+/// Print `error` and return exit code 1, for subcommands whose only failure status is 1
+fn report(result: Result<(), Box<dyn std::error::Error>>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            1
+        }
+    }
+}
+
+/// Value parser for error correction levels, case-insensitive
+fn parse_error_correction(value: &str) -> Result<ErrorCorrection, String> {
+    value.parse()
+}
+
+/// Value parser for numbers that must be greater than zero
+fn parse_positive<T: std::str::FromStr + PartialOrd + Default>(value: &str) -> Result<T, String> {
+    match value.parse::<T>() {
+        Ok(v) if v > T::default() => Ok(v),
+        _ => Err("must be a positive number".to_string()),
+    }
+}
+
+/// Value parser for percentages from 0 to 100
+fn parse_percentage(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(p) if (0.0..=100.0).contains(&p) => Ok(p),
+        _ => Err("must be a number between 0 and 100".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli_definition_is_consistent() {
+        Cli::command().debug_assert();
+    }
+}
@@ -0,0 +1,811 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::{imageops, ImageFormat, Rgb, RgbImage};
+use std::collections::BTreeMap;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use crate::decoder::{locate_symbol, read_format_words, sample_matrix, SymbolGrid};
+use crate::format_info;
+use crate::function_modules::{module_regions, ModuleRegion};
+use crate::capacity::image_size_to_version;
+use crate::pixel_mapping::{get_data_ecc_positions, trace};
+use crate::capacity::get_total_codewords_in_bytes;
+use crate::types::{ErrorCorrection, Version};
+use serde::Serialize;
+use std::io::Write;
+use super::{parse_error_correction, parse_percentage, parse_positive};
+use super::input::{open_image, FileFormat};
+use super::output::{save_image_as, write_file};
+
+/// Which data/ECC modules are eligible for noise
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    /// Every data and ECC module, remainder bits included
+    All,
+    /// Modules of one Reed-Solomon block, numbered from 1 as in qr-analyzer
+    Block(usize),
+    EccOnly,
+    DataOnly,
+}
+
+/// How much to corrupt
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Amount {
+    /// Percentage of the eligible modules, each flipped on its own
+    Pixels(f64),
+    /// Number of whole codewords, all 8 modules of each flipped
+    Codewords(usize),
+    /// Percentage of the eligible codewords, flipped whole
+    CodewordPercentage(f64),
+}
+
+/// Structured damage as given on the command line, in image pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DamageSpec {
+    Occlude { x: u32, y: u32, width: u32, height: u32 },
+    Stain { cx: u32, cy: u32, radius: u32 },
+    /// A random line across the symbol
+    Scratch,
+    /// A random corner torn off
+    CornerTear,
+}
+
+/// Structured damage with its random geometry resolved, in image pixels
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Damage {
+    /// A sticker: white rectangle
+    Occlude { x: u32, y: u32, width: u32, height: u32 },
+    /// An ink blot: dark disc
+    Stain { cx: u32, cy: u32, radius: u32 },
+    /// Ink scraped off along a 1px line
+    Scratch { from: (u32, u32), to: (u32, u32) },
+    /// Paper missing from a right triangle with legs of `size` pixels
+    CornerTear { corner: Corner, size: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Damage {
+    fn covers(&self, x: u32, y: u32, img_size: u32) -> bool {
+        match *self {
+            Damage::Occlude { x: left, y: top, width, height } => {
+                (left..left.saturating_add(width)).contains(&x) && (top..top.saturating_add(height)).contains(&y)
+            }
+            Damage::Stain { cx, cy, radius } => {
+                let (dx, dy) = (x as i64 - cx as i64, y as i64 - cy as i64);
+                dx * dx + dy * dy <= radius as i64 * radius as i64
+            }
+            Damage::Scratch { from, to } => {
+                // Distance from the pixel centre to the segment, at most half a pixel
+                let (ax, ay, bx, by) = (from.0 as f64, from.1 as f64, to.0 as f64, to.1 as f64);
+                let (px, py) = (x as f64, y as f64);
+                let length_sq = (bx - ax).powi(2) + (by - ay).powi(2);
+                let t = if length_sq == 0.0 { 0.0 } else { (((px - ax) * (bx - ax) + (py - ay) * (by - ay)) / length_sq).clamp(0.0, 1.0) };
+                (px - ax - t * (bx - ax)).hypot(py - ay - t * (by - ay)) <= 0.5
+            }
+            Damage::CornerTear { corner, size } => {
+                let last = img_size - 1;
+                let (dx, dy) = match corner {
+                    Corner::TopLeft => (x, y),
+                    Corner::TopRight => (last - x, y),
+                    Corner::BottomLeft => (x, last - y),
+                    Corner::BottomRight => (last - x, last - y),
+                };
+                dx + dy < size
+            }
+        }
+    }
+
+    fn color(&self) -> Rgb<u8> {
+        match self {
+            Damage::Stain { .. } => Rgb([0, 0, 0]),
+            _ => Rgb([255, 255, 255]),
+        }
+    }
+}
+
+/// Resolve the random parts of a damage spec for a square image of `img_size` pixels
+fn resolve_damage(spec: DamageSpec, img_size: u32, rng: &mut impl Rng) -> Damage {
+    match spec {
+        DamageSpec::Occlude { x, y, width, height } => Damage::Occlude { x, y, width, height },
+        DamageSpec::Stain { cx, cy, radius } => Damage::Stain { cx, cy, radius },
+        DamageSpec::Scratch => {
+            // From one edge to the opposite one, so the line crosses the whole symbol
+            let (a, b) = (rng.gen_range(0..img_size), rng.gen_range(0..img_size));
+            let last = img_size - 1;
+            if rng.r#gen() {
+                Damage::Scratch { from: (a, 0), to: (b, last) }
+            } else {
+                Damage::Scratch { from: (0, a), to: (last, b) }
+            }
+        }
+        DamageSpec::CornerTear => {
+            let corner = [Corner::TopLeft, Corner::TopRight, Corner::BottomLeft, Corner::BottomRight][rng.gen_range(0..4)];
+            Damage::CornerTear { corner, size: rng.gen_range(img_size / 5..=img_size * 2 / 5).max(1) }
+        }
+    }
+}
+
+/// Parse a comma-separated list of exactly `N` unsigned integers
+fn parse_numbers<const N: usize>(spec: &str) -> Result<[u32; N], String> {
+    let numbers: Option<Vec<u32>> = spec.split(',').map(|n| n.trim().parse().ok()).collect();
+    numbers.and_then(|numbers| numbers.try_into().ok()).ok_or_else(|| format!("expected {} comma-separated pixel values", N))
+}
+
+/// Camera conditions applied after all damage, in the order a photo would pick them up:
+/// lighting, perspective, lens blur, then compression
+#[derive(Debug, Clone, Copy, Default)]
+struct Optics {
+    /// Remaining contrast in percent; 100 leaves the image unchanged, 0 makes it flat grey
+    contrast: Option<f64>,
+    /// Added to every channel, -255 to 255
+    brightness: Option<i32>,
+    /// Each corner moves inward by up to this fraction of the image size
+    skew: Option<f64>,
+    /// Gaussian blur sigma in pixels
+    blur: Option<f32>,
+    /// JPEG quality, 1-100
+    jpeg: Option<u8>,
+}
+
+impl Optics {
+    fn is_empty(&self) -> bool {
+        self.contrast.is_none() && self.brightness.is_none() && self.skew.is_none() && self.blur.is_none() && self.jpeg.is_none()
+    }
+}
+
+/// Corners of a quadrilateral in pixels: top-left, top-right, bottom-right, bottom-left
+type Quad = [(f64, f64); 4];
+
+/// Apply `optics` and return the skewed corners if a skew was applied
+fn apply_optics(img: RgbImage, optics: &Optics, rng: &mut impl Rng) -> Result<(RgbImage, Option<Quad>), Box<dyn std::error::Error>> {
+    let mut img = img;
+    if let Some(contrast) = optics.contrast {
+        for pixel in img.pixels_mut() {
+            for channel in pixel.0.iter_mut() {
+                *channel = (128.0 + (*channel as f64 - 128.0) * contrast / 100.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    if let Some(brightness) = optics.brightness {
+        img = imageops::brighten(&img, brightness);
+    }
+
+    let mut corners = None;
+    if let Some(skew) = optics.skew {
+        let size = img.width() as f64;
+        let mut inset = || rng.gen_range(0.0..=skew) * size;
+        let quad = [
+            (inset(), inset()),
+            (size - inset(), inset()),
+            (size - inset(), size - inset()),
+            (inset(), size - inset()),
+        ];
+        img = warp_into_quad(&img, quad);
+        corners = Some(quad);
+    }
+
+    if let Some(sigma) = optics.blur {
+        img = imageops::blur(&img, sigma);
+    }
+
+    if let Some(quality) = optics.jpeg {
+        let mut bytes = Vec::new();
+        JpegEncoder::new_with_quality(&mut bytes, quality).encode_image(&img)?;
+        img = image::load_from_memory_with_format(&bytes, ImageFormat::Jpeg)?.to_rgb8();
+    }
+    Ok((img, corners))
+}
+
+/// Project the whole image onto the quadrilateral `quad` (clockwise from top-left) with bilinear
+/// sampling; everything outside it becomes white background
+fn warp_into_quad(img: &RgbImage, quad: Quad) -> RgbImage {
+    let (width, height) = img.dimensions();
+    let inverse = invert_3x3(square_to_quad(quad));
+    RgbImage::from_fn(width, height, |x, y| {
+        let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+        let w = inverse[2][0] * px + inverse[2][1] * py + inverse[2][2];
+        let u = (inverse[0][0] * px + inverse[0][1] * py + inverse[0][2]) / w;
+        let v = (inverse[1][0] * px + inverse[1][1] * py + inverse[1][2]) / w;
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return Rgb([255, 255, 255]);
+        }
+        sample_bilinear(img, u * width as f64 - 0.5, v * height as f64 - 0.5)
+    })
+}
+
+/// Homography taking the unit square's corners (0,0), (1,0), (1,1), (0,1) to `quad`
+fn square_to_quad(quad: Quad) -> [[f64; 3]; 3] {
+    let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = quad;
+    let (dx1, dx2, dx3) = (x1 - x2, x3 - x2, x0 - x1 + x2 - x3);
+    let (dy1, dy2, dy3) = (y1 - y2, y3 - y2, y0 - y1 + y2 - y3);
+    let det = dx1 * dy2 - dx2 * dy1;
+    let g = (dx3 * dy2 - dx2 * dy3) / det;
+    let h = (dx1 * dy3 - dx3 * dy1) / det;
+    [
+        [x1 - x0 + g * x1, x3 - x0 + h * x3, x0],
+        [y1 - y0 + g * y1, y3 - y0 + h * y3, y0],
+        [g, h, 1.0],
+    ]
+}
+
+/// Inverse of a 3x3 matrix up to scale (the adjugate), which is all a homography needs
+fn invert_3x3(m: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    [
+        [cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2)],
+        [-cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2)],
+        [cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1)],
+    ]
+}
+
+fn sample_bilinear(img: &RgbImage, x: f64, y: f64) -> Rgb<u8> {
+    let max_x = img.width() as f64 - 1.0;
+    let max_y = img.height() as f64 - 1.0;
+    let (x, y) = (x.clamp(0.0, max_x), y.clamp(0.0, max_y));
+    let (x0, y0) = (x.floor(), y.floor());
+    let (x1, y1) = ((x0 + 1.0).min(max_x), (y0 + 1.0).min(max_y));
+    let (fx, fy) = (x - x0, y - y0);
+    let at = |x: f64, y: f64| img.get_pixel(x as u32, y as u32).0;
+    let (a, b, c, d) = (at(x0, y0), at(x1, y0), at(x0, y1), at(x1, y1));
+    Rgb(std::array::from_fn(|i| {
+        let top = a[i] as f64 * (1.0 - fx) + b[i] as f64 * fx;
+        let bottom = c[i] as f64 * (1.0 - fx) + d[i] as f64 * fx;
+        (top * (1.0 - fy) + bottom * fy).round() as u8
+    }))
+}
+
+struct NoiseOptions {
+    /// Random flips to make, if any, before the structured damage is painted
+    amount: Option<Amount>,
+    /// Regions whose modules may be flipped; `--target` narrows the data region
+    regions: Vec<ModuleRegion>,
+    damage: Vec<DamageSpec>,
+    optics: Optics,
+    /// Pixels per module and quiet zone in modules; detected from the image when absent
+    layout: Option<(u32, u32)>,
+    /// ChaCha8 seed; a random one is drawn when absent
+    seed: Option<u64>,
+    target: Target,
+    error_correction: Option<ErrorCorrection>,
+    /// Format of the output image when its extension does not name one
+    format: Option<FileFormat>,
+    /// Replace an existing output image
+    force: bool,
+}
+
+/// A flipped module, in module coordinates without the border
+#[derive(Debug, Serialize)]
+struct FlippedModule {
+    row: usize,
+    col: usize,
+}
+
+/// What `add_noise` did, written as the JSON sidecar
+#[derive(Debug, Serialize)]
+struct NoiseRecord {
+    input: String,
+    output: String,
+    /// Pass as --seed to reproduce the same pattern
+    seed: u64,
+    flipped: usize,
+    modules: Vec<FlippedModule>,
+    damage: Vec<Damage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skew_corners: Option<Quad>,
+}
+
+const FUNCTION_REGIONS: [ModuleRegion; 5] = [ModuleRegion::Finder, ModuleRegion::Timing, ModuleRegion::Alignment, ModuleRegion::Format, ModuleRegion::Version];
+
+fn parse_region(name: &str) -> Result<ModuleRegion, String> {
+    match name.trim() {
+        "data" => Ok(ModuleRegion::Data),
+        "finder" => Ok(ModuleRegion::Finder),
+        "timing" => Ok(ModuleRegion::Timing),
+        "alignment" => Ok(ModuleRegion::Alignment),
+        "format" => Ok(ModuleRegion::Format),
+        "version" => Ok(ModuleRegion::Version),
+        _ => Err("use data, finder, timing, alignment, format or version".to_string()),
+    }
+}
+
+fn parse_target(spec: &str) -> Result<Target, String> {
+    match spec {
+        "all" => Ok(Target::All),
+        "ecc-only" => Ok(Target::EccOnly),
+        "data-only" => Ok(Target::DataOnly),
+        _ => spec.strip_prefix("block:")
+            .and_then(|n| n.parse().ok())
+            .filter(|&n| n >= 1)
+            .map(Target::Block)
+            .ok_or_else(|| "use all, data-only, ecc-only or block:N".to_string()),
+    }
+}
+
+fn parse_byte_percentage(value: &str) -> Result<f64, String> {
+    parse_percentage(value).and_then(|p| if p > 0.0 { Ok(p) } else { Err("must be greater than 0".to_string()) })
+}
+
+fn parse_skew(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction < 0.5 => Ok(fraction),
+        _ => Err("must be a fraction of the image size between 0 and 0.5".to_string()),
+    }
+}
+
+/// Flips modules of a QR code image at random, paints structured damage over it and then
+/// simulates camera conditions. At least one of --percentage, --bytes, --byte-percentage or a
+/// damage or camera option is required.
+#[derive(Debug, clap::Args)]
+#[command(group(clap::ArgGroup::new("amount").args(["percentage", "bytes", "byte_percentage"])))]
+pub struct Args {
+    /// Input image: PNG, JPEG, BMP, GIF, TIFF or any other format the image crate reads
+    #[arg(short, long)]
+    input: String,
+    /// Output image, in the format its extension names
+    #[arg(short, long)]
+    output: String,
+    /// Format of the output image, for names without a known extension
+    #[arg(long, value_enum)]
+    format: Option<FileFormat>,
+    /// Percentage of eligible modules to flip (0-100)
+    #[arg(short, long, value_parser = parse_percentage)]
+    percentage: Option<f64>,
+    /// Number of whole codewords to corrupt (all 8 modules each)
+    #[arg(short, long, value_parser = parse_positive::<usize>)]
+    bytes: Option<usize>,
+    /// Percentage of codewords to corrupt whole (0-100)
+    #[arg(long, value_parser = parse_byte_percentage)]
+    byte_percentage: Option<f64>,
+    /// Seed for the noise pattern; the same seed and input always give the same noise
+    #[arg(short, long)]
+    seed: Option<u64>,
+    /// Same as --seed 0
+    #[arg(long, conflicts_with = "seed")]
+    deterministic: bool,
+    /// Data modules to corrupt: all, data-only, ecc-only, or block:N for the Nth Reed-Solomon block
+    #[arg(short, long, default_value = "all", value_parser = parse_target)]
+    target: Target,
+    /// Regions to corrupt, comma-separated: data, finder, timing, alignment, format, version
+    #[arg(short = 'c', long, value_delimiter = ',', default_value = "data", value_parser = parse_region)]
+    corrupt: Vec<ModuleRegion>,
+    /// Corrupt every region, same as --corrupt with all of them
+    #[arg(long, conflicts_with = "corrupt")]
+    include_function_modules: bool,
+    /// ECC level (L, M, Q, H) for --target; read from the format information when omitted
+    #[arg(short, long, value_parser = parse_error_correction)]
+    ecc: Option<ErrorCorrection>,
+    /// Pixels per module; detected from the image with --border when omitted
+    #[arg(long, requires = "border", value_parser = parse_positive::<u32>)]
+    module_size: Option<u32>,
+    /// Quiet zone in modules; detected from the image with --module-size when omitted
+    #[arg(long, requires = "module_size")]
+    border: Option<u32>,
+    /// White rectangle in image pixels, like a sticker over the label (repeatable)
+    #[arg(long, value_name = "X,Y,W,H", value_parser = parse_numbers::<4>, help_heading = "Structured damage")]
+    occlude: Vec<[u32; 4]>,
+    /// Dark disc in image pixels, like an ink blot (repeatable)
+    #[arg(long, value_name = "CX,CY,R", value_parser = parse_numbers::<3>, help_heading = "Structured damage")]
+    stain: Vec<[u32; 3]>,
+    /// White 1px line across the symbol at a random angle (repeatable)
+    #[arg(long, action = clap::ArgAction::Count, help_heading = "Structured damage")]
+    scratch: u8,
+    /// White triangle torn off a random corner (repeatable)
+    #[arg(long, action = clap::ArgAction::Count, help_heading = "Structured damage")]
+    corner_tear: u8,
+    /// Contrast left in percent, 100 = unchanged, 0 = flat grey
+    #[arg(long, value_parser = parse_percentage, help_heading = "Camera conditions")]
+    contrast: Option<f64>,
+    /// Add -255 to 255 to every channel
+    #[arg(long, allow_negative_numbers = true, value_parser = clap::value_parser!(i32).range(-255..=255), help_heading = "Camera conditions")]
+    brightness: Option<i32>,
+    /// Perspective: move each corner inward by up to this fraction of the image size (0-0.5)
+    #[arg(long, value_parser = parse_skew, help_heading = "Camera conditions")]
+    skew: Option<f64>,
+    /// Gaussian blur, sigma in pixels
+    #[arg(long, value_parser = parse_positive::<f32>, help_heading = "Camera conditions")]
+    blur: Option<f32>,
+    /// Round-trip through JPEG at quality 1-100
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100), help_heading = "Camera conditions")]
+    jpeg: Option<u8>,
+    /// Print the seed and the coordinates of every flipped module
+    #[arg(short, long)]
+    verbose: bool,
+    /// Write the seed and flipped modules as JSON
+    #[arg(long, value_name = "FILE")]
+    sidecar: Option<String>,
+    /// Overwrite existing output files
+    #[arg(long)]
+    force: bool,
+}
+
+pub fn run(args: Args) -> i32 {
+    let (input_file, output_file) = (args.input, args.output);
+    let amount = match (args.percentage, args.bytes, args.byte_percentage) {
+        (Some(p), _, _) if p > 0.0 => Some(Amount::Pixels(p)),
+        (_, Some(n), _) => Some(Amount::Codewords(n)),
+        (_, _, Some(p)) => Some(Amount::CodewordPercentage(p)),
+        _ => None,
+    };
+
+    // Shapes are painted by kind, each kind in the order given
+    let damage: Vec<DamageSpec> = args.occlude.iter().map(|&[x, y, width, height]| DamageSpec::Occlude { x, y, width, height })
+        .chain(args.stain.iter().map(|&[cx, cy, radius]| DamageSpec::Stain { cx, cy, radius }))
+        .chain((0..args.scratch).map(|_| DamageSpec::Scratch))
+        .chain((0..args.corner_tear).map(|_| DamageSpec::CornerTear))
+        .collect();
+    let optics = Optics { contrast: args.contrast, brightness: args.brightness, skew: args.skew, blur: args.blur, jpeg: args.jpeg };
+    if amount.is_none() && damage.is_empty() && optics.is_empty() {
+        eprintln!("Error: one of --percentage, --bytes, --byte-percentage or a damage or camera option is required");
+        return 2;
+    }
+
+    let regions = if args.include_function_modules {
+        std::iter::once(ModuleRegion::Data).chain(FUNCTION_REGIONS).collect()
+    } else {
+        args.corrupt
+    };
+    let options = NoiseOptions {
+        amount,
+        regions,
+        damage,
+        optics,
+        layout: args.module_size.zip(args.border),
+        seed: if args.deterministic { Some(0) } else { args.seed },
+        target: args.target,
+        error_correction: args.ecc,
+        format: args.format,
+        force: args.force,
+    };
+    let record = match add_noise(&input_file, &output_file, &options) {
+        Ok(record) => record,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return 1;
+        }
+    };
+    
+    match amount {
+        Some(Amount::Pixels(percentage)) => println!("Added {:.1}% noise to {} -> {}", percentage, input_file, output_file),
+        Some(_) => println!("Corrupted {} codewords ({} modules) in {} -> {}", record.flipped / 8, record.flipped, input_file, output_file),
+        None if !record.damage.is_empty() => println!("Applied {} damage shapes to {} -> {}", record.damage.len(), input_file, output_file),
+        None => println!("Simulated camera conditions on {} -> {}", input_file, output_file),
+    }
+    if args.verbose {
+        println!("Flipped {} modules (seed {}):", record.flipped, record.seed);
+        for module in &record.modules {
+            println!("  row {:3}, col {:3}", module.row, module.col);
+        }
+        for damage in &record.damage {
+            println!("  {:?}", damage);
+        }
+        if let Some(corners) = record.skew_corners {
+            let corners: Vec<String> = corners.iter().map(|(x, y)| format!("({:.1}, {:.1})", x, y)).collect();
+            println!("  Skewed corners: {}", corners.join(", "));
+        }
+    }
+    if let Some(sidecar) = args.sidecar {
+        let written = serde_json::to_string_pretty(&record).map_err(|e| e.to_string())
+            .and_then(|json| write_file(&sidecar, args.force, |file| Ok(writeln!(file, "{}", json)?)).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("Error: cannot write {}: {}", sidecar, e);
+            return 1;
+        }
+    }
+    0
+}
+
+/// Corrupt the image according to `options` and record which modules were flipped
+fn add_noise(input_file: &str, output_file: &str, options: &NoiseOptions) -> Result<NoiseRecord, Box<dyn std::error::Error>> {
+    let img = open_image(input_file, None)?;
+    let rgb_img = img.to_rgb8();
+    let (img_width, img_height) = rgb_img.dimensions();
+    if img_width != img_height {
+        return Err("image is not square".into());
+    }
+    
+    let grid = match options.layout {
+        Some(layout) => grid_from_layout(img_width, layout)?,
+        None => locate_symbol(&img.to_luma8())?,
+    };
+    let version = image_size_to_version(grid.modules).ok_or("Unsupported QR code size")?;
+    let matrix = sample_matrix(&img.to_luma8(), &grid);
+
+    // Randomly select modules to flip. ChaCha8 is portable and its output is stable across
+    // rand releases, unlike StdRng. Unseeded runs still go through it so the seed they drew can
+    // be reported.
+    let seed = options.seed.unwrap_or_else(|| thread_rng().r#gen());
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let selected: Vec<(usize, usize)> = match options.amount {
+        None => Vec::new(),
+        Some(amount) => {
+            let mut modules = if options.regions.contains(&ModuleRegion::Data) {
+                eligible_modules(&matrix, version, options.target, options.error_correction)?
+            } else {
+                Vec::new()
+            };
+            modules.extend(function_modules(version, &options.regions));
+            select_modules(&modules, amount, &mut rng)?
+        }
+    };
+    
+    // Flip selected modules, painting every pixel of the module the opposite of its sampled color
+    let mut output_img = rgb_img.clone();
+    for &(row, col) in &selected {
+        let color = if matrix[row][col] == 1 {
+            Rgb([255, 255, 255]) // Black to white
+        } else {
+            Rgb([0, 0, 0]) // White to black
+        };
+        let (xs, ys) = module_pixels(&grid, row, col);
+        for y in ys {
+            for x in xs.clone() {
+                output_img.put_pixel(x, y, color);
+            }
+        }
+    }
+    
+    // Paint structured damage over the noise, in the order given
+    let damage: Vec<Damage> = options.damage.iter().map(|&spec| resolve_damage(spec, img_width, &mut rng)).collect();
+    for shape in &damage {
+        for (x, y, pixel) in output_img.enumerate_pixels_mut() {
+            if shape.covers(x, y, img_width) {
+                *pixel = shape.color();
+            }
+        }
+    }
+    
+    let (output_img, skew_corners) = apply_optics(output_img, &options.optics, &mut rng)?;
+    
+    save_image_as(output_img, output_file, options.format, options.force)?;
+    Ok(NoiseRecord {
+        input: input_file.to_string(),
+        output: output_file.to_string(),
+        seed,
+        flipped: selected.len(),
+        modules: selected.into_iter().map(|(row, col)| FlippedModule { row, col }).collect(),
+        damage,
+        skew_corners,
+    })
+}
+
+/// Grid for a symbol drawn at `module_size` pixels per module inside a quiet zone of `border`
+/// modules on every side
+fn grid_from_layout(img_size: u32, (module_size, border): (u32, u32)) -> Result<SymbolGrid, Box<dyn std::error::Error>> {
+    let margin = border * module_size;
+    let symbol = img_size.checked_sub(2 * margin).filter(|symbol| symbol % module_size == 0)
+        .ok_or_else(|| format!("a {}px image does not fit {}px modules with a {}-module border", img_size, module_size, border))?;
+    Ok(SymbolGrid {
+        left: margin as f64,
+        top: margin as f64,
+        module_width: module_size as f64,
+        module_height: module_size as f64,
+        modules: (symbol / module_size) as usize,
+    })
+}
+
+/// Pixel columns and rows covered by a module
+fn module_pixels(grid: &SymbolGrid, row: usize, col: usize) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+    let edge = |origin: f64, pitch: f64, index: usize| (origin + index as f64 * pitch).round() as u32;
+    (
+        edge(grid.left, grid.module_width, col)..edge(grid.left, grid.module_width, col + 1),
+        edge(grid.top, grid.module_height, row)..edge(grid.top, grid.module_height, row + 1),
+    )
+}
+
+/// Modules of the selected function regions. They carry no codeword, so whole-codeword modes
+/// never pick them.
+fn function_modules(version: Version, regions: &[ModuleRegion]) -> Vec<Module> {
+    let mut modules = Vec::new();
+    for (row, line) in module_regions(version).into_iter().enumerate() {
+        for (col, region) in line.into_iter().enumerate() {
+            if region != ModuleRegion::Data && regions.contains(&region) {
+                modules.push((row, col, None));
+            }
+        }
+    }
+    modules
+}
+
+/// Pick the modules to flip from the eligible ones
+fn select_modules(modules: &[Module], amount: Amount, rng: &mut impl Rng) -> Result<Vec<(usize, usize)>, Box<dyn std::error::Error>> {
+    Ok(match amount {
+        Amount::Pixels(percentage) => {
+            let num_to_flip = ((modules.len() as f64 * percentage / 100.0).round() as usize).min(modules.len());
+            modules.choose_multiple(rng, num_to_flip).map(|&(row, col, _)| (row, col)).collect()
+        }
+        Amount::Codewords(_) | Amount::CodewordPercentage(_) => {
+            // Remainder bits belong to no codeword and are never picked here
+            let mut codewords: BTreeMap<usize, Vec<(usize, usize)>> = BTreeMap::new();
+            for &(row, col, codeword) in modules {
+                if let Some(codeword) = codeword {
+                    codewords.entry(codeword).or_default().push((row, col));
+                }
+            }
+            let available: Vec<&Vec<(usize, usize)>> = codewords.values().collect();
+            let count = match amount {
+                Amount::Codewords(n) => n,
+                Amount::CodewordPercentage(p) => (available.len() as f64 * p / 100.0).round() as usize,
+                Amount::Pixels(_) => unreachable!(),
+            };
+            if count > available.len() {
+                return Err(format!("cannot corrupt {} codewords, only {} are eligible", count, available.len()).into());
+            }
+            available.choose_multiple(rng, count).flat_map(|&positions| positions.iter().copied()).collect()
+        }
+    })
+}
+
+/// A data or ECC module as (row, col, codeword): the codeword's index in the interleaved
+/// stream, or `None` for remainder bits
+type Module = (usize, usize, Option<usize>);
+
+/// Data and ECC modules the target allows. Targets other than `All` need the ECC level, which
+/// is read from the format information unless given.
+fn eligible_modules(matrix: &[Vec<u8>], version: Version, target: Target, error_correction: Option<ErrorCorrection>) -> Result<Vec<Module>, Box<dyn std::error::Error>> {
+    if target == Target::All {
+        let total_codewords = get_total_codewords_in_bytes(version);
+        return Ok(get_data_ecc_positions(version)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (row, col))| (row, col, (i / 8 < total_codewords).then_some(i / 8)))
+            .collect());
+    }
+
+    let error_correction = match error_correction {
+        Some(level) => level,
+        None => read_format_words(matrix)
+            .into_iter()
+            .find_map(|word| format_info::decode(word).ok())
+            .map(|(level, _)| level)
+            .ok_or("format information is unreadable; pass --ecc")?,
+    };
+    let modules: Vec<Module> = trace(version, error_correction)
+        .into_iter()
+        .filter_map(|origin| {
+            let location = origin.codeword?;
+            let selected = match target {
+                Target::All => true,
+                Target::Block(n) => location.block + 1 == n,
+                Target::EccOnly => location.is_ecc,
+                Target::DataOnly => !location.is_ecc,
+            };
+            selected.then_some((origin.row, origin.col, Some(location.codeword)))
+        })
+        .collect();
+    if modules.is_empty() {
+        return Err(format!("--target selects no modules in this {:?}-{:?} symbol", version, error_correction).into());
+    }
+    Ok(modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::tests::reference_matrix;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Noise {
+        #[command(flatten)]
+        args: Args,
+    }
+
+    /// Run `qr noise` with `args` after the input, output and sidecar options
+    fn noise(input: &std::path::Path, output: &std::path::Path, sidecar: &std::path::Path, args: &[&str]) -> serde_json::Value {
+        let paths = [input, output, sidecar].map(|path| path.to_str().unwrap().to_string());
+        let options = ["-i", &paths[0], "-o", &paths[1], "--sidecar", &paths[2], "--force", "--module-size", "10", "--border", "4"];
+        let Noise { args } = Noise::try_parse_from(["qr-noise"].iter().chain(&options).chain(args)).unwrap();
+        assert_eq!(run(args), 0);
+        serde_json::from_str(&std::fs::read_to_string(sidecar).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_same_seed_and_input_give_identical_output() {
+        let dir = std::env::temp_dir().join(format!("qr-tools-noise-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let qr = qrcodegen::QrCode::encode_text("noise", qrcodegen::QrCodeEcc::Medium).unwrap();
+        let matrix = reference_matrix(&qr);
+        let input = dir.join("input.png");
+        crate::render::render_png(&matrix, &crate::render::RenderOptions::default(), std::fs::File::create(&input).unwrap()).unwrap();
+
+        // Every random choice: flipped modules, scratch and corner geometry, and skew
+        let options = ["-p", "10", "--scratch", "--corner-tear", "--skew", "0.1", "--blur", "1"];
+        let seeded = |name: &str, seed: &str| {
+            let output = dir.join(format!("{}.png", name));
+            let record = noise(&input, &output, &dir.join(format!("{}.json", name)), &[&options[..], &["--seed", seed]].concat());
+            (std::fs::read(output).unwrap(), record)
+        };
+        let (first, record) = seeded("first", "42");
+        let (second, _) = seeded("second", "42");
+        assert!(first == second);
+        assert_eq!(record["seed"], 42);
+        let (other, _) = seeded("other", "43");
+        assert!(first != other);
+
+        // An unseeded run records the seed it drew, which reproduces it
+        let record = noise(&input, &dir.join("drawn.png"), &dir.join("drawn.json"), &options);
+        let (again, _) = seeded("again", &record["seed"].to_string());
+        assert!(std::fs::read(dir.join("drawn.png")).unwrap() == again);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Pixels of a square image of `size` pixels that `damage` covers
+    fn covered(damage: Damage, size: u32) -> Vec<(u32, u32)> {
+        (0..size).flat_map(|y| (0..size).map(move |x| (x, y))).filter(|&(x, y)| damage.covers(x, y, size)).collect()
+    }
+
+    #[test]
+    fn test_seeded_damage_shapes() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut resolve = |spec| resolve_damage(spec, 100, &mut rng);
+
+        let occlusion = resolve(DamageSpec::Occlude { x: 2, y: 3, width: 4, height: 2 });
+        assert_eq!(covered(occlusion, 100), [(2, 3), (3, 3), (4, 3), (5, 3), (2, 4), (3, 4), (4, 4), (5, 4)]);
+        let stain = resolve(DamageSpec::Stain { cx: 50, cy: 50, radius: 2 });
+        assert_eq!(covered(stain, 100).len(), 13);
+        assert_eq!(stain.color(), Rgb([0, 0, 0]));
+
+        // The random shapes come out the same for the same seed
+        let scratches = [resolve(DamageSpec::Scratch), resolve(DamageSpec::Scratch)];
+        assert_eq!(scratches, [Damage::Scratch { from: (0, 14), to: (99, 15) }, Damage::Scratch { from: (0, 27), to: (99, 70) }]);
+        let pixels = covered(scratches[1], 100);
+        assert_eq!(pixels.len(), 108);
+        assert!(pixels.contains(&(0, 27)) && pixels.contains(&(99, 70)));
+        // Unbroken from edge to edge
+        assert!((0..100).all(|x| pixels.iter().any(|&(px, _)| px == x)));
+
+        let tears = [resolve(DamageSpec::CornerTear), resolve(DamageSpec::CornerTear)];
+        assert_eq!(tears, [Damage::CornerTear { corner: Corner::TopLeft, size: 32 }, Damage::CornerTear { corner: Corner::TopRight, size: 25 }]);
+        assert_eq!(covered(tears[0], 100).len(), 32 * 33 / 2);
+        let pixels = covered(tears[1], 100);
+        assert!(pixels.contains(&(99, 0)) && pixels.contains(&(75, 0)) && pixels.contains(&(99, 24)));
+        assert!(!pixels.contains(&(74, 0)) && !pixels.contains(&(99, 25)));
+        assert!(tears.iter().chain(&scratches).chain([&occlusion]).all(|damage| damage.color() == Rgb([255, 255, 255])));
+    }
+
+    #[test]
+    fn test_seeded_optics() {
+        // Black left half, white right half
+        let img = RgbImage::from_fn(40, 40, |x, _| if x < 20 { Rgb([0, 0, 0]) } else { Rgb([255, 255, 255]) });
+        let apply = |optics: Optics, seed| apply_optics(img.clone(), &optics, &mut ChaCha8Rng::seed_from_u64(seed)).unwrap();
+
+        let (faded, corners) = apply(Optics { contrast: Some(50.0), brightness: Some(10), ..Optics::default() }, 1);
+        assert_eq!((faded.get_pixel(0, 0).0, faded.get_pixel(39, 0).0, corners), ([74; 3], [202; 3], None));
+
+        let skew = Optics { skew: Some(0.2), ..Optics::default() };
+        let (skewed, corners) = apply(skew, 1);
+        let corners = corners.unwrap();
+        assert_eq!((skewed.clone(), Some(corners)), apply(skew, 1));
+        assert_ne!(Some(corners), apply(skew, 2).1);
+        for (i, (x, y)) in corners.into_iter().enumerate() {
+            let inset = |v: f64, far: bool| if far { 40.0 - v } else { v };
+            assert!((0.0..=8.0).contains(&inset(x, i == 1 || i == 2)) && (0.0..=8.0).contains(&inset(y, i >= 2)), "{:?}", corners);
+        }
+        // Outside the quadrilateral is background
+        assert_eq!(skewed.get_pixel(0, 0).0, [255; 3]);
+
+        // Blur softens the edge into greys and leaves the far sides alone
+        let (blurred, _) = apply(Optics { blur: Some(2.0), ..Optics::default() }, 1);
+        let row: Vec<u8> = (0..40).map(|x| blurred.get_pixel(x, 20)[0]).collect();
+        assert!(row[19] > 0 && row[20] < 255 && row.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!((row[0], row[39]), (0, 255));
+
+        // JPEG adds artifacts as the quality drops
+        let error = |quality| {
+            let (jpeg, _) = apply(Optics { jpeg: Some(quality), ..Optics::default() }, 1);
+            assert_eq!(jpeg.dimensions(), img.dimensions());
+            jpeg.pixels().zip(img.pixels()).map(|(a, b)| (a[0] as i32 - b[0] as i32).unsigned_abs()).sum::<u32>()
+        };
+        let (fine, coarse) = (error(95), error(5));
+        assert!(fine < coarse, "{} {}", fine, coarse);
+    }
+}
@@ -0,0 +1,159 @@
+//! Output files are written to a temporary file next to the destination and moved into place
+//! once complete, so an interrupted run never leaves a truncated image behind. Existing files
+//! are only replaced with `--force`; without it the file is published with a hard link, which
+//! fails rather than replace a file another process created in the meantime.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use image::{DynamicImage, ImageFormat};
+use super::input::FileFormat;
+
+/// Distinguishes temporary files of the same process, e.g. parallel batch workers
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A file that only appears at its path once [`commit`](AtomicFile::commit) is called
+pub struct AtomicFile {
+    writer: Option<BufWriter<File>>,
+    temp: PathBuf,
+    path: PathBuf,
+    force: bool,
+}
+
+impl AtomicFile {
+    /// Start writing `path`. Fails right away if it exists and `force` is not set.
+    pub fn create(path: impl AsRef<Path>, force: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref().to_path_buf();
+        if !force && path.exists() {
+            return Err(already_exists(&path));
+        }
+        let name = path.file_name().ok_or_else(|| format!("{} is not a file name", path.display()))?;
+        let temp = path.with_file_name(format!(
+            ".{}.{}-{}.tmp",
+            name.to_string_lossy(),
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = File::create(&temp).map_err(|e| format!("cannot create {}: {}", path.display(), e))?;
+        Ok(Self { writer: Some(BufWriter::new(file)), temp, path, force })
+    }
+
+    /// Flush everything to disk and move the file into place
+    pub fn commit(mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let file = self.writer.take().expect("not yet committed").into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        drop(file);
+        if self.force {
+            fs::rename(&self.temp, &self.path)?;
+            return Ok(());
+        }
+        // Unlike a rename, linking never replaces a file another process created while this
+        // one was writing
+        match fs::hard_link(&self.temp, &self.path) {
+            Ok(()) => fs::remove_file(&self.temp)?,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Err(already_exists(&self.path)),
+            // Filesystems without hard links, such as FAT, fall back to checking first
+            Err(_) => {
+                if self.path.exists() {
+                    return Err(already_exists(&self.path));
+                }
+                fs::rename(&self.temp, &self.path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn writer(&mut self) -> &mut BufWriter<File> {
+        self.writer.as_mut().expect("not yet committed")
+    }
+}
+
+fn already_exists(path: &Path) -> Box<dyn std::error::Error> {
+    format!("{} already exists; use --force to overwrite it", path.display()).into()
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+impl Seek for AtomicFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.writer().seek(pos)
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.temp);
+    }
+}
+
+/// Write `path` through an [`AtomicFile`]; nothing appears at `path` if `write` fails
+pub fn write_file(
+    path: impl AsRef<Path>,
+    force: bool,
+    write: impl FnOnce(&mut AtomicFile) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = AtomicFile::create(path, force)?;
+    write(&mut file)?;
+    file.commit()
+}
+
+/// Save `image` through an [`AtomicFile`] in the format its extension names
+pub fn save_image(image: impl Into<DynamicImage>, path: impl AsRef<Path>, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    save_image_as(image, path, None, force)
+}
+
+/// [`save_image`] in `format`, or the format the extension names when it is `None`
+pub fn save_image_as(image: impl Into<DynamicImage>, path: impl AsRef<Path>, format: Option<FileFormat>, force: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let format = match format {
+        Some(format) => format.into(),
+        None => ImageFormat::from_path(path).map_err(|_| format!("cannot tell the image format of {} from its extension; pass --format", path.display()))?,
+    };
+    let image = image.into();
+    write_file(path, force, |file| Ok(image.write_to(file, format)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_write_leaves_no_file() {
+        let dir = std::env::temp_dir().join(format!("qr-tools-output-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("code.png");
+
+        let failed = write_file(&path, false, |file| {
+            file.write_all(b"partial")?;
+            Err("render failed".into())
+        });
+        assert!(failed.is_err());
+        assert!(!path.exists());
+
+        write_file(&path, false, |file| Ok(file.write_all(b"first")?)).unwrap();
+        assert!(write_file(&path, false, |file| Ok(file.write_all(b"second")?)).is_err());
+        write_file(&path, true, |file| Ok(file.write_all(b"third")?)).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"third");
+
+        // A file that appears while the output is being written is kept
+        let raced = dir.join("raced.png");
+        let mut file = AtomicFile::create(&raced, false).unwrap();
+        file.write_all(b"ours").unwrap();
+        fs::write(&raced, b"theirs").unwrap();
+        assert!(file.commit().unwrap_err().to_string().contains("already exists"));
+        assert_eq!(fs::read(&raced).unwrap(), b"theirs");
+        fs::remove_file(&raced).unwrap();
+        // Only the finished file is left in the directory
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,179 @@
+//! Subcommands of `qr generate` that build a structured payload from options, in place of the
+//! text argument: `qr generate [OPTIONS] contact --name ...`
+
+use crate::payload::{geo, mailto, sms, tel, Contact, SepaTransfer, Totp, TotpAlgorithm, EPC_ERROR_CORRECTION};
+use crate::types::ErrorCorrection;
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Payload {
+    /// Contact card as a vCard 3.0, or a MeCard with --mecard
+    Contact(ContactArgs),
+    /// Email draft (mailto: link) with an optional subject and body
+    Email {
+        /// Recipient address
+        to: String,
+        #[arg(long)]
+        subject: Option<String>,
+        #[arg(long)]
+        body: Option<String>,
+    },
+    /// Phone number to call (tel: link)
+    Tel {
+        number: String,
+    },
+    /// Text message (sms: link), optionally with the message filled in
+    Sms {
+        number: String,
+        /// Message text
+        #[arg(long)]
+        body: Option<String>,
+    },
+    /// Location (geo: link) in decimal degrees
+    #[command(allow_negative_numbers = true)]
+    Geo {
+        latitude: f64,
+        longitude: f64,
+        /// Altitude in meters
+        #[arg(long)]
+        altitude: Option<f64>,
+    },
+    /// SEPA credit transfer in the EPC format read by European banking apps
+    Sepa(SepaArgs),
+    /// Time-based one-time password secret for authenticator apps (otpauth:// link)
+    Totp(TotpArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ContactArgs {
+    /// Full name; write "Family, Given" to split it explicitly
+    #[arg(long)]
+    name: String,
+    /// Phone number; may be repeated
+    #[arg(long)]
+    phone: Vec<String>,
+    /// Email address; may be repeated
+    #[arg(long)]
+    email: Vec<String>,
+    /// Company or organization
+    #[arg(long)]
+    org: Option<String>,
+    /// Job title (vCard only)
+    #[arg(long)]
+    title: Option<String>,
+    /// Website
+    #[arg(long)]
+    url: Option<String>,
+    /// Postal address on one line
+    #[arg(long)]
+    address: Option<String>,
+    /// Free-form note
+    #[arg(long)]
+    note: Option<String>,
+    /// Encode a MeCard, which is shorter than a vCard
+    #[arg(long)]
+    mecard: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SepaArgs {
+    /// Beneficiary account
+    #[arg(long)]
+    iban: String,
+    /// Beneficiary bank; optional within the EEA
+    #[arg(long)]
+    bic: Option<String>,
+    /// Beneficiary name, up to 70 characters
+    #[arg(long)]
+    name: String,
+    /// Amount in euros, such as 12.50; left for the payer to fill in if omitted
+    #[arg(long)]
+    amount: Option<String>,
+    /// ISO 11649 creditor reference (RF...)
+    #[arg(long, conflicts_with = "text")]
+    reference: Option<String>,
+    /// Free-form remittance text, up to 140 characters
+    #[arg(long)]
+    text: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TotpArgs {
+    /// Service the account belongs to
+    #[arg(long)]
+    issuer: String,
+    /// Account name, such as the user's email address
+    #[arg(long)]
+    account: String,
+    /// Shared secret in base32
+    #[arg(long)]
+    secret: String,
+    /// Password length
+    #[arg(long, default_value_t = 6)]
+    digits: u8,
+    /// Seconds each password is valid for
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    period: u32,
+    /// Hash function (sha1, sha256, sha512)
+    #[arg(long, default_value = "sha1", value_parser = parse_algorithm)]
+    algorithm: TotpAlgorithm,
+}
+
+fn parse_algorithm(value: &str) -> Result<TotpAlgorithm, String> {
+    match value.to_lowercase().as_str() {
+        "sha1" => Ok(TotpAlgorithm::Sha1),
+        "sha256" => Ok(TotpAlgorithm::Sha256),
+        "sha512" => Ok(TotpAlgorithm::Sha512),
+        _ => Err("use sha1, sha256 or sha512".to_string()),
+    }
+}
+
+impl Payload {
+    /// The error correction level the payload's format prescribes, if any
+    pub fn required_error_correction(&self) -> Option<ErrorCorrection> {
+        match self {
+            Payload::Sepa(_) => Some(EPC_ERROR_CORRECTION),
+            _ => None,
+        }
+    }
+
+    /// The text to encode
+    pub fn build(self) -> Result<String, String> {
+        match self {
+            Payload::Contact(args) => {
+                let contact = Contact {
+                    name: args.name,
+                    phones: args.phone,
+                    emails: args.email,
+                    organization: args.org,
+                    title: args.title,
+                    url: args.url,
+                    address: args.address,
+                    note: args.note,
+                };
+                if args.mecard { contact.to_mecard() } else { contact.to_vcard() }
+            }
+            Payload::Email { to, subject, body } => mailto(&to, subject.as_deref(), body.as_deref()),
+            Payload::Tel { number } => tel(&number),
+            Payload::Sms { number, body } => sms(&number, body.as_deref()),
+            Payload::Geo { latitude, longitude, altitude } => geo(latitude, longitude, altitude),
+            Payload::Sepa(args) => SepaTransfer {
+                iban: args.iban,
+                bic: args.bic,
+                name: args.name,
+                amount: args.amount,
+                reference: args.reference,
+                text: args.text,
+            }
+            .to_epc(),
+            Payload::Totp(args) => Totp {
+                issuer: args.issuer,
+                account: args.account,
+                secret: args.secret,
+                digits: args.digits,
+                period: args.period,
+                algorithm: args.algorithm,
+            }
+            .to_uri(),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::path::Path;
+use rayon::prelude::*;
+use crate::types::{QrConfig, ErrorCorrection};
+use crate::generator::try_generate_qr_matrix;
+use crate::sheet::{parse_items, render_page_pdf, render_page_png, PageSize, SheetLayout};
+use super::{parse_error_correction, parse_positive, report};
+use super::output::write_file;
+
+/// The CSV has the payload in the first column and an optional caption in the second.
+/// One file is written per page: sheet.pdf becomes sheet-1.pdf, sheet-2.pdf, ...
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// CSV file with one code per row
+    #[arg(short, long)]
+    input: String,
+    /// Output file; .pdf or .png selects the format
+    #[arg(short, long)]
+    output: String,
+    /// Paper size
+    #[arg(long, default_value = "a4", value_parser = parse_page)]
+    page: PageSize,
+    /// Rows per page
+    #[arg(long, default_value_t = 6, value_parser = parse_positive::<usize>)]
+    rows: usize,
+    /// Columns per page
+    #[arg(long, visible_alias = "columns", default_value_t = 4, value_parser = parse_positive::<usize>)]
+    cols: usize,
+    /// Page margin in millimetres
+    #[arg(long, default_value_t = 10.0, value_parser = parse_positive::<f64>)]
+    margin: f64,
+    /// Caption height in millimetres
+    #[arg(long, default_value_t = 4.0, value_parser = parse_positive::<f64>)]
+    caption_size: f64,
+    /// Resolution for PNG pages
+    #[arg(long, default_value_t = 300.0, value_parser = parse_positive::<f64>)]
+    dpi: f64,
+    /// Error correction level (L, M, Q, H)
+    #[arg(short, long, default_value = "M", value_parser = parse_error_correction)]
+    error_correction: ErrorCorrection,
+    /// Do not print captions
+    #[arg(long)]
+    no_captions: bool,
+    /// Overwrite existing page files
+    #[arg(long)]
+    force: bool,
+}
+
+fn parse_page(value: &str) -> Result<PageSize, String> {
+    match value.to_lowercase().as_str() {
+        "a4" => Ok(PageSize::A4),
+        "letter" => Ok(PageSize::Letter),
+        _ => Err("use a4 or letter".to_string()),
+    }
+}
+
+pub fn run(args: Args) -> i32 {
+    let layout = SheetLayout {
+        page: args.page,
+        rows: args.rows,
+        columns: args.cols,
+        margin_mm: args.margin,
+        caption_mm: args.caption_size,
+        ..SheetLayout::default()
+    };
+    let config = QrConfig { error_correction: args.error_correction, ..QrConfig::default() };
+    report(generate_sheets(&args.input, &args.output, &layout, &config, args.dpi, !args.no_captions, args.force))
+}
+
+fn generate_sheets(
+    input_file: &str,
+    output_file: &str,
+    layout: &SheetLayout,
+    config: &QrConfig,
+    dpi: f64,
+    captions: bool,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let items = parse_items(&std::fs::read_to_string(input_file)?);
+    if items.is_empty() {
+        return Err(format!("No rows found in {}", input_file).into());
+    }
+
+    let output = Path::new(output_file);
+    let extension = output.extension().and_then(|e| e.to_str()).unwrap_or("pdf").to_lowercase();
+    if extension != "pdf" && extension != "png" {
+        return Err("Output file must end in .pdf or .png".into());
+    }
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or("sheet");
+
+    for (page, chunk) in items.chunks(layout.codes_per_page()).enumerate() {
+        let codes: Vec<(Vec<Vec<u8>>, Option<String>)> = chunk
+            .par_iter()
+            .enumerate()
+            .map(|(i, item)| {
+                let caption = if captions { item.caption.clone() } else { None };
+                let number = page * layout.codes_per_page() + i + 1;
+                let matrix = try_generate_qr_matrix(item.payload.as_bytes(), config).map_err(|e| format!("code {}: {}", number, e))?;
+                Ok::<_, String>((matrix, caption))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let page_file = output.with_file_name(format!("{}-{}.{}", stem, page + 1, extension));
+        write_file(&page_file, force, |file| {
+            if extension == "pdf" {
+                render_page_pdf(&codes, layout, file)
+            } else {
+                render_page_png(&codes, layout, dpi, file)
+            }
+        })?;
+        println!("Page {} written: {}", page + 1, page_file.display());
+    }
+    Ok(())
+}
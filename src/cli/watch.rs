@@ -0,0 +1,56 @@
+//! `--watch` for the analysis tools: poll the input files and run again whenever one of them
+//! changes, so an encoder fix can be checked by regenerating the image and glancing at the
+//! terminal. Polling keeps this free of platform-specific notification APIs; a few checks a
+//! second is plenty for a file written by hand-run tools.
+
+use std::fs;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+/// How often the files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Modification time and length of a file; `None` while it does not exist, as when a tool
+/// replaces a file by deleting and recreating it
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamps(paths: &[&str]) -> Vec<Stamp> {
+    paths
+        .iter()
+        .map(|path| fs::metadata(path).ok().map(|meta| (meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len())))
+        .collect()
+}
+
+/// Call `run` now, then again each time one of `paths` changes, until the process is
+/// interrupted. A change is acted on once the files have stayed the same for a whole poll
+/// interval, so an image still being written is not read half-way.
+pub fn watch(paths: &[&str], mut run: impl FnMut()) -> ! {
+    let mut seen = stamps(paths);
+    run();
+    loop {
+        sleep(POLL_INTERVAL);
+        let mut current = stamps(paths);
+        if current == seen {
+            continue;
+        }
+        loop {
+            sleep(POLL_INTERVAL);
+            let settled = stamps(paths);
+            if settled == current {
+                break;
+            }
+            current = settled;
+        }
+        let mut changed: Vec<&str> = paths.iter().zip(current.iter().zip(&seen)).filter(|(_, (now, before))| now != before).map(|(path, _)| *path).collect();
+        changed.dedup();
+        seen = current;
+        println!();
+        println!("{} changed", changed.join(", "));
+        run();
+    }
+}
+
+/// "label: before -> after" when the two differ
+pub fn change<T: PartialEq>(label: &str, before: T, after: T, show: impl Fn(T) -> String) -> Option<String> {
+    (before != after).then(|| format!("{}: {} -> {}", label, show(before), show(after)))
+}
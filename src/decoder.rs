@@ -0,0 +1,1385 @@
+use std::path::Path;
+use image::{DynamicImage, GrayImage, ImageFormat};
+use crate::types::{Version, ErrorCorrection, MaskPattern};
+use crate::capacity::{get_block_layout, image_size_to_version};
+use crate::ecc::{correct_errors, correct_errors_with_erasures, CorrectionResult};
+use crate::format_info;
+use crate::version_info;
+use crate::function_modules::function_module_map;
+use crate::pixel_mapping::get_data_ecc_positions;
+use crate::detect::{detect_symbol, Detection};
+use crate::binarize::binarize;
+use crate::svg;
+
+/// Result of decoding a QR symbol
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    /// Payload as text. Byte segments are read as UTF-8, falling back to ISO-8859-1.
+    pub text: String,
+    /// Payload bytes exactly as stored in the symbol
+    pub bytes: Vec<u8>,
+    pub version: Version,
+    pub error_correction: ErrorCorrection,
+    pub mask_pattern: MaskPattern,
+    /// Whether Reed-Solomon correction had to repair at least one block
+    pub corrected: bool,
+    /// How the symbol was turned in the input before it was normalized
+    pub orientation: Orientation,
+    /// Whether the symbol was printed light-on-dark
+    pub inverted: bool,
+    /// Character set designator of the last ECI header, if the symbol has one
+    pub eci: Option<u32>,
+    /// Place of the symbol in a Structured Append sequence, if it is one of several
+    pub structured_append: Option<StructuredAppend>,
+    /// How cleanly the symbol read, for print quality checks
+    pub quality: Quality,
+}
+
+/// Structured Append header: the symbol holds one part of a payload split over up to 16
+/// symbols, to be joined in sequence order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StructuredAppend {
+    /// Position of the symbol in the sequence, from 0
+    pub index: u8,
+    /// Number of symbols in the sequence
+    pub total: u8,
+    /// XOR of all bytes of the whole payload, the same in every symbol of the sequence
+    pub parity: u8,
+}
+
+/// Symbol quality measured while decoding: how much of the error correction the symbol used
+/// up and how damaged its fixed patterns are. The grades follow the unused error correction,
+/// format information and fixed pattern damage grades of ISO/IEC 15415 and 18004 section
+/// 12, measured on the decoded modules rather than on a reflectance profile.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Quality {
+    /// Codewords repaired in each block, in percent of the block's codewords
+    pub corrected_per_block: Vec<f64>,
+    /// Share of the error correction capacity the worst block has left, from 0.0 to 1.0
+    pub unused_error_correction: f64,
+    /// Bits corrected in the format information copy that was used
+    pub format_bit_errors: u32,
+    /// Share of the timing pattern modules that alternate as they should, from 0.0 to 1.0
+    pub timing_regularity: f64,
+    /// The lowest grade of the three measures
+    pub grade: Grade,
+}
+
+/// Print quality grade, from A (best) to F (fail), as in ISO/IEC 15415
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+pub enum Grade {
+    F,
+    D,
+    C,
+    B,
+    A,
+}
+
+impl Grade {
+    /// Grade for unused error correction: A from 0.62, B from 0.50, C from 0.37, D from 0.25
+    pub fn for_unused_error_correction(unused: f64) -> Grade {
+        match unused {
+            u if u >= 0.62 => Grade::A,
+            u if u >= 0.50 => Grade::B,
+            u if u >= 0.37 => Grade::C,
+            u if u >= 0.25 => Grade::D,
+            _ => Grade::F,
+        }
+    }
+
+    /// Grade for the bits corrected in the format information: one grade lower per bit
+    pub fn for_format_bit_errors(errors: u32) -> Grade {
+        match errors {
+            0 => Grade::A,
+            1 => Grade::B,
+            2 => Grade::C,
+            3 => Grade::D,
+            _ => Grade::F,
+        }
+    }
+
+    /// Grade for timing pattern regularity: A when every module is right, then one grade lower
+    /// for each further 5% of modules wrong
+    pub fn for_timing_regularity(regularity: f64) -> Grade {
+        match 1.0 - regularity {
+            wrong if wrong <= 0.0 => Grade::A,
+            wrong if wrong <= 0.05 => Grade::B,
+            wrong if wrong <= 0.10 => Grade::C,
+            wrong if wrong <= 0.15 => Grade::D,
+            _ => Grade::F,
+        }
+    }
+}
+
+impl std::fmt::Display for Grade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl std::str::FromStr for Grade {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_uppercase().as_str() {
+            "A" => Ok(Grade::A),
+            "B" => Ok(Grade::B),
+            "C" => Ok(Grade::C),
+            "D" => Ok(Grade::D),
+            "F" => Ok(Grade::F),
+            _ => Err("grade must be A, B, C, D or F".to_string()),
+        }
+    }
+}
+
+/// Rotation and mirroring of a symbol relative to its upright form
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Orientation {
+    /// Clockwise rotation in degrees: 0, 90, 180 or 270
+    pub rotation: u16,
+    /// Whether the symbol was mirrored (its rows and columns swapped once upright)
+    pub mirrored: bool,
+}
+
+/// Decode the QR symbol in an image file. Axis-aligned synthetic images are read directly;
+/// if that fails, the symbol is located by its finder patterns and resampled through the
+/// estimated perspective, which handles photos and rotated scans. When neither works, the
+/// image is read again with inverted polarity for light-on-dark symbols.
+pub fn decode_image(path: impl AsRef<Path>) -> Result<Decoded, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    decode_pixels(&load_image(&bytes, ImageFormat::from_path(path).ok())?)
+}
+
+/// [`decode_image`] for the contents of an image file already in memory, such as an upload
+/// or a rendered symbol that never touched the disk
+pub fn decode_bytes(bytes: &[u8]) -> Result<Decoded, Box<dyn std::error::Error>> {
+    decode_pixels(&load_image(bytes, None)?)
+}
+
+/// Read the contents of an image file in any format the `image` crate recognizes from them,
+/// else as `format`, else as an SVG document, rasterized
+pub fn load_image(bytes: &[u8], format: Option<ImageFormat>) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    match (image::guess_format(bytes), format) {
+        (Ok(format), _) | (Err(_), Some(format)) => Ok(image::load_from_memory_with_format(bytes, format)?),
+        (Err(_), None) if svg::is_svg(bytes) => Ok(DynamicImage::ImageRgb8(svg::rasterize(std::str::from_utf8(bytes)?)?)),
+        (Err(_), None) => Err("unrecognized image format".into()),
+    }
+}
+
+/// [`decode_image`] for an image already decoded into pixels
+pub fn decode_pixels(image: &DynamicImage) -> Result<Decoded, Box<dyn std::error::Error>> {
+    read_pixels(image).1
+}
+
+/// The grid of module centers the decoder sampled the image on, and whether the modules it
+/// read decode. Plotted over a photo that fails to decode, it tells a grid that drifts off the
+/// modules from one that sits on them and reads damaged modules. Fails only when no symbol is
+/// found at all.
+pub fn sampling_grid(image: &DynamicImage) -> Result<SamplingGrid, Box<dyn std::error::Error>> {
+    match read_pixels(image) {
+        (Some(grid), result) => Ok(SamplingGrid { error: result.err().map(|e| e.to_string()), ..grid }),
+        (None, result) => Err(result.err().unwrap_or_else(|| "no sampling grid".into())),
+    }
+}
+
+/// Decode an image in either polarity, with the grid of the last attempt that got as far as
+/// sampling modules
+fn read_pixels(image: &DynamicImage) -> (Option<SamplingGrid>, Result<Decoded, Box<dyn std::error::Error>>) {
+    let luma = binarize(&image.to_luma8());
+
+    let (grid, result) = read_luma(&luma);
+    let Err(error) = result else {
+        return (grid, result);
+    };
+    let mut negative = luma;
+    image::imageops::invert(&mut negative);
+    match read_luma(&negative) {
+        (inverted_grid, Ok(decoded)) => (inverted_grid.map(|grid| SamplingGrid { inverted: true, ..grid }), Ok(Decoded { inverted: true, ..decoded })),
+        (_, Err(_)) => (grid, Err(error)),
+    }
+}
+
+/// Decode a binarized image of a dark-on-light symbol. Axis-aligned synthetic images are
+/// read directly; if that fails, the symbol is located by its finder patterns.
+fn read_luma(luma: &GrayImage) -> (Option<SamplingGrid>, Result<Decoded, Box<dyn std::error::Error>>) {
+    let (direct_grid, direct_error) = match locate_symbol(luma) {
+        Ok(located) => {
+            let grid = SamplingGrid::located(luma, &located);
+            match decode_matrix(&grid.sampled) {
+                Ok(decoded) => return (Some(grid), Ok(decoded)),
+                Err(error) => (Some(grid), error),
+            }
+        }
+        Err(error) => (None, error),
+    };
+    match detect_symbol(luma).and_then(|detection| refit_detection(luma, detection)) {
+        Ok(detection) => {
+            let grid = SamplingGrid::detected(luma, &detection);
+            let result = decode_matrix(&grid.sampled);
+            (Some(grid), result)
+        }
+        Err(_) => (direct_grid, Err(direct_error)),
+    }
+}
+
+/// Sample a detected symbol, refitting the grid when its version information names a
+/// different version than the finder spacing suggested
+pub fn sample_detection(luma: &GrayImage, detection: Detection) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    Ok(refit_detection(luma, detection)?.sample(luma))
+}
+
+/// The detection fitted to the version its version information names. Only neighbouring
+/// versions are considered: the spacing estimate is never far off, while a damaged version
+/// block can still land within correction distance of an unrelated version.
+fn refit_detection(luma: &GrayImage, detection: Detection) -> Result<Detection, Box<dyn std::error::Error>> {
+    let modules = detection.modules;
+    match read_version_info(&detection.sample(luma)) {
+        Some(version) if version.size() != modules && version.size().abs_diff(modules) <= 8 => detection.with_version(luma, version),
+        _ => Ok(detection),
+    }
+}
+
+/// Position and module pitch of a symbol within an image, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SymbolGrid {
+    pub left: f64,
+    pub top: f64,
+    pub module_width: f64,
+    pub module_height: f64,
+    /// Symbol width in modules, excluding the quiet zone
+    pub modules: usize,
+}
+
+impl SymbolGrid {
+    /// Pixel at the center of a module
+    pub fn module_center(&self, row: usize, col: usize) -> (u32, u32) {
+        (
+            (self.left + (col as f64 + 0.5) * self.module_width) as u32,
+            (self.top + (row as f64 + 0.5) * self.module_height) as u32,
+        )
+    }
+
+    /// Light margin around the symbol, in whole modules (the smallest of the four sides)
+    pub fn quiet_zone(&self, image_width: u32, image_height: u32) -> usize {
+        let right = image_width as f64 - (self.left + self.modules as f64 * self.module_width);
+        let bottom = image_height as f64 - (self.top + self.modules as f64 * self.module_height);
+        let margin = (self.left / self.module_width)
+            .min(self.top / self.module_height)
+            .min(right / self.module_width)
+            .min(bottom / self.module_height);
+        margin.max(0.0).round() as usize
+    }
+}
+
+/// How the decoder found the grid it sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GridMethod {
+    /// Dark bounding box of an upright symbol, divided into equal modules
+    BoundingBox,
+    /// Perspective transform fitted to the finder patterns
+    FinderPatterns,
+}
+
+/// Where the decoder sampled each module, in pixels of the input image
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SamplingGrid {
+    pub method: GridMethod,
+    /// Whether the modules were read as light on dark
+    pub inverted: bool,
+    /// Symbol width in modules
+    pub modules: usize,
+    /// Centers of the top-left, top-right and bottom-left finder patterns, when detected
+    pub finder_patterns: Vec<(f64, f64)>,
+    /// Center of the bottom-right alignment pattern, when one was found
+    pub alignment: Option<(f64, f64)>,
+    /// Center of every module, row by row, before the symbol is turned upright
+    pub centers: Vec<Vec<(f64, f64)>>,
+    /// Module read at each center, 1 = dark
+    pub sampled: Vec<Vec<u8>>,
+    /// Why the sampled modules did not decode, `None` when they did
+    pub error: Option<String>,
+}
+
+impl SamplingGrid {
+    fn located(luma: &GrayImage, grid: &SymbolGrid) -> Self {
+        // Sampling reads the pixel containing the center, as `sample_matrix` does
+        let centers = (0..grid.modules)
+            .map(|row| (0..grid.modules).map(|col| {
+                let (x, y) = grid.module_center(row, col);
+                (x as f64 + 0.5, y as f64 + 0.5)
+            }).collect())
+            .collect();
+        SamplingGrid {
+            method: GridMethod::BoundingBox,
+            inverted: false,
+            modules: grid.modules,
+            finder_patterns: Vec::new(),
+            alignment: None,
+            centers,
+            sampled: sample_matrix(luma, grid),
+            error: None,
+        }
+    }
+
+    fn detected(luma: &GrayImage, detection: &Detection) -> Self {
+        let centers = (0..detection.modules).map(|row| (0..detection.modules).map(|col| detection.module_center(row, col)).collect()).collect();
+        SamplingGrid {
+            method: GridMethod::FinderPatterns,
+            inverted: false,
+            modules: detection.modules,
+            finder_patterns: [detection.top_left, detection.top_right, detection.bottom_left].iter().map(|finder| (finder.x, finder.y)).collect(),
+            alignment: detection.alignment,
+            centers,
+            sampled: detection.sample(luma),
+            error: None,
+        }
+    }
+}
+
+/// Locate an axis-aligned symbol at any scale. The dark bounding box gives the symbol extent;
+/// a finder pattern in one of its corners, 7 modules across, gives the module size, which is then snapped
+/// so the symbol width is a valid version size. Widths under 19 modules snap to the Micro QR
+/// sizes, 11 to 17.
+pub fn locate_symbol(luma: &GrayImage) -> Result<SymbolGrid, Box<dyn std::error::Error>> {
+    let (width, height) = luma.dimensions();
+    let dark = |x: u32, y: u32| luma.get_pixel(x, y)[0] < 128;
+
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (width, height, 0, 0);
+    for y in 0..height {
+        for x in 0..width {
+            if dark(x, y) {
+                min_x = min_x.min(x);
+                min_y = min_y.min(y);
+                max_x = max_x.max(x);
+                max_y = max_y.max(y);
+            }
+        }
+    }
+    if min_x > max_x {
+        return Err("image contains no dark modules".into());
+    }
+    let extent_x = (max_x - min_x + 1) as f64;
+    let extent_y = (max_y - min_y + 1) as f64;
+
+    // Both outer edges of a finder pattern are 7 modules of solid dark from the corner. An
+    // upright QR symbol has finders in three corners; a Micro QR symbol has one, in any corner
+    // once turned. The corner with the longest pair of runs that agree gives the module size.
+    // If the finders are damaged, fall back to the shortest run of equal pixels, which is one
+    // module.
+    let run = |(mut x, mut y): (i64, i64), (dx, dy): (i64, i64)| {
+        let mut length = 0.0;
+        while (min_x as i64..=max_x as i64).contains(&x) && (min_y as i64..=max_y as i64).contains(&y) && dark(x as u32, y as u32) {
+            length += 1.0;
+            (x, y) = (x + dx, y + dy);
+        }
+        length
+    };
+    let finder_edge = [(min_x, min_y, 1, 1), (max_x, min_y, -1, 1), (min_x, max_y, 1, -1), (max_x, max_y, -1, -1)]
+        .into_iter()
+        .map(|(x, y, dx, dy)| (run((x as i64, y as i64), (dx, 0)), run((x as i64, y as i64), (0, dy))))
+        .filter(|&(horizontal, vertical): &(f64, f64)| (horizontal - vertical).abs() <= 0.2 * horizontal.max(vertical))
+        .map(|(horizontal, vertical)| (horizontal + vertical) / 2.0)
+        .fold(0.0, f64::max);
+    let candidates = [finder_edge / 7.0, shortest_run(luma, min_x, min_y, max_x, max_y) as f64];
+
+    if (extent_x / extent_y - 1.0).abs() > 0.1 {
+        return Err(format!("symbol is not square ({}x{} pixels)", extent_x, extent_y).into());
+    }
+    let mut estimated_modules = 0.0;
+    let mut modules = None;
+    for module_estimate in candidates.into_iter().filter(|&m| m > 0.0) {
+        estimated_modules = extent_x / module_estimate;
+        // Micro QR sizes are 2 modules apart, so they get half the tolerance
+        let (snapped, tolerance) = if estimated_modules < 19.0 {
+            let micro_index = ((estimated_modules - 11.0) / 2.0).round().clamp(0.0, 3.0);
+            (11 + 2 * micro_index as usize, 0.75)
+        } else {
+            let version_index = ((estimated_modules - 21.0) / 4.0).round().clamp(0.0, 39.0);
+            (21 + 4 * version_index as usize, 1.5)
+        };
+        if (estimated_modules - snapped as f64).abs() <= tolerance {
+            modules = Some(snapped);
+            break;
+        }
+    }
+    let modules = modules.ok_or_else(|| format!("symbol is about {:.1} modules wide, which is not a QR or Micro QR version size", estimated_modules))?;
+
+    Ok(SymbolGrid {
+        left: min_x as f64,
+        top: min_y as f64,
+        module_width: extent_x / modules as f64,
+        module_height: extent_y / modules as f64,
+        modules,
+    })
+}
+
+/// Length of the shortest run of same-colored pixels along any row inside the bounding box.
+/// Runs touching the box edge are skipped since they may be cut short.
+fn shortest_run(luma: &GrayImage, min_x: u32, min_y: u32, max_x: u32, max_y: u32) -> u32 {
+    let mut shortest = u32::MAX;
+    for y in min_y..=max_y {
+        let mut start = min_x;
+        for x in min_x + 1..=max_x + 1 {
+            let boundary = x > max_x || (luma.get_pixel(x, y)[0] < 128) != (luma.get_pixel(start, y)[0] < 128);
+            if boundary {
+                if start > min_x && x <= max_x {
+                    shortest = shortest.min(x - start);
+                }
+                start = x;
+            }
+        }
+    }
+    if shortest == u32::MAX { 0 } else { shortest }
+}
+
+/// Sample each module at its center
+pub fn sample_matrix(luma: &GrayImage, grid: &SymbolGrid) -> Vec<Vec<u8>> {
+    (0..grid.modules)
+        .map(|row| {
+            (0..grid.modules)
+                .map(|col| {
+                    let (x, y) = grid.module_center(row, col);
+                    (luma.get_pixel(x, y)[0] < 128) as u8
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Read the module matrix from an image of an upright symbol at any whole or fractional scale
+pub fn image_to_matrix(img: &DynamicImage) -> Result<Vec<Vec<u8>>, Box<dyn std::error::Error>> {
+    let luma = binarize(&img.to_luma8());
+    let grid = locate_symbol(&luma)?;
+    Ok(sample_matrix(&luma, &grid))
+}
+
+/// Finder modules that must match at a corner before it is trusted for orientation; a few
+/// damaged modules are tolerated, random data almost never gets this close.
+pub(crate) const MIN_FINDER_MATCH: usize = 40;
+
+/// Number of the 49 modules of a finder pattern that match at the given top-left position
+pub(crate) fn finder_match(matrix: &[Vec<u8>], top: usize, left: usize) -> usize {
+    let mut matches = 0;
+    for dy in 0..7 {
+        for dx in 0..7 {
+            let ring = dy.min(dx).min(6 - dy).min(6 - dx);
+            let expected = (ring != 1) as u8;
+            matches += (matrix[top + dy][left + dx] == expected) as usize;
+        }
+    }
+    matches
+}
+
+fn rotate_clockwise<T: Copy>(matrix: &[Vec<T>]) -> Vec<Vec<T>> {
+    let size = matrix.len();
+    (0..size).map(|row| (0..size).map(|col| matrix[size - 1 - col][row]).collect()).collect()
+}
+
+fn transpose<T: Copy>(matrix: &[Vec<T>]) -> Vec<Vec<T>> {
+    let size = matrix.len();
+    (0..size).map(|row| (0..size).map(|col| matrix[col][row]).collect()).collect()
+}
+
+/// Apply the turn that `normalize_orientation` reported to another map over the same modules
+pub fn orient<T: Copy>(map: &[Vec<T>], orientation: Orientation) -> Vec<Vec<T>> {
+    let mut upright = map.to_vec();
+    for _ in 0..(4 - orientation.rotation as usize / 90) % 4 {
+        upright = rotate_clockwise(&upright);
+    }
+    if orientation.mirrored { transpose(&upright) } else { upright }
+}
+
+/// Turn a square matrix upright. The corner without a finder pattern tells the rotation; a
+/// mirrored symbol looks upright after that, so it is recognized by its format information
+/// only reading correctly once transposed. When the finder patterns are too damaged to tell
+/// the corners apart, the matrix is returned unchanged.
+pub fn normalize_orientation(matrix: &[Vec<u8>]) -> (Vec<Vec<u8>>, Orientation) {
+    let size = matrix.len();
+    let mut orientation = Orientation::default();
+    if size < 21 || matrix.iter().any(|row| row.len() != size) {
+        return (matrix.to_vec(), orientation);
+    }
+
+    // Corners clockwise from top-left; the missing one is at bottom-right when upright
+    let far = size - 7;
+    let scores = [(0, 0), (0, far), (far, far), (far, 0)].map(|(top, left)| finder_match(matrix, top, left));
+    let missing = (0..4).min_by_key(|&i| scores[i]).unwrap_or(2);
+    let mut upright = matrix.to_vec();
+    if (0..4).filter(|&i| i != missing).all(|i| scores[i] >= MIN_FINDER_MATCH) {
+        // Rotating clockwise by 90 degrees moves the missing corner one step clockwise
+        let steps = (missing + 2) % 4;
+        orientation.rotation = steps as u16 * 90;
+        for _ in 0..(4 - steps) % 4 {
+            upright = rotate_clockwise(&upright);
+        }
+    }
+
+    if read_format_info(&upright).is_none() {
+        let transposed = transpose(&upright);
+        if read_format_info(&transposed).is_some() {
+            orientation.mirrored = true;
+            upright = transposed;
+        }
+    }
+    (upright, orientation)
+}
+
+/// Decode a module matrix (1 = dark) without quiet zone. Rotated and mirrored symbols are
+/// turned upright first.
+pub fn decode_matrix(matrix: &[Vec<u8>]) -> Result<Decoded, Box<dyn std::error::Error>> {
+    decode_matrix_with_erasures(matrix, &[])
+}
+
+/// Like `decode_matrix`, with modules known to be unreadable (occluded, glared, torn) marked
+/// `true` in `unreadable`, which is indexed like `matrix`. Every codeword touching such a
+/// module is corrected as an erasure, which costs half as much ECC as an unknown error. An
+/// empty `unreadable` marks nothing.
+pub fn decode_matrix_with_erasures(matrix: &[Vec<u8>], unreadable: &[Vec<bool>]) -> Result<Decoded, Box<dyn std::error::Error>> {
+    let size = matrix.len();
+    let version = image_size_to_version(size).ok_or_else(|| format!("no QR version is {} modules wide", size))?;
+    if matrix.iter().any(|row| row.len() != size) {
+        return Err("matrix is not square".into());
+    }
+    if !unreadable.is_empty() && (unreadable.len() != size || unreadable.iter().any(|row| row.len() != size)) {
+        return Err("erasure map does not match the matrix size".into());
+    }
+    let (matrix, orientation) = normalize_orientation(matrix);
+    let matrix = matrix.as_slice();
+
+    let (error_correction, mask, format_bit_errors) = closest_format_info(matrix).ok_or("format information is unreadable")?;
+    let mask_pattern = MaskPattern::from_index(mask);
+    let codewords = read_codewords(matrix, version, mask);
+    let erased = if unreadable.is_empty() { Vec::new() } else { erased_codewords(&orient(unreadable, orientation), version) };
+    let (data, repairs) = correct_blocks(&codewords, &erased, version, error_correction)?;
+    let Payload { bytes, text, eci, structured_append } = parse_segments(&data, version)?;
+
+    let corrected = repairs.iter().any(|repair| repair.errors > 0);
+    let quality = measure_quality(&repairs, format_bit_errors, timing_regularity(matrix));
+    Ok(Decoded { text, bytes, version, error_correction, mask_pattern, corrected, orientation, inverted: false, eci, structured_append, quality })
+}
+
+/// What Reed-Solomon correction did to one block
+struct BlockRepair {
+    codewords: usize,
+    ecc_codewords: usize,
+    /// Codewords repaired, erasures included
+    errors: usize,
+    /// Codewords flagged as erasures
+    erasures: usize,
+}
+
+fn measure_quality(repairs: &[BlockRepair], format_bit_errors: u32, timing_regularity: f64) -> Quality {
+    let corrected_per_block = repairs.iter().map(|repair| 100.0 * repair.errors as f64 / repair.codewords as f64).collect();
+    // An erasure costs one ECC codeword and an unknown error two
+    let unused_error_correction = repairs
+        .iter()
+        .map(|repair| {
+            let unknown = repair.errors.saturating_sub(repair.erasures);
+            1.0 - (repair.erasures + 2 * unknown) as f64 / repair.ecc_codewords as f64
+        })
+        .fold(1.0, f64::min)
+        .max(0.0);
+    let grade = Grade::for_unused_error_correction(unused_error_correction)
+        .min(Grade::for_format_bit_errors(format_bit_errors))
+        .min(Grade::for_timing_regularity(timing_regularity));
+    Quality { corrected_per_block, unused_error_correction, format_bit_errors, timing_regularity, grade }
+}
+
+/// Share of the modules of both timing patterns, between the finder separators, that match
+/// the alternating dark-light sequence starting dark
+fn timing_regularity(matrix: &[Vec<u8>]) -> f64 {
+    let size = matrix.len();
+    let expected = |i: usize| i.is_multiple_of(2) as u8;
+    let right = (8..size - 8).filter(|&i| matrix[6][i] == expected(i)).count() + (8..size - 8).filter(|&i| matrix[i][6] == expected(i)).count();
+    right as f64 / (2 * (size - 16)) as f64
+}
+
+/// Both format information words as read, bit 0 first along `format_info::positions`
+pub fn read_format_words(matrix: &[Vec<u8>]) -> [u16; 2] {
+    format_info::positions(matrix.len())
+        .map(|copy| copy.iter().enumerate().fold(0u16, |word, (i, &(row, col))| word | (matrix[row][col] as u16) << i))
+}
+
+/// Read both format information copies and return the (ECC level, mask) of the copy that needs
+/// the fewest corrections, if either is within the BCH(15,5) correction distance of 3 bits.
+fn read_format_info(matrix: &[Vec<u8>]) -> Option<(ErrorCorrection, u8)> {
+    closest_format_info(matrix).map(|(ecc, mask, _)| (ecc, mask))
+}
+
+/// [`read_format_info`] with the number of bits corrected in the copy used
+fn closest_format_info(matrix: &[Vec<u8>]) -> Option<(ErrorCorrection, u8, u32)> {
+    read_format_words(matrix)
+        .into_iter()
+        .filter_map(|word| format_info::decode(word).ok().map(|(ecc, mask)| (ecc, mask, format_info::distance(word, ecc, mask))))
+        .min_by_key(|&(_, _, distance)| distance)
+}
+
+/// Number of error-free Reed-Solomon blocks when the codewords are read with this ECC level
+/// and mask, or `None` if any block is beyond correction
+pub fn error_free_blocks(matrix: &[Vec<u8>], version: Version, error_correction: ErrorCorrection, mask: u8) -> Option<usize> {
+    let layout = get_block_layout(version, error_correction);
+    let blocks = layout.deinterleave(&read_codewords(matrix, version, mask))?;
+    let mut error_free = 0;
+    for block in &blocks {
+        match correct_errors(block, layout.ecc_per_block) {
+            CorrectionResult::ErrorFree(_) => error_free += 1,
+            CorrectionResult::Corrected { .. } => {}
+            CorrectionResult::Uncorrectable => return None,
+        }
+    }
+    Some(error_free)
+}
+
+/// The same symbol under another mask: data modules are unmasked with `from_mask` and masked
+/// again with `to_mask`, and both format information copies are rewritten to match
+pub fn remask(matrix: &[Vec<u8>], version: Version, error_correction: ErrorCorrection, from_mask: u8, to_mask: u8) -> Vec<Vec<u8>> {
+    let reserved = function_module_map(version);
+    let mut remasked = matrix.to_vec();
+    for (row, modules) in remasked.iter_mut().enumerate() {
+        for (col, module) in modules.iter_mut().enumerate() {
+            if !reserved[row][col] {
+                *module ^= (mask_bit(from_mask, row, col) ^ mask_bit(to_mask, row, col)) as u8;
+            }
+        }
+    }
+    let format = format_info::encode(error_correction, to_mask);
+    for copy in format_info::positions(matrix.len()) {
+        for (i, (row, col)) in copy.into_iter().enumerate() {
+            remasked[row][col] = (format >> i & 1) as u8;
+        }
+    }
+    remasked
+}
+
+/// Recover the ECC level and mask of a symbol whose format information is beyond repair by
+/// trying all 32 combinations. A combination counts only if every Reed-Solomon block of the
+/// resulting codewords is valid or correctable; among those, the one with the most error-free
+/// blocks wins, since a wrong mask or level leaves syndromes that almost never check out.
+pub fn recover_format_info(matrix: &[Vec<u8>], version: Version) -> Option<(ErrorCorrection, u8)> {
+    [ErrorCorrection::L, ErrorCorrection::M, ErrorCorrection::Q, ErrorCorrection::H]
+        .into_iter()
+        .flat_map(|error_correction| (0..8).map(move |mask| (error_correction, mask)))
+        .filter_map(|(error_correction, mask)| {
+            error_free_blocks(matrix, version, error_correction, mask).map(|count| (error_correction, mask, count))
+        })
+        .max_by_key(|&(_, _, count)| count)
+        .map(|(error_correction, mask, _)| (error_correction, mask))
+}
+
+/// Both raw version information words of a symbol of version 7 or up: the one above the
+/// bottom-left finder, then the one left of the top-right finder, bit 0 first along
+/// `version_info::positions`
+pub fn read_version_words(matrix: &[Vec<u8>]) -> (u32, u32) {
+    let [bottom_left, top_right] = version_info::positions(matrix.len())
+        .map(|copy| copy.iter().enumerate().fold(0u32, |word, (i, &(row, col))| word | (matrix[row][col] as u32) << i));
+    (bottom_left, top_right)
+}
+
+/// Version encoded in the version information of a sampled symbol, from whichever copy is
+/// closer to a valid word. Symbols narrower than version 7 carry none.
+pub fn read_version_info(matrix: &[Vec<u8>]) -> Option<Version> {
+    if matrix.len() < Version::V7.size() {
+        return None;
+    }
+    let (bottom_left, top_right) = read_version_words(matrix);
+    [version_info::decode(bottom_left), version_info::decode(top_right)]
+        .into_iter()
+        .flatten()
+        .min_by_key(|&(_, distance)| distance)
+        .map(|(version, _)| version)
+}
+
+/// Whether mask pattern `mask` flips the module at (`row`, `col`)
+pub fn mask_bit(mask: u8, row: usize, col: usize) -> bool {
+    let (x, y) = (col, row);
+    match mask {
+        0 => (x + y) % 2 == 0,
+        1 => y % 2 == 0,
+        2 => x % 3 == 0,
+        3 => (x + y) % 3 == 0,
+        4 => (x / 3 + y / 2) % 2 == 0,
+        5 => x * y % 2 + x * y % 3 == 0,
+        6 => (x * y % 2 + x * y % 3) % 2 == 0,
+        _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+    }
+}
+
+/// Read the unmasked codeword stream along the zigzag placement path. Remainder bits that do
+/// not fill a whole codeword are dropped.
+fn read_codewords(matrix: &[Vec<u8>], version: Version, mask: u8) -> Vec<u8> {
+    let bits: Vec<u8> = get_data_ecc_positions(version)
+        .into_iter()
+        .map(|(row, col)| matrix[row][col] ^ mask_bit(mask, row, col) as u8)
+        .collect();
+
+    bits.chunks_exact(8)
+        .map(|byte| byte.iter().fold(0u8, |acc, &b| (acc << 1) | b))
+        .collect()
+}
+
+/// Flag every codeword of the stream that has at least one module marked in `unreadable`
+fn erased_codewords(unreadable: &[Vec<bool>], version: Version) -> Vec<bool> {
+    let path = get_data_ecc_positions(version);
+    path.chunks_exact(8)
+        .map(|modules| modules.iter().any(|&(row, col)| unreadable[row][col]))
+        .collect()
+}
+
+/// De-interleave the codeword stream into Reed-Solomon blocks, correct each block and return
+/// the data codewords in message order, plus what correction did to each block. Codewords
+/// flagged in `erased` are handed to the Reed-Solomon decoder as erasures; an empty slice
+/// means none are.
+fn correct_blocks(codewords: &[u8], erased: &[bool], version: Version, error_correction: ErrorCorrection) -> Result<(Vec<u8>, Vec<BlockRepair>), Box<dyn std::error::Error>> {
+    let layout = get_block_layout(version, error_correction);
+    let blocks = layout.deinterleave(codewords).ok_or("symbol holds fewer codewords than its version requires")?;
+    let flags: Vec<u8> = (0..codewords.len()).map(|i| erased.get(i).copied().unwrap_or(false) as u8).collect();
+    let block_flags = layout.deinterleave(&flags).ok_or("symbol holds fewer codewords than its version requires")?;
+
+    let mut data = Vec::new();
+    let mut repairs = Vec::with_capacity(blocks.len());
+    for (index, (block, flags)) in blocks.iter().zip(&block_flags).enumerate() {
+        let erasures: Vec<usize> = flags.iter().enumerate().filter(|&(_, &f)| f == 1).map(|(i, _)| i).collect();
+        let errors = match correct_errors_with_erasures(block, layout.ecc_per_block, &erasures) {
+            CorrectionResult::ErrorFree(block_data) => {
+                data.extend(block_data);
+                0
+            }
+            CorrectionResult::Corrected { data: block_data, error_positions, .. } => {
+                data.extend(block_data);
+                error_positions.len()
+            }
+            CorrectionResult::Uncorrectable => {
+                return Err(format!("block {} has too many errors to correct", index + 1).into());
+            }
+        };
+        repairs.push(BlockRepair { codewords: block.len(), ecc_codewords: layout.ecc_per_block, errors, erasures: erasures.len() });
+    }
+    Ok((data, repairs))
+}
+
+/// Sequential reader over the bits of the data codewords
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+
+    fn read(&mut self, count: usize) -> Option<u32> {
+        if count > self.remaining() {
+            return None;
+        }
+        let mut value = 0u32;
+        for _ in 0..count {
+            let bit = (self.data[self.position / 8] >> (7 - self.position % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+const ALPHANUMERIC_CHARSET: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Segment mode indicator at the start of each segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SegmentMode {
+    Numeric,
+    Alphanumeric,
+    Byte,
+    Kanji,
+    /// Extended Channel Interpretation header selecting the character set of what follows
+    Eci,
+    /// Header placing the symbol in a sequence of several
+    StructuredAppend,
+}
+
+impl SegmentMode {
+    /// Mode for a 4-bit indicator; `None` for the terminator and unsupported modes
+    pub fn from_bits(bits: u32) -> Option<SegmentMode> {
+        match bits {
+            0b0001 => Some(SegmentMode::Numeric),
+            0b0010 => Some(SegmentMode::Alphanumeric),
+            0b0011 => Some(SegmentMode::StructuredAppend),
+            0b0100 => Some(SegmentMode::Byte),
+            0b0111 => Some(SegmentMode::Eci),
+            0b1000 => Some(SegmentMode::Kanji),
+            _ => None,
+        }
+    }
+
+    /// Width of the character count field in the given version (ECI and Structured Append
+    /// headers have none)
+    pub fn char_count_bits(&self, version: Version) -> usize {
+        let range = match version as u8 {
+            1..=9 => 0,
+            10..=26 => 1,
+            _ => 2,
+        };
+        match self {
+            SegmentMode::Numeric => [10, 12, 14][range],
+            SegmentMode::Alphanumeric => [9, 11, 13][range],
+            SegmentMode::Byte => [8, 16, 16][range],
+            SegmentMode::Kanji => [8, 10, 12][range],
+            SegmentMode::Eci | SegmentMode::StructuredAppend => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for SegmentMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SegmentMode::Numeric => "Numeric",
+            SegmentMode::Alphanumeric => "Alphanumeric",
+            SegmentMode::Byte => "Byte",
+            SegmentMode::Kanji => "Kanji",
+            SegmentMode::Eci => "ECI",
+            SegmentMode::StructuredAppend => "Structured Append",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Read an ECI designator: 1, 2 or 3 bytes, the number of leading one bits in the first byte
+/// telling how many bytes follow. `read(n)` returns the next `n` bits.
+pub fn read_eci_designator(mut read: impl FnMut(usize) -> Option<u32>) -> Option<u32> {
+    let first = read(8)?;
+    if first & 0x80 == 0 {
+        Some(first)
+    } else if first & 0xC0 == 0x80 {
+        Some(((first & 0x3F) << 8) | read(8)?)
+    } else if first & 0xE0 == 0xC0 {
+        Some(((first & 0x1F) << 16) | read(16)?)
+    } else {
+        None
+    }
+}
+
+/// Read the rest of a Structured Append header: the symbol's position, the number of symbols
+/// less one, 4 bits each, and the parity byte. `read(n)` returns the next `n` bits.
+pub fn read_structured_append(mut read: impl FnMut(usize) -> Option<u32>) -> Option<StructuredAppend> {
+    let index = read(4)? as u8;
+    let total = read(4)? as u8 + 1;
+    let parity = read(8)? as u8;
+    Some(StructuredAppend { index, total, parity })
+}
+
+/// The symbols of one Structured Append sequence, given in any order, in sequence order.
+/// Every symbol must carry a header with the same total and parity, every position must be
+/// there exactly once, and the parity must match the joined payload bytes.
+pub fn join_structured_append(symbols: &[Decoded]) -> Result<Vec<&Decoded>, Box<dyn std::error::Error>> {
+    let mut headers = Vec::with_capacity(symbols.len());
+    for (i, symbol) in symbols.iter().enumerate() {
+        headers.push(symbol.structured_append.ok_or_else(|| format!("symbol {} has no Structured Append header", i + 1))?);
+    }
+    let Some(&first) = headers.first() else {
+        return Err("no symbols to join".into());
+    };
+    if let Some(other) = headers.iter().find(|header| (header.total, header.parity) != (first.total, first.parity)) {
+        return Err(format!(
+            "the symbols belong to different sequences: {} symbols with parity {:#04x} and {} with parity {:#04x}",
+            first.total, first.parity, other.total, other.parity
+        )
+        .into());
+    }
+
+    let mut ordered: Vec<Option<&Decoded>> = vec![None; first.total as usize];
+    for (symbol, header) in symbols.iter().zip(&headers) {
+        let slot = ordered.get_mut(header.index as usize).ok_or_else(|| format!("symbol {} of a sequence of {} cannot exist", header.index + 1, first.total))?;
+        if slot.replace(symbol).is_some() {
+            return Err(format!("symbol {} of {} is given twice", header.index + 1, first.total).into());
+        }
+    }
+    let missing: Vec<String> = ordered.iter().enumerate().filter(|(_, symbol)| symbol.is_none()).map(|(i, _)| (i + 1).to_string()).collect();
+    if !missing.is_empty() {
+        return Err(format!("symbols {} of {} are missing", missing.join(", "), first.total).into());
+    }
+    let ordered: Vec<&Decoded> = ordered.into_iter().flatten().collect();
+
+    let parity = ordered.iter().flat_map(|symbol| &symbol.bytes).fold(0, |parity, byte| parity ^ byte);
+    if parity != first.parity {
+        return Err(format!("parity check failed: the sequence gives {:#04x}, the joined payload {:#04x}", first.parity, parity).into());
+    }
+    Ok(ordered)
+}
+
+/// Shift JIS bytes of a 13-bit Kanji mode character
+pub fn kanji_to_shift_jis(value: u16) -> [u8; 2] {
+    let packed = ((value / 0xC0) << 8) | (value % 0xC0);
+    let code = if packed < 0x1F00 { packed + 0x8140 } else { packed + 0xC140 };
+    code.to_be_bytes()
+}
+
+/// Character set registered for an ECI designator
+fn eci_encoding(eci: u32) -> Option<&'static encoding_rs::Encoding> {
+    use encoding_rs::*;
+    Some(match eci {
+        4 => ISO_8859_2,
+        5 => ISO_8859_3,
+        6 => ISO_8859_4,
+        7 => ISO_8859_5,
+        8 => ISO_8859_6,
+        9 => ISO_8859_7,
+        10 => ISO_8859_8,
+        11 => WINDOWS_1254, // superset of ISO-8859-9
+        12 => ISO_8859_10,
+        13 => WINDOWS_874, // superset of ISO-8859-11
+        15 => ISO_8859_13,
+        16 => ISO_8859_14,
+        17 => ISO_8859_15,
+        18 => ISO_8859_16,
+        20 => SHIFT_JIS,
+        21 => WINDOWS_1250,
+        22 => WINDOWS_1251,
+        23 => WINDOWS_1252,
+        24 => WINDOWS_1256,
+        25 => UTF_16BE,
+        26 => UTF_8,
+        28 => BIG5,
+        29 => GB18030,
+        30 => EUC_KR,
+        _ => return None,
+    })
+}
+
+/// Text of a byte segment under the character set selected by `eci`. Without an ECI (or with
+/// one this decoder does not know) the bytes are read as UTF-8, falling back to ISO-8859-1.
+pub fn decode_byte_text(bytes: &[u8], eci: Option<u32>) -> String {
+    match eci {
+        Some(1 | 3 | 27 | 170) => bytes.iter().map(|&b| b as char).collect(),
+        Some(eci) if eci_encoding(eci).is_some() => eci_encoding(eci).unwrap().decode_without_bom_handling(bytes).0.into_owned(),
+        _ => match std::str::from_utf8(bytes) {
+            Ok(text) => text.to_string(),
+            Err(_) => bytes.iter().map(|&b| b as char).collect(),
+        },
+    }
+}
+
+/// Text of Shift JIS bytes from a Kanji segment
+pub fn decode_shift_jis(bytes: &[u8]) -> String {
+    encoding_rs::SHIFT_JIS.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+/// Read the characters of a Numeric, Alphanumeric, Byte or Kanji segment of `count`
+/// characters, as bytes: digits and alphanumeric characters in ASCII, Kanji as Shift JIS
+/// pairs. `read(n)` returns the next `n` bits.
+pub fn read_segment_data(mode: SegmentMode, count: usize, mut read: impl FnMut(usize) -> Option<u32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let truncated = "segment runs past the end of the data codewords";
+    let mut segment = Vec::new();
+    match mode {
+        SegmentMode::Numeric => {
+            let mut left = count;
+            while left > 0 {
+                let digits = left.min(3);
+                let value = read([0, 4, 7, 10][digits]).ok_or(truncated)?;
+                if value >= 10u32.pow(digits as u32) {
+                    return Err(format!("invalid numeric group {}", value).into());
+                }
+                segment.extend(format!("{:0width$}", value, width = digits).bytes());
+                left -= digits;
+            }
+        }
+        SegmentMode::Alphanumeric => {
+            let mut left = count;
+            while left > 0 {
+                if left >= 2 {
+                    let value = read(11).ok_or(truncated)? as usize;
+                    if value >= 45 * 45 {
+                        return Err(format!("invalid alphanumeric pair {}", value).into());
+                    }
+                    segment.push(ALPHANUMERIC_CHARSET[value / 45]);
+                    segment.push(ALPHANUMERIC_CHARSET[value % 45]);
+                    left -= 2;
+                } else {
+                    let value = read(6).ok_or(truncated)? as usize;
+                    segment.push(*ALPHANUMERIC_CHARSET.get(value).ok_or("invalid alphanumeric character")?);
+                    left -= 1;
+                }
+            }
+        }
+        SegmentMode::Kanji => {
+            for _ in 0..count {
+                segment.extend(kanji_to_shift_jis(read(13).ok_or(truncated)? as u16));
+            }
+        }
+        _ => {
+            for _ in 0..count {
+                segment.push(read(8).ok_or(truncated)? as u8);
+            }
+        }
+    }
+    Ok(segment)
+}
+
+/// Text of the bytes `read_segment_data` returned, with byte segments read in the character
+/// set selected by `eci`
+pub fn segment_text(mode: SegmentMode, segment: &[u8], eci: Option<u32>) -> String {
+    match mode {
+        SegmentMode::Kanji => decode_shift_jis(segment),
+        SegmentMode::Byte => decode_byte_text(segment, eci),
+        _ => String::from_utf8_lossy(segment).into_owned(),
+    }
+}
+
+/// Decoded data segments of a symbol
+struct Payload {
+    /// Raw segment contents; Kanji characters as Shift JIS byte pairs
+    bytes: Vec<u8>,
+    text: String,
+    eci: Option<u32>,
+    structured_append: Option<StructuredAppend>,
+}
+
+/// Parse the segments of the data codewords up to the terminator
+fn parse_segments(data: &[u8], version: Version) -> Result<Payload, Box<dyn std::error::Error>> {
+    let mut reader = BitReader { data, position: 0 };
+    let mut payload = Payload { bytes: Vec::new(), text: String::new(), eci: None, structured_append: None };
+    let truncated = "segment runs past the end of the data codewords";
+
+    while reader.remaining() >= 4 {
+        let bits = reader.read(4).unwrap();
+        if bits == 0 {
+            break;
+        }
+        let mode = SegmentMode::from_bits(bits).ok_or_else(|| format!("unsupported segment mode {:04b}", bits))?;
+        if mode == SegmentMode::Eci {
+            payload.eci = Some(read_eci_designator(|n| reader.read(n)).ok_or("invalid ECI designator")?);
+            continue;
+        }
+        if mode == SegmentMode::StructuredAppend {
+            payload.structured_append = Some(read_structured_append(|n| reader.read(n)).ok_or(truncated)?);
+            continue;
+        }
+        let count = reader.read(mode.char_count_bits(version)).ok_or(truncated)? as usize;
+        let segment = read_segment_data(mode, count, |n| reader.read(n))?;
+        payload.text.push_str(&segment_text(mode, &segment, payload.eci));
+        payload.bytes.extend(segment);
+    }
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::tests::reference_matrix;
+    use qrcodegen::{QrCode, QrCodeEcc, QrSegment};
+
+    #[test]
+    fn test_decodes_reference_symbols() {
+        for (text, ecc) in [("HELLO WORLD", QrCodeEcc::Quartile), ("https://example.com/a?b=c", QrCodeEcc::Low), ("Grüße, 世界", QrCodeEcc::High)] {
+            let qr = QrCode::encode_text(text, ecc).unwrap();
+            let decoded = decode_matrix(&reference_matrix(&qr)).unwrap();
+            assert_eq!(decoded.text, text);
+            assert!(!decoded.corrected);
+        }
+    }
+
+    #[test]
+    fn test_grades_quality_by_its_weakest_measure() {
+        let qr = QrCode::encode_segments_advanced(&QrSegment::make_segments("quality"), QrCodeEcc::Medium, qrcodegen::Version::new(1), qrcodegen::Version::new(1), None, false).unwrap();
+        let mut matrix = reference_matrix(&qr);
+        let clean = decode_matrix(&matrix).unwrap().quality;
+        assert_eq!((clean.unused_error_correction, clean.format_bit_errors, clean.timing_regularity, clean.grade), (1.0, 0, 1.0, Grade::A));
+
+        // Each unknown error uses two of the ten ECC codewords, so two leave 1 - 4/10 unused: a B
+        for &(row, col) in [8, 16].map(|bit| get_data_ecc_positions(Version::V1)[bit]).iter() {
+            matrix[row][col] ^= 1;
+        }
+        let [first, second] = format_info::positions(21);
+        for (row, col) in [first[3], second[3]] {
+            matrix[row][col] ^= 1;
+        }
+        // One of the ten timing modules wrong
+        matrix[6][9] ^= 1;
+        let quality = decode_matrix(&matrix).unwrap().quality;
+        assert_eq!(quality.corrected_per_block, [100.0 * 2.0 / 26.0]);
+        assert!((quality.unused_error_correction - 0.6).abs() < 1e-9);
+        assert_eq!((quality.format_bit_errors, quality.timing_regularity), (1, 0.9));
+        assert_eq!(quality.grade, Grade::C);
+    }
+
+    #[test]
+    fn test_decodes_multi_block_and_large_versions() {
+        let text = "0123456789".repeat(20);
+        let segments = QrSegment::make_segments(&text);
+        for version in [7, 15, 27, 40] {
+            let qr = QrCode::encode_segments_advanced(&segments, QrCodeEcc::Medium, qrcodegen::Version::new(version), qrcodegen::Version::new(version), None, false).unwrap();
+            let decoded = decode_matrix(&reference_matrix(&qr)).unwrap();
+            assert_eq!(decoded.text, text);
+            assert_eq!(decoded.version as u8, version);
+        }
+    }
+
+    #[test]
+    fn test_recovers_destroyed_format_information() {
+        let segments = QrSegment::make_segments("format info is gone");
+        for (ecc, expected, mask) in [(QrCodeEcc::Quartile, ErrorCorrection::Q, 5), (QrCodeEcc::Low, ErrorCorrection::L, 2)] {
+            let qr = QrCode::encode_segments_advanced(&segments, ecc, qrcodegen::Version::new(3), qrcodegen::Version::new(3), Some(qrcodegen::Mask::new(mask)), false).unwrap();
+            let mut matrix = reference_matrix(&qr);
+            let size = matrix.len();
+            for i in (0..9).chain(size - 8..size) {
+                matrix[8][i] = 1;
+                matrix[i][8] = 1;
+            }
+            assert_eq!(read_format_info(&matrix), None);
+            assert_eq!(recover_format_info(&matrix, Version::V3), Some((expected, mask)));
+        }
+    }
+
+    #[test]
+    fn test_remask_matches_reference_encoder() {
+        let segments = QrSegment::make_segments("remask");
+        let encode = |mask: u8| {
+            let qr = QrCode::encode_segments_advanced(&segments, QrCodeEcc::Medium, qrcodegen::Version::new(8), qrcodegen::Version::new(8), Some(qrcodegen::Mask::new(mask)), false).unwrap();
+            reference_matrix(&qr)
+        };
+        assert_eq!(remask(&encode(3), Version::V8, ErrorCorrection::M, 3, 6), encode(6));
+    }
+
+    #[test]
+    fn test_corrects_version_information() {
+        let segments = QrSegment::make_segments("version");
+        for version in [7, 22, 40] {
+            let qr = QrCode::encode_segments_advanced(&segments, QrCodeEcc::Low, qrcodegen::Version::new(version), qrcodegen::Version::new(version), None, false).unwrap();
+            let mut matrix = reference_matrix(&qr);
+            let size = matrix.len();
+            assert_eq!(read_version_info(&matrix).map(|v| v as u8), Some(version));
+
+            // Three flipped bits in one copy are corrected; four in both are not
+            for i in 0..3 {
+                matrix[size - 11 + i][i] ^= 1;
+            }
+            assert_eq!(read_version_words(&matrix).0 ^ version_info::encode(Version::from_u8(version).unwrap()), 0b100_010_001);
+            assert_eq!(version_info::decode(read_version_words(&matrix).0).map(|(v, errors)| (v as u8, errors)), Some((version, 3)));
+            matrix[size - 11][3] ^= 1;
+            for row in matrix.iter_mut().take(4) {
+                row[size - 11] ^= 1;
+            }
+            assert_eq!(read_version_info(&matrix), None);
+        }
+    }
+
+    /// Rasterize a matrix with `scale` pixels per module and a 4-module quiet zone
+    fn rasterize(matrix: &[Vec<u8>], scale: f64) -> DynamicImage {
+        let side = ((matrix.len() + 8) as f64 * scale).round() as u32;
+        DynamicImage::ImageLuma8(GrayImage::from_fn(side, side, |x, y| {
+            let col = (x as f64 / scale) as usize;
+            let row = (y as f64 / scale) as usize;
+            let dark = (4..matrix.len() + 4).contains(&row) && (4..matrix.len() + 4).contains(&col) && matrix[row - 4][col - 4] == 1;
+            image::Luma([if dark { 0 } else { 255 }])
+        }))
+    }
+
+    #[test]
+    fn test_reads_scaled_images() {
+        let qr = QrCode::encode_text("scaled", QrCodeEcc::Medium).unwrap();
+        let matrix = reference_matrix(&qr);
+        for scale in [1.0, 3.0, 10.0, 4.5] {
+            assert_eq!(image_to_matrix(&rasterize(&matrix, scale)).unwrap(), matrix, "scale {}", scale);
+        }
+
+        // A damaged top-left finder forces the run-length fallback
+        let mut damaged = matrix.clone();
+        damaged[0][0] = 0;
+        damaged[1][0] = 0;
+        assert_eq!(image_to_matrix(&rasterize(&damaged, 6.0)).unwrap(), damaged);
+    }
+
+    #[test]
+    fn test_sampling_grid_reports_centers_even_when_decoding_fails() {
+        let qr = QrCode::encode_text("grid", QrCodeEcc::Low).unwrap();
+        let mut matrix = reference_matrix(&qr);
+        let grid = sampling_grid(&rasterize(&matrix, 5.0)).unwrap();
+        assert_eq!((grid.method, grid.modules, grid.error), (GridMethod::BoundingBox, matrix.len(), None));
+        assert_eq!((grid.centers[0][0], grid.centers[2][1]), ((22.5, 22.5), (27.5, 32.5)));
+        assert_eq!(grid.sampled, matrix);
+
+        // Wipe out the data region: the grid still lands on the modules, which no longer decode
+        for row in &mut matrix[9..] {
+            for module in &mut row[9..] {
+                *module ^= 1;
+            }
+        }
+        let grid = sampling_grid(&rasterize(&matrix, 5.0)).unwrap();
+        assert_eq!(grid.sampled, matrix);
+        assert!(grid.error.is_some());
+        assert!(sampling_grid(&rasterize(&vec![vec![0; 21]; 21], 5.0)).is_err());
+    }
+
+    #[test]
+    fn test_decodes_inverted_image() {
+        let qr = QrCode::encode_text("negative", QrCodeEcc::Medium).unwrap();
+        let mut img = rasterize(&reference_matrix(&qr), 5.0).to_luma8();
+        image::imageops::invert(&mut img);
+        let path = std::env::temp_dir().join(format!("qr-inverted-{}.png", std::process::id()));
+        img.save(&path).unwrap();
+        let decoded = decode_image(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded.text, "negative");
+        assert!(decoded.inverted);
+    }
+
+    #[test]
+    fn test_decodes_image_files_in_memory() {
+        let qr = QrCode::encode_text("in memory", QrCodeEcc::Medium).unwrap();
+        let matrix = reference_matrix(&qr);
+        let options = crate::render::RenderOptions::default();
+        let mut png = Vec::new();
+        crate::render::render_png(&matrix, &options, &mut png).unwrap();
+        assert_eq!(decode_bytes(&png).unwrap().text, "in memory");
+        let mut svg = Vec::new();
+        crate::render::render_svg(&matrix, &options, &mut svg).unwrap();
+        assert_eq!(decode_bytes(&svg).unwrap().text, "in memory");
+        assert_eq!(decode_bytes(b"not an image").unwrap_err().to_string(), "unrecognized image format");
+    }
+
+    /// Symbol holding `part` under a Structured Append header. The reference encoder has no
+    /// such mode, so the header and a byte segment are written as the data bits of an empty
+    /// byte segment.
+    fn structured_append_symbol(part: &str, index: u32, total: u32, parity: u32) -> Vec<Vec<u8>> {
+        let fields = [(0b0011, 4), (index, 4), (total - 1, 4), (parity, 8), (0b0100, 4), (part.len() as u32, 8)];
+        let bits = fields
+            .into_iter()
+            .chain(part.bytes().map(|b| (b as u32, 8)))
+            .flat_map(|(value, width)| (0..width).rev().map(move |i| (value >> i) & 1 == 1))
+            .collect();
+        let segment = QrSegment::new(qrcodegen::QrSegmentMode::Byte, 0, bits);
+        reference_matrix(&QrCode::encode_segments(&[segment], QrCodeEcc::Medium).unwrap())
+    }
+
+    #[test]
+    fn test_joins_structured_append_sequence() {
+        let parts = ["Hell", "o, W", "orld"];
+        let parity = parts.iter().flat_map(|part| part.bytes()).fold(0, |parity, byte| parity ^ byte) as u32;
+        let decode = |index: usize, parity: u32| decode_matrix(&structured_append_symbol(parts[index], index as u32, 3, parity)).unwrap();
+        let symbols: Vec<Decoded> = [2, 0, 1].into_iter().map(|index| decode(index, parity)).collect();
+        assert_eq!(symbols[0].structured_append, Some(StructuredAppend { index: 2, total: 3, parity: parity as u8 }));
+        assert_eq!(symbols[0].text, "orld");
+
+        let joined = join_structured_append(&symbols).unwrap();
+        assert_eq!(joined.iter().map(|symbol| symbol.text.as_str()).collect::<String>(), "Hello, World");
+
+        assert!(join_structured_append(&symbols[..2]).unwrap_err().to_string().contains("symbols 2 of 3 are missing"));
+        let twice = [symbols[1].clone(), symbols[1].clone(), symbols[2].clone()];
+        assert!(join_structured_append(&twice).unwrap_err().to_string().contains("given twice"));
+        let wrong_parity: Vec<Decoded> = (0..3).map(|index| decode(index, parity ^ 1)).collect();
+        assert!(join_structured_append(&wrong_parity).unwrap_err().to_string().contains("parity check failed"));
+        let plain = decode_matrix(&reference_matrix(&QrCode::encode_text("alone", QrCodeEcc::Medium).unwrap())).unwrap();
+        assert!(join_structured_append(&[plain]).is_err());
+    }
+
+    #[test]
+    fn test_decodes_kanji_and_eci_segments() {
+        // 点茗 as 13-bit Kanji values (JIS X 0208 Shift JIS 0x935F, 0xE4AA)
+        let bits = [0xD9Fu32, 0x1AAA].iter().flat_map(|&v| (0..13).rev().map(move |i| (v >> i) & 1 == 1)).collect();
+        let kanji = QrSegment::new(qrcodegen::QrSegmentMode::Kanji, 2, bits);
+        let qr = QrCode::encode_segments(&[kanji], QrCodeEcc::Medium).unwrap();
+        let decoded = decode_matrix(&reference_matrix(&qr)).unwrap();
+        assert_eq!(decoded.text, "点茗");
+        assert_eq!(decoded.bytes, [0x93, 0x5F, 0xE4, 0xAA]);
+
+        // ECI 7 (ISO-8859-5) turns bytes 0xD0 0xD1 into Cyrillic "аб"
+        let segments = [QrSegment::make_eci(7), QrSegment::make_bytes(&[0xD0, 0xD1])];
+        let qr = QrCode::encode_segments(&segments, QrCodeEcc::Medium).unwrap();
+        let decoded = decode_matrix(&reference_matrix(&qr)).unwrap();
+        assert_eq!(decoded.text, "аб");
+        assert_eq!(decoded.eci, Some(7));
+    }
+
+    #[test]
+    fn test_corrects_damaged_modules() {
+        let qr = QrCode::encode_text("correct me", QrCodeEcc::High).unwrap();
+        let mut matrix = reference_matrix(&qr);
+        for (i, row) in matrix.iter_mut().enumerate().skip(9).take(5) {
+            row[i] ^= 1;
+        }
+        let decoded = decode_matrix(&matrix).unwrap();
+        assert_eq!(decoded.text, "correct me");
+        assert!(decoded.corrected);
+    }
+
+    #[test]
+    fn test_erasures_extend_correction_capacity() {
+        // Version 1-L carries 7 ECC codewords: three unknown errors, or seven erasures
+        let segments = QrSegment::make_segments("erase me");
+        let qr = QrCode::encode_segments_advanced(&segments, QrCodeEcc::Low, qrcodegen::Version::new(1), qrcodegen::Version::new(1), None, false).unwrap();
+        let mut matrix = reference_matrix(&qr);
+        let mut unreadable = vec![vec![false; matrix.len()]; matrix.len()];
+        for &(row, col) in &get_data_ecc_positions(Version::V1)[8 * 3..8 * 9] {
+            matrix[row][col] ^= 1;
+            unreadable[row][col] = true;
+        }
+        assert!(decode_matrix(&matrix).is_err());
+        let decoded = decode_matrix_with_erasures(&matrix, &unreadable).unwrap();
+        assert_eq!(decoded.text, "erase me");
+        assert!(decoded.corrected);
+
+        // The erasure map follows the matrix when the symbol is turned
+        let (turned, turned_map) = (rotate_clockwise(&matrix), rotate_clockwise(&unreadable));
+        assert_eq!(decode_matrix_with_erasures(&turned, &turned_map).unwrap().text, "erase me");
+    }
+
+    #[test]
+    fn test_normalizes_rotated_and_mirrored_symbols() {
+        let qr = QrCode::encode_text("turn me", QrCodeEcc::Medium).unwrap();
+        let upright = reference_matrix(&qr);
+        let mut turned = upright.clone();
+        for rotation in [0, 90, 180, 270] {
+            for mirrored in [false, true] {
+                let input = if mirrored { transpose(&turned) } else { turned.clone() };
+                let decoded = decode_matrix(&input).unwrap();
+                assert_eq!(decoded.text, "turn me");
+                // Transposing a turned symbol reverses the direction of the turn
+                let expected_rotation = if mirrored { (360 - rotation) % 360 } else { rotation };
+                assert_eq!(decoded.orientation, Orientation { rotation: expected_rotation, mirrored });
+            }
+            turned = rotate_clockwise(&turned);
+        }
+    }
+
+    #[test]
+    fn test_damaged_and_random_input_never_panics() {
+        use rand::{Rng, SeedableRng};
+        use rand_chacha::ChaCha8Rng;
+
+        let mut rng = ChaCha8Rng::seed_from_u64(3829);
+        let qr = QrCode::encode_text("fuzz the decode path", QrCodeEcc::Medium).unwrap();
+        let reference = reference_matrix(&qr);
+        for round in 0..200 {
+            // Reference symbols with ever more flipped modules, then pure noise of valid sizes
+            let matrix = if round < 150 {
+                let rate = round as f64 / 150.0;
+                reference.iter().map(|row| row.iter().map(|&m| m ^ rng.gen_bool(rate) as u8).collect()).collect()
+            } else {
+                let size = 17 + 4 * rng.gen_range(1..=12);
+                (0..size).map(|_| (0..size).map(|_| rng.gen_range(0..=1)).collect()).collect::<Vec<Vec<u8>>>()
+            };
+            let size = matrix.len();
+            let unreadable: Vec<Vec<bool>> = (0..size).map(|_| (0..size).map(|_| rng.gen_bool(0.05)).collect()).collect();
+
+            let _ = decode_matrix(&matrix);
+            let _ = decode_matrix_with_erasures(&matrix, &unreadable);
+            if let Some(version) = image_size_to_version(size) {
+                let _ = recover_format_info(&matrix, version);
+                let _ = read_version_info(&matrix);
+            }
+            if round % 20 == 0 {
+                let _ = read_luma(&rasterize(&matrix, 3.0).to_luma8());
+            }
+        }
+
+        // Matrices that are not symbols at all
+        for matrix in [vec![], vec![vec![1u8; 21]; 20], vec![vec![0u8; 22]; 22]] {
+            assert!(decode_matrix(&matrix).is_err());
+        }
+        let _ = read_luma(&GrayImage::new(1, 1));
+    }
+}
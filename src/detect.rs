@@ -0,0 +1,410 @@
+use image::GrayImage;
+use crate::types::Version;
+
+/// Center and module size of a finder pattern found in an image, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FinderPattern {
+    pub x: f64,
+    pub y: f64,
+    pub module_size: f64,
+}
+
+/// Projective transform between two planes, used to map module coordinates to pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Homography {
+    m: [f64; 9],
+}
+
+impl Homography {
+    /// Transform mapping each point in `from` onto the corresponding point in `to`.
+    /// Returns `None` if the points are degenerate (three of them collinear).
+    #[allow(clippy::needless_range_loop)]
+    pub fn from_points(from: [(f64, f64); 4], to: [(f64, f64); 4]) -> Option<Homography> {
+        // Solve the 8x8 system for h0..h7 with h8 = 1
+        let mut a = [[0.0f64; 9]; 8];
+        for (i, (&(u, v), &(x, y))) in from.iter().zip(to.iter()).enumerate() {
+            a[2 * i] = [u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x, x];
+            a[2 * i + 1] = [0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y, y];
+        }
+
+        for col in 0..8 {
+            let pivot = (col..8).max_by(|&r, &s| a[r][col].abs().total_cmp(&a[s][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            for row in 0..8 {
+                if row != col {
+                    let factor = a[row][col] / a[col][col];
+                    for k in col..9 {
+                        a[row][k] -= factor * a[col][k];
+                    }
+                }
+            }
+        }
+
+        let mut m = [1.0; 9];
+        for i in 0..8 {
+            m[i] = a[i][8] / a[i][i];
+        }
+        Some(Homography { m })
+    }
+
+    pub fn map(&self, x: f64, y: f64) -> (f64, f64) {
+        let m = &self.m;
+        let w = m[6] * x + m[7] * y + m[8];
+        ((m[0] * x + m[1] * y + m[2]) / w, (m[3] * x + m[4] * y + m[5]) / w)
+    }
+}
+
+/// Rectangle of an image, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y && x - self.x < self.width && y - self.y < self.height
+    }
+}
+
+/// A symbol located in an image: its finder patterns and the module-to-pixel transform
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub top_left: FinderPattern,
+    pub top_right: FinderPattern,
+    pub bottom_left: FinderPattern,
+    /// Center of the bottom-right alignment pattern, when one was found
+    pub alignment: Option<(f64, f64)>,
+    /// Symbol width in modules
+    pub modules: usize,
+    transform: Homography,
+}
+
+impl Detection {
+    /// Pixel position of the center of a module
+    pub fn module_center(&self, row: usize, col: usize) -> (f64, f64) {
+        self.transform.map(col as f64 + 0.5, row as f64 + 0.5)
+    }
+
+    /// Fit the same finder patterns to a symbol of another version, for when the version
+    /// information disagrees with the width estimated from the finder spacing
+    pub fn with_version(&self, img: &GrayImage, version: Version) -> Result<Detection, Box<dyn std::error::Error>> {
+        fit_grid(img, self.top_left, self.top_right, self.bottom_left, version as usize)
+    }
+
+    /// Bounding box of the symbol and `margin` modules of quiet zone around it, clipped to
+    /// `img`
+    pub fn bounds(&self, img: &GrayImage, margin: f64) -> Region {
+        let d = self.modules as f64;
+        let corners = [(-margin, -margin), (d + margin, -margin), (-margin, d + margin), (d + margin, d + margin)].map(|(u, v)| self.transform.map(u, v));
+        let (left, top) = corners.iter().fold((f64::MAX, f64::MAX), |(x, y), c| (x.min(c.0), y.min(c.1)));
+        let (right, bottom) = corners.iter().fold((f64::MIN, f64::MIN), |(x, y), c| (x.max(c.0), y.max(c.1)));
+        let x = left.round().clamp(0.0, img.width() as f64) as u32;
+        let y = top.round().clamp(0.0, img.height() as f64) as u32;
+        let width = (right.round().clamp(0.0, img.width() as f64) as u32).saturating_sub(x);
+        let height = (bottom.round().clamp(0.0, img.height() as f64) as u32).saturating_sub(y);
+        Region { x, y, width, height }
+    }
+
+    /// Sample every module through the perspective transform. Modules that fall outside the
+    /// image read as light.
+    pub fn sample(&self, img: &GrayImage) -> Vec<Vec<u8>> {
+        (0..self.modules)
+            .map(|row| (0..self.modules).map(|col| {
+                let (x, y) = self.module_center(row, col);
+                is_dark_at(img, x, y) as u8
+            }).collect())
+            .collect()
+    }
+}
+
+fn is_dark_at(img: &GrayImage, x: f64, y: f64) -> bool {
+    if x < 0.0 || y < 0.0 || x >= img.width() as f64 || y >= img.height() as f64 {
+        return false;
+    }
+    img.get_pixel(x as u32, y as u32)[0] < 128
+}
+
+/// Runs of equal color along a line: (dark, start, length)
+fn runs(pixels: impl Iterator<Item = bool>) -> Vec<(bool, usize, usize)> {
+    let mut runs: Vec<(bool, usize, usize)> = Vec::new();
+    for (i, dark) in pixels.enumerate() {
+        match runs.last_mut() {
+            Some(run) if run.0 == dark => run.2 += 1,
+            _ => runs.push((dark, i, 1)),
+        }
+    }
+    runs
+}
+
+/// Whether run lengths match `ratios` within half a module each, returning the module size
+fn matches_ratio(lengths: &[usize], ratios: &[f64]) -> Option<f64> {
+    let total: usize = lengths.iter().sum();
+    let module = total as f64 / ratios.iter().sum::<f64>();
+    if module < 1.0 {
+        return None;
+    }
+    let fits = lengths.iter().zip(ratios).all(|(&len, &ratio)| (len as f64 - ratio * module).abs() <= ratio.max(1.0) * module * 0.5);
+    fits.then_some(module)
+}
+
+/// Run sequence across the center of a pattern: the color of the middle run and the ratios
+struct RunPattern {
+    center_dark: bool,
+    ratio: &'static [f64],
+}
+
+/// Dark-light-dark-light-dark across a finder pattern
+const FINDER: RunPattern = RunPattern { center_dark: true, ratio: &[1.0, 1.0, 3.0, 1.0, 1.0] };
+
+/// Light-dark-light across the center of an alignment pattern. Its outer dark ring can merge
+/// with neighbouring data modules, so only the inner three runs are checked.
+const ALIGNMENT: RunPattern = RunPattern { center_dark: true, ratio: &[1.0, 1.0, 1.0] };
+
+/// Centers of run sequences matching `pattern` along a line, with the module size each implies
+fn scan_line(line: &[(bool, usize, usize)], pattern: &RunPattern) -> Vec<(f64, f64)> {
+    let n = pattern.ratio.len();
+    line.windows(n)
+        .filter(|w| w[n / 2].0 == pattern.center_dark)
+        .filter_map(|w| {
+            let lengths: Vec<usize> = w.iter().map(|r| r.2).collect();
+            let module = matches_ratio(&lengths, pattern.ratio)?;
+            Some((w[n / 2].1 as f64 + w[n / 2].2 as f64 / 2.0, module))
+        })
+        .collect()
+}
+
+/// Confirm a candidate along a column (or row) through it: the pattern must appear there too,
+/// centered within a module of the candidate. Returns the refined center coordinate.
+fn cross_check(pixels: impl Iterator<Item = bool>, center: f64, pattern: &RunPattern, module: f64) -> Option<(f64, f64)> {
+    let line = runs(pixels);
+    scan_line(&line, pattern)
+        .into_iter()
+        .filter(|&(c, m)| (c - center).abs() <= module && (m - module).abs() <= module * 0.5)
+        .min_by(|a, b| (a.0 - center).abs().total_cmp(&(b.0 - center).abs()))
+}
+
+/// Scan the image for finder patterns: 1:1:3:1:1 dark/light runs found along a row and
+/// confirmed along the column and row through their center. Nearby hits are merged, and only
+/// centers confirmed on at least two rows are kept.
+pub fn find_finder_patterns(img: &GrayImage) -> Vec<FinderPattern> {
+    let (width, height) = img.dimensions();
+    let dark = |x: u32, y: u32| img.get_pixel(x, y)[0] < 128;
+    let mut found: Vec<(FinderPattern, usize)> = Vec::new();
+
+    for y in 0..height {
+        let line = runs((0..width).map(|x| dark(x, y)));
+        for (cx, module) in scan_line(&line, &FINDER) {
+            let Some((cy, module_v)) = cross_check((0..height).map(|yy| dark(cx as u32, yy)), y as f64 + 0.5, &FINDER, module) else { continue };
+            let Some((cx, module_h)) = cross_check((0..width).map(|xx| dark(xx, cy as u32)), cx, &FINDER, module) else { continue };
+            let candidate = FinderPattern { x: cx, y: cy, module_size: (module_v + module_h) / 2.0 };
+
+            match found.iter_mut().find(|(f, _)| (f.x - cx).hypot(f.y - cy) <= f.module_size * 2.0) {
+                Some((existing, count)) => {
+                    let n = *count as f64;
+                    existing.x = (existing.x * n + candidate.x) / (n + 1.0);
+                    existing.y = (existing.y * n + candidate.y) / (n + 1.0);
+                    existing.module_size = (existing.module_size * n + candidate.module_size) / (n + 1.0);
+                    *count += 1;
+                }
+                None => found.push((candidate, 1)),
+            }
+        }
+    }
+
+    found.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    found.into_iter().filter(|&(_, count)| count >= 2).map(|(f, _)| f).collect()
+}
+
+fn distance(a: &FinderPattern, b: &FinderPattern) -> f64 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+/// Pick the three patterns that best form the corner of a square, ordered as
+/// (top-left, top-right, bottom-left)
+fn select_corners(patterns: &[FinderPattern]) -> Option<(FinderPattern, FinderPattern, FinderPattern)> {
+    let candidates = &patterns[..patterns.len().min(8)];
+    let mut best: Option<(f64, (FinderPattern, FinderPattern, FinderPattern))> = None;
+
+    for i in 0..candidates.len() {
+        for j in i + 1..candidates.len() {
+            for k in j + 1..candidates.len() {
+                let mut trio = [candidates[i], candidates[j], candidates[k]];
+                // The corner opposite the longest side is the top-left finder
+                let sides = [distance(&trio[1], &trio[2]), distance(&trio[0], &trio[2]), distance(&trio[0], &trio[1])];
+                let corner = (0..3).max_by(|&a, &b| sides[a].total_cmp(&sides[b])).unwrap();
+                trio.swap(0, corner);
+                let [tl, mut tr, mut bl] = trio;
+
+                let (ax, ay) = (tr.x - tl.x, tr.y - tl.y);
+                let (bx, by) = (bl.x - tl.x, bl.y - tl.y);
+                if ax * by - ay * bx < 0.0 {
+                    std::mem::swap(&mut tr, &mut bl);
+                }
+
+                let (d1, d2) = (distance(&tl, &tr), distance(&tl, &bl));
+                let cos = (ax * bx + ay * by).abs() / (d1 * d2);
+                let sizes = [tl.module_size, tr.module_size, bl.module_size];
+                let max_size = sizes.iter().cloned().fold(f64::MIN, f64::max);
+                let min_size = sizes.iter().cloned().fold(f64::MAX, f64::min);
+                if d1.min(d2) < max_size * 14.0 || min_size < max_size * 0.5 {
+                    continue;
+                }
+
+                let score = (d1 - d2).abs() / d1.max(d2) + cos + (max_size - min_size) / max_size;
+                if best.as_ref().is_none_or(|(s, _)| score < *s) {
+                    best = Some((score, (tl, tr, bl)));
+                }
+            }
+        }
+    }
+    best.filter(|(score, _)| *score < 0.5).map(|(_, corners)| corners)
+}
+
+/// Look for the bottom-right alignment pattern within a few modules of `(x, y)`
+fn find_alignment(img: &GrayImage, x: f64, y: f64, module: f64) -> Option<(f64, f64)> {
+    let dark = |px: i64, py: i64| px >= 0 && py >= 0 && (px as u32) < img.width() && (py as u32) < img.height() && img.get_pixel(px as u32, py as u32)[0] < 128;
+    let reach = (module * 5.0).ceil() as i64;
+    let span = (module * 8.0).ceil() as i64;
+    let mut best: Option<(f64, (f64, f64))> = None;
+
+    for py in (y as i64 - reach)..=(y as i64 + reach) {
+        let start = x as i64 - span;
+        let line = runs((start..=x as i64 + span).map(|px| dark(px, py)));
+        for (cx, m) in scan_line(&line, &ALIGNMENT) {
+            let cx = start as f64 + cx;
+            if (m - module).abs() > module * 0.5 || (cx - x).abs() > reach as f64 {
+                continue;
+            }
+            let top = py - span;
+            let column = (top..=py + span).map(|yy| dark(cx as i64, yy));
+            let Some((cy, _)) = cross_check(column, (py - top) as f64 + 0.5, &ALIGNMENT, module) else { continue };
+            let cy = top as f64 + cy;
+            let off = (cx - x).hypot(cy - y);
+            if best.is_none_or(|(d, _)| off < d) {
+                best = Some((off, (cx, cy)));
+            }
+        }
+    }
+    best.map(|(_, center)| center)
+}
+
+/// Locate a symbol from its finder patterns and estimate the perspective transform. The
+/// bottom-right alignment pattern, when present, anchors the fourth corner; otherwise the
+/// symbol is assumed to be a parallelogram.
+pub fn detect_symbol(img: &GrayImage) -> Result<Detection, Box<dyn std::error::Error>> {
+    let patterns = find_finder_patterns(img);
+    if patterns.len() < 3 {
+        return Err(format!("found {} finder patterns, need 3", patterns.len()).into());
+    }
+    let (tl, tr, bl) = select_corners(&patterns).ok_or("finder patterns do not form a symbol")?;
+
+    let module = (tl.module_size + tr.module_size + bl.module_size) / 3.0;
+    let span = (distance(&tl, &tr) + distance(&tl, &bl)) / 2.0 / module;
+    let version = ((span + 7.0 - 17.0) / 4.0).round().clamp(1.0, 40.0) as usize;
+    fit_grid(img, tl, tr, bl, version)
+}
+
+/// Find a symbol pasted among other content, such as a screenshot of a page, by its finder
+/// patterns, and return the region to crop it to: the symbol and `margin` modules of quiet
+/// zone. `None` when no symbol is found, or when nothing dark lies outside that region, so
+/// there is nothing to crop away.
+pub fn symbol_region(img: &GrayImage, margin: f64) -> Option<Region> {
+    let region = detect_symbol(img).ok()?.bounds(img, margin);
+    let clutter = img.enumerate_pixels().any(|(x, y, pixel)| pixel[0] < 128 && !region.contains(x, y));
+    clutter.then_some(region)
+}
+
+/// Module-to-pixel transform for a symbol of `version` whose finder centers are known
+fn fit_grid(img: &GrayImage, tl: FinderPattern, tr: FinderPattern, bl: FinderPattern, version: usize) -> Result<Detection, Box<dyn std::error::Error>> {
+    let module = (tl.module_size + tr.module_size + bl.module_size) / 3.0;
+    let modules = 17 + 4 * version;
+    let d = modules as f64;
+
+    let mut from = [(3.5, 3.5), (d - 3.5, 3.5), (3.5, d - 3.5), (d - 3.5, d - 3.5)];
+    let mut to = [(tl.x, tl.y), (tr.x, tr.y), (bl.x, bl.y), (tr.x + bl.x - tl.x, tr.y + bl.y - tl.y)];
+
+    let mut alignment = None;
+    if version >= 2 {
+        // Predict the alignment center with the affine estimate, then search around it
+        let t = (d - 6.5 - 3.5) / (d - 7.0);
+        let guess_x = tl.x + t * (tr.x - tl.x) + t * (bl.x - tl.x);
+        let guess_y = tl.y + t * (tr.y - tl.y) + t * (bl.y - tl.y);
+        alignment = find_alignment(img, guess_x, guess_y, module);
+        if let Some(center) = alignment {
+            from[3] = (d - 6.5, d - 6.5);
+            to[3] = center;
+        }
+    }
+
+    let transform = Homography::from_points(from, to).ok_or("finder patterns are collinear")?;
+    Ok(Detection { top_left: tl, top_right: tr, bottom_left: bl, alignment, modules, transform })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::tests::reference_matrix;
+    use qrcodegen::{QrCode, QrCodeEcc};
+
+    /// Draw a matrix as a photo would see it: the symbol corners land on `quad` in pixels
+    fn warp(matrix: &[Vec<u8>], quad: [(f64, f64); 4], width: u32, height: u32) -> GrayImage {
+        let d = matrix.len() as f64;
+        let to_module = Homography::from_points(quad, [(0.0, 0.0), (d, 0.0), (d, d), (0.0, d)]).unwrap();
+        GrayImage::from_fn(width, height, |x, y| {
+            let (u, v) = to_module.map(x as f64 + 0.5, y as f64 + 0.5);
+            let inside = u >= 0.0 && v >= 0.0 && u < d && v < d;
+            image::Luma([if inside && matrix[v as usize][u as usize] == 1 { 20 } else { 235 }])
+        })
+    }
+
+    #[test]
+    fn test_homography_maps_corners() {
+        let from = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        let to = [(10.0, 20.0), (110.0, 30.0), (100.0, 140.0), (5.0, 120.0)];
+        let h = Homography::from_points(from, to).unwrap();
+        for (f, t) in from.iter().zip(to.iter()) {
+            let (x, y) = h.map(f.0, f.1);
+            assert!((x - t.0).abs() < 1e-6 && (y - t.1).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_finds_symbol_region_on_a_busy_page() {
+        let qr = QrCode::encode_text("https://example.com/screenshot", QrCodeEcc::Low).unwrap();
+        let matrix = reference_matrix(&qr);
+        let d = matrix.len() as f64 * 4.0;
+
+        // The symbol at 4 px per module, right of a column of text-like bars
+        let mut img = warp(&matrix, [(300.0, 80.0), (300.0 + d, 80.0), (300.0 + d, 80.0 + d), (300.0, 80.0 + d)], 520, 320);
+        for line in 0..12 {
+            for y in 40 + line * 20..48 + line * 20 {
+                for x in 20..(120 + line * 13 % 100) {
+                    img.put_pixel(x, y, image::Luma([20]));
+                }
+            }
+        }
+
+        let region = symbol_region(&img, 4.0).unwrap();
+        assert_eq!((region.x, region.y, region.width, region.height), (284, 64, d as u32 + 32, d as u32 + 32));
+        let alone = warp(&matrix, [(16.0, 16.0), (16.0 + d, 16.0), (16.0 + d, 16.0 + d), (16.0, 16.0 + d)], d as u32 + 32, d as u32 + 32);
+        assert_eq!(symbol_region(&alone, 4.0), None);
+    }
+
+    #[test]
+    fn test_detects_rotated_perspective_symbol() {
+        let qr = QrCode::encode_text("https://example.com/perspective", QrCodeEcc::Medium).unwrap();
+        let matrix = reference_matrix(&qr);
+
+        let img = warp(&matrix, [(90.0, 60.0), (390.0, 100.0), (360.0, 420.0), (60.0, 370.0)], 460, 480);
+        let detection = detect_symbol(&img).unwrap();
+        assert_eq!(detection.modules, matrix.len());
+        assert!(detection.alignment.is_some());
+        assert_eq!(detection.sample(&img), matrix);
+    }
+}
@@ -0,0 +1,96 @@
+use alloc::{format, string::String, vec::Vec};
+use crate::generator::Segment;
+use crate::mask::Penalty;
+
+/// Collects what the encoder decided while building a symbol. Attach one to
+/// [`QrConfig::diagnostics`](crate::types::QrConfig::diagnostics) and read the stages back once
+/// the matrix is built; clones share the same record.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    stages: Shared<Vec<Stage>>,
+}
+
+/// Shared across threads with `std`; without it there are no threads to share with
+#[cfg(feature = "std")]
+type Shared<T> = std::sync::Arc<std::sync::Mutex<T>>;
+#[cfg(not(feature = "std"))]
+type Shared<T> = alloc::rc::Rc<core::cell::RefCell<T>>;
+
+/// One step of encoding, in the order the encoder reaches them
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum Stage {
+    /// Segments the payload was encoded as
+    Segments { segments: Vec<Segment> },
+    /// Data and ECC codewords of each block, before interleaving
+    Blocks { data: Vec<Vec<u8>>, ecc: Vec<Vec<u8>> },
+    /// Data and ECC codewords in the order they are placed in the matrix
+    Codewords { codewords: Vec<u8> },
+    /// Penalty of the symbol under each of the eight mask patterns, and the pattern applied
+    MaskScores { penalties: Vec<Penalty>, applied: Option<u8> },
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, stage: Stage) {
+        self.with_stages(|stages| stages.push(stage));
+    }
+
+    /// The stages recorded so far
+    pub fn stages(&self) -> Vec<Stage> {
+        self.with_stages(|stages| stages.clone())
+    }
+
+    fn with_stages<R>(&self, f: impl FnOnce(&mut Vec<Stage>) -> R) -> R {
+        #[cfg(feature = "std")]
+        let mut stages = self.stages.lock().unwrap();
+        #[cfg(not(feature = "std"))]
+        let mut stages = self.stages.borrow_mut();
+        f(&mut stages)
+    }
+}
+
+/// Human-readable rendering of the stages, one fact per line
+pub fn format_stages(stages: &[Stage]) -> String {
+    let mut lines = Vec::new();
+    for stage in stages {
+        match stage {
+            Stage::Segments { segments } => {
+                for (i, segment) in segments.iter().enumerate() {
+                    lines.push(format!("Segment {}: {}, {} characters, {} bits", i + 1, segment.mode, segment.characters, segment.bits));
+                }
+            }
+            Stage::Blocks { data, ecc } => {
+                let hex = |block: &Vec<u8>| block.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+                for group in data.chunk_by(|a, b| a.len() == b.len()) {
+                    lines.push(format!("{} blocks of {} data codewords", group.len(), group[0].len()));
+                }
+                lines.push(format!("ECC codewords per block: {}", ecc.first().map_or(0, Vec::len)));
+                lines.extend(data.iter().enumerate().map(|(i, block)| format!("Data block {}: {}", i + 1, hex(block))));
+                lines.extend(ecc.iter().enumerate().map(|(i, block)| format!("ECC block {}: {}", i + 1, hex(block))));
+            }
+            Stage::Codewords { codewords } => {
+                let hex = codewords.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+                lines.push(format!("Placed codewords: {}", hex));
+            }
+            Stage::MaskScores { penalties, applied } => {
+                for (mask, penalty) in penalties.iter().enumerate() {
+                    lines.push(format!(
+                        "Mask {}: penalty {} (runs {}, blocks {}, finder-like {}, balance {}){}",
+                        mask,
+                        penalty.total,
+                        penalty.runs,
+                        penalty.blocks,
+                        penalty.finder_like,
+                        penalty.balance,
+                        if *applied == Some(mask as u8) { ", applied" } else { "" }
+                    ));
+                }
+            }
+        }
+    }
+    lines.join("\n")
+}
@@ -1,25 +1,92 @@
-use crate::capacity::get_data_capacity_in_bits;
+use alloc::vec::Vec;
+use crate::capacity::{get_block_layout, get_data_capacity_in_bits};
 use crate::types::{DataMode, ErrorCorrection, Version};
-use crate::ecc::generate_ecc as generate_reed_solomon_ecc;
+use crate::ecc::generate_ecc_into;
+use crate::diagnostics::{Diagnostics, Stage};
+use crate::fit::mode_holds;
 
+#[derive(Debug, Clone, Default)]
 pub struct EncodedData {
     pub data_bits: Vec<u8>,
-    pub ecc_bits: Vec<u8>,
+    /// Data and ECC codewords interleaved across the blocks, in the order they are placed
+    pub stream: Vec<u8>,
+    /// The data codewords split into blocks, followed by each block's ECC codewords; working
+    /// space kept between [`encode_data_into`] calls
+    codewords: Vec<u8>,
 }
 
-pub fn encode_data(data: &str, version: Version, error_correction: ErrorCorrection, mode: DataMode) -> EncodedData {
-    let mut data_bits = match mode {
-        DataMode::Numeric => encode_numeric(data, version),
-        DataMode::Byte => encode_byte(data, version),
-        DataMode::Alphanumeric => encode_alphanumeric(data, version),
-    };
-    
+/// Encode `data` in the given mode. Byte mode takes arbitrary bytes; numeric and alphanumeric
+/// mode expect ASCII digits or characters from the alphanumeric set, and a payload with any
+/// other byte is encoded in byte mode instead (see [`segment_mode`]). The block structure is
+/// recorded in `diagnostics` when given.
+pub fn encode_data(data: &[u8], version: Version, error_correction: ErrorCorrection, mode: DataMode, diagnostics: Option<&Diagnostics>) -> EncodedData {
+    let mut encoded = EncodedData::default();
+    encode_data_into(data, version, error_correction, mode, diagnostics, &mut encoded);
+    encoded
+}
+
+/// [`encode_data`] into the buffers of an earlier result, which only allocates when the
+/// symbol is larger than any `encoded` has held before
+pub fn encode_data_into(data: &[u8], version: Version, error_correction: ErrorCorrection, mode: DataMode, diagnostics: Option<&Diagnostics>, encoded: &mut EncodedData) {
+    encoded.data_bits.clear();
+    encode_segment_into(data, version, mode, &mut encoded.data_bits);
+
     // Add padding to reach required data capacity
-    add_padding(&mut data_bits, version, error_correction);
-    
-    let ecc_bits = generate_ecc(&data_bits, version, error_correction);
+    add_padding(&mut encoded.data_bits, version, error_correction);
     
-    EncodedData { data_bits, ecc_bits }
+    generate_ecc(encoded, version, error_correction, diagnostics);
+}
+
+/// Bits of a single segment: mode indicator, character count and the encoded characters,
+/// before the terminator and padding
+pub fn encode_segment(data: &[u8], version: Version, mode: DataMode) -> Vec<u8> {
+    let mut bits = Vec::new();
+    encode_segment_into(data, version, mode, &mut bits);
+    bits
+}
+
+/// The mode `data` is encoded in: `mode` if it has a character for every byte, else byte
+/// mode, which holds any byte. [`check_mode`](crate::fit::check_mode) reports the difference.
+pub fn segment_mode(data: &[u8], mode: DataMode) -> DataMode {
+    if data.iter().all(|&byte| mode_holds(mode, byte)) { mode } else { DataMode::Byte }
+}
+
+fn encode_segment_into(data: &[u8], version: Version, mode: DataMode, bits: &mut Vec<u8>) {
+    let start = bits.len();
+    let encoded = match mode {
+        DataMode::Numeric => encode_numeric(data, version, bits),
+        DataMode::Alphanumeric => encode_alphanumeric(data, version, bits),
+        DataMode::Byte => {
+            encode_byte(data, version, bits);
+            return;
+        }
+    };
+    // A byte the mode has no character for: the whole payload goes in byte mode instead
+    if encoded.is_none() {
+        bits.truncate(start);
+        encode_byte(data, version, bits);
+    }
+}
+
+/// Width of the character count indicator, which grows at versions 10 and 27 (table 3)
+fn char_count_bits(mode: DataMode, version: Version) -> usize {
+    let range = match version as u8 {
+        1..=9 => 0,
+        10..=26 => 1,
+        _ => 2,
+    };
+    match mode {
+        DataMode::Numeric => [10, 12, 14][range],
+        DataMode::Alphanumeric => [9, 11, 13][range],
+        DataMode::Byte => [8, 16, 16][range],
+    }
+}
+
+/// Append the low `count` bits of `value`, most significant first
+fn push_bits(bits: &mut Vec<u8>, value: u32, count: usize) {
+    for i in (0..count).rev() {
+        bits.push(((value >> i) & 1) as u8);
+    }
 }
 
 fn add_padding(data_bits: &mut Vec<u8>, version: Version, error_correction: ErrorCorrection) {
@@ -28,12 +95,12 @@ fn add_padding(data_bits: &mut Vec<u8>, version: Version, error_correction: Erro
     
     // Add terminator (up to 4 zero bits, only if there's space)
     if data_bits.len() < data_capacity_bits {
-        let terminator_bits = std::cmp::min(4, data_capacity_bits - data_bits.len());
-        data_bits.extend(vec![0; terminator_bits]);
+        let terminator_bits = core::cmp::min(4, data_capacity_bits - data_bits.len());
+        data_bits.resize(data_bits.len() + terminator_bits, 0);
     }
     
     // Pad to byte boundary
-    while data_bits.len() % 8 != 0 && data_bits.len() < data_capacity_bits {
+    while !data_bits.len().is_multiple_of(8) && data_bits.len() < data_capacity_bits {
         data_bits.push(0);
     }
     
@@ -49,231 +116,102 @@ fn add_padding(data_bits: &mut Vec<u8>, version: Version, error_correction: Erro
     }
 }
 
-fn encode_numeric(data: &str, _version: Version) -> Vec<u8> {
-    let mut bits = Vec::new();
-    
+/// `None` at the first byte that is not a digit
+fn encode_numeric(data: &[u8], version: Version, bits: &mut Vec<u8>) -> Option<()> {
     // Mode indicator (4 bits) - Numeric = 0001
     bits.extend_from_slice(&[0, 0, 0, 1]);
     
-    // Character count (10 bits for Version 3)
-    let count = data.len();
-    for i in (0..10).rev() {
-        bits.push(((count >> i) & 1) as u8);
-    }
+    push_bits(bits, data.len() as u32, char_count_bits(DataMode::Numeric, version));
     
-    // Encode digits in groups of 3
-    let digits: Vec<char> = data.chars().collect();
-    for chunk in digits.chunks(3) {
-        match chunk.len() {
-            3 => {
-                let val = chunk[0].to_digit(10).unwrap() * 100 + 
-                         chunk[1].to_digit(10).unwrap() * 10 + 
-                         chunk[2].to_digit(10).unwrap();
-                for i in (0..10).rev() {
-                    bits.push(((val >> i) & 1) as u8);
-                }
-            }
-            2 => {
-                let val = chunk[0].to_digit(10).unwrap() * 10 + 
-                         chunk[1].to_digit(10).unwrap();
-                for i in (0..7).rev() {
-                    bits.push(((val >> i) & 1) as u8);
-                }
-            }
-            1 => {
-                let val = chunk[0].to_digit(10).unwrap();
-                for i in (0..4).rev() {
-                    bits.push(((val >> i) & 1) as u8);
-                }
-            }
-            _ => {}
+    // Encode digits in groups of 3: 10 bits for three, 7 for two and 4 for one
+    for chunk in data.chunks(3) {
+        let mut value = 0;
+        for &digit in chunk {
+            value = value * 10 + (digit as char).to_digit(10)?;
         }
+        push_bits(bits, value, 3 * chunk.len() + 1);
     }
-    
-    bits
+    Some(())
 }
 
-fn encode_byte(data: &str, _version: Version) -> Vec<u8> {
-    let mut bits = Vec::new();
-    
+fn encode_byte(data: &[u8], version: Version, bits: &mut Vec<u8>) {
     // Mode indicator (4 bits) - Byte = 0100
     bits.extend_from_slice(&[0, 1, 0, 0]);
     
-    // Character count (8 bits for Version 3)
-    let count = data.len();
-    for i in (0..8).rev() {
-        bits.push(((count >> i) & 1) as u8);
-    }
+    push_bits(bits, data.len() as u32, char_count_bits(DataMode::Byte, version));
     
     // Encode each byte
-    for byte in data.bytes() {
-        for i in (0..8).rev() {
-            bits.push(((byte >> i) & 1) as u8);
-        }
+    for &byte in data {
+        push_bits(bits, byte as u32, 8);
     }
-    
-    bits
 }
 
-fn encode_alphanumeric(data: &str, _version: Version) -> Vec<u8> {
-    let mut bits = Vec::new();
-    
+/// `None` at the first byte with no alphanumeric character
+fn encode_alphanumeric(data: &[u8], version: Version, bits: &mut Vec<u8>) -> Option<()> {
     // Mode indicator (4 bits) - Alphanumeric = 0010
     bits.extend_from_slice(&[0, 0, 1, 0]);
     
-    // Character count (9 bits for Version 3)
-    let count = data.len();
-    for i in (0..9).rev() {
-        bits.push(((count >> i) & 1) as u8);
-    }
+    push_bits(bits, data.len() as u32, char_count_bits(DataMode::Alphanumeric, version));
     
     // Encode character pairs
-    let chars: Vec<char> = data.chars().collect();
-    for chunk in chars.chunks(2) {
+    for chunk in data.chunks(2) {
         if chunk.len() == 2 {
-            let val1 = alphanumeric_value(chunk[0]);
-            let val2 = alphanumeric_value(chunk[1]);
-            let combined = val1 * 45 + val2;
-            for i in (0..11).rev() {
-                bits.push(((combined >> i) & 1) as u8);
-            }
+            let combined = alphanumeric_value(chunk[0] as char)? * 45 + alphanumeric_value(chunk[1] as char)?;
+            push_bits(bits, combined as u32, 11);
         } else {
-            let val = alphanumeric_value(chunk[0]);
-            for i in (0..6).rev() {
-                bits.push(((val >> i) & 1) as u8);
-            }
+            push_bits(bits, alphanumeric_value(chunk[0] as char)? as u32, 6);
         }
     }
-    
-    bits
+    Some(())
 }
 
-fn alphanumeric_value(c: char) -> u16 {
+fn alphanumeric_value(c: char) -> Option<u16> {
     match c {
-        '0'..='9' => (c as u16) - ('0' as u16),
-        'A'..='Z' => (c as u16) - ('A' as u16) + 10,
-        ' ' => 36, '$' => 37, '%' => 38, '*' => 39, '+' => 40,
-        '-' => 41, '.' => 42, '/' => 43, ':' => 44,
-        _ => 0, // Invalid character, treat as 0
+        '0'..='9' => Some((c as u16) - ('0' as u16)),
+        'A'..='Z' => Some((c as u16) - ('A' as u16) + 10),
+        ' ' => Some(36), '$' => Some(37), '%' => Some(38), '*' => Some(39), '+' => Some(40),
+        '-' => Some(41), '.' => Some(42), '/' => Some(43), ':' => Some(44),
+        _ => None,
     }
 }
 
-fn generate_ecc(data_bits: &[u8], version: Version, error_correction: ErrorCorrection) -> Vec<u8> {
-    // Get block structure info
-    let (num_blocks_group1, data_codewords_group1, num_blocks_group2, data_codewords_group2, ecc_codewords_per_block) = 
-        get_block_info(version, error_correction);
-    
-    // Convert bits to bytes
-    let mut data_bytes = Vec::new();
-    for chunk in data_bits.chunks(8) {
+fn generate_ecc(encoded: &mut EncodedData, version: Version, error_correction: ErrorCorrection, diagnostics: Option<&Diagnostics>) {
+    let layout = get_block_layout(version, error_correction);
+    let ecc_per_block = layout.ecc_per_block;
+    let EncodedData { data_bits, stream, codewords } = encoded;
+    
+    // Convert bits to bytes, cut or padded to the blocks' total length
+    let data_len = layout.data_ranges().last().map_or(0, |range| range.end);
+    codewords.clear();
+    for chunk in data_bits.chunks(8).take(data_len) {
         let mut byte = 0u8;
         for (i, &bit) in chunk.iter().enumerate() {
             byte |= bit << (7 - i);
         }
-        data_bytes.push(byte);
+        codewords.push(byte);
     }
+    codewords.resize(data_len, 0xEC); // Pad with standard padding
     
-    // Split data into blocks
-    let mut data_blocks = Vec::new();
-    let mut byte_index = 0;
-    
-    // Group 1 blocks
-    for _ in 0..num_blocks_group1 {
-        let block_size = data_codewords_group1;
-        let block = if byte_index + block_size <= data_bytes.len() {
-            data_bytes[byte_index..byte_index + block_size].to_vec()
-        } else {
-            let mut block = data_bytes[byte_index..].to_vec();
-            block.resize(block_size, 0xEC); // Pad with standard padding
-            block
-        };
-        data_blocks.push(block);
-        byte_index += block_size;
-    }
-    
-    // Group 2 blocks
-    for _ in 0..num_blocks_group2 {
-        let block_size = data_codewords_group2;
-        let block = if byte_index + block_size <= data_bytes.len() {
-            data_bytes[byte_index..byte_index + block_size].to_vec()
-        } else {
-            let mut block = data_bytes[byte_index..].to_vec();
-            block.resize(block_size, 0xEC); // Pad with standard padding
-            block
-        };
-        data_blocks.push(block);
-        byte_index += block_size;
+    // Generate ECC for each block, group 1 blocks first
+    codewords.resize(data_len + layout.total_blocks() * ecc_per_block, 0);
+    let (data_blocks, ecc_blocks) = codewords.split_at_mut(data_len);
+    for (range, ecc_block) in layout.data_ranges().zip(ecc_blocks.chunks_mut(ecc_per_block)) {
+        generate_ecc_into(&data_blocks[range], ecc_block);
     }
     
-    // Generate ECC for each block
-    let mut ecc_blocks = Vec::new();
-    for data_block in &data_blocks {
-        let ecc_block = generate_reed_solomon_ecc(data_block, ecc_codewords_per_block);
-        ecc_blocks.push(ecc_block);
+    if let Some(diagnostics) = diagnostics {
+        let data = layout.data_ranges().map(|range| data_blocks[range].to_vec()).collect();
+        let ecc = ecc_blocks.chunks(ecc_per_block).map(<[u8]>::to_vec).collect();
+        diagnostics.record(Stage::Blocks { data, ecc });
     }
     
-    // Print verbose block information
-    if std::env::args().any(|arg| arg == "--verbose" || arg == "-V") {
-        println!("\n=== Block Structure ===");
-        println!("Group 1: {} blocks of {} data codewords each", num_blocks_group1, data_codewords_group1);
-        if num_blocks_group2 > 0 {
-            println!("Group 2: {} blocks of {} data codewords each", num_blocks_group2, data_codewords_group2);
-        }
-        println!("ECC codewords per block: {}", ecc_codewords_per_block);
-        
-        for (i, block) in data_blocks.iter().enumerate() {
-            println!("Data Block {}: {} bytes", i + 1, block.len());
-            println!("  Hex: {}", block.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "));
-        }
-        
-        for (i, block) in ecc_blocks.iter().enumerate() {
-            println!("ECC Block {}: {} bytes", i + 1, block.len());
-            println!("  Hex: {}", block.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" "));
-        }
+    // Interleave the data codewords of all blocks, then their ECC codewords, one at a time
+    stream.clear();
+    let longest = layout.data_ranges().map(|range| range.len()).max().unwrap_or(0);
+    for i in 0..longest {
+        stream.extend(layout.data_ranges().filter(|range| i < range.len()).map(|range| data_blocks[range.start + i]));
     }
-    
-    // Interleave and convert back to bits
-    let mut all_ecc_bits = Vec::new();
-    
-    // Interleave ECC blocks byte by byte
-    let max_ecc_bytes = ecc_blocks.iter().map(|b| b.len()).max().unwrap_or(0);
-    for byte_index in 0..max_ecc_bytes {
-        for block in &ecc_blocks {
-            if byte_index < block.len() {
-                // Convert byte to bits
-                for bit_pos in 0..8 {
-                    all_ecc_bits.push((block[byte_index] >> (7 - bit_pos)) & 1);
-                }
-            }
-        }
-    }
-    
-    all_ecc_bits
-}
-
-fn get_block_info(version: Version, error_correction: ErrorCorrection) -> (usize, usize, usize, usize, usize) {
-    // Returns: (num_blocks_group1, data_codewords_group1, num_blocks_group2, data_codewords_group2, ecc_codewords_per_block)
-    match (version, error_correction) {
-        // Version 1
-        (Version::V1, ErrorCorrection::L) => (1, 19, 0, 0, 7),
-        (Version::V1, ErrorCorrection::M) => (1, 16, 0, 0, 10),
-        (Version::V1, ErrorCorrection::Q) => (1, 13, 0, 0, 13),
-        (Version::V1, ErrorCorrection::H) => (1, 9, 0, 0, 17),
-        // Version 2
-        (Version::V2, ErrorCorrection::L) => (1, 34, 0, 0, 10),
-        (Version::V2, ErrorCorrection::M) => (1, 28, 0, 0, 16),
-        (Version::V2, ErrorCorrection::Q) => (1, 22, 0, 0, 22),
-        (Version::V2, ErrorCorrection::H) => (1, 16, 0, 0, 28),
-        // Version 3
-        (Version::V3, ErrorCorrection::L) => (1, 55, 0, 0, 15),
-        (Version::V3, ErrorCorrection::M) => (1, 44, 0, 0, 26),
-        (Version::V3, ErrorCorrection::Q) => (2, 17, 0, 0, 18),
-        (Version::V3, ErrorCorrection::H) => (2, 13, 0, 0, 22),
-        // Version 4
-        (Version::V4, ErrorCorrection::L) => (1, 80, 0, 0, 20),
-        (Version::V4, ErrorCorrection::M) => (2, 32, 0, 0, 18),
-        (Version::V4, ErrorCorrection::Q) => (2, 24, 0, 0, 26),
-        (Version::V4, ErrorCorrection::H) => (4, 9, 0, 0, 16),
-        _ => (1, 16, 0, 0, 10), // Default fallback
+    for i in 0..ecc_per_block {
+        stream.extend(ecc_blocks.chunks(ecc_per_block).map(|block| block[i]));
     }
 }
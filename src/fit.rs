@@ -0,0 +1,227 @@
+//! Whether a payload fits in a QR code, and when it does not, by how much and what would make
+//! it fit: a lower error correction level, a denser data mode, or several symbols joined with
+//! structured append.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use core::fmt;
+use crate::capacity::{max_characters, smallest_version_for};
+use crate::types::{DataMode, ErrorCorrection, Version};
+
+/// Most symbols a structured append sequence can join
+pub const MAX_STRUCTURED_APPEND_SYMBOLS: usize = 16;
+
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// A change that makes an oversized payload fit in a single version 40 symbol, or failing
+/// that, in a structured append sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Suggestion {
+    /// The payload fits as it is at this lower level
+    ErrorCorrection(ErrorCorrection),
+    /// The payload fits as it is in this denser mode
+    Mode(DataMode),
+    /// The payload fits in alphanumeric mode once uppercased
+    Uppercase,
+    /// The payload fits split across this many symbols
+    StructuredAppend(usize),
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Suggestion::ErrorCorrection(level) => write!(f, "fits at error correction {:?}", level),
+            Suggestion::Mode(mode) => write!(f, "fits in {} mode", mode.to_string().to_lowercase()),
+            Suggestion::Uppercase => write!(f, "fits if uppercased, in alphanumeric mode"),
+            Suggestion::StructuredAppend(symbols) => write!(f, "fits split across {} symbols with structured append", symbols),
+        }
+    }
+}
+
+/// A payload that does not fit in a version 40 symbol at the requested level and mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadTooLarge {
+    /// Characters in the payload: digits, alphanumeric characters or bytes, as for the mode
+    pub characters: usize,
+    /// Characters a version 40 symbol holds
+    pub capacity: usize,
+    pub error_correction: ErrorCorrection,
+    pub data_mode: DataMode,
+    /// What would make it fit, most preferable first
+    pub suggestions: Vec<Suggestion>,
+}
+
+impl PayloadTooLarge {
+    /// Characters over the version 40 capacity
+    pub fn excess(&self) -> usize {
+        self.characters - self.capacity
+    }
+}
+
+impl fmt::Display for PayloadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} characters do not fit in {} mode at error correction {:?}: version 40 holds {} ({} over)",
+            self.characters,
+            self.data_mode.to_string().to_lowercase(),
+            self.error_correction,
+            self.capacity,
+            self.excess()
+        )?;
+        for suggestion in &self.suggestions {
+            write!(f, "\n  {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for PayloadTooLarge {}
+
+/// A payload byte the requested data mode has no character for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidCharacter {
+    /// Offset of the byte in the payload
+    pub position: usize,
+    pub byte: u8,
+    pub data_mode: DataMode,
+}
+
+impl fmt::Display for InvalidCharacter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.byte.is_ascii_graphic() || self.byte == b' ' {
+            write!(f, "'{}'", self.byte as char)?;
+        } else {
+            write!(f, "byte 0x{:02X}", self.byte)?;
+        }
+        let characters = match self.data_mode {
+            DataMode::Numeric => "the digits 0-9",
+            DataMode::Alphanumeric => "0-9, A-Z, space and $%*+-./:",
+            DataMode::Byte => "any byte",
+        };
+        write!(f, " at position {} cannot be encoded in {} mode, which holds {}", self.position, self.data_mode.to_string().to_lowercase(), characters)
+    }
+}
+
+impl core::error::Error for InvalidCharacter {}
+
+/// Whether `data_mode` has a character for `byte`
+pub fn mode_holds(data_mode: DataMode, byte: u8) -> bool {
+    match data_mode {
+        DataMode::Numeric => byte.is_ascii_digit(),
+        DataMode::Alphanumeric => ALPHANUMERIC.contains(&byte),
+        DataMode::Byte => true,
+    }
+}
+
+/// The first byte of `data` that `data_mode` cannot encode, if any. Check this before
+/// [`check_fit`]: the encoder keeps a payload its mode cannot hold in byte mode instead.
+pub fn check_mode(data: &[u8], data_mode: DataMode) -> Result<(), InvalidCharacter> {
+    match data.iter().position(|&byte| !mode_holds(data_mode, byte)) {
+        Some(position) => Err(InvalidCharacter { position, byte: data[position], data_mode }),
+        None => Ok(()),
+    }
+}
+
+/// The smallest version that holds `data`, or how far it is from fitting and what would help
+pub fn check_fit(data: &[u8], error_correction: ErrorCorrection, data_mode: DataMode) -> Result<Version, PayloadTooLarge> {
+    smallest_version_for(data.len(), error_correction, data_mode).ok_or_else(|| PayloadTooLarge {
+        characters: data.len(),
+        capacity: max_characters(Version::V40, error_correction, data_mode),
+        error_correction,
+        data_mode,
+        suggestions: suggestions(data, error_correction, data_mode),
+    })
+}
+
+/// Each kind of change, tried on its own: the strongest lower level that fits, the densest
+/// mode the payload allows, and the fewest symbols of a structured append sequence
+fn suggestions(data: &[u8], error_correction: ErrorCorrection, data_mode: DataMode) -> Vec<Suggestion> {
+    let fits = |level: ErrorCorrection, mode: DataMode| smallest_version_for(data.len(), level, mode).is_some();
+    let mut suggestions = Vec::new();
+
+    if let Some(level) = [ErrorCorrection::Q, ErrorCorrection::M, ErrorCorrection::L]
+        .into_iter()
+        .find(|&level| (level as u8) < error_correction as u8 && fits(level, data_mode))
+    {
+        suggestions.push(Suggestion::ErrorCorrection(level));
+    }
+
+    let alphanumeric = |c: &u8| mode_holds(DataMode::Alphanumeric, *c);
+    let denser = if data_mode != DataMode::Numeric && data.iter().all(u8::is_ascii_digit) {
+        Some((Suggestion::Mode(DataMode::Numeric), DataMode::Numeric))
+    } else if data_mode == DataMode::Byte && data.iter().all(alphanumeric) {
+        Some((Suggestion::Mode(DataMode::Alphanumeric), DataMode::Alphanumeric))
+    } else if data_mode == DataMode::Byte && data.iter().map(u8::to_ascii_uppercase).all(|c| alphanumeric(&c)) {
+        Some((Suggestion::Uppercase, DataMode::Alphanumeric))
+    } else {
+        None
+    };
+    if let Some((suggestion, _)) = denser.filter(|&(_, mode)| fits(error_correction, mode)) {
+        suggestions.push(suggestion);
+    }
+
+    let per_symbol = max_characters(Version::V40, error_correction, data_mode) - structured_append_overhead(data_mode);
+    let symbols = data.len().div_ceil(per_symbol);
+    if symbols <= MAX_STRUCTURED_APPEND_SYMBOLS {
+        suggestions.push(Suggestion::StructuredAppend(symbols));
+    }
+    suggestions
+}
+
+/// Characters of `data_mode` displaced by the 20-bit structured append header (mode
+/// indicator, symbol position and count, parity) that starts every symbol of a sequence
+fn structured_append_overhead(data_mode: DataMode) -> usize {
+    match data_mode {
+        // 10 bits per 3 digits
+        DataMode::Numeric => 6,
+        // 11 bits per 2 characters
+        DataMode::Alphanumeric => 4,
+        DataMode::Byte => 3,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_fit_returns_the_smallest_version() {
+        assert_eq!(check_fit(b"HELLO WORLD", ErrorCorrection::Q, DataMode::Alphanumeric), Ok(Version::V1));
+        assert_eq!(check_fit(&[b'a'; 2331], ErrorCorrection::M, DataMode::Byte), Ok(Version::V40));
+    }
+
+    #[test]
+    fn test_check_mode_reports_the_first_character_the_mode_lacks() {
+        assert_eq!(check_mode(b"0123", DataMode::Numeric), Ok(()));
+        assert_eq!(check_mode(b"HTTPS://EXAMPLE.COM", DataMode::Alphanumeric), Ok(()));
+        assert_eq!(check_mode(&[0x00, 0xFF], DataMode::Byte), Ok(()));
+        let error = check_mode(b"12a", DataMode::Numeric).unwrap_err();
+        assert_eq!((error.position, error.byte), (2, b'a'));
+        assert_eq!(error.to_string(), "'a' at position 2 cannot be encoded in numeric mode, which holds the digits 0-9");
+        assert_eq!(check_mode(&[0x00, 0xFF], DataMode::Numeric).unwrap_err().to_string(), "byte 0x00 at position 0 cannot be encoded in numeric mode, which holds the digits 0-9");
+        assert_eq!(check_mode(b"abc", DataMode::Alphanumeric).unwrap_err().position, 0);
+    }
+
+    #[test]
+    fn test_oversized_payload_reports_the_excess() {
+        let error = check_fit(&[b'a'; 2400], ErrorCorrection::M, DataMode::Byte).unwrap_err();
+        assert_eq!((error.capacity, error.excess()), (2331, 69));
+        assert_eq!(error.suggestions, [Suggestion::ErrorCorrection(ErrorCorrection::L), Suggestion::Uppercase, Suggestion::StructuredAppend(2)]);
+        assert_eq!(
+            error.to_string(),
+            "2400 characters do not fit in byte mode at error correction M: version 40 holds 2331 (69 over)\n  \
+             fits at error correction L\n  fits if uppercased, in alphanumeric mode\n  fits split across 2 symbols with structured append"
+        );
+    }
+
+    #[test]
+    fn test_suggestions_pick_the_densest_mode_that_applies() {
+        let digits = check_fit(&[b'7'; 3000], ErrorCorrection::H, DataMode::Byte).unwrap_err();
+        assert_eq!(digits.suggestions, [Suggestion::Mode(DataMode::Numeric), Suggestion::StructuredAppend(3)]);
+
+        // Binary data cannot change mode, and nothing fits 16 symbols of version 40
+        let binary = check_fit(&[0xFF; 50000], ErrorCorrection::L, DataMode::Byte).unwrap_err();
+        assert!(binary.suggestions.is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+use alloc::{vec, vec::Vec};
+use crate::types::ErrorCorrection;
+
+/// XOR mask applied to every format information word, so that no word is all zeros
+pub const FORMAT_MASK: u16 = 0x5412;
+
+/// BCH(15,5) generator polynomial x^10 + x^8 + x^5 + x^4 + x^2 + x + 1
+const GENERATOR: u16 = 0x537;
+
+/// Powers of α in GF(16) with primitive polynomial x^4 + x + 1
+const GF16_EXP: [u8; 15] = [1, 2, 4, 8, 3, 6, 12, 11, 5, 10, 7, 14, 15, 13, 9];
+
+/// The format information word is more than 3 bits away from every valid word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uncorrectable;
+
+impl core::fmt::Display for Uncorrectable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "format information has more than 3 bit errors")
+    }
+}
+
+impl core::error::Error for Uncorrectable {}
+
+/// 15-bit format information word for an ECC level and mask, including the BCH code and XOR
+/// mask. Bit 14 is the most significant data bit.
+pub fn encode(error_correction: ErrorCorrection, mask: u8) -> u16 {
+    let ecc_bits: u16 = match error_correction {
+        ErrorCorrection::L => 0b01,
+        ErrorCorrection::M => 0b00,
+        ErrorCorrection::Q => 0b11,
+        ErrorCorrection::H => 0b10,
+    };
+    let data = (ecc_bits << 3) | (mask & 0b111) as u16;
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * GENERATOR);
+    }
+    ((data << 10) | remainder) ^ FORMAT_MASK
+}
+
+/// Decode a format information word as read from the symbol (still masked), correcting up to
+/// 3 bit errors with BCH syndrome decoding: Berlekamp-Massey over the six syndromes finds the
+/// error locator, and a Chien search finds the flipped bits.
+pub fn decode(word: u16) -> Result<(ErrorCorrection, u8), Uncorrectable> {
+    let received = (word ^ FORMAT_MASK) & 0x7FFF;
+
+    // S_j = r(α^j) for the generator's roots α^1 .. α^6
+    let syndromes: Vec<u8> = (1..=6)
+        .map(|j| (0..15).filter(|&i| received >> i & 1 == 1).fold(0u8, |acc, i| acc ^ GF16_EXP[(i * j) % 15]))
+        .collect();
+
+    let mut corrected = received;
+    if syndromes.iter().any(|&s| s != 0) {
+        let locator = berlekamp_massey(&syndromes);
+        let degree = locator.len() - 1;
+        let errors: Vec<usize> = (0..15).filter(|&i| poly_eval(&locator, GF16_EXP[(15 - i) % 15]) == 0).collect();
+        if degree > 3 || errors.len() != degree {
+            return Err(Uncorrectable);
+        }
+        for i in errors {
+            corrected ^= 1 << i;
+        }
+    }
+
+    let data = corrected >> 10;
+    let error_correction = match data >> 3 {
+        0b01 => ErrorCorrection::L,
+        0b00 => ErrorCorrection::M,
+        0b11 => ErrorCorrection::Q,
+        _ => ErrorCorrection::H,
+    };
+    let mask = (data & 0b111) as u8;
+    // More than 3 errors can land next to another valid word; re-encoding must reproduce it
+    if encode(error_correction, mask) ^ FORMAT_MASK != corrected {
+        return Err(Uncorrectable);
+    }
+    Ok((error_correction, mask))
+}
+
+/// Number of bits by which `word` differs from the format information for this ECC level and mask
+pub fn distance(word: u16, error_correction: ErrorCorrection, mask: u8) -> u32 {
+    (word ^ encode(error_correction, mask)).count_ones()
+}
+
+/// Module positions (row, col) of format information bits 0-14 in both copies: the first runs
+/// around the top-left finder, the second is split between the top-right and bottom-left ones
+#[allow(clippy::needless_range_loop)]
+pub fn positions(size: usize) -> [[(usize, usize); 15]; 2] {
+    let mut positions = [[(0, 0); 15]; 2];
+    for i in 0..15 {
+        positions[0][i] = match i {
+            0..6 => (i, 8),
+            6 => (7, 8),
+            7 => (8, 8),
+            8 => (8, 7),
+            _ => (8, 14 - i),
+        };
+        positions[1][i] = if i < 8 { (8, size - 1 - i) } else { (size - 15 + i, 8) };
+    }
+    positions
+}
+
+fn gf16_multiply(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    GF16_EXP[(gf16_log(a) + gf16_log(b)) % 15]
+}
+
+fn gf16_log(a: u8) -> usize {
+    GF16_EXP.iter().position(|&x| x == a).unwrap_or(0)
+}
+
+fn gf16_inverse(a: u8) -> u8 {
+    GF16_EXP[(15 - gf16_log(a)) % 15]
+}
+
+/// Error locator polynomial, lowest degree first, from the syndromes S_1 ..
+fn berlekamp_massey(syndromes: &[u8]) -> Vec<u8> {
+    let mut locator = vec![1u8];
+    let mut previous = vec![1u8];
+    let mut length = 0;
+
+    for r in 0..syndromes.len() {
+        let delta = (0..locator.len().min(r + 1)).fold(0u8, |acc, i| acc ^ gf16_multiply(locator[i], syndromes[r - i]));
+        previous.insert(0, 0);
+        if delta == 0 {
+            continue;
+        }
+
+        let mut next = locator.clone();
+        next.resize(next.len().max(previous.len()), 0);
+        for (i, &coeff) in previous.iter().enumerate() {
+            next[i] ^= gf16_multiply(delta, coeff);
+        }
+        if 2 * length <= r {
+            length = r + 1 - length;
+            let inverse = gf16_inverse(delta);
+            previous = locator.iter().map(|&coeff| gf16_multiply(coeff, inverse)).collect();
+        }
+        locator = next;
+    }
+
+    while locator.len() > 1 && locator.last() == Some(&0) {
+        locator.pop();
+    }
+    locator
+}
+
+/// Evaluate a polynomial over GF(16) stored lowest degree first
+fn poly_eval(poly: &[u8], x: u8) -> u8 {
+    poly.iter().rev().fold(0u8, |acc, &coeff| gf16_multiply(acc, x) ^ coeff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVELS: [ErrorCorrection; 4] = [ErrorCorrection::L, ErrorCorrection::M, ErrorCorrection::Q, ErrorCorrection::H];
+
+    #[test]
+    fn test_encode_matches_specification_table() {
+        let table = [
+            (0b111011111000100, ErrorCorrection::L, 0),
+            (0b111001011110011, ErrorCorrection::L, 1),
+            (0b111110110101010, ErrorCorrection::L, 2),
+            (0b111100010011101, ErrorCorrection::L, 3),
+            (0b110011000101111, ErrorCorrection::L, 4),
+            (0b110001100011000, ErrorCorrection::L, 5),
+            (0b110110001000001, ErrorCorrection::L, 6),
+            (0b110100101110110, ErrorCorrection::L, 7),
+            (0b101010000010010, ErrorCorrection::M, 0),
+            (0b101000100100101, ErrorCorrection::M, 1),
+            (0b101111001111100, ErrorCorrection::M, 2),
+            (0b101101101001011, ErrorCorrection::M, 3),
+            (0b100010111111001, ErrorCorrection::M, 4),
+            (0b100000011001110, ErrorCorrection::M, 5),
+            (0b100111110010111, ErrorCorrection::M, 6),
+            (0b100101010100000, ErrorCorrection::M, 7),
+            (0b011010101011111, ErrorCorrection::Q, 0),
+            (0b011000001101000, ErrorCorrection::Q, 1),
+            (0b011111100110001, ErrorCorrection::Q, 2),
+            (0b011101000000110, ErrorCorrection::Q, 3),
+            (0b010010010110100, ErrorCorrection::Q, 4),
+            (0b010000110000011, ErrorCorrection::Q, 5),
+            (0b010111011011010, ErrorCorrection::Q, 6),
+            (0b010101111101101, ErrorCorrection::Q, 7),
+            (0b001011010001001, ErrorCorrection::H, 0),
+            (0b001001110111110, ErrorCorrection::H, 1),
+            (0b001110011100111, ErrorCorrection::H, 2),
+            (0b001100111010000, ErrorCorrection::H, 3),
+            (0b000011101100010, ErrorCorrection::H, 4),
+            (0b000001001010101, ErrorCorrection::H, 5),
+            (0b000110100001100, ErrorCorrection::H, 6),
+            (0b000100000111011, ErrorCorrection::H, 7),
+        ];
+        for (word, error_correction, mask) in table {
+            assert_eq!(encode(error_correction, mask), word, "{:?} mask {}", error_correction, mask);
+            assert_eq!(decode(word), Ok((error_correction, mask)));
+        }
+    }
+
+    #[test]
+    fn test_decode_corrects_up_to_three_bit_errors() {
+        // Two bit errors in L / mask 3 (111100010011101)
+        assert_eq!(decode(0b111100010001111), Ok((ErrorCorrection::L, 3)));
+
+        for error_correction in LEVELS {
+            for mask in 0..8 {
+                let word = encode(error_correction, mask);
+                for a in 0..15 {
+                    for b in a + 1..15 {
+                        let c = (b * 7 + a) % 15;
+                        let damaged = word ^ (1 << a) ^ (1 << b) ^ if c != a && c != b { 1 << c } else { 0 };
+                        assert_eq!(decode(damaged), Ok((error_correction, mask)));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_words_far_from_any_codeword() {
+        // Every word is either within 3 bits of exactly one codeword or rejected
+        for word in 0..1u16 << 15 {
+            let nearest = LEVELS
+                .iter()
+                .flat_map(|&ecc| (0..8).map(move |mask| (distance(word, ecc, mask), ecc, mask)))
+                .min_by_key(|&(d, _, _)| d)
+                .unwrap();
+            match decode(word) {
+                Ok(decoded) => assert_eq!((nearest.1, nearest.2), decoded, "word {:015b}", word),
+                Err(Uncorrectable) => assert!(nearest.0 > 3, "word {:015b}", word),
+            }
+        }
+    }
+}
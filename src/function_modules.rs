@@ -0,0 +1,130 @@
+//! Function patterns of a symbol: the modules that carry no data. The encoder places codewords
+//! around them, the decoder and analyzer skip them when reading, and the diagnostics classify
+//! modules by them, so all of them use this one definition.
+
+use alloc::vec::Vec;
+use crate::alignment::get_alignment_positions;
+use crate::types::Version;
+
+/// Structural role of a module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModuleRegion {
+    /// Finder pattern or its separator
+    Finder,
+    Timing,
+    Alignment,
+    /// Format information, including the dark module
+    Format,
+    Version,
+    /// Data, ECC and remainder bits
+    Data,
+}
+
+/// Role of the module at `row`, `col` of a `version` symbol. Where patterns overlap, version
+/// information wins over alignment patterns, which win over the timing patterns, which cross
+/// the format strips at row and column 6.
+pub fn module_region(row: usize, col: usize, version: Version) -> ModuleRegion {
+    let size = version.size();
+    let version_strip = size - 11..size - 8;
+    if version >= Version::V7 && ((row < 6 && version_strip.contains(&col)) || (version_strip.contains(&row) && col < 6)) {
+        return ModuleRegion::Version;
+    }
+    // Looked up by coordinate rather than by scanning `alignment_centers`: this runs for every
+    // module of every symbol encoded or read
+    let coordinates = get_alignment_positions(version);
+    let last = coordinates.len().saturating_sub(1);
+    let near = |i: usize| coordinates.iter().position(|&c| i.abs_diff(c) <= 2);
+    if let (Some(y), Some(x)) = (near(row), near(col)) && ![(0, 0), (0, last), (last, 0)].contains(&(y, x)) {
+        return ModuleRegion::Alignment;
+    }
+    let between_separators = 8..size - 8;
+    if (row == 6 && between_separators.contains(&col)) || (col == 6 && between_separators.contains(&row)) {
+        return ModuleRegion::Timing;
+    }
+    // The bottom-left strip starts with the dark module
+    if (row == 8 && (col < 9 || col >= size - 8)) || (col == 8 && (row < 9 || row >= size - 8)) {
+        return ModuleRegion::Format;
+    }
+    let near = |i: usize| i < 8;
+    let far = |i: usize| i >= size - 8;
+    if (near(row) && near(col)) || (near(row) && far(col)) || (far(row) && near(col)) {
+        return ModuleRegion::Finder;
+    }
+    ModuleRegion::Data
+}
+
+/// Position (row, col) of the dark module, which is dark in every symbol: column 8, row
+/// `4 * version + 9`, right above the bottom-left finder's separator
+pub fn dark_module(version: Version) -> (usize, usize) {
+    (4 * version as usize + 9, 8)
+}
+
+/// Whether the module at `row`, `col` belongs to a function pattern rather than the data
+pub fn is_function_module(row: usize, col: usize, version: Version) -> bool {
+    module_region(row, col, version) != ModuleRegion::Data
+}
+
+/// Classify every module of a symbol by its structural role
+pub fn module_regions(version: Version) -> Vec<Vec<ModuleRegion>> {
+    let size = version.size();
+    (0..size).map(|row| (0..size).map(|col| module_region(row, col, version)).collect()).collect()
+}
+
+/// Function modules of a symbol: finder patterns with their separators, format and version
+/// information, timing patterns, alignment patterns and the dark module
+pub fn function_module_map(version: Version) -> Vec<Vec<bool>> {
+    let size = version.size();
+    (0..size).map(|row| (0..size).map(|col| is_function_module(row, col, version)).collect()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alignment::alignment_centers;
+    use crate::capacity::get_total_codewords_in_bytes;
+    use crate::format_info;
+
+    /// Modules left over after the last codeword (ISO/IEC 18004 table 1)
+    fn remainder_bits(version: Version) -> usize {
+        match version as u8 {
+            2..=6 => 7,
+            14..=20 | 28..=34 => 3,
+            21..=27 => 4,
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn test_data_modules_hold_every_codeword() {
+        for v in 1..=40u8 {
+            let version = Version::from_u8(v).unwrap();
+            let size = version.size();
+            let regions = module_regions(version);
+            let count = |region| regions.iter().flatten().filter(|&&r| r == region).count();
+            assert_eq!(count(ModuleRegion::Data), get_total_codewords_in_bytes(version) * 8 + remainder_bits(version), "V{}", v);
+
+            let centers = alignment_centers(version).count();
+            assert_eq!(count(ModuleRegion::Alignment), 25 * centers, "V{}", v);
+            assert_eq!(count(ModuleRegion::Finder), 3 * 64);
+            assert_eq!(count(ModuleRegion::Format), 31);
+            assert_eq!(count(ModuleRegion::Version), if v >= 7 { 36 } else { 0 });
+            // Each alignment pattern on a timing line hides five of its modules
+            let on_timing = alignment_centers(version).filter(|&(x, y)| x == 6 || y == 6).count();
+            assert_eq!(count(ModuleRegion::Timing), 2 * (size - 16) - 5 * on_timing, "V{}", v);
+
+            let function_modules = function_module_map(version).iter().flatten().filter(|&&f| f).count();
+            assert_eq!(function_modules + count(ModuleRegion::Data), size * size);
+        }
+    }
+
+    #[test]
+    fn test_dark_module_sits_between_the_format_copies() {
+        for v in 1..=40u8 {
+            let version = Version::from_u8(v).unwrap();
+            let (row, col) = dark_module(version);
+            assert_eq!((row, col), (version.size() - 8, 8), "V{}", v);
+            assert_eq!(module_region(row, col, version), ModuleRegion::Format);
+            assert!(format_info::positions(version.size()).iter().flatten().all(|&position| position != (row, col)), "V{}", v);
+        }
+    }
+}
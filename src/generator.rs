@@ -1,63 +1,275 @@
+use alloc::{vec, vec::Vec};
 use crate::types::{Version, ErrorCorrection, MaskPattern, DataMode, QrConfig};
-use crate::mask::apply_mask;
-use crate::encoding::{encode_data, EncodedData};
-use crate::alignment::{is_alignment_pattern, get_alignment_positions};
-use crate::capacity::get_unencoded_capacity_in_bytes;
+use crate::mask::{apply_mask, penalty_score, Penalty};
+use crate::encoding::{encode_data_into, encode_segment, segment_mode, EncodedData};
+use crate::alignment::alignment_centers;
+use crate::function_modules::{dark_module, is_function_module};
+use crate::capacity::{get_block_layout, smallest_version_for};
+use crate::fit::{check_fit, PayloadTooLarge};
+use crate::format_info;
+use crate::version_info;
+use crate::diagnostics::Stage;
 
-pub fn generate_qr_matrix(data: &str, config: &QrConfig) -> Vec<Vec<u8>> {
+/// Snapshot of the matrix after one step of construction
+#[derive(Debug, Clone)]
+pub struct ConstructionStage {
+    pub name: &'static str,
+    pub matrix: Vec<Vec<u8>>,
+}
+
+/// Module matrix (1 = dark) of `data` in the smallest version that holds it. A payload too
+/// large for version 40 is cut short; [`try_generate_qr_matrix`] reports it instead.
+pub fn generate_qr_matrix(data: &[u8], config: &QrConfig) -> Vec<Vec<u8>> {
+    let mut matrix = Vec::new();
+    build_matrix(data, config, &mut Buffers::default(), &mut matrix, &mut |_, _| {});
+    matrix
+}
+
+/// [`generate_qr_matrix`], failing with how far over capacity `data` is and what would make it
+/// fit when even version 40 is too small
+pub fn try_generate_qr_matrix(data: &[u8], config: &QrConfig) -> Result<Vec<Vec<u8>>, PayloadTooLarge> {
+    check_fit(data, config.error_correction, segment_mode(data, config.data_mode))?;
+    Ok(generate_qr_matrix(data, config))
+}
+
+/// Builds the same symbols as [`generate_qr_matrix`], but keeps its working buffers from one
+/// call to the next. Once warmed up it allocates nothing unless a symbol is larger than any
+/// before it, which adds up when small symbols are made by the thousand.
+///
+/// ```
+/// use qr_tools::generator::Encoder;
+/// use qr_tools::types::QrConfig;
+///
+/// let mut encoder = Encoder::new(QrConfig::default());
+/// let mut matrix = Vec::new();
+/// for id in 1..=3 {
+///     encoder.encode_into(format!("https://example.com/{}", id).as_bytes(), &mut matrix);
+///     assert_eq!(matrix.len(), 25);
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct Encoder {
+    config: QrConfig,
+    buffers: Buffers,
+}
+
+impl Encoder {
+    pub fn new(config: QrConfig) -> Encoder {
+        Encoder { config, buffers: Buffers::default() }
+    }
+
+    pub fn config(&self) -> &QrConfig {
+        &self.config
+    }
+
+    /// Encode `data` into `matrix`, resizing it to the symbol and overwriting every module.
+    /// Pass the same matrix each time to reuse its rows too.
+    pub fn encode_into(&mut self, data: &[u8], matrix: &mut Vec<Vec<u8>>) {
+        build_matrix(data, &self.config, &mut self.buffers, matrix, &mut |_, _| {});
+    }
+}
+
+/// Working memory of `build_matrix`
+#[derive(Debug, Clone, Default)]
+struct Buffers {
+    encoded: EncodedData,
+}
+
+/// Generate the matrix and keep a copy after each construction step: function patterns,
+/// data placement, masking and format information. The last stage is the finished symbol.
+pub fn generate_qr_stages(data: &[u8], config: &QrConfig) -> Vec<ConstructionStage> {
+    let mut stages = Vec::new();
+    build_matrix(data, config, &mut Buffers::default(), &mut Vec::new(), &mut |name, matrix| {
+        stages.push(ConstructionStage { name, matrix: matrix.to_vec() });
+    });
+    stages
+}
+
+/// The finished symbol under one mask pattern, as `qr generate --explain-mask` shows it
+#[derive(Debug, Clone)]
+pub struct MaskCandidate {
+    pub mask: MaskPattern,
+    pub penalty: Penalty,
+    pub matrix: Vec<Vec<u8>>,
+}
+
+/// The symbol of `data` under each of the eight mask patterns, in pattern order. Masking is
+/// applied whatever `config.skip_mask` says.
+pub fn mask_candidates(data: &[u8], config: &QrConfig) -> Vec<MaskCandidate> {
+    (0..8)
+        .map(|index| {
+            let mask = MaskPattern::from_index(index);
+            let config = QrConfig { mask_pattern: mask, skip_mask: false, diagnostics: None, ..config.clone() };
+            let matrix = generate_qr_matrix(data, &config);
+            MaskCandidate { mask, penalty: penalty_score(&matrix), matrix }
+        })
+        .collect()
+}
+
+/// The mask ISO/IEC 18004 section 7.8.3 selects: the lowest total penalty, and of several
+/// equal ones the lowest pattern number
+pub fn lowest_penalty_mask(candidates: &[MaskCandidate]) -> Option<MaskPattern> {
+    candidates.iter().min_by_key(|candidate| candidate.penalty.total).map(|candidate| candidate.mask)
+}
+
+/// What the encoder chose for a payload, as reported by `qr generate --meta-json`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SymbolMetadata {
+    pub version: u8,
+    /// Modules per side
+    pub size: usize,
+    pub error_correction: ErrorCorrection,
+    /// Mask pattern number, `None` when masking was skipped
+    pub mask: Option<u8>,
+    pub mode: DataMode,
+    pub segments: Vec<Segment>,
+    pub codewords: CodewordCounts,
+    /// Penalty of the finished symbol under the chosen mask
+    pub penalty: Penalty,
+    /// The finished symbol, in the compact [`packed`](crate::matrix::packed) form
+    #[serde(with = "crate::matrix::packed")]
+    pub matrix: Vec<Vec<u8>>,
+}
+
+/// One run of the payload encoded in a single mode
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Segment {
+    pub mode: DataMode,
+    /// Characters (bytes in byte mode) in the segment
+    pub characters: usize,
+    /// Bits including the mode indicator and character count
+    pub bits: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CodewordCounts {
+    pub total: usize,
+    pub data: usize,
+    pub ecc: usize,
+    /// Data codewords taken up by the segments; the rest are terminator and padding
+    pub used: usize,
+    pub blocks: Vec<BlockGroup>,
+}
+
+/// Blocks of the same length
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BlockGroup {
+    pub count: usize,
+    pub data_codewords: usize,
+    pub ecc_codewords: usize,
+}
+
+/// Describe the symbol [`generate_qr_matrix`] builds for `data`
+pub fn symbol_metadata(data: &[u8], config: &QrConfig) -> SymbolMetadata {
+    let version = calculate_version(data, config.error_correction, config.data_mode);
+    let segment_bits = encode_segment(data, version, config.data_mode).len();
+    let layout = get_block_layout(version, config.error_correction);
+    let blocks: Vec<BlockGroup> = [(layout.group1_blocks, layout.group1_data_codewords), (layout.group2_blocks, layout.group2_data_codewords)]
+        .into_iter()
+        .filter(|&(count, _)| count > 0)
+        .map(|(count, data_codewords)| BlockGroup { count, data_codewords, ecc_codewords: layout.ecc_per_block })
+        .collect();
+    let data_codewords: usize = blocks.iter().map(|group| group.count * group.data_codewords).sum();
+    let ecc_codewords = layout.total_blocks() * layout.ecc_per_block;
+    let matrix = generate_qr_matrix(data, config);
+
+    SymbolMetadata {
+        version: version as u8,
+        size: version.size(),
+        error_correction: config.error_correction,
+        mask: (!config.skip_mask).then_some(config.mask_pattern as u8),
+        mode: segment_mode(data, config.data_mode),
+        segments: vec![Segment { mode: segment_mode(data, config.data_mode), characters: data.len(), bits: segment_bits }],
+        codewords: CodewordCounts {
+            total: data_codewords + ecc_codewords,
+            data: data_codewords,
+            ecc: ecc_codewords,
+            used: segment_bits.div_ceil(8).min(data_codewords),
+            blocks,
+        },
+        penalty: penalty_score(&matrix),
+        matrix,
+    }
+}
+
+fn build_matrix(data: &[u8], config: &QrConfig, buffers: &mut Buffers, matrix: &mut Vec<Vec<u8>>, on_stage: &mut dyn FnMut(&'static str, &[Vec<u8>])) {
     let version = calculate_version(data, config.error_correction, config.data_mode);
     let size = 21 + (version as usize - 1) * 4;
-    let mut matrix = vec![vec![0u8; size]; size];
+    matrix.resize_with(size, Vec::new);
+    for row in matrix.iter_mut() {
+        row.clear();
+        row.resize(size, 0);
+    }
 
     // Add finder patterns
-    add_position_pattern(&mut matrix, 0, 0);
-    add_position_pattern(&mut matrix, size - 7, 0);
-    add_position_pattern(&mut matrix, 0, size - 7);
+    add_position_pattern(matrix, 0, 0);
+    add_position_pattern(matrix, size - 7, 0);
+    add_position_pattern(matrix, 0, size - 7);
 
     // Add separators (white borders around finder patterns)
-    add_timing_patterns(&mut matrix, size);
-    add_alignment_patterns(&mut matrix, version);
-    add_dark_module(&mut matrix, version);
+    add_timing_patterns(matrix, size);
+    add_alignment_patterns(matrix, version);
+    add_dark_module(matrix, version);
 
     if version >= Version::V7 {
-        add_version_info(&mut matrix, version);
+        add_version_info(matrix, version);
     }
+    on_stage("function patterns", matrix);
 
-    let encoded = encode_data(data, version, config.error_correction, config.data_mode);
-    place_data_bits(&mut matrix, &encoded, version);
+    let diagnostics = config.diagnostics.as_ref();
+    if let Some(diagnostics) = diagnostics {
+        let bits = encode_segment(data, version, config.data_mode).len();
+        diagnostics.record(Stage::Segments { segments: vec![Segment { mode: segment_mode(data, config.data_mode), characters: data.len(), bits }] });
+    }
+    encode_data_into(data, version, config.error_correction, config.data_mode, diagnostics, &mut buffers.encoded);
+    if let Some(diagnostics) = diagnostics {
+        diagnostics.record(Stage::Codewords { codewords: buffers.encoded.stream.clone() });
+    }
+    place_codewords(matrix, &buffers.encoded.stream, version);
+    on_stage("data placement", matrix);
+
+    if let Some(diagnostics) = diagnostics {
+        let penalties = (0..8)
+            .map(|mask| {
+                let mut masked = matrix.clone();
+                apply_mask(&mut masked, MaskPattern::from_index(mask));
+                add_format_info(&mut masked, config.error_correction, MaskPattern::from_index(mask));
+                penalty_score(&masked)
+            })
+            .collect();
+        diagnostics.record(Stage::MaskScores { penalties, applied: (!config.skip_mask).then_some(config.mask_pattern as u8) });
+    }
 
     if !config.skip_mask {
-        apply_mask(&mut matrix, config.mask_pattern);
+        apply_mask(matrix, config.mask_pattern);
+        on_stage("masking", matrix);
     }
 
-    add_format_info(&mut matrix, config.error_correction, config.mask_pattern);
+    add_format_info(matrix, config.error_correction, config.mask_pattern);
+    on_stage("format info", matrix);
+}
 
-    matrix
+pub fn calculate_version(data: &[u8], error_correction: ErrorCorrection, data_mode: DataMode) -> Version {
+    smallest_version_for(data.len(), error_correction, segment_mode(data, data_mode)).unwrap_or(Version::V40)
 }
 
-pub fn calculate_version(data: &str, error_correction: ErrorCorrection, data_mode: DataMode) -> Version {
-    for version in 1..=40 {
-        let version_enum = match version {
-            1 => Version::V1, 2 => Version::V2, 3 => Version::V3, 4 => Version::V4, 5 => Version::V5,
-            6 => Version::V6, 7 => Version::V7, 8 => Version::V8, 9 => Version::V9, 10 => Version::V10,
-            11 => Version::V11, 12 => Version::V12, 13 => Version::V13, 14 => Version::V14, 15 => Version::V15,
-            16 => Version::V16, 17 => Version::V17, 18 => Version::V18, 19 => Version::V19, 20 => Version::V20,
-            21 => Version::V21, 22 => Version::V22, 23 => Version::V23, 24 => Version::V24, 25 => Version::V25,
-            26 => Version::V26, 27 => Version::V27, 28 => Version::V28, 29 => Version::V29, 30 => Version::V30,
-            31 => Version::V31, 32 => Version::V32, 33 => Version::V33, 34 => Version::V34, 35 => Version::V35,
-            36 => Version::V36, 37 => Version::V37, 38 => Version::V38, 39 => Version::V39, 40 => Version::V40,
-            _ => continue,
-        };
-        
-        let capacity = get_unencoded_capacity_in_bytes(version_enum, error_correction, data_mode);
-        if data.len() <= capacity {
-            return version_enum;
-        }
-    }
-    Version::V40
+/// Whether `data` fits a version 40 symbol at all
+pub fn fits_largest_version(data: &[u8], error_correction: ErrorCorrection, data_mode: DataMode) -> bool {
+    smallest_version_for(data.len(), error_correction, data_mode).is_some()
 }
 
-fn add_position_pattern(matrix: &mut Vec<Vec<u8>>, x: usize, y: usize) {
+/// The highest error correction level, starting from `minimum`, that still fits `data` in the
+/// version `minimum` needs. The extra recovery comes for free: the symbol stays the same size.
+pub fn boost_error_correction(data: &[u8], minimum: ErrorCorrection, data_mode: DataMode) -> ErrorCorrection {
+    let version = calculate_version(data, minimum, data_mode);
+    [ErrorCorrection::H, ErrorCorrection::Q, ErrorCorrection::M]
+        .into_iter()
+        .filter(|&level| level as u8 > minimum as u8)
+        .find(|&level| fits_largest_version(data, level, data_mode) && calculate_version(data, level, data_mode) == version)
+        .unwrap_or(minimum)
+}
+
+fn add_position_pattern(matrix: &mut [Vec<u8>], x: usize, y: usize) {
     let size = matrix.len();
     
     // White border (9x9)
@@ -105,7 +317,7 @@ fn add_position_pattern(matrix: &mut Vec<Vec<u8>>, x: usize, y: usize) {
     }
 }
 
-fn add_alignment_pattern(matrix: &mut Vec<Vec<u8>>, x: usize, y: usize) {
+fn add_alignment_pattern(matrix: &mut [Vec<u8>], x: usize, y: usize) {
     for dy in 0..5 {
         for dx in 0..5 {
             matrix[y + dy][x + dx] = if (dy == 0 || dy == 4 || dx == 0 || dx == 4) || (dy == 2 && dx == 2) { 1 } else { 0 };
@@ -113,239 +325,187 @@ fn add_alignment_pattern(matrix: &mut Vec<Vec<u8>>, x: usize, y: usize) {
     }
 }
 
-fn add_timing_patterns(matrix: &mut Vec<Vec<u8>>, size: usize) {
+#[allow(clippy::needless_range_loop)]
+fn add_timing_patterns(matrix: &mut [Vec<u8>], size: usize) {
     for i in 8..size-8 {
         matrix[6][i] = ((i + 1) % 2) as u8;
         matrix[i][6] = ((i + 1) % 2) as u8;
     }
 }
 
-fn get_format_info(error_correction: ErrorCorrection, mask_pattern: MaskPattern) -> u16 {
-    let ec_bits = match error_correction {
-        ErrorCorrection::L => 0b01,
-        ErrorCorrection::M => 0b00,
-        ErrorCorrection::Q => 0b11,
-        ErrorCorrection::H => 0b10,
-    };
-    
-    let mask_bits = match mask_pattern {
-        MaskPattern::Pattern0 => 0b000,
-        MaskPattern::Pattern1 => 0b001,
-        MaskPattern::Pattern2 => 0b010,
-        MaskPattern::Pattern3 => 0b011,
-        MaskPattern::Pattern4 => 0b100,
-        MaskPattern::Pattern5 => 0b101,
-        MaskPattern::Pattern6 => 0b110,
-        MaskPattern::Pattern7 => 0b111,
-    };
-    
-    let data = (ec_bits << 3) | mask_bits;
-    let mut format_info = (data as u16) << 10;
-    
-    // BCH(15,5) encoding with generator polynomial x^10 + x^8 + x^5 + x^4 + x^2 + x + 1
-    let generator = 0b10100110111;
-    let mut remainder = format_info;
-    
-    for _ in 0..5 {
-        if remainder & 0x4000 != 0 {
-            remainder = (remainder << 1) ^ generator;
-        } else {
-            remainder <<= 1;
+fn add_format_info(matrix: &mut [Vec<u8>], error_correction: ErrorCorrection, mask_pattern: MaskPattern) {
+    let format_info = format_info::encode(error_correction, mask_pattern as u8);
+    for copy in format_info::positions(matrix.len()) {
+        for (i, (row, col)) in copy.into_iter().enumerate() {
+            matrix[row][col] = ((format_info >> i) & 1) as u8;
         }
     }
-    
-    format_info |= remainder & 0x3FF;
-    format_info ^ 0x5412 // Apply mask
-}
-
-fn add_format_info(matrix: &mut Vec<Vec<u8>>, error_correction: ErrorCorrection, mask_pattern: MaskPattern) {
-    let format_info = get_format_info(error_correction, mask_pattern);
-    let size = matrix.len();
-    
-    // Place format info bits around top-left finder pattern
-    for i in 0..6 {
-        matrix[8][i] = ((format_info >> i) & 1) as u8;
-    }
-    matrix[8][7] = ((format_info >> 6) & 1) as u8;
-    matrix[8][8] = ((format_info >> 7) & 1) as u8;
-    matrix[7][8] = ((format_info >> 8) & 1) as u8;
-    for i in 0..6 {
-        matrix[5-i][8] = ((format_info >> (9+i)) & 1) as u8;
-    }
-    
-    // Place format info bits around other finder patterns
-    for i in 0..8 {
-        matrix[size-1-i][8] = ((format_info >> i) & 1) as u8;
-    }
-    for i in 0..7 {
-        matrix[8][size-7+i] = ((format_info >> (8+i)) & 1) as u8;
-    }
 }
 
-fn place_data_bits(matrix: &mut Vec<Vec<u8>>, encoded: &EncodedData, version: Version) {
+/// Place `codewords` bit by bit, most significant first, in the zigzag of ISO/IEC 18004: up
+/// and down two-module columns from the right, skipping the vertical timing pattern and every
+/// function module. Remainder modules past the last codeword stay light.
+fn place_codewords(matrix: &mut [Vec<u8>], codewords: &[u8], version: Version) {
     let size = matrix.len();
-    let (data_blocks, ecc_blocks) = get_block_structure(&encoded.data_bits, &encoded.ecc_bits);
-    
-    let mut all_bits = Vec::new();
-    let max_data_blocks = data_blocks.len();
-    let max_ecc_blocks = ecc_blocks.len();
-    let max_data_len = data_blocks.iter().map(|b| b.len()).max().unwrap_or(0);
-    let max_ecc_len = ecc_blocks.iter().map(|b| b.len()).max().unwrap_or(0);
-    
-    // Interleave data blocks
-    for i in 0..max_data_len {
-        for j in 0..max_data_blocks {
-            if i < data_blocks[j].len() {
-                all_bits.push(data_blocks[j][i]);
-            }
-        }
-    }
-    
-    // Interleave ECC blocks
-    for i in 0..max_ecc_len {
-        for j in 0..max_ecc_blocks {
-            if i < ecc_blocks[j].len() {
-                all_bits.push(ecc_blocks[j][i]);
-            }
-        }
-    }
-    
     let mut bit_index = 0;
-    let mut up = true;
-    let mut col = size - 1;
-    
-    while col > 0 {
-        if col == 6 { col -= 1; }
-        
-        for _ in 0..2 {
-            let mut row = if up { size - 1 } else { 0 };
-            
-            loop {
-                if !is_function_module(col, row, size, version) {
-                    if bit_index < all_bits.len() {
-                        matrix[row][col] = all_bits[bit_index];
-                        bit_index += 1;
-                    }
-                }
-                
-                if up {
-                    if row == 0 { break; }
-                    row -= 1;
-                } else {
-                    row += 1;
-                    if row >= size { break; }
+    let mut right = size - 1;
+    loop {
+        if right == 6 {
+            right = 5;
+        }
+        let upward = (right + 1) & 2 == 0;
+        for vert in 0..size {
+            let row = if upward { size - 1 - vert } else { vert };
+            for col in [right, right - 1] {
+                if is_function_module(row, col, version) {
+                    continue;
                 }
+                matrix[row][col] = codewords.get(bit_index / 8).map_or(0, |&byte| (byte >> (7 - bit_index % 8)) & 1);
+                bit_index += 1;
             }
-            
-            if col == 0 { break; }
-            col -= 1;
         }
-        
-        up = !up;
-        if col == 0 { break; }
-        col -= 1;
+        if right < 3 {
+            break;
+        }
+        right -= 2;
     }
 }
 
-fn get_block_structure(data_bits: &[u8], ecc_bits: &[u8]) -> (Vec<Vec<u8>>, Vec<Vec<u8>>) {
-    let data_bytes = bits_to_bytes(data_bits);
-    let ecc_bytes = bits_to_bytes(ecc_bits);
-    
-    let data_blocks = vec![data_bytes];
-    let ecc_blocks = vec![ecc_bytes];
-    
-    (data_blocks, ecc_blocks)
+fn add_version_info(matrix: &mut [Vec<u8>], version: Version) {
+    let version_info = version_info::encode(version);
+    for copy in version_info::positions(matrix.len()) {
+        for (i, (row, col)) in copy.into_iter().enumerate() {
+            matrix[row][col] = ((version_info >> i) & 1) as u8;
+        }
+    }
 }
 
-fn bits_to_bytes(bits: &[u8]) -> Vec<u8> {
-    let mut bytes = Vec::new();
-    for chunk in bits.chunks(8) {
-        let mut byte = 0u8;
-        for (i, &bit) in chunk.iter().enumerate() {
-            byte |= bit << (7 - i);
-        }
-        bytes.push(byte);
+fn add_alignment_patterns(matrix: &mut [Vec<u8>], version: Version) {
+    for (x, y) in alignment_centers(version) {
+        add_alignment_pattern(matrix, x - 2, y - 2);
     }
-    bytes
 }
 
-fn is_function_module(x: usize, y: usize, size: usize, version: Version) -> bool {
-    // Finder patterns and separators
-    if (x < 9 && y < 9) || (x >= size - 8 && y < 9) || (x < 9 && y >= size - 8) {
-        return true;
+fn add_dark_module(matrix: &mut [Vec<u8>], version: Version) {
+    let (row, col) = dark_module(version);
+    matrix[row][col] = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capacity::max_characters;
+    use crate::diagnostics::Diagnostics;
+
+    #[test]
+    fn test_last_stage_is_finished_matrix() {
+        let config = QrConfig::default();
+        let stages = generate_qr_stages(b"stages", &config);
+        let names: Vec<_> = stages.iter().map(|s| s.name).collect();
+        assert_eq!(names, ["function patterns", "data placement", "masking", "format info"]);
+        assert_eq!(stages.last().unwrap().matrix, generate_qr_matrix(b"stages", &config));
     }
-    
-    // Timing patterns
-    if x == 6 || y == 6 {
-        return true;
+
+    #[test]
+    fn test_encoder_reuse_matches_fresh_matrices() {
+        let config = QrConfig { error_correction: ErrorCorrection::Q, mask_pattern: MaskPattern::Pattern5, ..QrConfig::default() };
+        let mut encoder = Encoder::new(config.clone());
+        let mut matrix = Vec::new();
+        // Shrinking and growing between calls must not leave modules of the previous symbol
+        for payload in [&b"a larger payload that needs version 3"[..], b"tiny", b"", b"a larger payload that needs version 3"] {
+            encoder.encode_into(payload, &mut matrix);
+            assert_eq!(matrix, generate_qr_matrix(payload, &config));
+        }
     }
-    
-    // Dark module
-    if x == 8 && y == 4 * version as usize + 9 {
-        return true;
+
+    #[test]
+    fn test_every_large_version_carries_version_info() {
+        for v in 7..=40u8 {
+            let version = Version::from_u8(v).unwrap();
+            let payload = vec![b'a'; max_characters(version, ErrorCorrection::L, DataMode::Byte)];
+            let matrix = generate_qr_matrix(&payload, &QrConfig { error_correction: ErrorCorrection::L, ..QrConfig::default() });
+            for copy in version_info::positions(matrix.len()) {
+                let word = copy.iter().enumerate().fold(0u32, |word, (i, &(row, col))| word | (matrix[row][col] as u32) << i);
+                assert_eq!(version_info::decode(word), Some((version, 0)), "V{}", v);
+            }
+        }
     }
-    
-    // Version information
-    if version >= Version::V7 {
-        if (x < 6 && y >= size - 11) || (y < 6 && x >= size - 11) {
-            return true;
+
+    #[test]
+    fn test_dark_module_survives_masking_and_format_info() {
+        for v in 1..=40u8 {
+            let version = Version::from_u8(v).unwrap();
+            let config = QrConfig { error_correction: ErrorCorrection::H, mask_pattern: MaskPattern::from_index(v % 8), ..QrConfig::default() };
+            let payload = vec![b'a'; max_characters(version, ErrorCorrection::H, DataMode::Byte)];
+            let matrix = generate_qr_matrix(&payload, &config);
+            assert_eq!(matrix.len(), version.size());
+            let (row, col) = dark_module(version);
+            assert_eq!(matrix[row][col], 1, "V{}", v);
         }
     }
-    
-    // Format information
-    if (x == 8 && (y < 9 || y >= size - 8)) || (y == 8 && (x < 9 || x >= size - 7)) {
-        return true;
+
+    #[test]
+    fn test_boost_error_correction_keeps_the_version() {
+        // 14 bytes fill a version 1 symbol at M; 7 fit at H
+        assert_eq!(boost_error_correction(&[b'a'; 14], ErrorCorrection::M, DataMode::Byte), ErrorCorrection::M);
+        assert_eq!(boost_error_correction(&[b'a'; 7], ErrorCorrection::M, DataMode::Byte), ErrorCorrection::H);
+        assert_eq!(boost_error_correction(&[b'a'; 7], ErrorCorrection::L, DataMode::Byte), ErrorCorrection::H);
+        assert!(fits_largest_version(&[b'a'; 1273], ErrorCorrection::H, DataMode::Byte));
+        assert!(!fits_largest_version(&[b'a'; 1274], ErrorCorrection::H, DataMode::Byte));
     }
-    
-    // Alignment patterns
-    is_alignment_pattern(x, y, version)
-}
 
-fn get_version_info(version: Version) -> Option<u32> {
-    match version {
-        Version::V7 => Some(0x07C94),
-        Version::V8 => Some(0x085BC),
-        Version::V9 => Some(0x09A99),
-        Version::V10 => Some(0x0A4D3),
-        Version::V11 => Some(0x0BBF6),
-        Version::V12 => Some(0x0C762),
-        Version::V13 => Some(0x0D847),
-        Version::V14 => Some(0x0E60D),
-        Version::V15 => Some(0x0F928),
-        Version::V16 => Some(0x10B78),
-        Version::V17 => Some(0x1145D),
-        Version::V18 => Some(0x12A17),
-        Version::V19 => Some(0x13532),
-        Version::V20 => Some(0x149A6),
-        _ => None,
+    #[test]
+    fn test_symbol_metadata_counts_codewords() {
+        let metadata = symbol_metadata(b"stages", &QrConfig::default());
+        assert_eq!((metadata.version, metadata.size, metadata.mask), (1, 21, Some(0)));
+        // Mode indicator, 8-bit count and six bytes
+        assert_eq!(metadata.segments[0].bits, 4 + 8 + 6 * 8);
+        let codewords = &metadata.codewords;
+        assert_eq!((codewords.total, codewords.data, codewords.ecc, codewords.used), (26, 16, 10, 8));
     }
-}
 
-fn add_version_info(matrix: &mut Vec<Vec<u8>>, version: Version) {
-    if let Some(version_info) = get_version_info(version) {
-        let size = matrix.len();
-        
-        for i in 0..18 {
-            let bit = ((version_info >> i) & 1) as u8;
-            matrix[i / 3][size - 11 + i % 3] = bit;
-            matrix[size - 11 + i % 3][i / 3] = bit;
+    #[test]
+    fn test_payload_the_mode_cannot_hold_is_kept_in_byte_mode() {
+        let byte = generate_qr_matrix(b"12a", &QrConfig::default());
+        for data_mode in [DataMode::Numeric, DataMode::Alphanumeric] {
+            assert_eq!(generate_qr_matrix(b"12a", &QrConfig { data_mode, ..QrConfig::default() }), byte);
+            assert_eq!(symbol_metadata(b"12a", &QrConfig { data_mode, ..QrConfig::default() }).mode, DataMode::Byte);
         }
+        let binary = QrConfig { data_mode: DataMode::Numeric, ..QrConfig::default() };
+        assert_eq!(generate_qr_matrix(&[0x00, 0xFF], &binary), generate_qr_matrix(&[0x00, 0xFF], &QrConfig::default()));
     }
-}
 
-fn add_alignment_patterns(matrix: &mut Vec<Vec<u8>>, version: Version) {
-    let positions = get_alignment_positions(version);
-    
-    for &y in &positions {
-        for &x in &positions {
-            if !((x < 9 && y < 9) || (x >= matrix.len() - 8 && y < 9) || (x < 9 && y >= matrix.len() - 8)) {
-                add_alignment_pattern(matrix, x - 2, y - 2);
+    #[test]
+    fn test_diagnostics_record_each_stage() {
+        let diagnostics = Diagnostics::new();
+        let config = QrConfig { mask_pattern: MaskPattern::Pattern3, diagnostics: Some(diagnostics.clone()), ..QrConfig::default() };
+        let matrix = generate_qr_matrix(b"stages", &config);
+        let stages = diagnostics.stages();
+        assert_eq!(stages.len(), 4);
+        assert!(matches!(&stages[0], Stage::Segments { segments } if segments[0].characters == 6));
+        assert!(matches!(&stages[1], Stage::Blocks { data, ecc } if data.len() == 1 && data[0].len() == 16 && ecc[0].len() == 10));
+        assert!(matches!(&stages[2], Stage::Codewords { codewords } if codewords.len() == 26));
+        match &stages[3] {
+            Stage::MaskScores { penalties, applied } => {
+                assert_eq!(*applied, Some(3));
+                assert_eq!(penalties[3], penalty_score(&matrix));
             }
+            stage => panic!("unexpected stage {:?}", stage),
         }
     }
-}
 
-fn add_dark_module(matrix: &mut Vec<Vec<u8>>, _version: Version) {
-    let size = matrix.len();
-    matrix[4 * _version as usize + 9][8] = 1;
+    #[test]
+    fn test_mask_candidates_match_the_recorded_mask_scores() {
+        let diagnostics = Diagnostics::new();
+        let config = QrConfig { skip_mask: true, diagnostics: Some(diagnostics.clone()), ..QrConfig::default() };
+        let candidates = mask_candidates(b"https://example.com/masks", &config);
+        generate_qr_matrix(b"https://example.com/masks", &config);
+        let Some(Stage::MaskScores { penalties, .. }) = diagnostics.stages().pop() else { panic!("no mask scores") };
+        assert_eq!(candidates.iter().map(|candidate| candidate.penalty).collect::<Vec<_>>(), penalties);
+        assert_eq!(candidates[5].matrix, generate_qr_matrix(b"https://example.com/masks", &QrConfig { mask_pattern: MaskPattern::Pattern5, ..QrConfig::default() }));
+
+        let lowest = penalties.iter().map(|penalty| penalty.total).min().unwrap();
+        let expected = penalties.iter().position(|penalty| penalty.total == lowest).unwrap() as u8;
+        assert_eq!(lowest_penalty_mask(&candidates), Some(MaskPattern::from_index(expected)));
+    }
 }
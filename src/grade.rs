@@ -0,0 +1,265 @@
+//! Print quality assessment after ISO/IEC 15415 and the QR-specific parameters of ISO/IEC
+//! 18004 section 12: symbol contrast, modulation, axial non-uniformity, fixed pattern damage
+//! and unused error correction, each with a letter grade, measured from the grey levels of a
+//! scanned image.
+//!
+//! Reflectance is taken as the grey level over 255 of an aperture 0.8 modules across at each
+//! module center, so the values are relative to the scan rather than calibrated against a
+//! reflectance standard.
+
+use image::{DynamicImage, GrayImage};
+use crate::binarize::binarize;
+use crate::capacity::{get_block_layout, get_total_codewords_in_bytes};
+use crate::decoder::{decode_matrix, locate_symbol, normalize_orientation, orient, Grade};
+use crate::detect::detect_symbol;
+use crate::pixel_mapping::trace;
+use crate::types::{ErrorCorrection, Version};
+
+/// One graded parameter
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Measure {
+    pub value: f64,
+    pub grade: Grade,
+}
+
+/// Grades of every parameter and the overall grade, the lowest of them
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PrintQuality {
+    /// Spread between the lightest and darkest module, in percent
+    pub symbol_contrast: Measure,
+    /// Lowest module modulation; the grade is that of the codewords, weighed against the error
+    /// correction that would absorb the worse ones. `None` when the symbol does not decode.
+    pub modulation: Option<Measure>,
+    /// Relative difference between the horizontal and vertical module pitch
+    pub axial_nonuniformity: Measure,
+    /// Modules wrong in the finder patterns, their separators and the timing patterns
+    pub fixed_pattern_damage: Measure,
+    /// Share of the error correction the worst block has left. `None` when the symbol does
+    /// not decode.
+    pub unused_error_correction: Option<Measure>,
+    /// A when the symbol decodes, F otherwise
+    pub decode: Grade,
+    pub overall: Grade,
+}
+
+/// Diameter of the sampling aperture, in modules
+const APERTURE: f64 = 0.8;
+
+/// Grade the dark-on-light symbol in `image`
+pub fn assess(image: &DynamicImage) -> Result<PrintQuality, Box<dyn std::error::Error>> {
+    let gray = image.to_luma8();
+    let luma = binarize(&gray);
+    let (centers, pitch) = match locate_symbol(&luma) {
+        Ok(grid) => {
+            let centers = grid_points(grid.modules, |row, col| {
+                (grid.left + (col as f64 + 0.5) * grid.module_width, grid.top + (row as f64 + 0.5) * grid.module_height)
+            });
+            (centers, (grid.module_width, grid.module_height))
+        }
+        Err(error) => {
+            let detection = detect_symbol(&luma).map_err(|_| error)?;
+            let n = detection.modules;
+            let span = |(x0, y0): (f64, f64), (x1, y1): (f64, f64)| (x1 - x0).hypot(y1 - y0) / (n - 1) as f64;
+            let pitch = (span(detection.module_center(0, 0), detection.module_center(0, n - 1)), span(detection.module_center(0, 0), detection.module_center(n - 1, 0)));
+            (grid_points(n, |row, col| detection.module_center(row, col)), pitch)
+        }
+    };
+    // The fixed patterns graded below are those of regular QR symbols
+    if centers.len() < 21 {
+        return Err(format!("{}x{} modules is a Micro QR symbol, which print quality grading does not support", centers.len(), centers.len()).into());
+    }
+
+    let radius = APERTURE / 2.0 * pitch.0.min(pitch.1);
+    let reflectance: Vec<Vec<f64>> = centers.iter().map(|row| row.iter().map(|&(x, y)| aperture_mean(&gray, x, y, radius)).collect()).collect();
+    let (darkest, lightest) = reflectance.iter().flatten().fold((f64::MAX, f64::MIN), |(lo, hi), &r| (lo.min(r), hi.max(r)));
+    let contrast = lightest - darkest;
+    let threshold = (lightest + darkest) / 2.0;
+    let matrix: Vec<Vec<u8>> = reflectance.iter().map(|row| row.iter().map(|&r| (r < threshold) as u8).collect()).collect();
+
+    let symbol_contrast = Measure { value: contrast * 100.0, grade: grade_symbol_contrast(contrast) };
+    let (x, y) = pitch;
+    let nonuniformity = (x - y).abs() / ((x + y) / 2.0);
+    let axial_nonuniformity = Measure { value: nonuniformity, grade: grade_axial_nonuniformity(nonuniformity) };
+    let fixed_pattern_damage = fixed_pattern_damage(&normalize_orientation(&matrix).0);
+
+    let modulation_of = |r: f64| if contrast > 0.0 { 2.0 * (r - threshold).abs() / contrast } else { 0.0 };
+    let (modulation, unused_error_correction, decode) = match decode_matrix(&matrix) {
+        Ok(decoded) => {
+            let modulation: Vec<Vec<f64>> = orient(&reflectance, decoded.orientation).iter().map(|row| row.iter().map(|&r| modulation_of(r)).collect()).collect();
+            let lowest = modulation.iter().flatten().copied().fold(f64::MAX, f64::min);
+            let grade = grade_codeword_modulation(&modulation, decoded.version, decoded.error_correction);
+            let unused = decoded.quality.unused_error_correction;
+            (
+                Some(Measure { value: lowest, grade }),
+                Some(Measure { value: unused, grade: Grade::for_unused_error_correction(unused) }),
+                Grade::A,
+            )
+        }
+        Err(_) => (None, None, Grade::F),
+    };
+
+    let overall = [Some(symbol_contrast), modulation, Some(axial_nonuniformity), Some(fixed_pattern_damage), unused_error_correction]
+        .into_iter()
+        .map(|measure| measure.map_or(Grade::F, |measure| measure.grade))
+        .fold(decode, Grade::min);
+    Ok(PrintQuality { symbol_contrast, modulation, axial_nonuniformity, fixed_pattern_damage, unused_error_correction, decode, overall })
+}
+
+fn grid_points(modules: usize, center: impl Fn(usize, usize) -> (f64, f64)) -> Vec<Vec<(f64, f64)>> {
+    (0..modules).map(|row| (0..modules).map(|col| center(row, col)).collect()).collect()
+}
+
+/// Mean grey level over 255 of the pixels within `radius` of (x, y); light outside the image
+fn aperture_mean(gray: &GrayImage, x: f64, y: f64, radius: f64) -> f64 {
+    let reach = radius.ceil().max(0.0) as i64;
+    let (cx, cy) = (x.floor() as i64, y.floor() as i64);
+    let (mut sum, mut count) = (0.0, 0);
+    for py in cy - reach..=cy + reach {
+        for px in cx - reach..=cx + reach {
+            let (dx, dy) = (px as f64 + 0.5 - x, py as f64 + 0.5 - y);
+            if dx.hypot(dy) > radius.max(0.5) {
+                continue;
+            }
+            let inside = px >= 0 && py >= 0 && (px as u32) < gray.width() && (py as u32) < gray.height();
+            sum += if inside { gray.get_pixel(px as u32, py as u32)[0] as f64 / 255.0 } else { 1.0 };
+            count += 1;
+        }
+    }
+    if count == 0 { 1.0 } else { sum / count as f64 }
+}
+
+/// A from 70%, B from 55%, C from 40%, D from 20%
+pub fn grade_symbol_contrast(contrast: f64) -> Grade {
+    match contrast {
+        c if c >= 0.70 => Grade::A,
+        c if c >= 0.55 => Grade::B,
+        c if c >= 0.40 => Grade::C,
+        c if c >= 0.20 => Grade::D,
+        _ => Grade::F,
+    }
+}
+
+/// A from 0.50, B from 0.40, C from 0.30, D from 0.20
+pub fn grade_modulation(modulation: f64) -> Grade {
+    match modulation {
+        m if m >= 0.50 => Grade::A,
+        m if m >= 0.40 => Grade::B,
+        m if m >= 0.30 => Grade::C,
+        m if m >= 0.20 => Grade::D,
+        _ => Grade::F,
+    }
+}
+
+/// A up to 0.06, B up to 0.08, C up to 0.10, D up to 0.12
+pub fn grade_axial_nonuniformity(nonuniformity: f64) -> Grade {
+    match nonuniformity {
+        n if n <= 0.06 => Grade::A,
+        n if n <= 0.08 => Grade::B,
+        n if n <= 0.10 => Grade::C,
+        n if n <= 0.12 => Grade::D,
+        _ => Grade::F,
+    }
+}
+
+/// Each codeword takes the grade of its worst module. For each grade level, the codewords
+/// below it are counted as erasures against their block's error correction; the level scores
+/// the lower of itself and the unused error correction left, and the best level is the grade.
+fn grade_codeword_modulation(modulation: &[Vec<f64>], version: Version, error_correction: ErrorCorrection) -> Grade {
+    let layout = get_block_layout(version, error_correction);
+    let mut codewords = vec![(Grade::A, 0); get_total_codewords_in_bytes(version)];
+    for origin in trace(version, error_correction) {
+        if let Some(location) = origin.codeword {
+            let grade = grade_modulation(modulation[origin.row][origin.col]);
+            codewords[location.codeword] = (codewords[location.codeword].0.min(grade), location.block);
+        }
+    }
+
+    [Grade::A, Grade::B, Grade::C, Grade::D]
+        .into_iter()
+        .map(|level| {
+            let mut erasures = vec![0; layout.total_blocks()];
+            for &(grade, block) in &codewords {
+                erasures[block] += (grade < level) as usize;
+            }
+            let unused = erasures.iter().map(|&e| 1.0 - e as f64 / layout.ecc_per_block as f64).fold(1.0, f64::min);
+            level.min(Grade::for_unused_error_correction(unused))
+        })
+        .max()
+        .unwrap_or(Grade::F)
+}
+
+/// Modules wrong in each finder pattern with its separator, graded A for none down to F for
+/// four or more, and in the timing patterns, graded as for the decoder; the worst grade wins
+#[allow(clippy::needless_range_loop)]
+fn fixed_pattern_damage(matrix: &[Vec<u8>]) -> Measure {
+    let size = matrix.len();
+    let mut damaged = 0;
+    let mut grade = Grade::A;
+    for (top, left) in [(0, 0), (0, size - 7), (size - 7, 0)] {
+        let mut wrong = 0;
+        // The finder and the light separator ring around it, where it lies inside the symbol
+        for row in top.saturating_sub(1)..(top + 8).min(size) {
+            for col in left.saturating_sub(1)..(left + 8).min(size) {
+                let ring = (row as isize - top as isize - 3).abs().max((col as isize - left as isize - 3).abs());
+                let dark = ring <= 1 || ring == 3;
+                wrong += (matrix[row][col] != dark as u8) as usize;
+            }
+        }
+        damaged += wrong;
+        grade = grade.min(Grade::for_format_bit_errors(wrong.min(4) as u32));
+    }
+
+    let expected = |i: usize| i.is_multiple_of(2) as u8;
+    let timing_wrong = (8..size - 8).filter(|&i| matrix[6][i] != expected(i)).count() + (8..size - 8).filter(|&i| matrix[i][6] != expected(i)).count();
+    damaged += timing_wrong;
+    let regularity = 1.0 - timing_wrong as f64 / (2 * (size - 16)) as f64;
+    grade = grade.min(Grade::for_timing_regularity(regularity));
+    Measure { value: damaged as f64, grade }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::golden;
+    use crate::render::{render_image, RenderOptions};
+    use image::Luma;
+
+    fn golden_image() -> GrayImage {
+        let matrix = golden("hello-world-1q").unwrap().matrix();
+        DynamicImage::from(render_image(&matrix, &RenderOptions::default()).unwrap()).to_luma8()
+    }
+
+    #[test]
+    fn test_clean_render_grades_a() {
+        let quality = assess(&DynamicImage::from(golden_image())).unwrap();
+        assert_eq!(quality.symbol_contrast.value, 100.0);
+        assert_eq!(quality.axial_nonuniformity.value, 0.0);
+        assert_eq!(quality.fixed_pattern_damage.value, 0.0);
+        assert_eq!(quality.modulation.map(|m| m.grade), Some(Grade::A));
+        assert_eq!(quality.overall, Grade::A);
+    }
+
+    #[test]
+    fn test_micro_symbols_are_rejected() {
+        use crate::micro::tests::{field, micro_symbol};
+        use crate::micro::{MicroFormat, MicroVersion};
+        let bits: Vec<u8> = field(5, 3).chain(field(12, 10)).chain(field(34, 7)).collect();
+        let matrix = micro_symbol(MicroFormat { version: MicroVersion::M1, error_correction: None, mask: 3 }, &bits);
+        let image = DynamicImage::from(render_image(&matrix, &RenderOptions::default()).unwrap());
+        assert!(assess(&image).unwrap_err().to_string().contains("11x11 modules is a Micro QR symbol"));
+    }
+
+    #[test]
+    fn test_faded_print_loses_contrast_grade() {
+        // Dark modules printed at 60% grey: contrast just over 0.4 is a C
+        let mut image = golden_image();
+        for pixel in image.pixels_mut() {
+            *pixel = Luma([if pixel[0] < 128 { 152 } else { 255 }]);
+        }
+        let quality = assess(&DynamicImage::from(image)).unwrap();
+        assert!((quality.symbol_contrast.value - 40.0).abs() < 0.5);
+        assert_eq!(quality.symbol_contrast.grade, Grade::C);
+        assert_eq!(quality.decode, Grade::A);
+        assert_eq!(quality.overall, Grade::C);
+    }
+}
@@ -1,8 +1,53 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub mod types;
 pub mod pixel_mapping;
 pub mod capacity;
+pub mod fit;
 pub mod alignment;
+pub mod function_modules;
 pub mod mask;
 pub mod encoding;
 pub mod ecc;
-pub mod generator;
\ No newline at end of file
+pub mod format_info;
+pub mod version_info;
+pub mod generator;
+pub mod diagnostics;
+pub mod matrix;
+pub mod reference;
+#[cfg(feature = "std")]
+pub mod render;
+#[cfg(feature = "std")]
+pub mod binarize;
+#[cfg(feature = "std")]
+pub mod detect;
+#[cfg(feature = "std")]
+pub mod decoder;
+#[cfg(feature = "std")]
+pub mod micro;
+#[cfg(feature = "std")]
+pub mod grade;
+#[cfg(feature = "std")]
+pub mod logo;
+#[cfg(feature = "std")]
+pub mod sheet;
+#[cfg(feature = "std")]
+pub mod svg;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod naming;
+#[cfg(feature = "std")]
+pub mod payload;
+#[cfg(feature = "std")]
+pub mod url;
+#[cfg(feature = "std")]
+pub mod visualize;
+#[cfg(feature = "std")]
+pub mod roundtrip;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "cli")]
+pub mod cli;
@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use crate::types::{Version, ErrorCorrection};
+use crate::alignment::alignment_centers;
+use crate::capacity::{get_block_layout, get_total_codewords_in_bytes};
+use crate::function_modules::{module_region, ModuleRegion};
+use crate::pixel_mapping::get_data_ecc_positions;
+
+/// Smallest logo side (in modules) worth drawing at all
+pub const MIN_LOGO_MODULES: usize = 5;
+
+/// Share of each block's correction capacity, in percent, a logo may take; the rest is left
+/// for print defects and scanning errors
+pub const LOGO_SHARE_PERCENT: usize = 60;
+
+/// Number of codewords each Reed-Solomon block can lose and still be recovered: half its ECC
+/// codewords, since errors are corrected block by block
+pub fn correctable_codewords_per_block(version: Version, error_correction: ErrorCorrection) -> usize {
+    get_block_layout(version, error_correction).ecc_per_block / 2
+}
+
+/// Codewords a logo may occlude in any one block, leaving the safety margin
+pub fn logo_budget_per_block(version: Version, error_correction: ErrorCorrection) -> usize {
+    correctable_codewords_per_block(version, error_correction) * LOGO_SHARE_PERCENT / 100
+}
+
+/// Top-left module of a centered square of `side` modules
+pub fn logo_origin(version: Version, side: usize) -> usize {
+    (version.size() - side.min(version.size())) / 2
+}
+
+/// Count the distinct data/ECC codewords of each block, in block order, that have at least
+/// one module inside a centered square of `side` modules. Codewords are assigned to blocks by
+/// de-interleaving the placement order; remainder bits belong to no codeword and are ignored.
+pub fn occluded_codewords(version: Version, error_correction: ErrorCorrection, side: usize) -> Vec<usize> {
+    let layout = get_block_layout(version, error_correction);
+    let stream_positions = layout.stream_positions();
+    let origin = logo_origin(version, side);
+    let end = origin + side;
+    let total_codewords = get_total_codewords_in_bytes(version);
+
+    let mut codewords = HashSet::new();
+    for (i, (row, col)) in get_data_ecc_positions(version).into_iter().enumerate() {
+        let codeword = i / 8;
+        if codeword >= total_codewords {
+            break;
+        }
+        if row >= origin && row < end && col >= origin && col < end {
+            codewords.insert(codeword);
+        }
+    }
+    let mut per_block = vec![0; layout.total_blocks()];
+    for codeword in codewords {
+        per_block[stream_positions[codeword].0] += 1;
+    }
+    per_block
+}
+
+/// Whether a centered square of `side` modules stays clear of every function pattern but the
+/// alignment pattern at the very center of the symbol, which the renderer draws back over the
+/// logo
+pub fn clear_of_function_patterns(version: Version, side: usize) -> bool {
+    let origin = logo_origin(version, side);
+    let center = version.size() / 2;
+    let centered_alignment = alignment_centers(version).any(|(x, y)| x == center && y == center);
+    (origin..origin + side).all(|row| {
+        (origin..origin + side).all(|col| match module_region(row, col, version) {
+            ModuleRegion::Data => true,
+            ModuleRegion::Alignment => centered_alignment && row.abs_diff(center) <= 2 && col.abs_diff(center) <= 2,
+            _ => false,
+        })
+    })
+}
+
+/// Largest centered square (in modules) that covers no function pattern and costs no block
+/// more than [`logo_budget_per_block`] codewords. Returns 0 if not even a single module fits.
+///
+/// Only odd sides are considered so the square sits exactly on the symbol's center module.
+pub fn max_logo_size(version: Version, error_correction: ErrorCorrection) -> usize {
+    let budget = logo_budget_per_block(version, error_correction);
+    let mut best = 0;
+    for side in (1..version.size()).step_by(2) {
+        if !clear_of_function_patterns(version, side) || occluded_codewords(version, error_correction, side).into_iter().any(|count| count > budget) {
+            break;
+        }
+        best = side;
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_logo_size_stays_within_each_blocks_correction_capacity() {
+        for version in [Version::V2, Version::V5, Version::V7, Version::V10, Version::V15] {
+            for ecc in [ErrorCorrection::L, ErrorCorrection::M, ErrorCorrection::Q, ErrorCorrection::H] {
+                let side = max_logo_size(version, ecc);
+                assert!(side % 2 == 1 || side == 0);
+                let budget = logo_budget_per_block(version, ecc);
+                assert!(budget < correctable_codewords_per_block(version, ecc), "no safety margin at {:?}-{:?}", version, ecc);
+                let occluded = occluded_codewords(version, ecc, side);
+                assert_eq!(occluded.len(), get_block_layout(version, ecc).total_blocks());
+                assert!(occluded.iter().all(|&count| count <= budget), "{:?}-{:?}: {:?} over {}", version, ecc, occluded, budget);
+                assert!(clear_of_function_patterns(version, side));
+                // The next size up goes over a block's budget or onto a function pattern
+                let next = side + 2;
+                assert!(!clear_of_function_patterns(version, next) || occluded_codewords(version, ecc, next).iter().any(|&count| count > budget));
+            }
+        }
+    }
+
+    #[test]
+    fn test_logo_square_avoids_function_patterns() {
+        // Version 5 has a single alignment pattern, off center at (30, 30)
+        assert!(!clear_of_function_patterns(Version::V5, 21));
+        // From version 7 the center alignment pattern is allowed, the others are not
+        assert!(clear_of_function_patterns(Version::V7, 9));
+        assert!(!clear_of_function_patterns(Version::V7, 29));
+    }
+
+    #[test]
+    fn test_higher_ecc_allows_larger_logo() {
+        assert!(max_logo_size(Version::V5, ErrorCorrection::H) > max_logo_size(Version::V5, ErrorCorrection::L));
+    }
+}
@@ -1,108 +1,115 @@
+use alloc::vec::Vec;
+use crate::capacity::image_size_to_version;
+use crate::function_modules::is_function_module;
 use crate::types::MaskPattern;
 
-pub fn apply_mask(matrix: &mut Vec<Vec<u8>>, pattern: MaskPattern) {
-    match pattern {
-        MaskPattern::Pattern0 => apply_pattern0(matrix),
-        MaskPattern::Pattern1 => apply_pattern1(matrix),
-        MaskPattern::Pattern2 => apply_pattern2(matrix),
-        MaskPattern::Pattern3 => apply_pattern3(matrix),
-        MaskPattern::Pattern4 => apply_pattern4(matrix),
-        MaskPattern::Pattern5 => apply_pattern5(matrix),
-        MaskPattern::Pattern6 => apply_pattern6(matrix),
-        MaskPattern::Pattern7 => apply_pattern7(matrix),
-    }
-}
-
-fn apply_pattern0(matrix: &mut Vec<Vec<u8>>) {
-    let size = matrix.len();
-    for y in 0..size {
-        for x in 0..size {
-            if (x + y) % 2 == 0 {
-                matrix[y][x] ^= 1;
+/// XOR the mask pattern onto every module except the function patterns, which ISO/IEC 18004
+/// leaves unmasked. A matrix of a size no QR version has is masked whole.
+pub fn apply_mask(matrix: &mut [Vec<u8>], pattern: MaskPattern) {
+    let version = image_size_to_version(matrix.len());
+    for (y, row) in matrix.iter_mut().enumerate() {
+        for (x, module) in row.iter_mut().enumerate() {
+            if flips(pattern, y, x) && !version.is_some_and(|version| is_function_module(y, x, version)) {
+                *module ^= 1;
             }
         }
     }
 }
 
-fn apply_pattern1(matrix: &mut Vec<Vec<u8>>) {
-    let size = matrix.len();
-    for y in 0..size {
-        for x in 0..size {
-            if y % 2 == 0 {
-                matrix[y][x] ^= 1;
-            }
-        }
+/// Whether `pattern` flips the module at `y`, `x`
+fn flips(pattern: MaskPattern, y: usize, x: usize) -> bool {
+    match pattern {
+        MaskPattern::Pattern0 => (x + y).is_multiple_of(2),
+        MaskPattern::Pattern1 => y.is_multiple_of(2),
+        MaskPattern::Pattern2 => x.is_multiple_of(3),
+        MaskPattern::Pattern3 => (x + y).is_multiple_of(3),
+        MaskPattern::Pattern4 => ((y / 2) + (x / 3)).is_multiple_of(2),
+        MaskPattern::Pattern5 => ((x * y) % 2) + ((x * y) % 3) == 0,
+        MaskPattern::Pattern6 => (((x * y) % 2) + ((x * y) % 3)).is_multiple_of(2),
+        MaskPattern::Pattern7 => (((x + y) % 2) + ((x * y) % 3)).is_multiple_of(2),
     }
 }
 
-fn apply_pattern2(matrix: &mut Vec<Vec<u8>>) {
-    let size = matrix.len();
-    for y in 0..size {
-        for x in 0..size {
-            if x % 3 == 0 {
-                matrix[y][x] ^= 1;
-            }
-        }
-    }
+/// Mask penalty of a finished symbol under the four rules of ISO/IEC 18004 section 7.8.3.
+/// Encoders pick the mask with the lowest total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Penalty {
+    /// Rule 1: runs of five or more same-colored modules in a row or column
+    pub runs: u32,
+    /// Rule 2: 2x2 blocks of one color
+    pub blocks: u32,
+    /// Rule 3: 1:1:3:1:1 finder-like patterns next to four light modules
+    pub finder_like: u32,
+    /// Rule 4: deviation of the dark module proportion from 50%
+    pub balance: u32,
+    pub total: u32,
 }
 
-fn apply_pattern3(matrix: &mut Vec<Vec<u8>>) {
+/// Score a module matrix (1 = dark) with all four penalty rules
+pub fn penalty_score(matrix: &[Vec<u8>]) -> Penalty {
     let size = matrix.len();
-    for y in 0..size {
-        for x in 0..size {
-            if (x + y) % 3 == 0 {
-                matrix[y][x] ^= 1;
-            }
-        }
-    }
-}
+    let rows: Vec<Vec<u8>> = matrix.to_vec();
+    let columns: Vec<Vec<u8>> = (0..size).map(|col| (0..size).map(|row| matrix[row][col]).collect()).collect();
 
-fn apply_pattern4(matrix: &mut Vec<Vec<u8>>) {
-    let size = matrix.len();
-    for y in 0..size {
-        for x in 0..size {
-            if ((y / 2) + (x / 3)) % 2 == 0 {
-                matrix[y][x] ^= 1;
+    let mut runs = 0;
+    for line in rows.iter().chain(&columns) {
+        for run in line.chunk_by(|a, b| a == b) {
+            if run.len() >= 5 {
+                runs += 3 + (run.len() - 5) as u32;
             }
         }
     }
-}
 
-fn apply_pattern5(matrix: &mut Vec<Vec<u8>>) {
-    let size = matrix.len();
-    for y in 0..size {
-        for x in 0..size {
-            if ((x * y) % 2) + ((x * y) % 3) == 0 {
-                matrix[y][x] ^= 1;
+    let mut blocks = 0;
+    for row in 0..size.saturating_sub(1) {
+        for col in 0..size - 1 {
+            let color = matrix[row][col];
+            if matrix[row][col + 1] == color && matrix[row + 1][col] == color && matrix[row + 1][col + 1] == color {
+                blocks += 3;
             }
         }
     }
-}
 
-fn apply_pattern6(matrix: &mut Vec<Vec<u8>>) {
-    let size = matrix.len();
-    for y in 0..size {
-        for x in 0..size {
-            if (((x * y) % 2) + ((x * y) % 3)) % 2 == 0 {
-                matrix[y][x] ^= 1;
-            }
-        }
+    const FINDER_LIKE: [[u8; 11]; 2] = [[1, 0, 1, 1, 1, 0, 1, 0, 0, 0, 0], [0, 0, 0, 0, 1, 0, 1, 1, 1, 0, 1]];
+    let mut finder_like = 0;
+    for line in rows.iter().chain(&columns) {
+        // The quiet zone counts as light on both ends
+        let line: Vec<u8> = [0; 4].iter().chain(line).chain(&[0; 4]).copied().collect();
+        finder_like += 40 * line.windows(11).filter(|window| FINDER_LIKE.iter().any(|pattern| window == pattern)).count() as u32;
     }
+
+    let total_modules = size * size;
+    let dark = matrix.iter().flatten().filter(|&&m| m == 1).count();
+    // Every full 5% step away from 50% costs 10 points; 45% to 55% is free
+    let balance = if total_modules == 0 { 0 } else { 10 * ((dark * 20).abs_diff(total_modules * 10).div_ceil(total_modules).saturating_sub(1)) as u32 };
+
+    Penalty { runs, blocks, finder_like, balance, total: runs + blocks + finder_like + balance }
 }
 
-fn apply_pattern7(matrix: &mut Vec<Vec<u8>>) {
-    let size = matrix.len();
-    for y in 0..size {
-        for x in 0..size {
-            if (((x + y) % 2) + ((x * y) % 3)) % 2 == 0 {
-                matrix[y][x] ^= 1;
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::tests::reference_matrix;
+    use qrcodegen::{Mask, QrCode, QrCodeEcc, QrSegment};
+
+    #[test]
+    fn test_reference_encoder_picks_lowest_penalty() {
+        // qrcodegen chooses the mask by the same rules, so its pick must score lowest here
+        let segments = QrSegment::make_segments("https://example.com/penalty");
+        let auto = QrCode::encode_segments(&segments, QrCodeEcc::Medium).unwrap();
+        let score = |mask: u8| {
+            let qr = QrCode::encode_segments_advanced(&segments, QrCodeEcc::Medium, auto.version(), auto.version(), Some(Mask::new(mask)), false).unwrap();
+            let matrix = reference_matrix(&qr);
+            penalty_score(&matrix).total
+        };
+        let chosen = score(auto.mask().value());
+        assert!((0..8).all(|mask| score(mask) >= chosen));
     }
-}
 
-impl Default for MaskPattern {
-    fn default() -> Self {
-        MaskPattern::Pattern0
+    #[test]
+    fn test_penalty_rules() {
+        // All dark 5x5: five runs of 5 per direction, 16 blocks, no finder pattern, 100% dark
+        let penalty = penalty_score(&vec![vec![1; 5]; 5]);
+        assert_eq!(penalty, Penalty { runs: 30, blocks: 48, finder_like: 0, balance: 90, total: 168 });
     }
 }
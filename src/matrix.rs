@@ -0,0 +1,113 @@
+//! Serde formats for module matrices: square `Vec<Vec<u8>>`s holding 1 for a dark module and 0
+//! for a light one. Derived serialization writes them as nested arrays, which is easy to read
+//! but takes two or three bytes per module in JSON. [`packed`] is the compact alternative:
+//!
+//! ```
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Saved {
+//!     #[serde(with = "qr_tools::matrix::packed")]
+//!     matrix: Vec<Vec<u8>>,
+//! }
+//! ```
+
+use alloc::{string::String, vec::Vec};
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// One base64 string per row, of the row's modules packed eight to a byte, the first module in
+/// the most significant bit. The row length is the number of rows, since symbols are square.
+pub mod packed {
+    use super::*;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(matrix: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        let rows: Vec<String> = matrix.iter().map(|row| encode_base64(&pack_row(row))).collect();
+        rows.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Vec<u8>>, D::Error> {
+        let rows = Vec::<String>::deserialize(deserializer)?;
+        let size = rows.len();
+        rows.iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let bytes = decode_base64(row).ok_or_else(|| D::Error::custom(alloc::format!("row {} is not base64", i)))?;
+                if bytes.len() != size.div_ceil(8) {
+                    return Err(D::Error::custom(alloc::format!("row {} holds {} bytes; {} rows need {}", i, bytes.len(), size, size.div_ceil(8))));
+                }
+                Ok((0..size).map(|x| (bytes[x / 8] >> (7 - x % 8)) & 1).collect())
+            })
+            .collect()
+    }
+}
+
+fn pack_row(row: &[u8]) -> Vec<u8> {
+    let mut bytes = alloc::vec![0u8; row.len().div_ceil(8)];
+    for (x, &module) in row.iter().enumerate() {
+        if module == 1 {
+            bytes[x / 8] |= 0x80 >> (x % 8);
+        }
+    }
+    bytes
+}
+
+/// Standard base64 with padding (RFC 4648)
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &b)| group | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64[(group >> (18 - 6 * i)) as usize & 63] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub(crate) fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut bytes = Vec::new();
+    for chunk in text.as_bytes().chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut group = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            group |= (BASE64.iter().position(|&b| b == c)? as u32) << (18 - 6 * i);
+        }
+        bytes.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+    }
+    Some(bytes)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Saved {
+        #[serde(with = "packed")]
+        matrix: Vec<Vec<u8>>,
+    }
+
+    #[test]
+    fn test_packed_matrix_round_trip() {
+        let matrix: Vec<Vec<u8>> = (0..21).map(|y| (0..21).map(|x| ((x * 7 + y * 3) % 5 == 0) as u8).collect()).collect();
+        let json = serde_json::to_string(&Saved { matrix: matrix.clone() }).unwrap();
+        assert_eq!(serde_json::from_str::<Saved>(&json).unwrap().matrix, matrix);
+        // 21 modules fit in 3 bytes, 4 base64 characters
+        assert!(json.starts_with(r#"{"matrix":["hCEI","#));
+
+        assert_eq!(encode_base64(b"Ma"), "TWE=");
+        assert_eq!(decode_base64("TWFu").unwrap(), b"Man");
+        assert!(serde_json::from_str::<Saved>(r#"{"matrix":["AAAA","AA=="]}"#).is_err());
+        assert!(serde_json::from_str::<Saved>(r#"{"matrix":["A!"]}"#).is_err());
+    }
+}
@@ -0,0 +1,498 @@
+//! Reading Micro QR symbols, versions M1 to M4: 11 to 17 modules wide, with a single finder
+//! pattern in the top-left corner, timing patterns along the top row and left column, one
+//! copy of the format information and a single Reed-Solomon block. The encoder only makes
+//! regular QR symbols; this module is what the analyzer reads Micro QR with.
+
+use serde::{Deserialize, Serialize};
+use crate::decoder::{finder_match, orient, read_segment_data, segment_text, SegmentMode, MIN_FINDER_MATCH, Orientation};
+use crate::ecc::{correct_errors, CorrectionResult};
+use crate::types::ErrorCorrection;
+
+/// Micro QR version, from M1 (11x11 modules) to M4 (17x17)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MicroVersion {
+    M1 = 1,
+    M2 = 2,
+    M3 = 3,
+    M4 = 4,
+}
+
+impl MicroVersion {
+    /// Version whose symbols are `size` modules wide
+    pub fn from_size(size: usize) -> Option<MicroVersion> {
+        match size {
+            11 => Some(MicroVersion::M1),
+            13 => Some(MicroVersion::M2),
+            15 => Some(MicroVersion::M3),
+            17 => Some(MicroVersion::M4),
+            _ => None,
+        }
+    }
+
+    /// Width of the symbol in modules
+    pub fn size(self) -> usize {
+        9 + 2 * self as usize
+    }
+
+    /// Width of the mode indicator; M1 has none, since it only holds numeric data
+    pub fn mode_indicator_bits(self) -> usize {
+        self as usize - 1
+    }
+
+    /// Number of zero bits that end the data
+    pub fn terminator_bits(self) -> usize {
+        2 * self as usize + 1
+    }
+
+    /// Width of the character count field of a segment, `None` for modes the version lacks
+    pub fn char_count_bits(self, mode: SegmentMode) -> Option<usize> {
+        let version = self as usize;
+        match mode {
+            SegmentMode::Numeric => Some(version + 2),
+            SegmentMode::Alphanumeric if version >= 2 => Some(version + 1),
+            SegmentMode::Byte if version >= 3 => Some(version + 1),
+            SegmentMode::Kanji if version >= 3 => Some(version),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for MicroVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "M{}", *self as u8)
+    }
+}
+
+/// XOR mask applied to every Micro QR format information word
+pub const FORMAT_MASK: u16 = 0x4445;
+
+/// BCH(15,5) generator polynomial, the same as for QR format information
+const GENERATOR: u16 = 0x537;
+
+/// Version and ECC level of each symbol number in the format information. M1 only detects
+/// errors and has no level.
+const SYMBOLS: [(MicroVersion, Option<ErrorCorrection>); 8] = [
+    (MicroVersion::M1, None),
+    (MicroVersion::M2, Some(ErrorCorrection::L)),
+    (MicroVersion::M2, Some(ErrorCorrection::M)),
+    (MicroVersion::M3, Some(ErrorCorrection::L)),
+    (MicroVersion::M3, Some(ErrorCorrection::M)),
+    (MicroVersion::M4, Some(ErrorCorrection::L)),
+    (MicroVersion::M4, Some(ErrorCorrection::M)),
+    (MicroVersion::M4, Some(ErrorCorrection::Q)),
+];
+
+/// Data bits, ECC codewords and codewords Reed-Solomon may correct, by symbol number
+/// (ISO/IEC 18004 tables 7 and 9)
+const CAPACITIES: [(usize, usize, usize); 8] = [(20, 2, 0), (40, 5, 1), (32, 6, 2), (84, 6, 2), (68, 8, 4), (128, 8, 3), (112, 10, 5), (80, 14, 7)];
+
+/// What the format information of a Micro QR symbol holds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MicroFormat {
+    pub version: MicroVersion,
+    /// `None` for M1, which has error detection only
+    pub error_correction: Option<ErrorCorrection>,
+    /// One of the four Micro QR masks, 0-3
+    pub mask: u8,
+}
+
+impl MicroFormat {
+    /// Symbol number 0-7 encoding the version and ECC level; `None` for a combination Micro
+    /// QR does not have, such as M2-Q
+    pub fn symbol_number(&self) -> Option<u8> {
+        SYMBOLS.iter().position(|&symbol| symbol == (self.version, self.error_correction)).map(|number| number as u8)
+    }
+
+    pub fn capacity(&self) -> Option<Capacity> {
+        let (data_bits, ecc_codewords, correctable) = CAPACITIES[self.symbol_number()? as usize];
+        Some(Capacity { data_bits, ecc_codewords, correctable })
+    }
+}
+
+/// 15-bit format information word for `format`, including the BCH code and XOR mask. Bit 14
+/// is the most significant bit of the symbol number.
+pub fn encode_format(format: MicroFormat) -> Option<u16> {
+    let data = ((format.symbol_number()? as u16) << 2) | (format.mask & 0b11) as u16;
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * GENERATOR);
+    }
+    Some(((data << 10) | remainder) ^ FORMAT_MASK)
+}
+
+/// Decode a format information word as read from the symbol, and the number of bits that had
+/// to be corrected. There are only 32 valid words, so the closest one within 3 bits is taken.
+pub fn decode_format(word: u16) -> Option<(MicroFormat, u32)> {
+    (0..8)
+        .flat_map(|number| (0..4).map(move |mask| (number, mask)))
+        .map(|(number, mask)| {
+            let (version, error_correction) = SYMBOLS[number];
+            let format = MicroFormat { version, error_correction, mask };
+            (format, (word ^ encode_format(format).unwrap()).count_ones())
+        })
+        .min_by_key(|&(_, distance)| distance)
+        .filter(|&(_, distance)| distance <= 3)
+}
+
+/// Module positions (row, col) of format information bits 0-14: down the column right of the
+/// finder separator, then leftwards along the row below it
+pub fn format_positions() -> [(usize, usize); 15] {
+    let mut positions = [(0, 0); 15];
+    for (i, position) in positions.iter_mut().enumerate() {
+        *position = if i < 8 { (i + 1, 8) } else { (8, 15 - i) };
+    }
+    positions
+}
+
+/// The format information word as read from an upright symbol
+pub fn read_format_word(matrix: &[Vec<u8>]) -> u16 {
+    format_positions().iter().enumerate().fold(0, |word, (i, &(row, col))| word | ((matrix[row][col] as u16) << i))
+}
+
+/// Whether the module at (`row`, `col`) belongs to the finder pattern, its separator, the
+/// format information or the timing patterns
+pub fn is_function_module(row: usize, col: usize) -> bool {
+    row == 0 || col == 0 || (row <= 8 && col <= 8)
+}
+
+/// Whether Micro QR mask `mask` (0-3) flips the module at (`row`, `col`). The four masks are
+/// QR masks 1, 4, 6 and 7.
+pub fn mask_bit(mask: u8, row: usize, col: usize) -> bool {
+    crate::decoder::mask_bit([1, 4, 6, 7][mask as usize & 0b11], row, col)
+}
+
+/// Positions of the data and ECC modules in the order their bits are placed: the same
+/// two-column zigzag as QR, starting bottom-right, without a timing column to step over
+pub fn placement(version: MicroVersion) -> Vec<(usize, usize)> {
+    let size = version.size();
+    let mut positions = Vec::new();
+    for (pair, right) in (1..size).rev().step_by(2).enumerate() {
+        let upward = pair % 2 == 0;
+        for step in 0..size {
+            let row = if upward { size - 1 - step } else { step };
+            for col in [right, right - 1] {
+                if !is_function_module(row, col) {
+                    positions.push((row, col));
+                }
+            }
+        }
+    }
+    positions
+}
+
+/// Data and error correction capacity of one Micro QR version and ECC level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    /// In M1 and M3 this is not a whole number of codewords: the last data codeword is 4 bits
+    pub data_bits: usize,
+    pub ecc_codewords: usize,
+    /// Codewords correction may repair. The rest of the ECC codewords guard against
+    /// miscorrection, and M1 symbols can only detect errors.
+    pub correctable: usize,
+}
+
+impl Capacity {
+    pub fn data_codewords(&self) -> usize {
+        self.data_bits.div_ceil(8)
+    }
+}
+
+/// Unmasked data and ECC bits of an upright symbol, in placement order
+pub fn read_bits(matrix: &[Vec<u8>], version: MicroVersion, mask: u8) -> Vec<u8> {
+    placement(version).into_iter().map(|(row, col)| matrix[row][col] ^ mask_bit(mask, row, col) as u8).collect()
+}
+
+/// Codewords of a bit stream: the data bits packed into bytes, a final 4-bit codeword
+/// right-aligned in its byte, then the ECC codewords
+pub fn codewords(bits: &[u8], capacity: Capacity) -> Vec<u8> {
+    let pack = |bits: &[u8]| bits.iter().fold(0u8, |byte, &bit| (byte << 1) | bit);
+    let (data, ecc) = bits.split_at(capacity.data_bits.min(bits.len()));
+    data.chunks(8).chain(ecc.chunks_exact(8)).map(pack).collect()
+}
+
+/// Data bits of codewords laid out as `codewords` returns them
+pub fn data_bits(codewords: &[u8], capacity: Capacity) -> Vec<u8> {
+    let mut bits: Vec<u8> = codewords[..capacity.data_codewords()].iter().flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1)).collect();
+    if !capacity.data_bits.is_multiple_of(8) {
+        // Drop the empty high nibble of the short codeword
+        let short = bits.len() - 8;
+        bits.drain(short..short + 4);
+    }
+    bits
+}
+
+/// Correct the codewords of a symbol within what its ECC level allows. A repair needing more
+/// codewords than that, or setting the empty high nibble of a short codeword, is more likely a
+/// miscorrection and reported as uncorrectable.
+pub fn correct(codewords: &[u8], capacity: Capacity) -> CorrectionResult {
+    let result = correct_errors(codewords, capacity.ecc_codewords);
+    let repaired = match &result {
+        CorrectionResult::ErrorFree(_) => return result,
+        CorrectionResult::Corrected { error_positions, .. } => error_positions.len(),
+        CorrectionResult::Uncorrectable => return result,
+    };
+    let short = !capacity.data_bits.is_multiple_of(8);
+    match &result {
+        CorrectionResult::Corrected { data, .. } if repaired <= capacity.correctable && !(short && data[data.len() - 1] > 0x0F) => result,
+        _ => CorrectionResult::Uncorrectable,
+    }
+}
+
+/// One segment of a Micro QR symbol
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MicroSegment {
+    pub mode: SegmentMode,
+    /// Position of the mode indicator within the data bits
+    pub bit_offset: usize,
+    pub char_count: usize,
+    pub text: String,
+}
+
+/// Decoded data bits of a Micro QR symbol
+#[derive(Debug, Clone, PartialEq)]
+pub struct MicroPayload {
+    pub segments: Vec<MicroSegment>,
+    /// Raw segment contents; Kanji characters as Shift JIS byte pairs
+    pub bytes: Vec<u8>,
+    pub text: String,
+    /// Data bits taken by the segments and terminator; the rest is padding
+    pub end: usize,
+}
+
+/// Bits of a modes indicator, by value: Micro QR numbers its four modes from 0
+const MODES: [SegmentMode; 4] = [SegmentMode::Numeric, SegmentMode::Alphanumeric, SegmentMode::Byte, SegmentMode::Kanji];
+
+/// Parse the segments of the data bits up to the terminator. The terminator may be cut short
+/// or left out when the data fills the symbol. Micro QR has no ECI or Structured Append.
+pub fn parse_segments(bits: &[u8], version: MicroVersion) -> Result<MicroPayload, Box<dyn std::error::Error>> {
+    let mut position = 0;
+    let read = |position: &mut usize, count: usize| -> Option<u32> {
+        let field = bits.get(*position..*position + count)?;
+        *position += count;
+        Some(field.iter().fold(0, |value, &bit| (value << 1) | bit as u32))
+    };
+    let mut payload = MicroPayload { segments: Vec::new(), bytes: Vec::new(), text: String::new(), end: 0 };
+    let truncated = "segment runs past the end of the data bits";
+
+    loop {
+        let terminator = &bits[position..(position + version.terminator_bits()).min(bits.len())];
+        if terminator.iter().all(|&bit| bit == 0) {
+            payload.end = position + terminator.len();
+            break;
+        }
+        let bit_offset = position;
+        let mode = MODES[read(&mut position, version.mode_indicator_bits()).ok_or(truncated)? as usize];
+        let count_bits = version.char_count_bits(mode).ok_or_else(|| format!("{} has no {} mode", version, mode))?;
+        let char_count = read(&mut position, count_bits).ok_or(truncated)? as usize;
+        let data = read_segment_data(mode, char_count, |count| read(&mut position, count))?;
+        let text = segment_text(mode, &data, None);
+        payload.text.push_str(&text);
+        payload.bytes.extend(data);
+        payload.segments.push(MicroSegment { mode, bit_offset, char_count, text });
+    }
+    Ok(payload)
+}
+
+/// Turn a Micro QR matrix upright: of the eight rotations and reflections with the finder
+/// pattern in the top-left corner, take the one whose format information reads with the
+/// fewest bit errors, or the first when none reads. When neither turns up, the matrix is returned
+/// unchanged.
+pub fn normalize_orientation(matrix: &[Vec<u8>]) -> (Vec<Vec<u8>>, Orientation) {
+    let size = matrix.len();
+    if size < 11 || matrix.iter().any(|row| row.len() != size) {
+        return (matrix.to_vec(), Orientation::default());
+    }
+    let candidates: Vec<(Vec<Vec<u8>>, Orientation)> = [false, true]
+        .into_iter()
+        .flat_map(|mirrored| [0, 90, 180, 270].map(|rotation| Orientation { rotation, mirrored }))
+        .map(|orientation| (orient(matrix, orientation), orientation))
+        .filter(|(upright, _)| finder_match(upright, 0, 0) >= MIN_FINDER_MATCH)
+        .collect();
+    let readable = (0..candidates.len())
+        .filter_map(|index| Some((index, decode_format(read_format_word(&candidates[index].0)).filter(|(format, _)| format.version.size() == size)?.1)))
+        .min_by_key(|&(_, bit_errors)| bit_errors)
+        .map(|(index, _)| index);
+    match readable.or((!candidates.is_empty()).then_some(0)) {
+        Some(index) => candidates.into_iter().nth(index).unwrap(),
+        None => (matrix.to_vec(), Orientation::default()),
+    }
+}
+
+/// Result of decoding a Micro QR symbol
+#[derive(Debug, Clone, PartialEq)]
+pub struct MicroDecoded {
+    /// Payload as text. Byte segments are read as UTF-8, falling back to ISO-8859-1.
+    pub text: String,
+    /// Payload bytes exactly as stored in the symbol
+    pub bytes: Vec<u8>,
+    pub format: MicroFormat,
+    /// Bits corrected in the format information
+    pub format_bit_errors: u32,
+    /// Codewords Reed-Solomon correction repaired
+    pub corrected_codewords: usize,
+    /// How the symbol was turned in the input before it was normalized
+    pub orientation: Orientation,
+    pub segments: Vec<MicroSegment>,
+}
+
+/// Decode a Micro QR module matrix (1 = dark) without quiet zone. Rotated and mirrored
+/// symbols are turned upright first.
+pub fn decode_micro(matrix: &[Vec<u8>]) -> Result<MicroDecoded, Box<dyn std::error::Error>> {
+    let size = matrix.len();
+    let version = MicroVersion::from_size(size).ok_or_else(|| format!("no Micro QR version is {} modules wide", size))?;
+    if matrix.iter().any(|row| row.len() != size) {
+        return Err("module matrix is not square".into());
+    }
+    let (matrix, orientation) = normalize_orientation(matrix);
+    let (format, format_bit_errors) = decode_format(read_format_word(&matrix)).ok_or("format information is unreadable")?;
+    if format.version != version {
+        return Err(format!("format information says {} but the symbol is {} modules wide", format.version, size).into());
+    }
+    let capacity = format.capacity().expect("decoded formats have a capacity");
+    let codewords = codewords(&read_bits(&matrix, version, format.mask), capacity);
+    let (corrected, corrected_codewords) = match correct(&codewords, capacity) {
+        CorrectionResult::ErrorFree(data) => (data, 0),
+        CorrectionResult::Corrected { data, error_positions, .. } => (data, error_positions.len()),
+        CorrectionResult::Uncorrectable if version == MicroVersion::M1 => return Err("M1 symbols only detect errors, and this one has some".into()),
+        CorrectionResult::Uncorrectable => return Err("too many errors to correct".into()),
+    };
+    let payload = parse_segments(&data_bits(&corrected, capacity), version)?;
+    Ok(MicroDecoded {
+        text: payload.text,
+        bytes: payload.bytes,
+        format,
+        format_bit_errors,
+        corrected_codewords,
+        orientation,
+        segments: payload.segments,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::ecc::generate_ecc;
+
+    /// Module matrix of a Micro QR symbol holding `bits` as its data, padded as an encoder
+    /// would. Built here since the encoder only makes regular QR symbols.
+    #[allow(clippy::needless_range_loop)]
+    pub(crate) fn micro_symbol(format: MicroFormat, bits: &[u8]) -> Vec<Vec<u8>> {
+        let capacity = format.capacity().unwrap();
+        let mut data = bits.to_vec();
+        data.resize((data.len() + format.version.terminator_bits()).min(capacity.data_bits), 0);
+        data.resize(data.len().next_multiple_of(8).min(capacity.data_bits), 0);
+        for pad in [0xEC, 0x11].iter().cycle() {
+            if data.len() + 8 > capacity.data_bits {
+                break;
+            }
+            data.extend((0..8).rev().map(|i| (pad >> i) & 1));
+        }
+        data.resize(capacity.data_bits, 0);
+        let mut stream = codewords(&data, capacity);
+        stream.extend(generate_ecc(&stream, capacity.ecc_codewords));
+        let mut placed = data;
+        placed.extend(stream[capacity.data_codewords()..].iter().flat_map(|&byte| (0..8).rev().map(move |i| (byte >> i) & 1)));
+
+        let size = format.version.size();
+        let mut matrix = vec![vec![0; size]; size];
+        for row in 0..7 {
+            for col in 0..7 {
+                matrix[row][col] = (row.min(col).min(6 - row).min(6 - col) != 1) as u8;
+            }
+        }
+        for i in 8..size {
+            matrix[0][i] = (i % 2 == 0) as u8;
+            matrix[i][0] = (i % 2 == 0) as u8;
+        }
+        let word = encode_format(format).unwrap();
+        for (i, (row, col)) in format_positions().into_iter().enumerate() {
+            matrix[row][col] = ((word >> i) & 1) as u8;
+        }
+        for ((row, col), bit) in placement(format.version).into_iter().zip(placed) {
+            matrix[row][col] = bit ^ mask_bit(format.mask, row, col) as u8;
+        }
+        matrix
+    }
+
+    /// Bits of `value` in a field `width` bits wide, most significant first
+    pub(crate) fn field(value: u32, width: usize) -> impl Iterator<Item = u8> {
+        (0..width).rev().map(move |i| ((value >> i) & 1) as u8)
+    }
+
+    #[test]
+    fn test_format_words_match_the_published_table() {
+        // ISO/IEC 18004 table C.1, as tabulated by libqrencode
+        let word = |number: usize, mask| {
+            let (version, error_correction) = SYMBOLS[number];
+            encode_format(MicroFormat { version, error_correction, mask }).unwrap()
+        };
+        assert_eq!(word(1, 0), 0x55AE);
+        assert_eq!(word(0, 1), 0x4172);
+        assert_eq!(word(7, 3), 0x3BBA);
+
+        let format = MicroFormat { version: MicroVersion::M3, error_correction: Some(ErrorCorrection::M), mask: 2 };
+        assert_eq!(decode_format(encode_format(format).unwrap() ^ 0b100_0010_0000_0001), Some((format, 3)));
+        assert_eq!(encode_format(MicroFormat { error_correction: Some(ErrorCorrection::H), ..format }), None);
+    }
+
+    #[test]
+    fn test_data_modules_match_the_capacities() {
+        for (number, &(data_bits, ecc_codewords, _)) in CAPACITIES.iter().enumerate() {
+            assert_eq!(placement(SYMBOLS[number].0).len(), data_bits + 8 * ecc_codewords);
+        }
+        let data = [0b1011_0001, 0b0110_1110, 0b1001];
+        let capacity = MicroFormat { version: MicroVersion::M1, error_correction: None, mask: 0 }.capacity().unwrap();
+        let bits = data_bits(&data, capacity);
+        assert_eq!(bits.len(), 20);
+        assert_eq!(codewords(&bits, capacity), data);
+    }
+
+    #[test]
+    fn test_reed_solomon_matches_the_published_example() {
+        // "01234567" in M2-L, ISO/IEC 18004 annex I
+        assert_eq!(generate_ecc(&[0x40, 0x18, 0xAC, 0xC3, 0x00], 5), [0x86, 0x0D, 0x22, 0xAE, 0x30]);
+    }
+
+    #[test]
+    fn test_decodes_every_version_in_any_orientation() {
+        let numeric: Vec<u8> = field(5, 3).chain(field(12, 10)).chain(field(34, 7)).collect();
+        let format = MicroFormat { version: MicroVersion::M1, error_correction: None, mask: 3 };
+        assert_eq!(decode_micro(&micro_symbol(format, &numeric)).unwrap().text, "01234");
+
+        // "AC-42" as alphanumeric, then "é" as a byte segment in M3 and M4
+        let alphanumeric = |version: MicroVersion| {
+            let mode = field(1, version.mode_indicator_bits()).chain(field(5, version.char_count_bits(SegmentMode::Alphanumeric).unwrap()));
+            mode.chain(field(10 * 45 + 12, 11)).chain(field(41 * 45 + 4, 11)).chain(field(2, 6)).collect::<Vec<u8>>()
+        };
+        let format = MicroFormat { version: MicroVersion::M2, error_correction: Some(ErrorCorrection::L), mask: 1 };
+        assert_eq!(decode_micro(&micro_symbol(format, &alphanumeric(MicroVersion::M2))).unwrap().text, "AC-42");
+        for (version, level) in [(MicroVersion::M3, ErrorCorrection::M), (MicroVersion::M4, ErrorCorrection::Q)] {
+            let mut bits = alphanumeric(version);
+            bits.extend(field(2, version.mode_indicator_bits()).chain(field(2, version.char_count_bits(SegmentMode::Byte).unwrap())));
+            bits.extend("é".bytes().flat_map(|byte| field(byte as u32, 8)));
+            let format = MicroFormat { version, error_correction: Some(level), mask: 2 };
+            let mut matrix = micro_symbol(format, &bits);
+            let decoded = decode_micro(&matrix).unwrap();
+            assert_eq!((decoded.text.as_str(), decoded.format, decoded.corrected_codewords), ("AC-42é", format, 0));
+            assert_eq!(decoded.segments.iter().map(|s| s.mode).collect::<Vec<_>>(), [SegmentMode::Alphanumeric, SegmentMode::Byte]);
+
+            // Mirrored and turned a quarter clockwise, with a data module flipped
+            let size = version.size();
+            matrix[size - 2][size - 2] ^= 1;
+            let input = orient(&orient(&matrix, Orientation { rotation: 0, mirrored: true }), Orientation { rotation: 270, mirrored: false });
+            let decoded = decode_micro(&input).unwrap();
+            assert_eq!(decoded.text, "AC-42é");
+            assert_eq!(decoded.orientation, Orientation { rotation: 90, mirrored: true });
+            assert_eq!(decoded.corrected_codewords, 1);
+        }
+    }
+
+    #[test]
+    fn test_m1_detects_errors_without_correcting() {
+        let bits: Vec<u8> = field(2, 3).chain(field(42, 7)).collect();
+        let mut matrix = micro_symbol(MicroFormat { version: MicroVersion::M1, error_correction: None, mask: 0 }, &bits);
+        assert_eq!(decode_micro(&matrix).unwrap().text, "42");
+        matrix[10][10] ^= 1;
+        assert!(decode_micro(&matrix).unwrap_err().to_string().contains("only detect errors"));
+    }
+}
@@ -0,0 +1,80 @@
+use sha2::{Digest, Sha256};
+use crate::types::{ErrorCorrection, MaskPattern, Version};
+
+/// What a filename template can refer to for one code
+#[derive(Debug, Clone, Copy)]
+pub struct NameTokens<'a> {
+    /// 1-based position of the code, e.g. its batch row
+    pub index: usize,
+    pub payload: &'a [u8],
+    pub version: Version,
+    pub error_correction: ErrorCorrection,
+    pub mask: MaskPattern,
+}
+
+/// Expand the tokens in a filename template:
+///
+/// - `{index}` (or `{id}`): the code's position, counting from 1
+/// - `{hash}`: SHA-256 of the payload in hex; `{hash8}` and so on keep the first N digits
+/// - `{version}`, `{ecc}`, `{mask}`: symbol version, error correction level and mask pattern
+///
+/// `{{` and `}}` stand for literal braces.
+pub fn expand_name_template(template: &str, tokens: &NameTokens) -> Result<String, String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        name.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        if rest[start + 1..].starts_with(brace) {
+            name.push_str(brace);
+            rest = &rest[start + 2..];
+            continue;
+        }
+        if brace == "}" {
+            return Err(format!("unmatched }} in {:?}", template));
+        }
+        let end = rest[start..].find('}').ok_or_else(|| format!("unclosed {{ in {:?}", template))? + start;
+        name.push_str(&expand_token(&rest[start + 1..end], tokens).ok_or_else(|| format!("unknown token {{{}}} in {:?}", &rest[start + 1..end], template))?);
+        rest = &rest[end + 1..];
+    }
+    name.push_str(rest);
+    Ok(name)
+}
+
+/// Whether the template refers to the index, so that the names differ from one code to the next
+pub fn uses_index(template: &str) -> bool {
+    template.contains("{index}") || template.contains("{id}")
+}
+
+fn expand_token(token: &str, tokens: &NameTokens) -> Option<String> {
+    match token {
+        "index" | "id" => Some(tokens.index.to_string()),
+        "version" => Some((tokens.version as u8).to_string()),
+        "ecc" => Some(format!("{:?}", tokens.error_correction)),
+        "mask" => Some((tokens.mask as u8).to_string()),
+        _ => {
+            let digits = token.strip_prefix("hash")?;
+            let hash: String = Sha256::digest(tokens.payload).iter().map(|b| format!("{:02x}", b)).collect();
+            let length = if digits.is_empty() { hash.len() } else { digits.parse().ok().filter(|n| (1..=hash.len()).contains(n))? };
+            Some(hash[..length].to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_name_template() {
+        let tokens = NameTokens { index: 7, payload: b"abc", version: Version::V2, error_correction: ErrorCorrection::Q, mask: MaskPattern::Pattern5 };
+        let expand = |template| expand_name_template(template, &tokens);
+        // sha256("abc") = ba7816bf...
+        assert_eq!(expand("qr-{hash8}-{version}.png").unwrap(), "qr-ba7816bf-2.png");
+        assert_eq!(expand("{id}-{index}-{ecc}{mask}-{{x}}.svg").unwrap(), "7-7-Q5-{x}.svg");
+        assert_eq!(expand("{hash}").unwrap().len(), 64);
+        for bad in ["{payload}.png", "{hash0}", "{hash65}", "qr-{index", "qr}.png"] {
+            assert!(expand(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,601 @@
+//! Builders for the structured payloads that phones act on when they scan a code, such as
+//! contact cards, `mailto:` links and authenticator enrollments. Each builder checks its fields and returns the exact
+//! text to encode.
+
+use crate::types::ErrorCorrection;
+
+/// Longest value accepted for a single field, in bytes. Longer values push a code past the
+/// sizes phones scan comfortably.
+pub const MAX_FIELD_BYTES: usize = 256;
+
+/// Longest line of a vCard before it is folded, in bytes (RFC 2425)
+const VCARD_LINE_BYTES: usize = 75;
+
+/// Error correction level the EPC guidelines (EPC069-12) require for credit transfer codes
+pub const EPC_ERROR_CORRECTION: ErrorCorrection = ErrorCorrection::M;
+
+/// Longest EPC payload, the capacity of a version 13 symbol at level M
+const EPC_MAX_BYTES: usize = 331;
+
+/// A contact card, encoded as a vCard 3.0 or a MeCard
+#[derive(Debug, Clone, Default)]
+pub struct Contact {
+    /// Full name; "Family, Given" splits it explicitly, otherwise the last word is the family name
+    pub name: String,
+    pub phones: Vec<String>,
+    pub emails: Vec<String>,
+    pub organization: Option<String>,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    /// Postal address on one line
+    pub address: Option<String>,
+    pub note: Option<String>,
+}
+
+impl Contact {
+    /// The contact as a vCard 3.0 (RFC 2426), with CRLF line endings and long lines folded
+    pub fn to_vcard(&self) -> Result<String, String> {
+        self.validate()?;
+        let (family, given) = self.split_name();
+        let mut lines = vec![
+            "BEGIN:VCARD".to_string(),
+            "VERSION:3.0".to_string(),
+            format!("N:{};{};;;", escape_vcard(&family), escape_vcard(&given)),
+            format!("FN:{}", escape_vcard(self.name.trim())),
+        ];
+        if let Some(organization) = &self.organization {
+            lines.push(format!("ORG:{}", escape_vcard(organization)));
+        }
+        if let Some(title) = &self.title {
+            lines.push(format!("TITLE:{}", escape_vcard(title)));
+        }
+        lines.extend(self.phones.iter().map(|phone| format!("TEL:{}", phone)));
+        lines.extend(self.emails.iter().map(|email| format!("EMAIL;TYPE=INTERNET:{}", email)));
+        if let Some(url) = &self.url {
+            lines.push(format!("URL:{}", url));
+        }
+        if let Some(address) = &self.address {
+            lines.push(format!("ADR:;;{};;;;", escape_vcard(address)));
+        }
+        if let Some(note) = &self.note {
+            lines.push(format!("NOTE:{}", escape_vcard(note)));
+        }
+        lines.push("END:VCARD".to_string());
+        Ok(lines.iter().map(|line| fold_line(line) + "\r\n").collect())
+    }
+
+    /// The contact as a MeCard, the more compact format introduced by NTT DoCoMo. It has no
+    /// field for a job title.
+    pub fn to_mecard(&self) -> Result<String, String> {
+        self.validate()?;
+        if self.title.is_some() {
+            return Err("MeCard has no title field; use a vCard instead".to_string());
+        }
+        let (family, given) = self.split_name();
+        let name = if given.is_empty() { escape_mecard(&family) } else { format!("{},{}", escape_mecard(&family), escape_mecard(&given)) };
+        let mut card = format!("MECARD:N:{};", name);
+        if let Some(organization) = &self.organization {
+            card += &format!("ORG:{};", escape_mecard(organization));
+        }
+        for phone in &self.phones {
+            card += &format!("TEL:{};", phone);
+        }
+        for email in &self.emails {
+            card += &format!("EMAIL:{};", escape_mecard(email));
+        }
+        if let Some(url) = &self.url {
+            card += &format!("URL:{};", escape_mecard(url));
+        }
+        if let Some(address) = &self.address {
+            card += &format!("ADR:{};", escape_mecard(address));
+        }
+        if let Some(note) = &self.note {
+            card += &format!("NOTE:{};", escape_mecard(note));
+        }
+        Ok(card + ";")
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("the contact needs a name".to_string());
+        }
+        let fields = [("name", Some(&self.name)), ("organization", self.organization.as_ref()), ("title", self.title.as_ref()), ("url", self.url.as_ref())];
+        for (field, value) in fields.into_iter().filter_map(|(field, value)| Some((field, value?))) {
+            check_field(field, value, false)?;
+        }
+        for (field, value) in [("address", &self.address), ("note", &self.note)] {
+            if let Some(value) = value {
+                check_field(field, value, true)?;
+            }
+        }
+        for phone in &self.phones {
+            check_field("phone", phone, false)?;
+            check_phone(phone)?;
+        }
+        for email in &self.emails {
+            check_field("email", email, false)?;
+            check_email(email)?;
+        }
+        if let Some(url) = &self.url && url.contains(char::is_whitespace) {
+            return Err(format!("url {:?} contains whitespace", url));
+        }
+        Ok(())
+    }
+
+    /// (family, given) name
+    fn split_name(&self) -> (String, String) {
+        let name = self.name.trim();
+        if let Some((family, given)) = name.split_once(',') {
+            return (family.trim().to_string(), given.trim().to_string());
+        }
+        match name.rsplit_once(char::is_whitespace) {
+            Some((given, family)) => (family.to_string(), given.trim().to_string()),
+            None => (name.to_string(), String::new()),
+        }
+    }
+}
+
+/// A `mailto:` URI (RFC 6068) with an optional subject and body. Line breaks in the body are
+/// sent as CRLF, as the RFC requires.
+pub fn mailto(to: &str, subject: Option<&str>, body: Option<&str>) -> Result<String, String> {
+    check_field("email", to, false)?;
+    check_email(to)?;
+    let mut uri = format!("mailto:{}", percent_encode(to, "@+!$'*"));
+    let mut separator = '?';
+    if let Some(subject) = subject {
+        check_field("subject", subject, false)?;
+        uri += &format!("{}subject={}", separator, percent_encode(subject, ""));
+        separator = '&';
+    }
+    if let Some(body) = body {
+        check_field("body", body, true)?;
+        uri += &format!("{}body={}", separator, percent_encode(&body.replace('\n', "\r\n"), ""));
+    }
+    Ok(uri)
+}
+
+/// A `tel:` URI (RFC 3966). Separators are dropped from the number, so "+1 (555) 010-0100"
+/// becomes `tel:+15550100100`.
+pub fn tel(number: &str) -> Result<String, String> {
+    Ok(format!("tel:{}", phone_digits(number)?))
+}
+
+/// An `sms:` URI (RFC 5724), optionally with the message text filled in
+pub fn sms(number: &str, body: Option<&str>) -> Result<String, String> {
+    let mut uri = format!("sms:{}", phone_digits(number)?);
+    if let Some(body) = body {
+        check_field("message", body, true)?;
+        uri += &format!("?body={}", percent_encode(body, ""));
+    }
+    Ok(uri)
+}
+
+/// A `geo:` URI (RFC 5870) for WGS 84 coordinates in degrees, and the altitude in meters
+pub fn geo(latitude: f64, longitude: f64, altitude: Option<f64>) -> Result<String, String> {
+    if !(-90.0..=90.0).contains(&latitude) {
+        return Err(format!("latitude {} is not between -90 and 90", latitude));
+    }
+    if !(-180.0..=180.0).contains(&longitude) {
+        return Err(format!("longitude {} is not between -180 and 180", longitude));
+    }
+    match altitude {
+        Some(altitude) if !altitude.is_finite() => Err(format!("altitude {} is not a number of meters", altitude)),
+        Some(altitude) => Ok(format!("geo:{},{},{}", latitude, longitude, altitude)),
+        None => Ok(format!("geo:{},{}", latitude, longitude)),
+    }
+}
+
+/// A SEPA credit transfer in the EPC069-12 format that European banking apps scan
+#[derive(Debug, Clone, Default)]
+pub struct SepaTransfer {
+    /// Beneficiary account; spaces are ignored
+    pub iban: String,
+    /// Beneficiary bank, optional within the EEA
+    pub bic: Option<String>,
+    /// Beneficiary name
+    pub name: String,
+    /// Amount in euros, such as "12.50"
+    pub amount: Option<String>,
+    /// Structured ISO 11649 creditor reference, such as "RF18539007547034"
+    pub reference: Option<String>,
+    /// Unstructured remittance text, in place of a reference
+    pub text: Option<String>,
+}
+
+impl SepaTransfer {
+    /// The 11 lines of an EPC version 002 payload, UTF-8 encoded, separated by LF
+    pub fn to_epc(&self) -> Result<String, String> {
+        let iban: String = self.iban.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+        check_iban(&iban)?;
+        let bic = self.bic.as_deref().unwrap_or("").to_uppercase();
+        if !bic.is_empty() && !is_bic(&bic) {
+            return Err(format!("{:?} is not a BIC", bic));
+        }
+        let name = self.name.trim();
+        check_length("name", name, 1, 70)?;
+        let amount = match &self.amount {
+            Some(amount) => format!("EUR{}", parse_euros(amount)?),
+            None => String::new(),
+        };
+        let reference = self.reference.as_deref().unwrap_or("").to_uppercase();
+        if !reference.is_empty() {
+            check_creditor_reference(&reference)?;
+        }
+        let text = self.text.as_deref().unwrap_or("");
+        check_length("text", text, 0, 140)?;
+        if !reference.is_empty() && !text.is_empty() {
+            return Err("give either a reference or a text, not both".to_string());
+        }
+
+        let lines = ["BCD", "002", "1", "SCT", &bic, name, &iban, &amount, "", &reference, text];
+        if lines.iter().any(|line| line.chars().any(char::is_control)) {
+            return Err("payment fields cannot contain line breaks or control characters".to_string());
+        }
+        let payload = lines.join("\n");
+        if payload.len() > EPC_MAX_BYTES {
+            return Err(format!("the payment is {} bytes long; EPC codes hold at most {}", payload.len(), EPC_MAX_BYTES));
+        }
+        Ok(payload)
+    }
+}
+
+/// Hash function of a one-time password generator
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TotpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// A time-based one-time password secret (RFC 6238) for an authenticator app to enroll
+#[derive(Debug, Clone)]
+pub struct Totp {
+    /// Service the account belongs to, shown by the app
+    pub issuer: String,
+    /// Account name, usually a user name or email address
+    pub account: String,
+    /// Shared secret in base32; spaces and padding are ignored
+    pub secret: String,
+    pub digits: u8,
+    /// Seconds each password is valid for
+    pub period: u32,
+    pub algorithm: TotpAlgorithm,
+}
+
+impl Default for Totp {
+    fn default() -> Self {
+        Self { issuer: String::new(), account: String::new(), secret: String::new(), digits: 6, period: 30, algorithm: TotpAlgorithm::Sha1 }
+    }
+}
+
+impl Totp {
+    /// The `otpauth://totp/` URI of the Key Uri Format. Parameters at their defaults (SHA1, 6
+    /// digits, 30 seconds) are left out, since some apps only understand those.
+    pub fn to_uri(&self) -> Result<String, String> {
+        for (field, value) in [("issuer", &self.issuer), ("account", &self.account)] {
+            check_field(field, value, false)?;
+            if value.trim().is_empty() || value.contains(':') {
+                return Err(format!("{} must be non-empty and cannot contain ':'", field));
+            }
+        }
+        let secret = normalize_base32(&self.secret)?;
+        if !(6..=8).contains(&self.digits) {
+            return Err(format!("{} digits are not supported; use 6, 7 or 8", self.digits));
+        }
+        if self.period == 0 {
+            return Err("the period must be at least one second".to_string());
+        }
+
+        let issuer = percent_encode(self.issuer.trim(), "");
+        let mut uri = format!("otpauth://totp/{}:{}?secret={}&issuer={}", issuer, percent_encode(self.account.trim(), "@"), secret, issuer);
+        match self.algorithm {
+            TotpAlgorithm::Sha1 => {}
+            TotpAlgorithm::Sha256 => uri += "&algorithm=SHA256",
+            TotpAlgorithm::Sha512 => uri += "&algorithm=SHA512",
+        }
+        if self.digits != 6 {
+            uri += &format!("&digits={}", self.digits);
+        }
+        if self.period != 30 {
+            uri += &format!("&period={}", self.period);
+        }
+        Ok(uri)
+    }
+}
+
+/// Uppercase base32 (RFC 4648) without spaces or padding, checked to hold whole bytes and at
+/// least 80 bits, the shortest secret authenticator apps accept
+fn normalize_base32(secret: &str) -> Result<String, String> {
+    let secret: String = secret.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+    let secret = secret.trim_end_matches('=');
+    if let Some(c) = secret.chars().find(|c| !matches!(c, 'A'..='Z' | '2'..='7')) {
+        return Err(format!("the secret is not base32: {:?} is not one of A-Z and 2-7", c));
+    }
+    if secret.len() * 5 < 80 {
+        return Err(format!("the secret holds {} bits; use at least 80", secret.len() * 5 / 8 * 8));
+    }
+    // Lengths that leave a partial byte over are not produced by any encoder
+    if matches!(secret.len() % 8, 1 | 3 | 6) {
+        return Err(format!("a base32 secret cannot be {} characters long", secret.len()));
+    }
+    Ok(secret.to_string())
+}
+
+/// Percent-encode everything but the unreserved characters of RFC 3986 and those in `keep`
+pub fn percent_encode(value: &str, keep: &str) -> String {
+    let mut encoded = String::new();
+    for c in value.chars() {
+        if c.is_ascii_alphanumeric() || "-._~".contains(c) || keep.contains(c) {
+            encoded.push(c);
+        } else {
+            let mut bytes = [0; 4];
+            for byte in c.encode_utf8(&mut bytes).bytes() {
+                encoded += &format!("%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+/// Country code, check digits and up to 30 letters or digits, passing the ISO 7064 mod 97 check
+fn check_iban(iban: &str) -> Result<(), String> {
+    let bytes = iban.as_bytes();
+    let valid = (15..=34).contains(&iban.len())
+        && bytes[..2].iter().all(u8::is_ascii_uppercase)
+        && bytes[2..4].iter().all(u8::is_ascii_digit)
+        && bytes.iter().all(u8::is_ascii_alphanumeric);
+    if !valid {
+        return Err(format!("{:?} is not an IBAN", iban));
+    }
+    if mod97(&iban[4..], &iban[..4]) != 1 {
+        return Err(format!("IBAN {} has a wrong checksum", iban));
+    }
+    Ok(())
+}
+
+/// "RF", check digits and up to 21 letters or digits, passing the mod 97 check (ISO 11649)
+fn check_creditor_reference(reference: &str) -> Result<(), String> {
+    let bytes = reference.as_bytes();
+    let valid = (5..=25).contains(&reference.len())
+        && reference.starts_with("RF")
+        && bytes[2..4].iter().all(u8::is_ascii_digit)
+        && bytes.iter().all(u8::is_ascii_alphanumeric);
+    if !valid {
+        return Err(format!("{:?} is not an RF creditor reference; use --text for free-form references", reference));
+    }
+    if mod97(&reference[4..], &reference[..4]) != 1 {
+        return Err(format!("creditor reference {} has a wrong checksum", reference));
+    }
+    Ok(())
+}
+
+/// Remainder modulo 97 of `body` followed by `head`, with letters counting as 10 to 35
+fn mod97(body: &str, head: &str) -> u32 {
+    body.chars().chain(head.chars()).fold(0, |remainder, c| {
+        let value = c.to_digit(36).unwrap_or(0);
+        let shift = if value < 10 { 10 } else { 100 };
+        (remainder * shift + value) % 97
+    })
+}
+
+/// Bank code, country code, location and optional branch: 8 or 11 characters
+fn is_bic(bic: &str) -> bool {
+    let bytes = bic.as_bytes();
+    matches!(bic.len(), 8 | 11) && bytes[..6].iter().all(u8::is_ascii_uppercase) && bytes[6..].iter().all(u8::is_ascii_alphanumeric)
+}
+
+/// An amount from 0.01 to 999999999.99 with at most two decimals, normalized to two
+fn parse_euros(amount: &str) -> Result<String, String> {
+    let invalid = || format!("amount {:?} is not a number of euros like 12.50", amount);
+    let (euros, cents) = amount.split_once('.').unwrap_or((amount, ""));
+    if euros.is_empty() || euros.len() > 9 || cents.len() > 2 || !euros.chars().chain(cents.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let cents: u64 = euros.parse::<u64>().map_err(|_| invalid())? * 100 + format!("{:0<2}", cents).parse::<u64>().map_err(|_| invalid())?;
+    if cents == 0 {
+        return Err("the amount must be at least 0.01".to_string());
+    }
+    Ok(format!("{}.{:02}", cents / 100, cents % 100))
+}
+
+/// Check that a field holds between `min` and `max` characters
+fn check_length(field: &str, value: &str, min: usize, max: usize) -> Result<(), String> {
+    let length = value.chars().count();
+    if length < min || length > max {
+        return Err(format!("{} must be {} to {} characters long", field, min, max));
+    }
+    Ok(())
+}
+
+/// A phone number reduced to its digits and leading +
+fn phone_digits(number: &str) -> Result<String, String> {
+    check_field("phone", number, false)?;
+    check_phone(number)?;
+    Ok(number.chars().filter(|&c| c.is_ascii_digit() || c == '+').collect())
+}
+
+/// Check the length of a field and that it holds no control characters, apart from line
+/// breaks where `multiline`
+fn check_field(field: &str, value: &str, multiline: bool) -> Result<(), String> {
+    if value.len() > MAX_FIELD_BYTES {
+        return Err(format!("{} is {} bytes long; the limit is {}", field, value.len(), MAX_FIELD_BYTES));
+    }
+    if value.chars().any(|c| c.is_control() && !(multiline && c == '\n')) {
+        return Err(format!("{} contains control characters", field));
+    }
+    Ok(())
+}
+
+/// Digits with optional separators and a leading +
+fn check_phone(phone: &str) -> Result<(), String> {
+    let digits = phone.strip_prefix('+').unwrap_or(phone);
+    if !digits.chars().any(|c| c.is_ascii_digit()) || !digits.chars().all(|c| c.is_ascii_digit() || " -.()".contains(c)) {
+        return Err(format!("{:?} is not a phone number", phone));
+    }
+    Ok(())
+}
+
+/// local@domain, without the characters that separate fields
+fn check_email(email: &str) -> Result<(), String> {
+    let valid = match email.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.') && !domain.contains('@'),
+        None => false,
+    };
+    if !valid || email.contains(|c: char| c.is_whitespace() || ";,:\"<>".contains(c)) {
+        return Err(format!("{:?} is not an email address", email));
+    }
+    Ok(())
+}
+
+/// Escape a vCard text value (RFC 2426, section 4)
+fn escape_vcard(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape a MeCard value: the field separators and the backslash itself
+fn escape_mecard(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if "\\;,:\"".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(if c == '\n' { ' ' } else { c });
+    }
+    escaped
+}
+
+/// Fold a vCard line into pieces of at most 75 bytes, each continuation starting with a
+/// space. Multi-byte characters are never split.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > VCARD_LINE_BYTES {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contact_cards() {
+        let contact = Contact {
+            name: "Jane Q. Doe".to_string(),
+            phones: vec!["+1 555-0100".to_string()],
+            emails: vec!["jane@example.com".to_string()],
+            organization: Some("Doe, Inc.".to_string()),
+            note: Some("Line one\nLine two; done".to_string()),
+            ..Contact::default()
+        };
+        assert_eq!(
+            contact.to_vcard().unwrap(),
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Doe;Jane Q.;;;\r\nFN:Jane Q. Doe\r\nORG:Doe\\, Inc.\r\nTEL:+1 555-0100\r\n\
+             EMAIL;TYPE=INTERNET:jane@example.com\r\nNOTE:Line one\\nLine two\\; done\r\nEND:VCARD\r\n"
+        );
+        assert_eq!(
+            contact.to_mecard().unwrap(),
+            "MECARD:N:Doe,Jane Q.;ORG:Doe\\, Inc.;TEL:+1 555-0100;EMAIL:jane@example.com;NOTE:Line one Line two\\; done;;"
+        );
+
+        let long = Contact { note: Some("é".repeat(100)), ..contact.clone() };
+        let vcard = long.to_vcard().unwrap();
+        assert!(vcard.split("\r\n").all(|line| line.len() <= VCARD_LINE_BYTES));
+        assert!(vcard.replace("\r\n ", "").contains(&"é".repeat(100)));
+
+        for bad in [
+            Contact { name: " ".to_string(), ..contact.clone() },
+            Contact { phones: vec!["call me".to_string()], ..contact.clone() },
+            Contact { emails: vec!["jane@localhost".to_string()], ..contact.clone() },
+            Contact { note: Some("x".repeat(MAX_FIELD_BYTES + 1)), ..contact.clone() },
+        ] {
+            assert!(bad.to_vcard().is_err(), "{:?}", bad);
+        }
+        assert!(Contact { title: Some("CEO".to_string()), ..contact }.to_mecard().is_err());
+    }
+
+    #[test]
+    fn test_uri_payloads() {
+        assert_eq!(
+            mailto("jane+qr@example.com", Some("Hi & bye"), Some("a\nb")).unwrap(),
+            "mailto:jane+qr@example.com?subject=Hi%20%26%20bye&body=a%0D%0Ab"
+        );
+        assert_eq!(mailto("jane@example.com", None, Some("é")).unwrap(), "mailto:jane@example.com?body=%C3%A9");
+        assert_eq!(tel("+1 (555) 010-0100").unwrap(), "tel:+15550100100");
+        assert_eq!(sms("5550100", Some("Stop?")).unwrap(), "sms:5550100?body=Stop%3F");
+        assert_eq!(geo(52.5163, 13.3777, None).unwrap(), "geo:52.5163,13.3777");
+        assert_eq!(geo(-33.8568, 151.2153, Some(20.0)).unwrap(), "geo:-33.8568,151.2153,20");
+        assert!(mailto("not an address", None, None).is_err());
+        assert!(tel("555-CALL").is_err());
+        assert!(geo(91.0, 0.0, None).is_err());
+        assert!(geo(0.0, f64::NAN, None).is_err());
+    }
+
+    #[test]
+    fn test_totp_uri() {
+        let totp = Totp { issuer: "Example Co".to_string(), account: "jane@example.com".to_string(), secret: "jbsw y3dp ehpk 3pxp".to_string(), ..Totp::default() };
+        assert_eq!(totp.to_uri().unwrap(), "otpauth://totp/Example%20Co:jane@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example%20Co");
+        let custom = Totp { digits: 8, period: 60, algorithm: TotpAlgorithm::Sha256, ..totp.clone() };
+        assert!(custom.to_uri().unwrap().ends_with("&algorithm=SHA256&digits=8&period=60"));
+
+        for bad in [
+            Totp { secret: "JBSWY3DPEHPK3PX1".to_string(), ..totp.clone() },
+            Totp { secret: "JBSWY3DPEH".to_string(), ..totp.clone() },
+            Totp { secret: "JBSWY3DPEHPK3PXPA".to_string(), ..totp.clone() },
+            Totp { issuer: "Example:Co".to_string(), ..totp.clone() },
+            Totp { digits: 10, ..totp.clone() },
+            Totp { period: 0, ..totp.clone() },
+        ] {
+            assert!(bad.to_uri().is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_sepa_transfer() {
+        let transfer = SepaTransfer {
+            iban: "DE33 1002 0500 0001 1947 00".to_string(),
+            bic: Some("BFSWDE33BER".to_string()),
+            name: "Wikimedia Foerdergesellschaft".to_string(),
+            amount: Some("123.45".to_string()),
+            text: Some("Spende fuer Wikipedia".to_string()),
+            ..SepaTransfer::default()
+        };
+        assert_eq!(
+            transfer.to_epc().unwrap(),
+            "BCD\n002\n1\nSCT\nBFSWDE33BER\nWikimedia Foerdergesellschaft\nDE33100205000001194700\nEUR123.45\n\n\nSpende fuer Wikipedia"
+        );
+        let structured = SepaTransfer { bic: None, amount: Some("5".to_string()), reference: Some("rf18539007547034".to_string()), text: None, ..transfer.clone() };
+        assert_eq!(structured.to_epc().unwrap().split('\n').collect::<Vec<_>>()[4..], ["", "Wikimedia Foerdergesellschaft", "DE33100205000001194700", "EUR5.00", "", "RF18539007547034", ""]);
+
+        for bad in [
+            SepaTransfer { iban: "DE34100205000001194700".to_string(), ..transfer.clone() },
+            SepaTransfer { bic: Some("BFSW".to_string()), ..transfer.clone() },
+            SepaTransfer { amount: Some("1,50".to_string()), ..transfer.clone() },
+            SepaTransfer { amount: Some("0.001".to_string()), ..transfer.clone() },
+            SepaTransfer { amount: Some("0".to_string()), ..transfer.clone() },
+            SepaTransfer { reference: Some("RF19539007547034".to_string()), text: None, ..transfer.clone() },
+            SepaTransfer { reference: Some("RF18539007547034".to_string()), ..transfer.clone() },
+            SepaTransfer { name: "x".repeat(71), ..transfer.clone() },
+        ] {
+            assert!(bad.to_epc().is_err(), "{:?}", bad);
+        }
+    }
+}
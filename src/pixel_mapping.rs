@@ -1,183 +1,158 @@
-use crate::types::Version;
-use crate::alignment::get_alignment_positions;
+use alloc::{vec, vec::Vec};
+use crate::types::{ErrorCorrection, Version};
+use crate::capacity::get_block_layout;
+use crate::function_modules::function_module_map;
 
-/// Get all data and ECC pixel positions for a given QR code version
+/// Get all data and ECC pixel positions for a given QR code version, in the order of the
+/// zigzag placement path: two columns at a time from the right, alternately upwards and
+/// downwards, skipping the vertical timing pattern and all function modules
 pub fn get_data_ecc_positions(version: Version) -> Vec<(usize, usize)> {
-    let size = version_to_size(version);
+    let size = version.size();
+    let reserved = function_module_map(version);
     let mut positions = Vec::new();
-    
-    // Read data in zigzag pattern (right to left, alternating up/down)
-    let mut col = size - 1;
-    let mut going_up = true;
-    
-    while col > 0 {
-        // Skip timing column
-        if col == 6 {
-            col -= 1;
-            continue;
+
+    let mut right = size - 1;
+    loop {
+        if right == 6 {
+            right = 5;
         }
-        
-        // Process two columns at a time
-        for c in [col, col - 1] {
-            let rows: Vec<usize> = if going_up {
-                (0..size).rev().collect()
-            } else {
-                (0..size).collect()
-            };
-            
-            for row in rows {
-                if !is_function_module(row, c, size) {
-                    positions.push((row, c));
+        let upward = (right + 1) & 2 == 0;
+        for vert in 0..size {
+            let row = if upward { size - 1 - vert } else { vert };
+            for col in [right, right - 1] {
+                if !reserved[row][col] {
+                    positions.push((row, col));
                 }
             }
         }
-        
-        going_up = !going_up;
-        col = if col >= 2 { col - 2 } else { 0 };
+        if right < 3 {
+            break;
+        }
+        right -= 2;
     }
-    
     positions
 }
 
-/// Check if a position is a function module (finder, timing, format, etc.)
-pub fn is_function_module(row: usize, col: usize, size: usize) -> bool {
-    // Finder patterns (top-left, top-right, bottom-left)
-    if (row < 9 && col < 9) || 
-       (row < 9 && col >= size - 8) || 
-       (row >= size - 8 && col < 9) {
-        return true;
-    }
-    
-    // Timing patterns
-    if row == 6 || col == 6 {
-        return true;
-    }
-    
-    // Dark module
-    if row == 4 * ((size - 17) / 4) + 9 && col == 8 {
-        return true;
-    }
-    
-    // Format information areas
-    if (row < 9 && (col < 9 || col >= size - 8)) ||
-       (row >= size - 8 && col < 9) ||
-       (row == 8 && (col < 9 || col >= size - 7)) ||
-       (col == 8 && (row < 9 || row >= size - 7)) {
-        return true;
+/// Where a data or ECC module sits in the codeword stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleCodeword {
+    /// Index of the codeword in the interleaved stream as placed in the symbol
+    pub codeword: usize,
+    /// Bit within the codeword, 7 being the most significant
+    pub bit: u8,
+    /// Reed-Solomon block the codeword belongs to, from 0
+    pub block: usize,
+    /// Index of the codeword within its block, data codewords first
+    pub index_in_block: usize,
+    pub is_ecc: bool,
+}
+
+/// What a data-region module carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleSource {
+    /// Data codeword `k` of the message, counted before interleaving
+    Data(usize),
+    /// Data codeword `k` past the end of the encoded segments: terminator and pad bytes
+    Padding(usize),
+    /// ECC codeword `j` of its block
+    Ecc(usize),
+    /// Remainder bits after the last codeword
+    Remainder,
+}
+
+/// Where a module of the data region comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleOrigin {
+    pub row: usize,
+    pub col: usize,
+    /// The codeword in the stream, `None` for remainder bits
+    pub codeword: Option<ModuleCodeword>,
+    pub source: ModuleSource,
+}
+
+/// Trace every data and ECC module of a symbol back to its codeword, block and source, in
+/// placement order. Every data codeword counts as [`ModuleSource::Data`];
+/// [`trace_with_padding`] tells the padding apart.
+pub fn trace(version: Version, error_correction: ErrorCorrection) -> Vec<ModuleOrigin> {
+    trace_with_padding(version, error_correction, usize::MAX)
+}
+
+/// [`trace`] for a message whose segments fill the first `used` data codewords, such as
+/// [`CodewordCounts::used`](crate::generator::CodewordCounts::used)
+pub fn trace_with_padding(version: Version, error_correction: ErrorCorrection, used: usize) -> Vec<ModuleOrigin> {
+    let layout = get_block_layout(version, error_correction);
+    let lengths = layout.data_lengths();
+    let block_starts: Vec<usize> = layout.data_ranges().map(|range| range.start).collect();
+    let stream = layout.stream_positions();
+
+    get_data_ecc_positions(version)
+        .into_iter()
+        .enumerate()
+        .map(|(i, (row, col))| {
+            let codeword = i / 8;
+            let Some(&(block, index_in_block)) = stream.get(codeword) else {
+                return ModuleOrigin { row, col, codeword: None, source: ModuleSource::Remainder };
+            };
+            let is_ecc = index_in_block >= lengths[block];
+            let source = match block_starts[block] + index_in_block {
+                _ if is_ecc => ModuleSource::Ecc(index_in_block - lengths[block]),
+                k if k < used => ModuleSource::Data(k),
+                k => ModuleSource::Padding(k),
+            };
+            let location = ModuleCodeword { codeword, bit: 7 - (i % 8) as u8, block, index_in_block, is_ecc };
+            ModuleOrigin { row, col, codeword: Some(location), source }
+        })
+        .collect()
+}
+
+/// Map every module of a symbol to the codeword it carries, through the placement path and
+/// the block interleaving. Function modules and remainder bits map to `None`.
+pub fn module_codewords(version: Version, error_correction: ErrorCorrection) -> Vec<Vec<Option<ModuleCodeword>>> {
+    let size = version.size();
+    let mut map = vec![vec![None; size]; size];
+    for origin in trace(version, error_correction) {
+        map[origin.row][origin.col] = origin.codeword;
     }
-    
-    // Alignment patterns
-    let version = size_to_version(size).unwrap_or(Version::V1);
-    let alignment_positions = get_alignment_positions(version);
-    for &center_x in &alignment_positions {
-        for &center_y in &alignment_positions {
-            // Skip if overlaps with finder patterns (same logic as generator)
-            if (center_x <= 8 && center_y <= 8) ||
-               (center_x <= 8 && center_y >= size - 9) ||
-               (center_x >= size - 9 && center_y <= 8) {
-                continue;
-            }
-            
-            // Check if current position is within 5x5 alignment pattern
-            if row >= center_y.saturating_sub(2) && row <= center_y + 2 &&
-               col >= center_x.saturating_sub(2) && col <= center_x + 2 {
-                return true;
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capacity::get_total_codewords_in_bytes;
+
+    #[test]
+    fn test_every_codeword_has_eight_modules() {
+        for version in [Version::V1, Version::V7, Version::V22] {
+            let layout = get_block_layout(version, ErrorCorrection::Q);
+            let stream = layout.stream_positions();
+            let mut bits = vec![0u8; get_total_codewords_in_bytes(version)];
+            let mut ecc_modules = 0;
+            for location in module_codewords(version, ErrorCorrection::Q).into_iter().flatten().flatten() {
+                bits[location.codeword] |= 1 << location.bit;
+                assert_eq!(stream[location.codeword], (location.block, location.index_in_block));
+                ecc_modules += location.is_ecc as usize;
             }
+            assert!(bits.iter().all(|&b| b == 0xFF));
+            assert_eq!(ecc_modules, layout.ecc_per_block * layout.total_blocks() * 8);
         }
     }
-    
-    false
-}
 
-/// Convert version enum to size
-pub fn version_to_size(version: Version) -> usize {
-    match version {
-        Version::V1 => 21,
-        Version::V2 => 25,
-        Version::V3 => 29,
-        Version::V4 => 33,
-        Version::V5 => 37,
-        Version::V6 => 41,
-        Version::V7 => 45,
-        Version::V8 => 49,
-        Version::V9 => 53,
-        Version::V10 => 57,
-        Version::V11 => 61,
-        Version::V12 => 65,
-        Version::V13 => 69,
-        Version::V14 => 73,
-        Version::V15 => 77,
-        Version::V16 => 81,
-        Version::V17 => 85,
-        Version::V18 => 89,
-        Version::V19 => 93,
-        Version::V20 => 97,
-        Version::V21 => 101,
-        Version::V22 => 105,
-        Version::V23 => 109,
-        Version::V24 => 113,
-        Version::V25 => 117,
-        Version::V26 => 121,
-        Version::V27 => 125,
-        Version::V28 => 129,
-        Version::V29 => 133,
-        Version::V30 => 137,
-        Version::V31 => 141,
-        Version::V32 => 145,
-        Version::V33 => 149,
-        Version::V34 => 153,
-        Version::V35 => 157,
-        Version::V36 => 161,
-        Version::V37 => 165,
-        Version::V38 => 169,
-        Version::V39 => 173,
-        Version::V40 => 177,
-    }
-}
+    #[test]
+    fn test_trace_names_each_codeword_source() {
+        // 5-Q: two blocks of 15 data codewords and two of 16, 18 ECC codewords each
+        let origins = trace_with_padding(Version::V5, ErrorCorrection::Q, 20);
+        let count = |wanted: fn(&ModuleSource) -> bool| origins.iter().filter(|origin| wanted(&origin.source)).count();
+        assert_eq!(count(|source| matches!(source, ModuleSource::Data(_))), 20 * 8);
+        assert_eq!(count(|source| matches!(source, ModuleSource::Padding(_))), (62 - 20) * 8);
+        assert_eq!(count(|source| matches!(source, ModuleSource::Ecc(_))), 4 * 18 * 8);
+        assert_eq!(count(|source| matches!(source, ModuleSource::Remainder)), 7);
 
-/// Convert size to version
-pub fn size_to_version(size: usize) -> Option<Version> {
-    match size {
-        21 => Some(Version::V1),
-        25 => Some(Version::V2),
-        29 => Some(Version::V3),
-        33 => Some(Version::V4),
-        37 => Some(Version::V5),
-        41 => Some(Version::V6),
-        45 => Some(Version::V7),
-        49 => Some(Version::V8),
-        53 => Some(Version::V9),
-        57 => Some(Version::V10),
-        61 => Some(Version::V11),
-        65 => Some(Version::V12),
-        69 => Some(Version::V13),
-        73 => Some(Version::V14),
-        77 => Some(Version::V15),
-        81 => Some(Version::V16),
-        85 => Some(Version::V17),
-        89 => Some(Version::V18),
-        93 => Some(Version::V19),
-        97 => Some(Version::V20),
-        101 => Some(Version::V21),
-        105 => Some(Version::V22),
-        109 => Some(Version::V23),
-        113 => Some(Version::V24),
-        117 => Some(Version::V25),
-        121 => Some(Version::V26),
-        125 => Some(Version::V27),
-        129 => Some(Version::V28),
-        133 => Some(Version::V29),
-        137 => Some(Version::V30),
-        141 => Some(Version::V31),
-        145 => Some(Version::V32),
-        149 => Some(Version::V33),
-        153 => Some(Version::V34),
-        157 => Some(Version::V35),
-        161 => Some(Version::V36),
-        165 => Some(Version::V37),
-        169 => Some(Version::V38),
-        173 => Some(Version::V39),
-        177 => Some(Version::V40),
-        _ => None,
+        // The stream opens with the first codeword of each block; the third block starts at
+        // message codeword 30
+        let first_of = |codeword: usize| origins.iter().find(|origin| origin.codeword.is_some_and(|c| c.codeword == codeword)).unwrap().source;
+        assert_eq!([first_of(0), first_of(1), first_of(2), first_of(3)], [ModuleSource::Data(0), ModuleSource::Data(15), ModuleSource::Padding(30), ModuleSource::Padding(46)]);
+        assert_eq!(first_of(62), ModuleSource::Ecc(0));
+        assert!(origins.iter().all(|origin| origin.row < 37 && origin.col < 37));
     }
 }
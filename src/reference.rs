@@ -0,0 +1,166 @@
+//! Golden reference vectors: symbols published with their codewords and modules, which the
+//! encoder must reproduce bit for bit. `qr analyze --reference NAME` compares an image with
+//! one of them module by module.
+
+use alloc::vec::Vec;
+use crate::types::{DataMode, ErrorCorrection, MaskPattern, QrConfig, Version};
+
+/// A published symbol, with the parameters it was built with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Golden {
+    /// Short name, as given to `--reference`
+    pub name: &'static str,
+    /// Where the codewords and modules were published
+    pub source: &'static str,
+    pub text: &'static str,
+    pub version: Version,
+    pub error_correction: ErrorCorrection,
+    pub data_mode: DataMode,
+    pub mask: MaskPattern,
+    pub data_codewords: &'static [u8],
+    pub ecc_codewords: &'static [u8],
+    /// Modules row by row without quiet zone, `#` dark and `.` light
+    pub modules: &'static [&'static str],
+}
+
+impl Golden {
+    /// The modules as a matrix, 1 = dark
+    pub fn matrix(&self) -> Vec<Vec<u8>> {
+        self.modules.iter().map(|row| row.bytes().map(|module| (module == b'#') as u8).collect()).collect()
+    }
+
+    /// Encoder settings that reproduce the symbol from `text`
+    pub fn config(&self) -> QrConfig {
+        QrConfig { error_correction: self.error_correction, data_mode: self.data_mode, mask_pattern: self.mask, ..QrConfig::default() }
+    }
+}
+
+pub const GOLDENS: &[Golden] = &[
+    Golden {
+        name: "hello-world-1q",
+        source: "Thonky QR code tutorial, \"HELLO WORLD\" as 1-Q",
+        text: "HELLO WORLD",
+        version: Version::V1,
+        error_correction: ErrorCorrection::Q,
+        data_mode: DataMode::Alphanumeric,
+        mask: MaskPattern::Pattern6,
+        data_codewords: &[32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236],
+        ecc_codewords: &[168, 72, 22, 82, 217, 54, 156, 0, 46, 15, 180, 122, 16],
+        modules: &[
+            "#######....#..#######",
+            "#.....#.##..#.#.....#",
+            "#.###.#..#.##.#.###.#",
+            "#.###.#.#####.#.###.#",
+            "#.###.#.##.#..#.###.#",
+            "#.....#..#..#.#.....#",
+            "#######.#.#.#.#######",
+            "........##.##........",
+            ".#.####.##..###.##.#.",
+            "#.####.#....####.###.",
+            "..#.#.##...#..##.....",
+            "#.##.#...#.##...##...",
+            "##.########.###.#####",
+            "........#...#..#.#...",
+            "#######..##..##..####",
+            "#.....#.#.#..#..#.###",
+            "#.###.#.##.#..#...###",
+            "#.###.#.#.###...#.#..",
+            "#.###.#..#....#....##",
+            "#.....#.###..###..##.",
+            "#######..#.#.......#.",
+        ],
+    },
+    Golden {
+        name: "iso-01234567-1m",
+        source: "ISO/IEC 18004 Annex I, \"01234567\" as 1-M",
+        text: "01234567",
+        version: Version::V1,
+        error_correction: ErrorCorrection::M,
+        data_mode: DataMode::Numeric,
+        mask: MaskPattern::Pattern2,
+        data_codewords: &[0x10, 0x20, 0x0C, 0x56, 0x61, 0x80, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11],
+        ecc_codewords: &[0xA5, 0x24, 0xD4, 0xC1, 0xED, 0x36, 0xC7, 0x87, 0x2C, 0x55],
+        modules: &[
+            "#######..#.##.#######",
+            "#.....#..####.#.....#",
+            "#.###.#.#.....#.###.#",
+            "#.###.#.##....#.###.#",
+            "#.###.#.#.###.#.###.#",
+            "#.....#.#...#.#.....#",
+            "#######.#.#.#.#######",
+            "........#..##........",
+            "#.#####..#..#.#####..",
+            "...#.#.##.#.#..#.##..",
+            "..#...##.#.#.#..#####",
+            "....#....#.....####..",
+            "...######..#.#..#....",
+            "........#.#####..##..",
+            "#######..##.#.##.....",
+            "#.....#.#.#####...#.#",
+            "#.###.#.#...#..#.##..",
+            "#.###.#.##..#..#.....",
+            "#.###.#.#.##.#..#.#..",
+            "#.....#........##.##.",
+            "#######.####.#..#.#..",
+        ],
+    },
+];
+
+/// The golden vector called `name`
+pub fn golden(name: &str) -> Option<&'static Golden> {
+    GOLDENS.iter().find(|golden| golden.name == name)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::diagnostics::{Diagnostics, Stage};
+    use crate::generator::generate_qr_matrix;
+    use qrcodegen::QrCode;
+
+    /// Module matrix of a symbol built by an independent reference encoder
+    pub(crate) fn reference_matrix(qr: &QrCode) -> Vec<Vec<u8>> {
+        let size = qr.size();
+        (0..size).map(|y| (0..size).map(|x| qr.get_module(x, y) as u8).collect()).collect()
+    }
+
+    #[test]
+    fn test_encoder_reproduces_the_golden_codewords() {
+        for golden in GOLDENS {
+            let diagnostics = Diagnostics::new();
+            let config = QrConfig { diagnostics: Some(diagnostics.clone()), ..golden.config() };
+            generate_qr_matrix(golden.text.as_bytes(), &config);
+            let blocks = diagnostics.stages().into_iter().find_map(|stage| match stage {
+                Stage::Blocks { data, ecc } => Some((data, ecc)),
+                _ => None,
+            });
+            assert_eq!(blocks, Some((vec![golden.data_codewords.to_vec()], vec![golden.ecc_codewords.to_vec()])), "{}", golden.name);
+        }
+    }
+
+    #[test]
+    fn test_encoder_reproduces_the_golden_modules() {
+        for golden in GOLDENS {
+            let matrix = generate_qr_matrix(golden.text.as_bytes(), &golden.config());
+            assert_eq!(matrix.len(), golden.version.size(), "{}", golden.name);
+            assert_eq!(matrix, golden.matrix(), "{}", golden.name);
+        }
+    }
+
+    #[test]
+    fn test_goldens_agree_with_the_reference_encoder() {
+        for golden in GOLDENS {
+            let ecc = match golden.error_correction {
+                ErrorCorrection::L => qrcodegen::QrCodeEcc::Low,
+                ErrorCorrection::M => qrcodegen::QrCodeEcc::Medium,
+                ErrorCorrection::Q => qrcodegen::QrCodeEcc::Quartile,
+                ErrorCorrection::H => qrcodegen::QrCodeEcc::High,
+            };
+            let version = qrcodegen::Version::new(golden.version as u8);
+            let mask = Some(qrcodegen::Mask::new(golden.mask as u8));
+            let segments = qrcodegen::QrSegment::make_segments(golden.text);
+            let qr = qrcodegen::QrCode::encode_segments_advanced(&segments, ecc, version, version, mask, false).unwrap();
+            assert_eq!(reference_matrix(&qr), golden.matrix(), "{}", golden.name);
+        }
+    }
+}
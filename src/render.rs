@@ -0,0 +1,581 @@
+//! Drawing module matrices. The [`Renderer`] trait is the extension point: each output format
+//! is a backend implementing it, in a submodule of its own, and downstream code can add
+//! targets this crate does not know about.
+
+use std::io::Write;
+use image::{DynamicImage, RgbImage};
+use ab_glyph::{Font, FontVec, PxScale, ScaleFont, point};
+
+mod gif;
+mod png;
+mod pnm;
+mod svg;
+mod terminal;
+
+pub use gif::render_gif_stages;
+pub use png::{render_image, render_png, stream_png};
+pub(crate) use png::write_png;
+pub use pnm::{render_pbm, render_pgm};
+pub use svg::render_svg;
+pub use terminal::render_terminal;
+
+
+/// An image composited over the center of the symbol
+#[derive(Debug, Clone)]
+pub struct Logo {
+    pub image: DynamicImage,
+    /// Side of the covered square, in modules (see `logo::max_logo_size`)
+    pub size_modules: usize,
+}
+
+/// Where the caption goes relative to the symbol
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CaptionPosition {
+    Top,
+    #[default]
+    Bottom,
+}
+
+/// Text printed above or below the symbol, e.g. "Scan me"
+#[derive(Debug, Clone)]
+pub struct Caption {
+    pub text: String,
+    /// Font size in pixels
+    pub size: f32,
+    pub position: CaptionPosition,
+    /// TrueType/OpenType font used for PNG output; a common system font is used when unset
+    pub font_path: Option<String>,
+}
+
+/// Fonts tried, in order, when a caption has no explicit font
+const DEFAULT_FONT_PATHS: [&str; 6] = [
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/liberation/LiberationSans-Regular.ttf",
+    "/Library/Fonts/Arial.ttf",
+    "C:\\Windows\\Fonts\\arial.ttf",
+];
+
+/// Shape used to draw each dark data module
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ModuleStyle {
+    #[default]
+    Square,
+    Dot,
+    Rounded,
+}
+
+impl ModuleStyle {
+    /// Whether the pixel (dx, dy) inside a module cell of `scale` pixels is covered by the shape
+    fn contains(&self, scale: usize, dx: usize, dy: usize) -> bool {
+        let s = scale as f64;
+        let px = dx as f64 + 0.5;
+        let py = dy as f64 + 0.5;
+        match self {
+            ModuleStyle::Square => true,
+            ModuleStyle::Dot => in_rounded_square(px, py, s, s / 2.0),
+            ModuleStyle::Rounded => in_rounded_square(px, py, s, s * ROUNDED_CORNER_RATIO),
+        }
+    }
+}
+
+/// Corner radius of `ModuleStyle::Rounded`, as a fraction of the module size
+const ROUNDED_CORNER_RATIO: f64 = 0.3;
+
+/// Shape used to draw the three finder patterns ("eyes")
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EyeStyle {
+    #[default]
+    Square,
+    Rounded,
+    Circle,
+}
+
+impl EyeStyle {
+    /// Corner radius for a square of side `w` drawn in this style
+    fn corner_radius(&self, w: f64) -> f64 {
+        match self {
+            EyeStyle::Square => 0.0,
+            EyeStyle::Rounded => w * 0.25,
+            EyeStyle::Circle => w / 2.0,
+        }
+    }
+}
+
+/// Whether the point (px, py) lies inside a square of side `w` at the origin whose corners
+/// are rounded with radius `r` (0 gives a plain square, `w / 2` a circle).
+fn in_rounded_square(px: f64, py: f64, w: f64, r: f64) -> bool {
+    if px < 0.0 || py < 0.0 || px > w || py > w {
+        return false;
+    }
+    // Distance from the point to the inner square inset by the corner radius
+    let cx = px.clamp(r, w - r);
+    let cy = py.clamp(r, w - r);
+    (px - cx).powi(2) + (py - cy).powi(2) <= r * r
+}
+
+/// Whether (x, y) lies inside one of the three 7x7 finder patterns, which are never drawn
+/// with the data module style so that scanners can still locate the symbol.
+fn is_finder_module(x: usize, y: usize, size: usize) -> bool {
+    (x < 7 && (y < 7 || y + 7 >= size)) || (y < 7 && x + 7 >= size)
+}
+
+/// Top-left module of each of the three finder patterns
+fn finder_origins(size: usize) -> [(usize, usize); 3] {
+    [(0, 0), (size - 7, 0), (0, size - 7)]
+}
+
+/// The nested squares making up a finder pattern, as (inset in modules, side in modules, dark)
+const EYE_LAYERS: [(usize, usize, bool); 3] = [(0, 7, true), (1, 5, false), (2, 3, true)];
+
+/// Gradient fill for the dark data modules in SVG output
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gradient {
+    /// Linear gradient; 0 degrees runs left to right, 90 degrees top to bottom
+    Linear { from: [u8; 3], to: [u8; 3], angle: f64 },
+    /// Radial gradient from the center outwards
+    Radial { from: [u8; 3], to: [u8; 3] },
+}
+
+impl Gradient {
+    /// Parse `"#rrggbb,#rrggbb,<angle>deg"` or `"#rrggbb,#rrggbb,radial"`; the third part
+    /// defaults to a 0 degree linear gradient when omitted.
+    pub fn parse(value: &str) -> Option<Gradient> {
+        let parts: Vec<&str> = value.split(',').map(|p| p.trim()).collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return None;
+        }
+        let from = parse_hex_color(parts[0])?;
+        let to = parse_hex_color(parts[1])?;
+        match parts.get(2) {
+            None => Some(Gradient::Linear { from, to, angle: 0.0 }),
+            Some(&"radial") => Some(Gradient::Radial { from, to }),
+            Some(kind) => {
+                let angle = kind.strip_suffix("deg").unwrap_or(kind).parse::<f64>().ok()?;
+                Some(Gradient::Linear { from, to, angle })
+            }
+        }
+    }
+
+    /// `<defs>` block defining `#qr-gradient` over the symbol area starting at `origin`
+    fn svg_defs(&self, origin: f64, extent: f64, options: &RenderOptions) -> String {
+        let center = origin + extent / 2.0;
+        let stops = |from: [u8; 3], to: [u8; 3]| format!(
+            r#"<stop offset="0" stop-color="{}"/><stop offset="1" stop-color="{}"/>"#,
+            options.svg_color(from), options.svg_color(to)
+        );
+        match self {
+            Gradient::Linear { from, to, angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                let half = extent / 2.0;
+                format!(
+                    r#"<defs><linearGradient id="qr-gradient" gradientUnits="userSpaceOnUse" x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}">{}</linearGradient></defs>"#,
+                    center - cos * half, center - sin * half, center + cos * half, center + sin * half,
+                    stops(*from, *to)
+                )
+            }
+            Gradient::Radial { from, to } => format!(
+                r#"<defs><radialGradient id="qr-gradient" gradientUnits="userSpaceOnUse" cx="{}" cy="{}" r="{:.2}">{}</radialGradient></defs>"#,
+                center, center, extent / std::f64::consts::SQRT_2, stops(*from, *to)
+            ),
+        }
+    }
+}
+
+/// Parse a `#rrggbb` (or `rrggbb`) color
+pub fn parse_hex_color(value: &str) -> Option<[u8; 3]> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn hex_color(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// Options controlling how a module matrix is turned into an image.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Pixels per module
+    pub scale: usize,
+    /// Width of the light border around the symbol, in modules
+    pub quiet_zone: usize,
+    /// Optional logo drawn over the center of the symbol (PNG only)
+    pub logo: Option<Logo>,
+    /// Shape of the dark data modules (PNG and SVG)
+    pub style: ModuleStyle,
+    /// Shape of the finder patterns (PNG and SVG)
+    pub eye_style: EyeStyle,
+    /// Color of the dark finder pattern modules (PNG and SVG)
+    pub eye_color: [u8; 3],
+    /// Gradient fill for the dark data modules (SVG only)
+    pub gradient: Option<Gradient>,
+    /// Draw a dark frame around the whole image (PNG and SVG)
+    pub frame: bool,
+    /// Optional caption text (PNG and SVG)
+    pub caption: Option<Caption>,
+    /// Physical resolution in pixels per inch, written as PNG pHYs metadata and used for
+    /// millimetre width/height in SVG output. `None` leaves the output unitless.
+    pub dpi: Option<f64>,
+    /// Reject anything resolved from the host rather than given in the options. Today that is
+    /// only the caption font: without an explicit `font_path` rendering fails instead of
+    /// taking the first system font found. PNG encoder settings are pinned and no timestamp
+    /// is written either way, so the bytes then depend on the matrix, these options, the
+    /// contents of a given font file and the `image` and `png` versions in Cargo.lock; an
+    /// upgrade of either crate may change them, on any platform.
+    pub deterministic: bool,
+    /// Draw the symbol as a negative, light modules on a dark quiet zone, as some displays
+    /// show them. Every color inside the symbol area is complemented; frame and caption are not.
+    pub invert: bool,
+}
+
+/// Pixel layout of the final image once the frame and caption are added around the symbol
+struct Layout {
+    width: usize,
+    height: usize,
+    frame: usize,
+    /// Top-left corner of the symbol (including its quiet zone)
+    qr_x: usize,
+    qr_y: usize,
+    /// Vertical center of the caption band
+    caption_y: usize,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            scale: 10,
+            quiet_zone: 4,
+            logo: None,
+            style: ModuleStyle::Square,
+            eye_style: EyeStyle::Square,
+            eye_color: [0, 0, 0],
+            gradient: None,
+            frame: false,
+            caption: None,
+            dpi: None,
+            deterministic: false,
+            invert: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Border width in pixels
+    pub fn border(&self) -> usize {
+        self.quiet_zone * self.scale
+    }
+
+    /// Width (and height) in pixels of the rendered image for a matrix of `size` modules
+    pub fn total_size(&self, size: usize) -> usize {
+        size * self.scale + 2 * self.border()
+    }
+
+    fn layout(&self, size: usize) -> Layout {
+        let total_size = self.total_size(size);
+        let frame = if self.frame { self.scale } else { 0 };
+        let band = self.caption.as_ref().map_or(0, |c| (c.size * 1.8).ceil() as usize);
+        let top = self.caption.as_ref().is_some_and(|c| c.position == CaptionPosition::Top);
+        Layout {
+            width: total_size + 2 * frame,
+            height: total_size + 2 * frame + band,
+            frame,
+            qr_x: frame,
+            qr_y: frame + if top { band } else { 0 },
+            caption_y: frame + if top { band / 2 } else { total_size + band / 2 },
+        }
+    }
+
+    fn is_decorated(&self) -> bool {
+        self.frame || self.caption.is_some()
+    }
+
+    /// Returns 1 if the pixel at (px, py) is drawn dark: a dark module, or with `invert` a light
+    /// module or the quiet zone
+    fn pixel_value(&self, matrix: &[Vec<u8>], px: usize, py: usize) -> u8 {
+        let size = matrix.len();
+        let border = self.border();
+        let value = if px < border || py < border || px >= border + size * self.scale || py >= border + size * self.scale {
+            0
+        } else {
+            matrix[(py - border) / self.scale][(px - border) / self.scale]
+        };
+        value ^ self.invert as u8
+    }
+
+    /// Color of the pixel at (px, py) of the symbol and its quiet zone, as [`render_image`]
+    /// draws it before any logo
+    fn symbol_pixel(&self, matrix: &[Vec<u8>], px: usize, py: usize) -> [u8; 3] {
+        let size = matrix.len();
+        let scale = self.scale;
+        let border = self.border();
+        let mut color = [255u8, 255u8, 255u8];
+        if px >= border && py >= border && px < border + size * scale && py < border + size * scale {
+            let (x, y) = ((px - border) / scale, (py - border) / scale);
+            let finder = is_finder_module(x, y, size);
+            if matrix[y][x] == 1 && !(finder && self.eye_style != EyeStyle::Square) {
+                let (style, dark) = if finder { (ModuleStyle::Square, self.eye_color) } else { (self.style, [0u8, 0u8, 0u8]) };
+                if style.contains(scale, (px - border) % scale, (py - border) % scale) {
+                    color = dark;
+                }
+            }
+            if self.eye_style != EyeStyle::Square {
+                for (ox, oy) in finder_origins(size) {
+                    for (inset, side, dark) in EYE_LAYERS {
+                        let w = (side * scale) as f64;
+                        let left = border + (ox + inset) * scale;
+                        let top = border + (oy + inset) * scale;
+                        if px >= left && py >= top && in_rounded_square((px - left) as f64 + 0.5, (py - top) as f64 + 0.5, w, self.eye_style.corner_radius(w)) {
+                            color = if dark { self.eye_color } else { [255u8, 255u8, 255u8] };
+                        }
+                    }
+                }
+            }
+        }
+        if self.invert { color.map(|c| 255 - c) } else { color }
+    }
+
+    /// SVG color for `color` drawn inside the symbol area, complemented when inverting
+    fn svg_color(&self, color: [u8; 3]) -> String {
+        hex_color(if self.invert { color.map(|c| 255 - c) } else { color })
+    }
+}
+
+
+pub(crate) const MM_PER_INCH: f64 = 25.4;
+
+/// A target a symbol can be drawn to. The backends below write a file format to any `Write`;
+/// implement it to draw elsewhere, such as into an embedded framebuffer or onto a Cairo
+/// surface, without going through an image file.
+pub trait Renderer {
+    /// Draw `matrix`, 1 for a dark module and 0 for a light one, as `options` describe. A
+    /// backend ignores the options it cannot show, as PBM does colors.
+    fn render(&mut self, matrix: &[Vec<u8>], options: &RenderOptions) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// PNG image, see [`render_png`]
+pub struct Png<W: Write>(pub W);
+
+impl<W: Write> Renderer for Png<W> {
+    fn render(&mut self, matrix: &[Vec<u8>], options: &RenderOptions) -> Result<(), Box<dyn std::error::Error>> {
+        render_png(matrix, options, &mut self.0)
+    }
+}
+
+/// SVG document, see [`render_svg`]
+pub struct Svg<W: Write>(pub W);
+
+impl<W: Write> Renderer for Svg<W> {
+    fn render(&mut self, matrix: &[Vec<u8>], options: &RenderOptions) -> Result<(), Box<dyn std::error::Error>> {
+        render_svg(matrix, options, &mut self.0)
+    }
+}
+
+/// Netpbm bitmap, binary or plain text, see [`render_pbm`]
+pub struct Pbm<W: Write> {
+    pub writer: W,
+    pub plain: bool,
+}
+
+impl<W: Write> Renderer for Pbm<W> {
+    fn render(&mut self, matrix: &[Vec<u8>], options: &RenderOptions) -> Result<(), Box<dyn std::error::Error>> {
+        render_pbm(matrix, options, self.plain, &mut self.writer)
+    }
+}
+
+/// Netpbm graymap, see [`render_pgm`]
+pub struct Pgm<W: Write>(pub W);
+
+impl<W: Write> Renderer for Pgm<W> {
+    fn render(&mut self, matrix: &[Vec<u8>], options: &RenderOptions) -> Result<(), Box<dyn std::error::Error>> {
+        render_pgm(matrix, options, &mut self.0)
+    }
+}
+
+/// Unicode block characters for a terminal, see [`render_terminal`]
+pub struct Terminal<W: Write>(pub W);
+
+impl<W: Write> Renderer for Terminal<W> {
+    fn render(&mut self, matrix: &[Vec<u8>], options: &RenderOptions) -> Result<(), Box<dyn std::error::Error>> {
+        render_terminal(matrix, options, &mut self.0)
+    }
+}
+
+fn load_caption_font(caption: &Caption) -> Result<FontVec, Box<dyn std::error::Error>> {
+    let data = match &caption.font_path {
+        Some(path) => std::fs::read(path)?,
+        None => DEFAULT_FONT_PATHS
+            .iter()
+            .find_map(|path| std::fs::read(path).ok())
+            .ok_or("No default font found for the caption; specify one with --caption-font")?,
+    };
+    Ok(FontVec::try_from_vec(data)?)
+}
+
+/// Draw `caption` in black, centered on (center_x, center_y)
+pub fn draw_caption(img: &mut RgbImage, caption: &Caption, center_x: f32, center_y: f32) -> Result<(), Box<dyn std::error::Error>> {
+    let font = load_caption_font(caption)?;
+    let px_scale = PxScale::from(caption.size);
+    let scaled = font.as_scaled(px_scale);
+
+    // Lay the glyphs out on a baseline at y = 0, then shift the whole line into place
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for ch in caption.text.chars() {
+        let id = scaled.glyph_id(ch);
+        if let Some(prev) = previous {
+            caret += scaled.kern(prev, id);
+        }
+        glyphs.push(id.with_scale_and_position(px_scale, point(caret, 0.0)));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    let left = center_x - caret / 2.0;
+    let baseline = center_y + (scaled.ascent() + scaled.descent()) / 2.0;
+
+    for mut glyph in glyphs {
+        glyph.position = point(glyph.position.x + left, baseline);
+        if let Some(outline) = font.outline_glyph(glyph) {
+            let bounds = outline.px_bounds();
+            outline.draw(|gx, gy, coverage| {
+                let x = bounds.min.x as i64 + gx as i64;
+                let y = bounds.min.y as i64 + gy as i64;
+                if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+                    return;
+                }
+                let pixel = img.get_pixel_mut(x as u32, y as u32);
+                for c in 0..3 {
+                    pixel[c] = (pixel[c] as f32 * (1.0 - coverage.min(1.0))) as u8;
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::tests::reference_matrix;
+
+    #[test]
+    fn test_gradient_parse() {
+        let (red, blue) = ([255, 0, 0], [0, 0, 255]);
+        assert_eq!(Gradient::parse("#ff0000,#0000ff,90deg"), Some(Gradient::Linear { from: red, to: blue, angle: 90.0 }));
+        assert_eq!(Gradient::parse("ff0000, 0000FF, -45"), Some(Gradient::Linear { from: red, to: blue, angle: -45.0 }));
+        assert_eq!(Gradient::parse("#ff0000,#0000ff"), Some(Gradient::Linear { from: red, to: blue, angle: 0.0 }));
+        assert_eq!(Gradient::parse("#ff0000,#0000ff,radial"), Some(Gradient::Radial { from: red, to: blue }));
+        for malformed in ["#ff0000", "#ff0000,#0000ff,radial,90deg", "#ff00,#0000ff", "#ff0000,#gg0000", "#ff0000,#0000ff,sideways", "#ff0000,#0000ff,90rad", ""] {
+            assert_eq!(Gradient::parse(malformed), None, "{}", malformed);
+        }
+    }
+
+    /// The cell of `style` at `scale` pixels, one string per pixel row with `#` for covered
+    fn shape(style: ModuleStyle, scale: usize) -> Vec<String> {
+        (0..scale).map(|dy| (0..scale).map(|dx| if style.contains(scale, dx, dy) { '#' } else { '.' }).collect()).collect()
+    }
+
+    #[test]
+    fn test_module_styles_at_several_scales() {
+        for scale in [3, 5, 10] {
+            assert!(shape(ModuleStyle::Square, scale).iter().all(|row| !row.contains('.')));
+        }
+        // Too small to show any rounding
+        assert_eq!(shape(ModuleStyle::Dot, 3), ["###"; 3]);
+        assert_eq!(shape(ModuleStyle::Rounded, 5), ["#####"; 5]);
+
+        assert_eq!(shape(ModuleStyle::Dot, 5), [".###.", "#####", "#####", "#####", ".###."]);
+        assert_eq!(
+            shape(ModuleStyle::Dot, 10),
+            ["...####...", ".########.", ".########.", "##########", "##########", "##########", "##########", ".########.", ".########.", "...####..."]
+        );
+        let rounded = shape(ModuleStyle::Rounded, 10);
+        assert_eq!([&rounded[0], &rounded[9]], [".########."; 2]);
+        assert!(rounded[1..9].iter().all(|row| row == "##########"));
+    }
+
+    /// Lengths of the runs of dark and light pixels across the middle of the top-left finder,
+    /// along its center row and its center column, in an image with `module` pixels per module
+    fn finder_runs(img: &RgbImage, border: usize, module: usize) -> [Vec<usize>; 2] {
+        let center = (border + 3 * module + module / 2) as u32;
+        let along = |pixel: &dyn Fn(u32) -> [u8; 3]| {
+            let mut runs: Vec<usize> = Vec::new();
+            let mut last = None;
+            for i in border as u32..(border + 7 * module) as u32 {
+                let dark = pixel(i)[0] < 128;
+                if last == Some(dark) {
+                    *runs.last_mut().unwrap() += 1;
+                } else {
+                    runs.push(1);
+                    last = Some(dark);
+                }
+            }
+            runs
+        };
+        [along(&|x| img.get_pixel(x, center).0), along(&|y| img.get_pixel(center, y).0)]
+    }
+
+    fn decode_rqrr(img: &RgbImage) -> Option<String> {
+        let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(img.width() as usize, img.height() as usize, |x, y| img.get_pixel(x as u32, y as u32)[0]);
+        prepared.detect_grids().first()?.decode().ok().map(|(_, text)| text)
+    }
+
+    #[test]
+    fn test_eye_styles_keep_the_finder_proportions() {
+        let qr = qrcodegen::QrCode::encode_text("https://example.com/eyes", qrcodegen::QrCodeEcc::Medium).unwrap();
+        let matrix = reference_matrix(&qr);
+        for eye_style in [EyeStyle::Square, EyeStyle::Rounded, EyeStyle::Circle] {
+            let options = RenderOptions { scale: 6, eye_style, ..Default::default() };
+            let png = render_image(&matrix, &options).unwrap();
+            let mut svg = Vec::new();
+            render_svg(&matrix, &options, &mut svg).unwrap();
+            let svg = crate::svg::rasterize(&String::from_utf8(svg).unwrap()).unwrap();
+            // The rasterizer may scale small documents up by a whole factor
+            let factor = svg.width() as usize / options.total_size(matrix.len());
+
+            for (img, module) in [(&png, options.scale), (&svg, options.scale * factor)] {
+                let expected: Vec<usize> = [1, 1, 3, 1, 1].iter().map(|n| n * module).collect();
+                assert_eq!(finder_runs(img, options.quiet_zone * module, module), [expected.clone(), expected], "{:?}", eye_style);
+                let decoded = crate::decoder::decode_pixels(&DynamicImage::ImageRgb8(img.clone())).map(|decoded| decoded.text);
+                assert_eq!(decoded.ok().as_deref(), Some("https://example.com/eyes"), "{:?}", eye_style);
+                // rqrr finds finders by their corners, so it reads only the square eyes
+                if eye_style == EyeStyle::Square {
+                    assert_eq!(decode_rqrr(img).as_deref(), Some("https://example.com/eyes"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_frame_and_caption_layout() {
+        let matrix = vec![vec![1; 21]; 21];
+        let caption = |position| Caption { text: "Scan me".to_string(), size: 20.0, position, font_path: None };
+        // 290 px of symbol and quiet zone, a 10 px frame and a 36 px caption band
+        for (position, qr_y, caption_y) in [(CaptionPosition::Top, 46, 28), (CaptionPosition::Bottom, 10, 318)] {
+            let options = RenderOptions { frame: true, caption: Some(caption(position)), ..Default::default() };
+            let layout = options.layout(matrix.len());
+            assert_eq!((layout.width, layout.height, layout.frame), (310, 346, 10));
+            assert_eq!((layout.qr_x, layout.qr_y, layout.caption_y), (10, qr_y, caption_y));
+
+            let mut svg = Vec::new();
+            render_svg(&matrix, &options, &mut svg).unwrap();
+            let svg = String::from_utf8(svg).unwrap();
+            assert!(svg.contains(r#"width="310" height="346" viewBox="0 0 310 346""#));
+            assert!(svg.contains(&format!(r#"<text x="155" y="{}""#, caption_y)));
+            assert!(svg.contains(&format!(r#"<g transform="translate(10 {})">"#, qr_y)));
+        }
+
+        // Without a caption the frame alone surrounds the symbol
+        let options = RenderOptions { frame: true, ..Default::default() };
+        let img = render_image(&matrix, &options).unwrap();
+        assert_eq!(img.dimensions(), (310, 310));
+        assert_eq!(img.get_pixel(5, 150).0, [0, 0, 0]);
+        assert_eq!(img.get_pixel(10 + 39, 10 + 39).0, [255, 255, 255]);
+        assert_eq!(img.get_pixel(10 + 40, 10 + 40).0, [0, 0, 0]);
+    }
+}
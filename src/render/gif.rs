@@ -0,0 +1,31 @@
+use std::io::Write;
+use image::{Delay, Frame, Rgba, RgbaImage};
+use image::codecs::gif::{GifEncoder, Repeat};
+use crate::generator::ConstructionStage;
+use super::RenderOptions;
+
+/// Render construction stages as a looping animated GIF, one frame per stage. The finished
+/// symbol is held on screen longer before the animation restarts.
+pub fn render_gif_stages(stages: &[ConstructionStage], options: &RenderOptions, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let mut frames = Vec::with_capacity(stages.len());
+    for (index, stage) in stages.iter().enumerate() {
+        let total_size = options.total_size(stage.matrix.len()) as u32;
+        let img = RgbaImage::from_fn(total_size, total_size, |px, py| {
+            if options.pixel_value(&stage.matrix, px as usize, py as usize) == 1 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let delay_ms = if index + 1 == stages.len() { 3000 } else { 1000 };
+        frames.push(Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1)));
+    }
+
+    {
+        let mut encoder = GifEncoder::new(&mut writer);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
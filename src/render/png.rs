@@ -0,0 +1,342 @@
+use std::io::Write;
+use image::{ImageBuffer, ImageEncoder, Rgb, RgbImage};
+use image::codecs::png::{CompressionType, FilterType as PngFilterType, PngEncoder};
+use image::imageops::FilterType;
+use crate::capacity::image_size_to_version;
+use crate::function_modules::is_function_module;
+use super::{draw_caption, finder_origins, in_rounded_square, is_finder_module, EyeStyle, Logo, ModuleStyle, RenderOptions, EYE_LAYERS, MM_PER_INCH};
+
+/// Images with more pixels than this are streamed by [`render_png`] when they can be
+const STREAMING_PIXELS: usize = 4096 * 4096;
+
+/// Render the matrix as a PNG image into `writer`.
+///
+/// Up to 4096×4096 pixels the image is drawn in memory, three bytes per pixel. Larger images
+/// without a logo or caption go through [`stream_png`], which needs memory for a single pixel
+/// row only; a logo or caption keeps the whole image in memory at any size. PNG itself allows
+/// up to 2³¹ - 1 pixels per side, but many viewers give up well before that: a version 40
+/// symbol at `--scale 100` is already 18,500 pixels wide.
+pub fn render_png(matrix: &[Vec<u8>], options: &RenderOptions, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let layout = options.layout(matrix.len());
+    if layout.width * layout.height > STREAMING_PIXELS && options.logo.is_none() && options.caption.is_none() {
+        return stream_png(matrix, options, writer);
+    }
+    let img = render_image(matrix, options)?;
+    write_png(&img, options.dpi, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Render the matrix into an RGB image, as [`render_png`] encodes it
+pub fn render_image(matrix: &[Vec<u8>], options: &RenderOptions) -> Result<RgbImage, Box<dyn std::error::Error>> {
+    let size = matrix.len();
+    let scale = options.scale;
+    let border = options.border();
+    let total_size = options.total_size(size);
+
+    let mut img = ImageBuffer::from_pixel(total_size as u32, total_size as u32, Rgb([255u8, 255u8, 255u8]));
+
+    for (y, row) in matrix.iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            if cell != 1 {
+                continue;
+            }
+            let finder = is_finder_module(x, y, size);
+            if finder && options.eye_style != EyeStyle::Square {
+                continue; // drawn as whole shapes below
+            }
+            let (style, color) = if finder { (ModuleStyle::Square, options.eye_color) } else { (options.style, [0u8, 0u8, 0u8]) };
+
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    if style.contains(scale, dx, dy) {
+                        let px = border + x * scale + dx;
+                        let py = border + y * scale + dy;
+                        img.put_pixel(px as u32, py as u32, Rgb(color));
+                    }
+                }
+            }
+        }
+    }
+
+    if options.eye_style != EyeStyle::Square {
+        for (ox, oy) in finder_origins(size) {
+            for (inset, side, dark) in EYE_LAYERS {
+                let w = (side * scale) as f64;
+                let r = options.eye_style.corner_radius(w);
+                let color = if dark { options.eye_color } else { [255u8, 255u8, 255u8] };
+                let left = border + (ox + inset) * scale;
+                let top = border + (oy + inset) * scale;
+                for dy in 0..side * scale {
+                    for dx in 0..side * scale {
+                        if in_rounded_square(dx as f64 + 0.5, dy as f64 + 0.5, w, r) {
+                            img.put_pixel((left + dx) as u32, (top + dy) as u32, Rgb(color));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if options.invert {
+        image::imageops::invert(&mut img);
+    }
+
+    if let Some(logo) = &options.logo {
+        composite_logo(&mut img, logo, matrix, options);
+    }
+
+    if options.is_decorated() {
+        if options.deterministic && options.caption.as_ref().is_some_and(|c| c.font_path.is_none()) {
+            return Err("deterministic output needs an explicit caption font; the system font differs between hosts".into());
+        }
+        img = decorate(img, size, options)?;
+    }
+    Ok(img)
+}
+
+/// Render the matrix as a PNG image into `writer` one pixel row at a time, so that memory use
+/// stays at one row however large the scale. The pixels are those of [`render_png`]; logos
+/// and captions are not supported, since drawing them needs the whole image.
+pub fn stream_png(matrix: &[Vec<u8>], options: &RenderOptions, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    if options.logo.is_some() || options.caption.is_some() {
+        return Err("streamed PNG output cannot draw a logo or caption".into());
+    }
+    let layout = options.layout(matrix.len());
+    let (width, height) = (u32::try_from(layout.width)?, u32::try_from(layout.height)?);
+    let mut encoder = png::Encoder::new(&mut writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    // The settings write_png pins through the image crate
+    encoder.set_compression(png::Compression::Default);
+    encoder.set_filter(png::FilterType::Sub);
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+    if let Some(dpi) = options.dpi {
+        let pixels_per_meter = (dpi * 1000.0 / MM_PER_INCH).round() as u32;
+        encoder.set_pixel_dims(Some(png::PixelDimensions { xppu: pixels_per_meter, yppu: pixels_per_meter, unit: png::Unit::Meter }));
+    }
+
+    let mut png_writer = encoder.write_header()?;
+    let mut stream = png_writer.stream_writer()?;
+    let total_size = options.total_size(matrix.len());
+    let mut row = vec![0u8; layout.width * 3];
+    for y in 0..layout.height {
+        for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+            let in_frame = x < layout.frame || y < layout.frame || x >= layout.width - layout.frame || y >= layout.height - layout.frame;
+            let in_symbol = (layout.qr_x..layout.qr_x + total_size).contains(&x) && (layout.qr_y..layout.qr_y + total_size).contains(&y);
+            let color = if in_frame {
+                [0, 0, 0]
+            } else if in_symbol {
+                options.symbol_pixel(matrix, x - layout.qr_x, y - layout.qr_y)
+            } else {
+                [255, 255, 255]
+            };
+            pixel.copy_from_slice(&color);
+        }
+        stream.write_all(&row)?;
+    }
+    stream.finish()?;
+    png_writer.finish()?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Encode `img` as PNG, adding a pHYs chunk when a physical resolution is given so that
+/// printing software reproduces the intended size.
+pub(crate) fn write_png(img: &RgbImage, dpi: Option<f64>, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let mut png = Vec::new();
+    // Pin the encoder settings so output does not drift if the library defaults change
+    PngEncoder::new_with_quality(&mut png, CompressionType::Default, PngFilterType::Adaptive).write_image(img.as_raw(), img.width(), img.height(), image::ColorType::Rgb8)?;
+
+    if let Some(dpi) = dpi {
+        // The signature (8 bytes) and IHDR (4 + 4 + 13 + 4 bytes) always come first
+        let ihdr_end = 8 + 25;
+        let pixels_per_meter = (dpi * 1000.0 / MM_PER_INCH).round() as u32;
+        let mut chunk = Vec::with_capacity(21);
+        chunk.extend_from_slice(&9u32.to_be_bytes());
+        chunk.extend_from_slice(b"pHYs");
+        chunk.extend_from_slice(&pixels_per_meter.to_be_bytes());
+        chunk.extend_from_slice(&pixels_per_meter.to_be_bytes());
+        chunk.push(1); // unit: metre
+        let crc = crc32(&chunk[4..]);
+        chunk.extend_from_slice(&crc.to_be_bytes());
+        png.splice(ihdr_end..ihdr_end, chunk);
+    }
+
+    writer.write_all(&png)?;
+    Ok(())
+}
+
+/// CRC-32 as used by PNG chunks (ISO 3309, reflected polynomial 0xEDB88320)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Place the rendered symbol on a larger canvas with the frame and caption drawn around it
+fn decorate(img: RgbImage, size: usize, options: &RenderOptions) -> Result<RgbImage, Box<dyn std::error::Error>> {
+    let layout = options.layout(size);
+    let mut canvas = ImageBuffer::from_pixel(layout.width as u32, layout.height as u32, Rgb([255u8, 255u8, 255u8]));
+    image::imageops::replace(&mut canvas, &img, layout.qr_x as i64, layout.qr_y as i64);
+
+    for y in 0..layout.height {
+        for x in 0..layout.width {
+            if x < layout.frame || y < layout.frame || x >= layout.width - layout.frame || y >= layout.height - layout.frame {
+                canvas.put_pixel(x as u32, y as u32, Rgb([0, 0, 0]));
+            }
+        }
+    }
+
+    if let Some(caption) = &options.caption {
+        draw_caption(&mut canvas, caption, layout.width as f32 / 2.0, layout.caption_y as f32)?;
+    }
+    Ok(canvas)
+}
+
+/// Clear a centered square of `logo.size_modules` modules to white and draw the logo inside it,
+/// scaled to fit while keeping its aspect ratio and alpha-blended over the white background.
+/// Function modules inside the square, such as the center alignment pattern from version 7
+/// up, are drawn again on top.
+#[allow(clippy::needless_range_loop)]
+fn composite_logo(img: &mut RgbImage, logo: &Logo, matrix: &[Vec<u8>], options: &RenderOptions) {
+    let size = matrix.len();
+    let side = logo.size_modules.min(size);
+    if side == 0 {
+        return;
+    }
+    let first = (size - side) / 2;
+    let origin = options.border() + first * options.scale;
+    let side_px = (side * options.scale) as u32;
+
+    // Clear to the symbol's background, which an inverted symbol has dark
+    let background = if options.invert { [0u8, 0u8, 0u8] } else { [255u8, 255u8, 255u8] };
+    for y in 0..side_px {
+        for x in 0..side_px {
+            img.put_pixel(origin as u32 + x, origin as u32 + y, Rgb(background));
+        }
+    }
+
+    let scaled = logo.image.resize(side_px, side_px, FilterType::Lanczos3).to_rgba8();
+    let (w, h) = scaled.dimensions();
+    let offset_x = origin as u32 + (side_px - w) / 2;
+    let offset_y = origin as u32 + (side_px - h) / 2;
+
+    for (x, y, pixel) in scaled.enumerate_pixels() {
+        let alpha = pixel[3] as u32;
+        let target = img.get_pixel_mut(offset_x + x, offset_y + y);
+        for c in 0..3 {
+            target[c] = ((pixel[c] as u32 * alpha + target[c] as u32 * (255 - alpha)) / 255) as u8;
+        }
+    }
+
+    let Some(version) = image_size_to_version(size) else { return };
+    for row in first..first + side {
+        for col in first..first + side {
+            if !is_function_module(row, col, version) {
+                continue;
+            }
+            let dark = (matrix[row][col] == 1) != options.invert;
+            let color = if dark { [0u8, 0u8, 0u8] } else { [255u8, 255u8, 255u8] };
+            for dy in 0..options.scale {
+                for dx in 0..options.scale {
+                    let px = options.border() + col * options.scale + dx;
+                    let py = options.border() + row * options.scale + dy;
+                    img.put_pixel(px as u32, py as u32, Rgb(color));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::{Caption, CaptionPosition};
+
+    #[test]
+    #[allow(clippy::needless_range_loop)]
+    fn test_logo_leaves_the_center_alignment_pattern() {
+        let version = crate::types::Version::V7;
+        let matrix = crate::generator::generate_qr_matrix(&[b'a'; 60], &crate::types::QrConfig { error_correction: crate::types::ErrorCorrection::H, ..Default::default() });
+        assert_eq!(matrix.len(), version.size());
+        let side = crate::logo::max_logo_size(version, crate::types::ErrorCorrection::H);
+        assert!(side >= 7);
+        let logo = Logo { image: image::DynamicImage::ImageRgb8(RgbImage::from_pixel(4, 4, Rgb([200, 0, 0]))), size_modules: side };
+        let options = RenderOptions { scale: 2, quiet_zone: 0, logo: Some(logo), ..Default::default() };
+        let img = render_image(&matrix, &options).unwrap();
+        let center = version.size() / 2;
+        let module = |row: usize, col: usize| img.get_pixel((col * 2) as u32, (row * 2) as u32).0;
+        // The alignment pattern shows through exactly as the matrix has it
+        for row in center - 2..=center + 2 {
+            for col in center - 2..=center + 2 {
+                assert_eq!(module(row, col), if matrix[row][col] == 1 { [0, 0, 0] } else { [255, 255, 255] });
+            }
+        }
+        // Data modules under the logo are covered by it
+        assert_eq!(module(center - 3, center - 3), [200, 0, 0]);
+    }
+
+    #[test]
+    fn test_transparent_logo_shows_the_background() {
+        let matrix = crate::generator::generate_qr_matrix(&[b'a'; 60], &crate::types::QrConfig { error_correction: crate::types::ErrorCorrection::H, ..Default::default() });
+        let center = matrix.len() / 2;
+        let logo = Logo { image: image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(4, 4, image::Rgba([200, 0, 0, 0]))), size_modules: 7 };
+        for (invert, background) in [(false, [255, 255, 255]), (true, [0, 0, 0])] {
+            let options = RenderOptions { scale: 2, quiet_zone: 0, logo: Some(logo.clone()), invert, ..Default::default() };
+            let img = render_image(&matrix, &options).unwrap();
+            let module = |row: usize, col: usize| img.get_pixel((col * 2) as u32, (row * 2) as u32).0;
+            assert_eq!(module(center - 3, center - 3), background, "invert {}", invert);
+            assert_eq!(module(center + 3, center + 3), background, "invert {}", invert);
+        }
+    }
+
+    #[test]
+    fn test_png_phys_chunk_follows_ihdr() {
+        let img = RgbImage::from_pixel(2, 2, Rgb([255, 255, 255]));
+        let mut png = Vec::new();
+        write_png(&img, Some(300.0), &mut png).unwrap();
+
+        assert_eq!(&png[37..41], b"pHYs");
+        assert_eq!(u32::from_be_bytes(png[41..45].try_into().unwrap()), 11811);
+        assert_eq!(u32::from_be_bytes(png[50..54].try_into().unwrap()), crc32(&png[37..50]));
+        assert!(image::load_from_memory(&png).is_ok());
+    }
+
+    #[test]
+    fn test_deterministic_png_is_byte_identical() {
+        use sha2::{Digest, Sha256};
+        // SHA-256 of the PNG as first written. A change means every deterministic output
+        // changed, e.g. after an image or png crate upgrade; update it only on purpose.
+        const EXPECTED: &str = "dc94c37da26d575252235b6328af4e3cec04ccd995e6cb9ead2be317057b0c26";
+        let matrix = crate::reference::golden("hello-world-1q").unwrap().matrix();
+        let options = RenderOptions { scale: 4, dpi: Some(300.0), deterministic: true, ..Default::default() };
+        for _ in 0..2 {
+            let mut png = Vec::new();
+            render_png(&matrix, &options, &mut png).unwrap();
+            let hash: String = Sha256::digest(&png).iter().map(|b| format!("{:02x}", b)).collect();
+            assert_eq!(hash, EXPECTED);
+        }
+    }
+
+    #[test]
+    fn test_streamed_png_matches_the_drawn_image() {
+        let matrix = crate::generator::generate_qr_matrix(b"stream", &crate::types::QrConfig::default());
+        for options in [
+            RenderOptions { scale: 3, ..Default::default() },
+            RenderOptions { scale: 6, style: ModuleStyle::Dot, eye_style: EyeStyle::Circle, eye_color: [200, 0, 0], frame: true, invert: true, dpi: Some(300.0), ..Default::default() },
+        ] {
+            let mut streamed = Vec::new();
+            stream_png(&matrix, &options, &mut streamed).unwrap();
+            let decoded = image::load_from_memory(&streamed).unwrap().to_rgb8();
+            assert!(decoded == render_image(&matrix, &options).unwrap());
+        }
+        let caption = Caption { text: "Scan me".to_string(), size: 12.0, position: CaptionPosition::Bottom, font_path: None };
+        assert!(stream_png(&matrix, &RenderOptions { caption: Some(caption), ..Default::default() }, Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::Write;
+use super::RenderOptions;
+
+/// Render the matrix as a netpbm bitmap (P4 binary, or P1 plain text when `plain` is set).
+///
+/// In PBM a set bit is black, so dark modules map directly to 1.
+pub fn render_pbm(matrix: &[Vec<u8>], options: &RenderOptions, plain: bool, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let total_size = options.total_size(matrix.len());
+
+    let mut out = Vec::new();
+    if plain {
+        out.extend_from_slice(format!("P1\n{} {}\n", total_size, total_size).as_bytes());
+        for py in 0..total_size {
+            let row: Vec<&str> = (0..total_size)
+                .map(|px| if options.pixel_value(matrix, px, py) == 1 { "1" } else { "0" })
+                .collect();
+            // Plain PBM lines should not exceed 70 characters
+            for chunk in row.chunks(35) {
+                out.extend_from_slice(chunk.join(" ").as_bytes());
+                out.push(b'\n');
+            }
+        }
+    } else {
+        out.extend_from_slice(format!("P4\n{} {}\n", total_size, total_size).as_bytes());
+        let row_bytes = total_size.div_ceil(8);
+        for py in 0..total_size {
+            let mut row = vec![0u8; row_bytes];
+            for px in 0..total_size {
+                if options.pixel_value(matrix, px, py) == 1 {
+                    row[px / 8] |= 0x80 >> (px % 8);
+                }
+            }
+            out.extend_from_slice(&row);
+        }
+    }
+
+    writer.write_all(&out)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Render the matrix as a binary (P5) netpbm graymap with 0 for dark and 255 for light modules
+pub fn render_pgm(matrix: &[Vec<u8>], options: &RenderOptions, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let total_size = options.total_size(matrix.len());
+
+    let mut out = format!("P5\n{} {}\n255\n", total_size, total_size).into_bytes();
+    for py in 0..total_size {
+        for px in 0..total_size {
+            out.push(if options.pixel_value(matrix, px, py) == 1 { 0 } else { 255 });
+        }
+    }
+
+    writer.write_all(&out)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inverted_pgm_is_negative() {
+        let matrix = vec![vec![1, 0, 1], vec![0, 1, 0], vec![1, 0, 1]];
+        let options = RenderOptions { scale: 1, quiet_zone: 1, ..Default::default() };
+        let mut normal = Vec::new();
+        let mut inverted = Vec::new();
+        render_pgm(&matrix, &options, &mut normal).unwrap();
+        render_pgm(&matrix, &RenderOptions { invert: true, ..options }, &mut inverted).unwrap();
+
+        let header = b"P5\n5 5\n255\n".len();
+        assert_eq!(normal[..header], inverted[..header]);
+        assert!(normal[header..].iter().zip(&inverted[header..]).all(|(a, b)| a ^ b == 255));
+        assert_eq!(inverted[header], 0, "quiet zone is dark");
+    }
+}
@@ -0,0 +1,159 @@
+use std::io::Write;
+use super::{finder_origins, is_finder_module, EyeStyle, ModuleStyle, RenderOptions, EYE_LAYERS, MM_PER_INCH, ROUNDED_CORNER_RATIO};
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// SVG path data for a single module of `style` with its top-left corner at (x, y)
+fn module_path(style: ModuleStyle, x: f64, y: f64, s: f64) -> String {
+    match style {
+        ModuleStyle::Square => format!("M{} {}h{}v{}h-{}z", x, y, s, s, s),
+        ModuleStyle::Dot => {
+            let r = s / 2.0;
+            format!("M{} {}a{} {} 0 1 0 {} 0a{} {} 0 1 0 -{} 0z", x, y + r, r, r, s, r, r, s)
+        }
+        ModuleStyle::Rounded => {
+            let r = s * ROUNDED_CORNER_RATIO;
+            let edge = s - 2.0 * r;
+            format!(
+                "M{} {}h{}a{r} {r} 0 0 1 {r} {r}v{}a{r} {r} 0 0 1 -{r} {r}h-{}a{r} {r} 0 0 1 -{r} -{r}v-{}a{r} {r} 0 0 1 {r} -{r}z",
+                x + r, y, edge, edge, edge, edge, r = r
+            )
+        }
+    }
+}
+
+/// Render the matrix as an SVG document into `writer`
+pub fn render_svg(matrix: &[Vec<u8>], options: &RenderOptions, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let size = matrix.len();
+    let scale = options.scale;
+    let border = options.border();
+    let layout = options.layout(size);
+
+    // With a known resolution the document gets physical dimensions; the viewBox stays in pixels
+    let (width, height) = match options.dpi {
+        Some(dpi) => (
+            format!("{}mm", layout.width as f64 * MM_PER_INCH / dpi),
+            format!("{}mm", layout.height as f64 * MM_PER_INCH / dpi),
+        ),
+        None => (layout.width.to_string(), layout.height.to_string()),
+    };
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+        width, height, layout.width, layout.height
+    );
+
+    svg.push_str(&format!(r#"<rect width="{}" height="{}" fill="white"/>"#, layout.width, layout.height));
+
+    if options.is_decorated() {
+        if layout.frame > 0 {
+            let half = layout.frame as f64 / 2.0;
+            svg.push_str(&format!(
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black" stroke-width="{}"/>"#,
+                half, half, layout.width as f64 - 2.0 * half, layout.height as f64 - 2.0 * half, layout.frame
+            ));
+        }
+        if let Some(caption) = &options.caption {
+            svg.push_str(&format!(
+                r#"<text x="{}" y="{}" font-family="sans-serif" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+                layout.width as f64 / 2.0, layout.caption_y, caption.size, escape_xml(&caption.text)
+            ));
+        }
+        svg.push_str(&format!(r#"<g transform="translate({} {})">"#, layout.qr_x, layout.qr_y));
+    }
+
+    if options.invert {
+        let total_size = options.total_size(size);
+        svg.push_str(&format!(r#"<rect width="{}" height="{}" fill="black"/>"#, total_size, total_size));
+    }
+
+    if let Some(gradient) = &options.gradient {
+        svg.push_str(&gradient.svg_defs(border as f64, (size * scale) as f64, options));
+    }
+
+    // All data modules are merged into a single path so one fill (or gradient) covers them
+    let mut data_path = String::new();
+    for (y, row) in matrix.iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            if cell == 1 {
+                let rect_x = border + x * scale;
+                let rect_y = border + y * scale;
+                if is_finder_module(x, y, size) {
+                    if options.eye_style == EyeStyle::Square {
+                        svg.push_str(&format!(
+                            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                            rect_x, rect_y, scale, scale, options.svg_color(options.eye_color)
+                        ));
+                    }
+                    continue;
+                }
+                data_path.push_str(&module_path(options.style, rect_x as f64, rect_y as f64, scale as f64));
+            }
+        }
+    }
+
+    if !data_path.is_empty() {
+        let fill = if options.gradient.is_some() { "url(#qr-gradient)".to_string() } else { options.svg_color([0, 0, 0]) };
+        svg.push_str(&format!(r#"<path d="{}" fill="{}"/>"#, data_path, fill));
+    }
+
+    if options.eye_style != EyeStyle::Square {
+        for (ox, oy) in finder_origins(size) {
+            for (inset, side, dark) in EYE_LAYERS {
+                let w = side * scale;
+                svg.push_str(&format!(
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" rx="{}" fill="{}"/>"#,
+                    border + (ox + inset) * scale, border + (oy + inset) * scale, w, w,
+                    options.eye_style.corner_radius(w as f64),
+                    options.svg_color(if dark { options.eye_color } else { [255, 255, 255] })
+                ));
+            }
+        }
+    }
+
+    if options.is_decorated() {
+        svg.push_str("</g>");
+    }
+    svg.push_str("</svg>");
+    writer.write_all(svg.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::Gradient;
+
+    fn svg_with(gradient: Gradient, invert: bool) -> String {
+        let matrix = vec![vec![1; 21]; 21];
+        let options = RenderOptions { gradient: Some(gradient), invert, ..Default::default() };
+        let mut svg = Vec::new();
+        render_svg(&matrix, &options, &mut svg).unwrap();
+        String::from_utf8(svg).unwrap()
+    }
+
+    #[test]
+    fn test_gradients_span_the_symbol_area() {
+        // 21 modules of 10 px inside a 40 px quiet zone: the symbol runs from 40 to 250
+        let (red, blue) = ([255, 0, 0], [0, 0, 255]);
+        let stops = r##"<stop offset="0" stop-color="#ff0000"/><stop offset="1" stop-color="#0000ff"/>"##;
+        let linear = svg_with(Gradient::Linear { from: red, to: blue, angle: 90.0 }, false);
+        assert!(linear.contains(&format!(
+            r#"<defs><linearGradient id="qr-gradient" gradientUnits="userSpaceOnUse" x1="145.00" y1="40.00" x2="145.00" y2="250.00">{}</linearGradient></defs>"#,
+            stops
+        )));
+        assert!(linear.contains(r#"fill="url(#qr-gradient)""#));
+
+        let radial = svg_with(Gradient::Radial { from: red, to: blue }, false);
+        assert!(radial.contains(&format!(
+            r#"<defs><radialGradient id="qr-gradient" gradientUnits="userSpaceOnUse" cx="145" cy="145" r="148.49">{}</radialGradient></defs>"#,
+            stops
+        )));
+
+        // Inverting complements the stops along with every other color in the symbol
+        let inverted = svg_with(Gradient::Radial { from: red, to: blue }, true);
+        assert!(inverted.contains(r##"<stop offset="0" stop-color="#00ffff"/><stop offset="1" stop-color="#ffff00"/>"##));
+    }
+}
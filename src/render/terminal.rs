@@ -0,0 +1,56 @@
+use std::io::Write;
+use super::RenderOptions;
+
+/// Render the matrix as lines of Unicode half blocks for a terminal: one character per module
+/// across and two module rows per line, quiet zone included, so a version 1 symbol fits in 29
+/// columns and 15 lines. The blocks are light modules, as terminals draw text light on dark;
+/// with `invert` they are the dark modules instead, for a terminal with a light background.
+/// Scale, styles and colors do not apply.
+pub fn render_terminal(matrix: &[Vec<u8>], options: &RenderOptions, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let quiet_zone = options.quiet_zone;
+    let total_size = matrix.len() + 2 * quiet_zone;
+    // Whether the module at (x, y) of the symbol and its quiet zone is drawn as a block; below
+    // the last row is the bare terminal
+    let block = |x: usize, y: usize| {
+        if y >= total_size {
+            return false;
+        }
+        let inside = (quiet_zone..quiet_zone + matrix.len()).contains(&x) && (quiet_zone..quiet_zone + matrix.len()).contains(&y);
+        let dark = inside && matrix[y - quiet_zone][x - quiet_zone] == 1;
+        dark == options.invert
+    };
+
+    let mut out = String::new();
+    for y in (0..total_size).step_by(2) {
+        for x in 0..total_size {
+            out.push(match (block(x, y), block(x, y + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        out.push('\n');
+    }
+
+    writer.write_all(out.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terminal_pairs_rows_into_half_blocks() {
+        let matrix = vec![vec![1, 0, 1], vec![0, 1, 0], vec![1, 0, 1]];
+        let mut out = Vec::new();
+        render_terminal(&matrix, &RenderOptions { quiet_zone: 1, ..Default::default() }, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "█▀█▀█\n█▀▄▀█\n▀▀▀▀▀\n");
+
+        let mut out = Vec::new();
+        render_terminal(&matrix, &RenderOptions { quiet_zone: 1, invert: true, ..Default::default() }, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), " ▄ ▄ \n ▄▀▄ \n     \n");
+    }
+}
@@ -0,0 +1,225 @@
+//! Round-trip verification: encode a grid of symbols, one per version, error correction
+//! level, data mode and mask, read every one back with our decoder and optionally a sample
+//! with a reference decoder, and summarize the results as a version × level/mode table.
+//!
+//! The payload of each case fills its version to capacity, so the encoder cannot fall back
+//! to a smaller symbol and every block carries data.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use crate::capacity::max_characters;
+use crate::decoder::decode_matrix;
+use crate::generator::generate_qr_matrix;
+use crate::types::{DataMode, ErrorCorrection, MaskPattern, QrConfig, Version};
+
+pub const LEVELS: [ErrorCorrection; 4] = [ErrorCorrection::L, ErrorCorrection::M, ErrorCorrection::Q, ErrorCorrection::H];
+pub const MODES: [DataMode; 3] = [DataMode::Numeric, DataMode::Alphanumeric, DataMode::Byte];
+
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// One symbol of the grid
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Case {
+    pub version: Version,
+    pub error_correction: ErrorCorrection,
+    pub data_mode: DataMode,
+    pub mask: MaskPattern,
+}
+
+impl Case {
+    /// Text that fills the version exactly in the case's mode
+    pub fn payload(&self) -> String {
+        let length = max_characters(self.version, self.error_correction, self.data_mode);
+        (0..length)
+            .map(|i| match self.data_mode {
+                DataMode::Numeric => (b'0' + (i % 10) as u8) as char,
+                DataMode::Alphanumeric => ALPHANUMERIC[(i * 7) % ALPHANUMERIC.len()] as char,
+                DataMode::Byte => (b'a' + (i % 26) as u8) as char,
+            })
+            .collect()
+    }
+
+    pub fn config(&self) -> QrConfig {
+        QrConfig { error_correction: self.error_correction, data_mode: self.data_mode, mask_pattern: self.mask, ..QrConfig::default() }
+    }
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "V{} {:?} {} mask {}", self.version as u8, self.error_correction, self.data_mode, self.mask as u8)
+    }
+}
+
+/// Every combination of the given versions with all levels, modes and masks
+pub fn cases(versions: impl IntoIterator<Item = Version>) -> Vec<Case> {
+    let mut cases = Vec::new();
+    for version in versions {
+        for error_correction in LEVELS {
+            for data_mode in MODES {
+                for mask in 0..8 {
+                    cases.push(Case { version, error_correction, data_mode, mask: MaskPattern::from_index(mask) });
+                }
+            }
+        }
+    }
+    cases
+}
+
+/// What reading a symbol back produced
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// The decoder returned text other than the payload
+    Mismatch,
+    /// The decoder gave up, or panicked, with this message
+    Failed(String),
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        *self == Outcome::Passed
+    }
+
+    fn reason(&self) -> String {
+        match self {
+            Outcome::Passed => "passed".to_string(),
+            Outcome::Mismatch => "decoded text differs from the payload".to_string(),
+            Outcome::Failed(message) => message.clone(),
+        }
+    }
+}
+
+/// A decoder reading a module matrix as text
+pub type Decode = dyn Fn(&[Vec<u8>]) -> Result<String, String>;
+
+/// A decoder other than ours, run on every `every`th case
+pub struct Reference<'a> {
+    pub name: &'a str,
+    pub every: usize,
+    pub decode: &'a Decode,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub case: Case,
+    pub ours: Outcome,
+    /// `None` for cases outside the reference decoder's sample
+    pub reference: Option<Outcome>,
+}
+
+/// Read `matrix` back with `decode` and compare the text with `expected`
+pub fn check(matrix: &[Vec<u8>], expected: &str, decode: impl Fn(&[Vec<u8>]) -> Result<String, String>) -> Outcome {
+    match catch_unwind(AssertUnwindSafe(|| decode(matrix))) {
+        Ok(Ok(text)) if text == expected => Outcome::Passed,
+        Ok(Ok(_)) => Outcome::Mismatch,
+        Ok(Err(message)) => Outcome::Failed(message),
+        Err(_) => Outcome::Failed("decoder panicked".to_string()),
+    }
+}
+
+/// Our decoder as a `check` callback
+pub fn decode_text(matrix: &[Vec<u8>]) -> Result<String, String> {
+    decode_matrix(matrix).map(|decoded| decoded.text).map_err(|e| e.to_string())
+}
+
+/// Encode every case with our generator and read it back
+pub fn verify(cases: &[Case], reference: Option<&Reference>) -> Vec<CaseResult> {
+    cases
+        .iter()
+        .enumerate()
+        .map(|(i, &case)| {
+            let payload = case.payload();
+            let matrix = generate_qr_matrix(payload.as_bytes(), &case.config());
+            let ours = check(&matrix, &payload, decode_text);
+            let reference = reference.filter(|r| i % r.every.max(1) == 0).map(|r| check(&matrix, &payload, r.decode));
+            CaseResult { case, ours, reference }
+        })
+        .collect()
+}
+
+/// Markdown report: totals, a table of passing masks per version, level and mode, and the
+/// failures grouped by reason with the first case of each
+pub fn report(results: &[CaseResult], reference_name: Option<&str>) -> String {
+    let mut out = String::new();
+    let passed = results.iter().filter(|r| r.ours.passed()).count();
+    let _ = writeln!(out, "# Round trip\n\nOur decoder: {} of {} symbols read back.", passed, results.len());
+    let sampled: Vec<&Outcome> = results.iter().filter_map(|r| r.reference.as_ref()).collect();
+    if let Some(name) = reference_name {
+        let _ = writeln!(out, "{}: {} of {} sampled symbols read back.", name, sampled.iter().filter(|o| o.passed()).count(), sampled.len());
+    }
+
+    let _ = write!(out, "\nPassing masks out of 8:\n\n| Version |");
+    for level in LEVELS {
+        for mode in MODES {
+            let _ = write!(out, " {:?} {} |", level, mode);
+        }
+    }
+    let _ = write!(out, "\n|---|{}\n", "---|".repeat(LEVELS.len() * MODES.len()));
+    let mut cells: BTreeMap<u8, Vec<(usize, usize)>> = BTreeMap::new();
+    for result in results {
+        let row = cells.entry(result.case.version as u8).or_insert_with(|| vec![(0, 0); LEVELS.len() * MODES.len()]);
+        let level = LEVELS.iter().position(|&l| l == result.case.error_correction).unwrap_or(0);
+        let mode = MODES.iter().position(|&m| m == result.case.data_mode).unwrap_or(0);
+        let cell = &mut row[level * MODES.len() + mode];
+        cell.0 += result.ours.passed() as usize;
+        cell.1 += 1;
+    }
+    for (version, row) in &cells {
+        let _ = write!(out, "| {} |", version);
+        for &(passed, total) in row {
+            // Bold marks cells with failures
+            let mark = if passed < total { "**" } else { "" };
+            let _ = write!(out, " {}{}/{}{} |", mark, passed, total, mark);
+        }
+        out.push('\n');
+    }
+
+    write_failures(&mut out, "Our decoder", results.iter().map(|r| (r.case, &r.ours)));
+    if let Some(name) = reference_name {
+        write_failures(&mut out, name, results.iter().filter_map(|r| Some((r.case, r.reference.as_ref()?))));
+    }
+    out
+}
+
+fn write_failures<'a>(out: &mut String, decoder: &str, outcomes: impl Iterator<Item = (Case, &'a Outcome)>) {
+    let mut reasons: BTreeMap<String, (usize, Case)> = BTreeMap::new();
+    for (case, outcome) in outcomes.filter(|(_, outcome)| !outcome.passed()) {
+        reasons.entry(outcome.reason()).or_insert((0, case)).0 += 1;
+    }
+    if reasons.is_empty() {
+        return;
+    }
+    let _ = writeln!(out, "\n## {} failures\n", decoder);
+    let mut reasons: Vec<_> = reasons.into_iter().collect();
+    reasons.sort_by_key(|&(_, (count, _))| std::cmp::Reverse(count));
+    for (reason, (count, first)) in reasons {
+        let _ = writeln!(out, "- {} × {} (first: {})", count, reason, first);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_fills_the_version() {
+        for case in cases([Version::V1, Version::V9]).into_iter().step_by(8) {
+            let length = case.payload().len();
+            assert_eq!(crate::capacity::smallest_version_for(length, case.error_correction, case.data_mode), Some(case.version));
+            assert_eq!(crate::capacity::smallest_version_for(length + 1, case.error_correction, case.data_mode).map(|v| v as u8), Some(case.version as u8 + 1));
+        }
+    }
+
+    #[test]
+    fn test_report_counts_passing_masks() {
+        let results: Vec<CaseResult> = cases([Version::V2])
+            .into_iter()
+            .map(|case| CaseResult { case, ours: if (case.mask as u8).is_multiple_of(2) { Outcome::Passed } else { Outcome::Failed("bad".to_string()) }, reference: None })
+            .collect();
+        let report = report(&results, None);
+        assert!(report.contains("48 of 96 symbols"));
+        assert!(report.contains("| 2 | **4/8** |"));
+        assert!(report.contains("- 48 × bad (first: V2 L Numeric mask 1)"));
+    }
+}
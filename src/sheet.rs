@@ -0,0 +1,301 @@
+use std::io::Write;
+use image::{ImageBuffer, Rgb, RgbImage};
+use crate::render::{draw_caption, write_png, Caption, CaptionPosition, MM_PER_INCH};
+
+const POINTS_PER_INCH: f64 = 72.0;
+
+/// Paper size of a sheet
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageSize {
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    /// Width and height in millimetres
+    pub fn dimensions_mm(&self) -> (f64, f64) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+/// One code on a sheet: the payload to encode and an optional caption printed below it
+#[derive(Debug, Clone)]
+pub struct SheetItem {
+    pub payload: String,
+    pub caption: Option<String>,
+}
+
+/// Grid layout of a sheet
+#[derive(Debug, Clone)]
+pub struct SheetLayout {
+    pub page: PageSize,
+    pub rows: usize,
+    pub columns: usize,
+    /// Page margin in millimetres
+    pub margin_mm: f64,
+    /// Caption font size in millimetres (also the height reserved below each code)
+    pub caption_mm: f64,
+    /// Quiet zone around each code, in modules
+    pub quiet_zone: usize,
+}
+
+impl Default for SheetLayout {
+    fn default() -> Self {
+        Self {
+            page: PageSize::A4,
+            rows: 6,
+            columns: 4,
+            margin_mm: 10.0,
+            caption_mm: 4.0,
+            quiet_zone: 4,
+        }
+    }
+}
+
+/// Position of one code on the page, in millimetres from the top-left corner
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    pub x: f64,
+    pub y: f64,
+    /// Side of the code including its quiet zone
+    pub side: f64,
+    /// Vertical center of the caption line
+    pub caption_y: f64,
+}
+
+impl SheetLayout {
+    pub fn codes_per_page(&self) -> usize {
+        self.rows * self.columns
+    }
+
+    /// Placement of the `index`-th code on a page (row-major order)
+    pub fn placement(&self, index: usize) -> Placement {
+        let (width, height) = self.page.dimensions_mm();
+        let cell_w = (width - 2.0 * self.margin_mm) / self.columns as f64;
+        let cell_h = (height - 2.0 * self.margin_mm) / self.rows as f64;
+        let caption_band = self.caption_mm * 1.5;
+        let side = cell_w.min(cell_h - caption_band).max(0.0);
+
+        let row = index / self.columns;
+        let col = index % self.columns;
+        let x = self.margin_mm + col as f64 * cell_w + (cell_w - side) / 2.0;
+        let y = self.margin_mm + row as f64 * cell_h + (cell_h - side - caption_band) / 2.0;
+        Placement { x, y, side, caption_y: y + side + caption_band / 2.0 }
+    }
+}
+
+/// Read sheet items from CSV text: the first column is the payload and the optional second
+/// column the caption. A first row whose payload column reads `payload` is treated as a header.
+pub fn parse_items(csv: &str) -> Vec<SheetItem> {
+    let mut items = Vec::new();
+    for (line_no, line) in csv.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = parse_csv_line(line);
+        if line_no == 0 && fields[0].eq_ignore_ascii_case("payload") {
+            continue;
+        }
+        items.push(SheetItem {
+            payload: fields[0].clone(),
+            caption: fields.get(1).filter(|c| !c.is_empty()).cloned(),
+        });
+    }
+    items
+}
+
+/// Split a CSV line into fields, honouring double-quoted fields with `""` escapes
+pub fn parse_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Render one page of codes as a PNG at the given resolution
+pub fn render_page_png(
+    codes: &[(Vec<Vec<u8>>, Option<String>)],
+    layout: &SheetLayout,
+    dpi: f64,
+    mut writer: impl Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let px = |mm: f64| mm / MM_PER_INCH * dpi;
+    let (width, height) = layout.page.dimensions_mm();
+    let mut img: RgbImage = ImageBuffer::from_pixel(px(width).round() as u32, px(height).round() as u32, Rgb([255u8, 255u8, 255u8]));
+
+    for (index, (matrix, caption)) in codes.iter().enumerate() {
+        let placement = layout.placement(index);
+        let modules = matrix.len() + 2 * layout.quiet_zone;
+        let module_px = px(placement.side) / modules as f64;
+        let origin_x = px(placement.x) + layout.quiet_zone as f64 * module_px;
+        let origin_y = px(placement.y) + layout.quiet_zone as f64 * module_px;
+
+        for (y, row) in matrix.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                if cell != 1 {
+                    continue;
+                }
+                let x0 = (origin_x + x as f64 * module_px).round() as u32;
+                let x1 = (origin_x + (x + 1) as f64 * module_px).round() as u32;
+                let y0 = (origin_y + y as f64 * module_px).round() as u32;
+                let y1 = (origin_y + (y + 1) as f64 * module_px).round() as u32;
+                for py in y0..y1.min(img.height()) {
+                    for px in x0..x1.min(img.width()) {
+                        img.put_pixel(px, py, Rgb([0, 0, 0]));
+                    }
+                }
+            }
+        }
+
+        if let Some(text) = caption {
+            let caption = Caption {
+                text: text.clone(),
+                size: px(layout.caption_mm) as f32,
+                position: CaptionPosition::Bottom,
+                font_path: None,
+            };
+            draw_caption(&mut img, &caption, px(placement.x + placement.side / 2.0) as f32, px(placement.caption_y) as f32)?;
+        }
+    }
+
+    write_png(&img, Some(dpi), &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Render one page of codes as a single-page PDF with vector modules and Courier captions
+pub fn render_page_pdf(
+    codes: &[(Vec<Vec<u8>>, Option<String>)],
+    layout: &SheetLayout,
+    mut writer: impl Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pt = |mm: f64| mm / MM_PER_INCH * POINTS_PER_INCH;
+    let (width, height) = layout.page.dimensions_mm();
+    let page_h = pt(height);
+
+    let mut content = String::from("0 g\n");
+    for (index, (matrix, caption)) in codes.iter().enumerate() {
+        let placement = layout.placement(index);
+        let modules = matrix.len() + 2 * layout.quiet_zone;
+        let module_pt = pt(placement.side) / modules as f64;
+        let origin_x = pt(placement.x) + layout.quiet_zone as f64 * module_pt;
+        let origin_y = pt(placement.y) + layout.quiet_zone as f64 * module_pt;
+
+        for (y, row) in matrix.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                if cell == 1 {
+                    // PDF's origin is the bottom-left corner
+                    let bottom = page_h - (origin_y + (y + 1) as f64 * module_pt);
+                    content.push_str(&format!("{:.3} {:.3} {:.3} {:.3} re\n", origin_x + x as f64 * module_pt, bottom, module_pt, module_pt));
+                }
+            }
+        }
+        content.push_str("f\n");
+
+        if let Some(text) = caption {
+            let size = pt(layout.caption_mm);
+            // Courier glyphs are all 0.6 em wide, which makes centering exact
+            let text_w = text.chars().count() as f64 * size * 0.6;
+            let x = pt(placement.x + placement.side / 2.0) - text_w / 2.0;
+            let y = page_h - pt(placement.caption_y) - size * 0.3;
+            content.push_str(&format!("BT /F1 {:.2} Tf {:.3} {:.3} Td ({}) Tj ET\n", size, x, y, escape_pdf_string(text)));
+        }
+    }
+
+    let objects = [
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+        format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Contents 4 0 R /Resources << /Font << /F1 5 0 R >> >> >>",
+            pt(width), page_h
+        ),
+        format!("<< /Length {} >>\nstream\n{}endstream", content.len(), content),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_string(),
+    ];
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+    let xref = pdf.len();
+    pdf.push_str(&format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!("trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n", objects.len() + 1, xref));
+
+    writer.write_all(pdf.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Escape a caption for a PDF literal string in WinAnsiEncoding, which agrees with Latin-1
+/// from 0xA0 up: those characters are written as octal escapes and anything else outside
+/// printable ASCII is replaced with `?`
+fn escape_pdf_string(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '(' | ')' | '\\' => format!("\\{}", c),
+            ' '..='~' => c.to_string(),
+            '\u{a0}'..='\u{ff}' => format!("\\{:03o}", c as u32),
+            _ => "?".to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_items_with_header_and_quotes() {
+        let items = parse_items("payload,caption\nSN-001,First\n\"a,b\",\"say \"\"hi\"\"\"\nSN-003\n");
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].payload, "SN-001");
+        assert_eq!(items[0].caption.as_deref(), Some("First"));
+        assert_eq!(items[1].payload, "a,b");
+        assert_eq!(items[1].caption.as_deref(), Some("say \"hi\""));
+        assert!(items[2].caption.is_none());
+    }
+
+    #[test]
+    fn test_placements_stay_within_page() {
+        let layout = SheetLayout::default();
+        let (width, height) = layout.page.dimensions_mm();
+        for index in 0..layout.codes_per_page() {
+            let p = layout.placement(index);
+            assert!(p.x >= layout.margin_mm && p.x + p.side <= width - layout.margin_mm + 1e-9);
+            assert!(p.y >= layout.margin_mm && p.caption_y <= height - layout.margin_mm);
+        }
+    }
+
+    #[test]
+    fn test_pdf_captions_keep_latin_1_characters() {
+        assert_eq!(escape_pdf_string("Café (Zürich) \\ 5€\t"), "Caf\\351 \\(Z\\374rich\\) \\\\ 5??");
+
+        let mut pdf = Vec::new();
+        render_page_pdf(&[(vec![vec![1]], Some("Zürich".to_string()))], &SheetLayout::default(), &mut pdf).unwrap();
+        let pdf = String::from_utf8(pdf).unwrap();
+        assert!(pdf.contains("(Z\\374rich) Tj"));
+        assert!(pdf.contains("/BaseFont /Courier /Encoding /WinAnsiEncoding"));
+    }
+}
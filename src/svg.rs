@@ -0,0 +1,485 @@
+//! Rasterizer for the SVG this crate writes, so SVG output can be analyzed, decoded and diffed
+//! without converting it first. It handles what `render_svg` emits: `rect` (with rounded
+//! corners), `circle` and `path` elements with move, line and arc commands, filled in solid
+//! colors or gradients, inside `g` elements translated by `transform`. Text, strokes and other
+//! SVG features are skipped, which leaves captions and frames out but the symbol intact.
+
+use image::{Rgb, RgbImage};
+
+/// Smallest width or height to rasterize at; smaller documents are scaled up by a whole factor
+const MIN_EXTENT: f64 = 512.0;
+/// Largest canvas to rasterize, in pixels, so a hostile viewBox cannot exhaust memory
+const MAX_PIXELS: f64 = 8192.0 * 8192.0;
+/// Line segments per quarter circle when flattening arcs
+const ARC_SEGMENTS_PER_QUADRANT: f64 = 8.0;
+
+/// Whether `bytes` look like an SVG document rather than a bitmap
+pub fn is_svg(bytes: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<svg") || head.starts_with("<!--")) && head.contains("<svg")
+}
+
+/// Rasterize an SVG document on a white background, one pixel per user unit, or more for a
+/// document under 512 units across
+pub fn rasterize(svg: &str) -> Result<RgbImage, Box<dyn std::error::Error>> {
+    let mut canvas: Option<Canvas> = None;
+    let mut offsets = vec![(0.0, 0.0)];
+    let mut gradient_stops: Vec<[u8; 3]> = Vec::new();
+
+    for tag in tags(svg) {
+        if tag.closing {
+            if tag.name == "g" && offsets.len() > 1 {
+                offsets.pop();
+            }
+            continue;
+        }
+        let offset = *offsets.last().expect("root offset");
+        match tag.name {
+            "svg" => canvas = Some(Canvas::new(&tag)?),
+            "g" => {
+                let (dx, dy) = tag.attr("transform").map_or(Ok((0.0, 0.0)), parse_translate)?;
+                if !tag.self_closing {
+                    offsets.push((offset.0 + dx, offset.1 + dy));
+                }
+            }
+            "stop" => gradient_stops.extend(tag.attr("stop-color").and_then(|color| parse_color(color, &[]).ok().flatten())),
+            "rect" | "circle" | "path" => {
+                let canvas = canvas.as_mut().ok_or("shape outside the <svg> element")?;
+                let Some(color) = parse_color(tag.attr("fill").unwrap_or("black"), &gradient_stops)? else {
+                    continue;
+                };
+                let outline = match tag.name {
+                    "rect" => rect_outline(&tag)?,
+                    "circle" => circle_outline(&tag)?,
+                    _ => path_outline(tag.attr("d").unwrap_or_default())?,
+                };
+                let shifted: Vec<Polygon> = outline.into_iter().map(|points| points.into_iter().map(|(x, y)| (x + offset.0, y + offset.1)).collect()).collect();
+                canvas.fill(&shifted, color);
+            }
+            _ => {}
+        }
+    }
+    canvas.map(|canvas| canvas.image).ok_or_else(|| "no <svg> element".into())
+}
+
+/// Closed outline in user units
+type Polygon = Vec<(f64, f64)>;
+
+/// Pixels and the mapping from user units to them
+struct Canvas {
+    image: RgbImage,
+    origin: (f64, f64),
+    scale: f64,
+}
+
+impl Canvas {
+    fn new(tag: &Tag) -> Result<Self, Box<dyn std::error::Error>> {
+        let (origin, width, height) = match tag.attr("viewBox") {
+            Some(view_box) => match numbers(view_box)?[..] {
+                [x, y, width, height] => ((x, y), width, height),
+                _ => return Err(format!("viewBox {:?} is not four numbers", view_box).into()),
+            },
+            None => {
+                let length = |name: &str| tag.attr(name).ok_or(format!("<svg> has no viewBox or {}", name)).and_then(|value| {
+                    value.trim_end_matches("px").parse::<f64>().map_err(|_| format!("{} {:?} is not a length in pixels", name, value))
+                });
+                ((0.0, 0.0), length("width")?, length("height")?)
+            }
+        };
+        if !(width > 0.0 && height > 0.0) {
+            return Err("SVG has no area".into());
+        }
+        if !(width.is_finite() && height.is_finite() && origin.0.is_finite() && origin.1.is_finite()) {
+            return Err("SVG size is not a finite number".into());
+        }
+        let scale = (MIN_EXTENT / width.max(height)).ceil().max(1.0);
+        let (columns, rows) = ((width * scale).ceil(), (height * scale).ceil());
+        if columns * rows > MAX_PIXELS {
+            return Err(format!("SVG of {}x{} pixels is too large to rasterize", columns, rows).into());
+        }
+        let image = RgbImage::from_pixel(columns as u32, rows as u32, Rgb([255, 255, 255]));
+        Ok(Canvas { image, origin, scale })
+    }
+
+    /// Fill the polygons with the nonzero rule, testing each pixel center
+    fn fill(&mut self, polygons: &[Polygon], color: [u8; 3]) {
+        let to_pixels = |&(x, y): &(f64, f64)| ((x - self.origin.0) * self.scale, (y - self.origin.1) * self.scale);
+        let edges: Vec<((f64, f64), (f64, f64))> = polygons
+            .iter()
+            .flat_map(|polygon| {
+                let points: Vec<(f64, f64)> = polygon.iter().map(to_pixels).collect();
+                (0..points.len()).map(move |i| (points[i], points[(i + 1) % points.len()]))
+            })
+            .filter(|((_, y0), (_, y1))| y0 != y1)
+            .collect();
+        let Some((top, bottom)) = edges.iter().map(|((_, y0), (_, y1))| (y0.min(*y1), y0.max(*y1))).reduce(|a, b| (a.0.min(b.0), a.1.max(b.1))) else {
+            return;
+        };
+
+        let (width, height) = self.image.dimensions();
+        let first_row = (top - 0.5).ceil().max(0.0) as u32;
+        let last_row = ((bottom - 0.5).floor().min(height as f64 - 1.0)).max(-1.0) as i64;
+        let mut crossings: Vec<(f64, i32)> = Vec::new();
+        for row in first_row as i64..=last_row {
+            let y = row as f64 + 0.5;
+            crossings.clear();
+            for &((x0, y0), (x1, y1)) in &edges {
+                if (y0 <= y) != (y1 <= y) {
+                    let x = x0 + (y - y0) / (y1 - y0) * (x1 - x0);
+                    crossings.push((x, if y1 > y0 { 1 } else { -1 }));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding != 0 {
+                    let from = (pair[0].0 - 0.5).ceil().max(0.0) as u32;
+                    let to = (pair[1].0 - 0.5).floor().min(width as f64 - 1.0);
+                    if to >= 0.0 {
+                        for x in from..=to as u32 {
+                            self.image.put_pixel(x, row as u32, Rgb(color));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A start or end tag with its attributes, unparsed
+struct Tag<'a> {
+    name: &'a str,
+    attributes: &'a str,
+    closing: bool,
+    self_closing: bool,
+}
+
+impl<'a> Tag<'a> {
+    fn attr(&self, name: &str) -> Option<&'a str> {
+        let mut rest = self.attributes;
+        while let Some(eq) = rest.find('=') {
+            let key = rest[..eq].trim();
+            let value = rest[eq + 1..].trim_start();
+            let quote = value.chars().next()?;
+            if quote != '"' && quote != '\'' {
+                return None;
+            }
+            let end = value[1..].find(quote)? + 1;
+            if key == name {
+                return Some(&value[1..end]);
+            }
+            rest = &value[end + 1..];
+        }
+        None
+    }
+}
+
+/// Every tag of the document in order, skipping comments, declarations and text
+fn tags(svg: &str) -> impl Iterator<Item = Tag<'_>> {
+    let mut rest = svg;
+    std::iter::from_fn(move || loop {
+        let start = rest.find('<')?;
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let end = rest.find('>')?;
+        let body = &rest[..end];
+        rest = &rest[end + 1..];
+        if body.starts_with('?') || body.starts_with('!') {
+            continue;
+        }
+        let closing = body.starts_with('/');
+        let body = body.trim_start_matches('/');
+        let self_closing = body.ends_with('/');
+        let body = body.trim_end_matches('/');
+        let name_end = body.find(|c: char| c.is_whitespace()).unwrap_or(body.len());
+        return Some(Tag { name: &body[..name_end], attributes: &body[name_end..], closing, self_closing });
+    })
+}
+
+/// `None` for `fill="none"`; gradients paint as the average of their stops
+fn parse_color(value: &str, gradient_stops: &[[u8; 3]]) -> Result<Option<[u8; 3]>, Box<dyn std::error::Error>> {
+    let value = value.trim();
+    let unsupported = || format!("unsupported color {:?}", value);
+    Ok(Some(match value {
+        "none" | "transparent" => return Ok(None),
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        _ if value.starts_with("url(") => {
+            if gradient_stops.is_empty() {
+                return Err(unsupported().into());
+            }
+            let average = |i: usize| (gradient_stops.iter().map(|stop| stop[i] as usize).sum::<usize>() / gradient_stops.len()) as u8;
+            [average(0), average(1), average(2)]
+        }
+        _ if value.starts_with('#') => {
+            let hex = &value[1..];
+            let digits: Vec<u8> = match hex.len() {
+                3 => hex.chars().map(|c| c.to_digit(16).map(|d| d as u8 * 17)).collect::<Option<_>>(),
+                6 if hex.is_ascii() => (0..3).map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()).collect::<Option<_>>(),
+                _ => None,
+            }
+            .ok_or_else(unsupported)?;
+            [digits[0], digits[1], digits[2]]
+        }
+        _ if value.starts_with("rgb(") && value.ends_with(')') => match numbers(&value[4..value.len() - 1])?[..] {
+            [r, g, b] => [r, g, b].map(|c| c.clamp(0.0, 255.0) as u8),
+            _ => return Err(unsupported().into()),
+        },
+        _ => return Err(unsupported().into()),
+    }))
+}
+
+fn parse_translate(transform: &str) -> Result<(f64, f64), Box<dyn std::error::Error>> {
+    let arguments = transform.trim().strip_prefix("translate(").and_then(|rest| rest.strip_suffix(')'));
+    match arguments.map(numbers).transpose()?.as_deref() {
+        Some([dx]) => Ok((*dx, 0.0)),
+        Some([dx, dy]) => Ok((*dx, *dy)),
+        _ => Err(format!("unsupported transform {:?}", transform).into()),
+    }
+}
+
+/// Numbers separated by whitespace or commas
+fn numbers(text: &str) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse::<f64>().map_err(|_| format!("{:?} is not a number", part).into()))
+        .collect()
+}
+
+fn number_attr(tag: &Tag, name: &str) -> Result<f64, Box<dyn std::error::Error>> {
+    tag.attr(name).map_or(Ok(0.0), |value| value.trim().trim_end_matches("px").parse().map_err(|_| format!("{} {:?} is not a number", name, value).into()))
+}
+
+fn rect_outline(tag: &Tag) -> Result<Vec<Polygon>, Box<dyn std::error::Error>> {
+    let (x, y, width, height) = (number_attr(tag, "x")?, number_attr(tag, "y")?, number_attr(tag, "width")?, number_attr(tag, "height")?);
+    let radius = number_attr(tag, "rx")?.max(number_attr(tag, "ry")?).min(width / 2.0).min(height / 2.0);
+    if radius <= 0.0 {
+        return Ok(vec![vec![(x, y), (x + width, y), (x + width, y + height), (x, y + height)]]);
+    }
+    // Corners clockwise from the top right, each a quarter circle around its center
+    let corners = [(x + width - radius, y + radius, -90.0), (x + width - radius, y + height - radius, 0.0), (x + radius, y + height - radius, 90.0), (x + radius, y + radius, 180.0)];
+    let steps = ARC_SEGMENTS_PER_QUADRANT as usize;
+    let outline = corners
+        .iter()
+        .flat_map(|&(cx, cy, start)| (0..=steps).map(move |i| {
+            let angle = (start + 90.0 * i as f64 / steps as f64).to_radians();
+            (cx + radius * angle.cos(), cy + radius * angle.sin())
+        }))
+        .collect();
+    Ok(vec![outline])
+}
+
+fn circle_outline(tag: &Tag) -> Result<Vec<Polygon>, Box<dyn std::error::Error>> {
+    let (cx, cy, r) = (number_attr(tag, "cx")?, number_attr(tag, "cy")?, number_attr(tag, "r")?);
+    let steps = (4.0 * ARC_SEGMENTS_PER_QUADRANT) as usize;
+    Ok(vec![(0..steps).map(|i| {
+        let angle = std::f64::consts::TAU * i as f64 / steps as f64;
+        (cx + r * angle.cos(), cy + r * angle.sin())
+    }).collect()])
+}
+
+/// Flatten path data into closed polygons, one per subpath
+fn path_outline(d: &str) -> Result<Vec<Polygon>, Box<dyn std::error::Error>> {
+    let mut tokens = PathTokens { rest: d };
+    let mut polygons: Vec<Polygon> = Vec::new();
+    let mut current: Polygon = Vec::new();
+    let (mut position, mut start) = ((0.0, 0.0), (0.0, 0.0));
+    let mut command = None;
+
+    while let Some(next) = tokens.command().or_else(|| command.filter(|_| tokens.has_number())) {
+        let relative = next.is_ascii_lowercase();
+        let base = if relative { position } else { (0.0, 0.0) };
+        match next.to_ascii_uppercase() {
+            'M' => {
+                if current.len() > 2 {
+                    polygons.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                position = (base.0 + tokens.number()?, base.1 + tokens.number()?);
+                start = position;
+                current.push(position);
+                // Further coordinate pairs after a move are lines
+                command = Some(if relative { 'l' } else { 'L' });
+                continue;
+            }
+            'L' => position = (base.0 + tokens.number()?, base.1 + tokens.number()?),
+            'H' => position.0 = base.0 + tokens.number()?,
+            'V' => position.1 = base.1 + tokens.number()?,
+            'A' => {
+                let (rx, ry, rotation) = (tokens.number()?, tokens.number()?, tokens.number()?);
+                let (large_arc, sweep) = (tokens.flag()?, tokens.flag()?);
+                let end = (base.0 + tokens.number()?, base.1 + tokens.number()?);
+                current.extend(flatten_arc(position, end, rx, ry, rotation, large_arc, sweep));
+                position = end;
+            }
+            'Z' => {
+                if current.len() > 2 {
+                    polygons.push(std::mem::take(&mut current));
+                }
+                current.clear();
+                position = start;
+                current.push(position);
+                command = None;
+                continue;
+            }
+            other => return Err(format!("unsupported path command {:?}", other).into()),
+        }
+        current.push(position);
+        command = Some(next);
+    }
+    if current.len() > 2 {
+        polygons.push(current);
+    }
+    Ok(polygons)
+}
+
+/// Points along an elliptical arc after `from`, ending with `to` (SVG 1.1 appendix F.6.5)
+fn flatten_arc(from: (f64, f64), to: (f64, f64), rx: f64, ry: f64, rotation: f64, large_arc: bool, sweep: bool) -> Vec<(f64, f64)> {
+    let (mut rx, mut ry) = (rx.abs(), ry.abs());
+    if rx == 0.0 || ry == 0.0 || from == to {
+        return vec![to];
+    }
+    let (sin, cos) = rotation.to_radians().sin_cos();
+    let (dx, dy) = ((from.0 - to.0) / 2.0, (from.1 - to.1) / 2.0);
+    let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+    let numerator = (rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1).max(0.0);
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let factor = (numerator / denominator).sqrt() * if large_arc == sweep { -1.0 } else { 1.0 };
+    let (cx1, cy1) = (factor * rx * y1 / ry, -factor * ry * x1 / rx);
+    let center = (cos * cx1 - sin * cy1 + (from.0 + to.0) / 2.0, sin * cx1 + cos * cy1 + (from.1 + to.1) / 2.0);
+
+    let angle = |ux: f64, uy: f64| uy.atan2(ux);
+    let theta = angle((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut delta = angle((-x1 - cx1) / rx, (-y1 - cy1) / ry) - theta;
+    if sweep && delta < 0.0 {
+        delta += std::f64::consts::TAU;
+    } else if !sweep && delta > 0.0 {
+        delta -= std::f64::consts::TAU;
+    }
+
+    let steps = ((delta.abs() / std::f64::consts::FRAC_PI_2) * ARC_SEGMENTS_PER_QUADRANT).ceil().max(1.0) as usize;
+    (1..=steps)
+        .map(|i| {
+            if i == steps {
+                return to;
+            }
+            let t = theta + delta * i as f64 / steps as f64;
+            let (x, y) = (rx * t.cos(), ry * t.sin());
+            (cos * x - sin * y + center.0, sin * x + cos * y + center.1)
+        })
+        .collect()
+}
+
+/// Commands, numbers and arc flags of path data
+struct PathTokens<'a> {
+    rest: &'a str,
+}
+
+impl PathTokens<'_> {
+    fn skip_separators(&mut self) {
+        self.rest = self.rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+    }
+
+    fn command(&mut self) -> Option<char> {
+        self.skip_separators();
+        let c = self.rest.chars().next().filter(char::is_ascii_alphabetic)?;
+        self.rest = &self.rest[1..];
+        Some(c)
+    }
+
+    fn has_number(&mut self) -> bool {
+        self.skip_separators();
+        self.rest.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.')
+    }
+
+    fn number(&mut self) -> Result<f64, Box<dyn std::error::Error>> {
+        self.skip_separators();
+        let bytes = self.rest.as_bytes();
+        let mut end = 0;
+        let mut seen_dot = false;
+        let mut seen_exponent = false;
+        while end < bytes.len() {
+            match bytes[end] {
+                b'+' | b'-' if end == 0 || matches!(bytes[end - 1], b'e' | b'E') => {}
+                b'.' if !seen_dot && !seen_exponent => seen_dot = true,
+                b'e' | b'E' if !seen_exponent && end > 0 => seen_exponent = true,
+                b'0'..=b'9' => {}
+                _ => break,
+            }
+            end += 1;
+        }
+        let (number, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        number.parse().map_err(|_| format!("expected a number in path data at {:?}", number).into())
+    }
+
+    fn flag(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.skip_separators();
+        let flag = match self.rest.chars().next() {
+            Some('0') => false,
+            Some('1') => true,
+            _ => return Err("expected an arc flag in path data".into()),
+        };
+        self.rest = &self.rest[1..];
+        Ok(flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{decode_pixels, image_to_matrix};
+    use crate::reference::golden;
+    use crate::render::{render_svg, EyeStyle, ModuleStyle, RenderOptions};
+    use image::DynamicImage;
+
+    #[test]
+    fn test_rasterized_svg_reads_back_as_the_same_modules() {
+        let matrix = golden("hello-world-1q").unwrap().matrix();
+        for (style, eye_style) in [(ModuleStyle::Square, EyeStyle::Square), (ModuleStyle::Dot, EyeStyle::Rounded), (ModuleStyle::Rounded, EyeStyle::Square)] {
+            let options = RenderOptions { style, eye_style, ..RenderOptions::default() };
+            let mut svg = Vec::new();
+            render_svg(&matrix, &options, &mut svg).unwrap();
+            assert!(is_svg(&svg));
+            let image = DynamicImage::ImageRgb8(rasterize(&String::from_utf8(svg).unwrap()).unwrap());
+            assert_eq!(decode_pixels(&image).unwrap().text, "HELLO WORLD", "{:?}", style);
+            if style == ModuleStyle::Square {
+                assert_eq!(image_to_matrix(&image).unwrap(), matrix);
+            }
+        }
+    }
+
+    #[test]
+    fn test_hostile_documents_are_errors() {
+        for view_box in ["0 0 1e30 1e30", "0 0 inf 10", "0 0 100000 100000", "NaN 0 10 10"] {
+            let svg = format!(r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{}"><rect width="1" height="1"/></svg>"#, view_box);
+            assert!(rasterize(&svg).is_err(), "{}", view_box);
+            assert!(crate::decoder::decode_bytes(svg.as_bytes()).is_err(), "{}", view_box);
+        }
+        for color in ["#aéaaa", "#ééé", "#12345"] {
+            assert!(parse_color(color, &[]).is_err(), "{}", color);
+        }
+        assert_eq!(parse_color("#0a0B0c", &[]).unwrap(), Some([10, 11, 12]));
+    }
+
+    #[test]
+    fn test_path_arcs_flatten_to_circles() {
+        // A unit-radius dot drawn as two half-circle arcs
+        let outline = path_outline("M0 1a1 1 0 1 0 2 0a1 1 0 1 0 -2 0z").unwrap();
+        assert_eq!(outline.len(), 1);
+        for &(x, y) in &outline[0] {
+            assert!(((x - 1.0).hypot(y - 1.0) - 1.0).abs() < 1e-9, "({}, {})", x, y);
+        }
+        assert!(path_outline("M0 0C1 1 2 2 3 3").is_err());
+    }
+}
@@ -1,6 +1,9 @@
-use std::fmt;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use crate::diagnostics::Diagnostics;
 
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize)]
 #[allow(dead_code)]
 pub enum Version {
     V1 = 1, V2, V3, V4, V5, V6, V7, V8, V9, V10,
@@ -30,7 +33,7 @@ impl Version {
     }
 }
 
-#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum ErrorCorrection {
     L, // Low (~7%)
     M, // Medium (~15%)
@@ -38,7 +41,22 @@ pub enum ErrorCorrection {
     H, // High (~30%)
 }
 
-#[derive(Clone, Copy, Debug, serde::Serialize)]
+impl core::str::FromStr for ErrorCorrection {
+    type Err = String;
+
+    /// Parse a level name, case-insensitively
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_uppercase().as_str() {
+            "L" => Ok(ErrorCorrection::L),
+            "M" => Ok(ErrorCorrection::M),
+            "Q" => Ok(ErrorCorrection::Q),
+            "H" => Ok(ErrorCorrection::H),
+            _ => Err("use L, M, Q or H".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum DataMode {
     Numeric,
     Alphanumeric,
@@ -56,8 +74,9 @@ impl fmt::Display for DataMode {
     }
 }
 
-#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum MaskPattern {
+    #[default]
     Pattern0, Pattern1, Pattern2, Pattern3,
     Pattern4, Pattern5, Pattern6, Pattern7,
 }
@@ -83,8 +102,15 @@ impl MaskPattern {
 pub enum OutputFormat {
     Png,
     Svg,
+    Pbm,
+    PbmPlain,
+    Pgm,
+    Terminal,
+    GifStages,
+    Placement,
 }
 
+#[derive(Clone)]
 #[allow(dead_code)]
 pub struct QrConfig {
     pub error_correction: ErrorCorrection,
@@ -93,8 +119,12 @@ pub struct QrConfig {
     pub skip_mask: bool,
     pub output_filename: String,
     pub output_format: OutputFormat,
-    pub data: String,
+    /// Payload bytes, which need not be text
+    pub data: Vec<u8>,
     pub verbose: bool,
+    pub logo: Option<String>,
+    /// Records the encoder's intermediate results when set
+    pub diagnostics: Option<Diagnostics>,
 }
 
 impl Default for QrConfig {
@@ -106,8 +136,10 @@ impl Default for QrConfig {
             skip_mask: false,
             output_filename: "qr-code.png".to_string(),
             output_format: OutputFormat::Png,
-            data: "https://www.example.com/".to_string(),
+            data: b"https://www.example.com/".to_vec(),
             verbose: false,
+            logo: None,
+            diagnostics: None,
         }
     }
 }
@@ -0,0 +1,103 @@
+//! Checks and rewrites for payloads that are web addresses. Scanner apps are less forgiving
+//! than browsers: a raw space or a non-ASCII host can stop a code from opening at all.
+
+/// Characters of the alphanumeric encoding mode, which packs two characters into 11 bits
+const ALPHANUMERIC: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Whether `text` is an absolute URL with a `scheme://` prefix
+pub fn looks_like_url(text: &str) -> bool {
+    match text.split_once("://") {
+        Some((scheme, rest)) => is_scheme(scheme) && !rest.is_empty() && !text.contains('\n'),
+        None => false,
+    }
+}
+
+/// Lowercase the scheme and convert the host to its ASCII form: lowercase, with
+/// internationalized labels in punycode, as in `https://bücher.example` to
+/// `https://xn--bcher-kva.example`. The path, query and fragment are left alone.
+pub fn normalize_url(url: &str) -> Result<String, String> {
+    let parts = split_url(url)?;
+    let host = if parts.host.starts_with('[') {
+        // IPv6 literal
+        parts.host.to_ascii_lowercase()
+    } else {
+        idna::domain_to_ascii(parts.host).map_err(|_| format!("{:?} is not a valid host name", parts.host))?
+    };
+    Ok(format!("{}://{}{}{}", parts.scheme.to_ascii_lowercase(), parts.userinfo, host, parts.rest))
+}
+
+/// The URL in uppercase, so that it can be encoded in alphanumeric mode. Only correct for
+/// URLs whose path the server treats case-insensitively; scheme and host always are.
+pub fn uppercase_url(url: &str) -> Result<String, String> {
+    let upper = url.to_uppercase();
+    if let Some(c) = upper.chars().find(|&c| !ALPHANUMERIC.contains(c)) {
+        return Err(format!("{:?} cannot be encoded in alphanumeric mode, which has no {:?}", url, c));
+    }
+    Ok(upper)
+}
+
+/// Problems in the URL that commonly keep scanner apps from opening it
+pub fn url_warnings(url: &str) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let rest = split_url(url).map(|parts| parts.rest).unwrap_or(url);
+    if rest.contains(|c: char| !c.is_ascii()) {
+        warnings.push("the path or query contains non-ASCII characters; percent-encode them".to_string());
+    }
+    let unsafe_chars: String = url.chars().filter(|&c| c.is_ascii() && (c.is_ascii_whitespace() || c.is_ascii_control() || "\"<>\\^`{|}".contains(c))).collect();
+    if !unsafe_chars.is_empty() {
+        warnings.push(format!("{:?} are not allowed in URLs and often cut the link short; percent-encode them", unsafe_chars));
+    }
+    let bytes = url.as_bytes();
+    if bytes.iter().enumerate().any(|(i, &b)| b == b'%' && !bytes.get(i + 1..i + 3).is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit))) {
+        warnings.push("a % is not followed by two hex digits".to_string());
+    }
+    if url.ends_with(['.', ',', ')', '!', '?']) {
+        warnings.push("the URL ends in punctuation, which some scanners drop".to_string());
+    }
+    warnings
+}
+
+struct UrlParts<'a> {
+    scheme: &'a str,
+    /// `user:password@`, if any
+    userinfo: &'a str,
+    host: &'a str,
+    /// Port, path, query and fragment
+    rest: &'a str,
+}
+
+fn split_url(url: &str) -> Result<UrlParts<'_>, String> {
+    let (scheme, after) = url.split_once("://").filter(|(scheme, _)| is_scheme(scheme)).ok_or_else(|| format!("{:?} is not a URL", url))?;
+    let authority_end = after.find(['/', '?', '#']).unwrap_or(after.len());
+    let userinfo_end = after[..authority_end].rfind('@').map_or(0, |at| at + 1);
+    let host_end = if after[userinfo_end..].starts_with('[') {
+        after[userinfo_end..authority_end].find(']').map_or(authority_end, |end| userinfo_end + end + 1)
+    } else {
+        after[userinfo_end..authority_end].find(':').map_or(authority_end, |colon| userinfo_end + colon)
+    };
+    Ok(UrlParts { scheme, userinfo: &after[..userinfo_end], host: &after[userinfo_end..host_end], rest: &after[host_end..] })
+}
+
+/// A letter followed by letters, digits, `+`, `-` or `.` (RFC 3986)
+fn is_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic()) && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_and_uppercase_urls() {
+        assert!(looks_like_url("https://example.com"));
+        assert!(!looks_like_url("Hello, World!"));
+        assert_eq!(normalize_url("HTTPS://Bücher.Example:8080/Path?q=Ä").unwrap(), "https://xn--bcher-kva.example:8080/Path?q=Ä");
+        assert_eq!(normalize_url("http://user@[::1]/").unwrap(), "http://user@[::1]/");
+        assert_eq!(uppercase_url("https://example.com/menu").unwrap(), "HTTPS://EXAMPLE.COM/MENU");
+        assert!(uppercase_url("https://example.com/?q=1").is_err());
+
+        assert!(url_warnings("https://example.com/a-b").is_empty());
+        assert_eq!(url_warnings("https://example.com/a b|c%2").len(), 2);
+        assert_eq!(url_warnings("https://example.com/ä.").len(), 2);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::types::Version;
+
+/// BCH(18,6) generator polynomial x^12 + x^11 + x^10 + x^9 + x^8 + x^5 + x^2 + 1
+const GENERATOR: u32 = 0x1F25;
+
+/// 18-bit version information word for versions 7 and up: the version number followed by
+/// its BCH(18,6) remainder. Unlike format information it is not masked.
+pub fn encode(version: Version) -> u32 {
+    let data = version as u32;
+    let mut remainder = data;
+    for _ in 0..12 {
+        remainder = (remainder << 1) ^ ((remainder >> 11) * GENERATOR);
+    }
+    (data << 12) | remainder
+}
+
+/// Closest valid version information word to `word`, with the number of bits that differ.
+/// `None` when no version 7-40 is within the BCH(18,6) correction distance of 3 bits.
+pub fn decode(word: u32) -> Option<(Version, u32)> {
+    (7..=40)
+        .filter_map(Version::from_u8)
+        .map(|version| (version, (word ^ encode(version)).count_ones()))
+        .min_by_key(|&(_, distance)| distance)
+        .filter(|&(_, distance)| distance <= 3)
+}
+
+/// Module positions (row, col) of version information bits 0-17 in both copies: the block
+/// above the bottom-left finder, then the one left of the top-right finder. Bit 0 sits in the
+/// corner nearest the top-left of each block.
+#[allow(clippy::needless_range_loop)]
+pub fn positions(size: usize) -> [[(usize, usize); 18]; 2] {
+    let mut positions = [[(0, 0); 18]; 2];
+    for i in 0..18 {
+        positions[0][i] = (size - 11 + i % 3, i / 3);
+        positions[1][i] = (i / 3, size - 11 + i % 3);
+    }
+    positions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_matches_specification_table() {
+        // ISO/IEC 18004 table D.1
+        let table = [
+            (7, 0x07C94), (8, 0x085BC), (9, 0x09A99), (10, 0x0A4D3), (11, 0x0BBF6), (12, 0x0C762),
+            (13, 0x0D847), (14, 0x0E60D), (15, 0x0F928), (16, 0x10B78), (17, 0x1145D), (18, 0x12A17),
+            (19, 0x13532), (20, 0x149A6), (21, 0x15683), (22, 0x168C9), (23, 0x177EC), (24, 0x18EC4),
+            (25, 0x191E1), (26, 0x1AFAB), (27, 0x1B08E), (28, 0x1CC1A), (29, 0x1D33F), (30, 0x1ED75),
+            (31, 0x1F250), (32, 0x209D5), (33, 0x216F0), (34, 0x228BA), (35, 0x2379F), (36, 0x24B0B),
+            (37, 0x2542E), (38, 0x26A64), (39, 0x27541), (40, 0x28C69),
+        ];
+        for (version, word) in table {
+            let version = Version::from_u8(version).unwrap();
+            assert_eq!(encode(version), word, "{:?}", version);
+            assert_eq!(decode(word), Some((version, 0)));
+        }
+    }
+
+    #[test]
+    fn test_decode_corrects_up_to_three_bit_errors() {
+        for version in (7..=40).filter_map(Version::from_u8) {
+            let word = encode(version);
+            for a in 0..18 {
+                for b in a + 1..18 {
+                    let c = (b * 7 + a) % 18;
+                    let damaged = word ^ (1 << a) ^ (1 << b) ^ if c != a && c != b { 1 << c } else { 0 };
+                    assert_eq!(decode(damaged).map(|(decoded, _)| decoded), Some(version));
+                }
+            }
+        }
+        // Four errors are more than BCH(18,6) corrects
+        assert_eq!(decode(encode(Version::V7) ^ 0b1111), None);
+    }
+
+    #[test]
+    fn test_copies_sit_beside_the_finders() {
+        let [bottom_left, top_right] = positions(Version::V7.size());
+        assert_eq!((bottom_left[0], bottom_left[17]), ((34, 0), (36, 5)));
+        assert_eq!((top_right[0], top_right[17]), ((0, 34), (5, 36)));
+    }
+}
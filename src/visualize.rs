@@ -0,0 +1,293 @@
+use std::io::Write;
+use image::{DynamicImage, ImageBuffer, Rgb, RgbImage};
+use crate::types::{ErrorCorrection, Version};
+use crate::function_modules::{module_region, ModuleRegion};
+use crate::capacity::{get_data_capacity_in_bytes, get_total_codewords_in_bytes};
+use crate::pixel_mapping::get_data_ecc_positions;
+use crate::render::{write_png, RenderOptions};
+use crate::decoder::SamplingGrid;
+
+const FUNCTION_COLOR: [u8; 3] = [200, 200, 200];
+const REMAINDER_COLOR: [u8; 3] = [90, 90, 90];
+const BOUNDARY_COLOR: [u8; 3] = [0, 0, 0];
+const SAMPLED_DARK_COLOR: [u8; 3] = [230, 30, 30];
+const SAMPLED_LIGHT_COLOR: [u8; 3] = [30, 90, 230];
+const FINDER_COLOR: [u8; 3] = [250, 200, 0];
+
+/// Codeword index of every module along the placement path, `None` for function modules.
+/// Modules past the last codeword are remainder bits and get `Some(usize::MAX)`.
+fn codeword_map(version: Version) -> Vec<Vec<Option<usize>>> {
+    let size = version.size();
+    let total_codewords = get_total_codewords_in_bytes(version);
+    let mut map = vec![vec![None; size]; size];
+    for (i, (row, col)) in get_data_ecc_positions(version).into_iter().enumerate() {
+        let codeword = i / 8;
+        map[row][col] = Some(if codeword < total_codewords { codeword } else { usize::MAX });
+    }
+    map
+}
+
+/// Blue-to-red hue ramp for `t` in `0.0..=1.0`
+fn ramp_color(t: f64) -> [u8; 3] {
+    let hue = (1.0 - t.clamp(0.0, 1.0)) * 240.0;
+    let x = 1.0 - ((hue / 60.0) % 2.0 - 1.0).abs();
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        _ => (x, 0.0, 1.0),
+    };
+    [(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8]
+}
+
+/// Paint every module of a `size`-module symbol with `color_of(row, col)` on a white canvas
+fn paint_modules(size: usize, options: &RenderOptions, color_of: impl Fn(usize, usize) -> [u8; 3]) -> RgbImage {
+    let scale = options.scale;
+    let border = options.border();
+    let total_size = options.total_size(size) as u32;
+    let mut img = ImageBuffer::from_pixel(total_size, total_size, Rgb([255u8, 255u8, 255u8]));
+    for row in 0..size {
+        for col in 0..size {
+            let color = Rgb(color_of(row, col));
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    img.put_pixel((border + col * scale + dx) as u32, (border + row * scale + dy) as u32, color);
+                }
+            }
+        }
+    }
+    img
+}
+
+/// Render a debug PNG that colors each data/ECC module by its position along the zigzag
+/// placement path, from blue (first bit) to red (last bit). Function modules are light gray
+/// and remainder bits dark gray. With `codeword_boundaries`, a dark line is drawn wherever
+/// two neighbouring modules belong to different codewords.
+pub fn render_placement_order(version: Version, options: &RenderOptions, codeword_boundaries: bool, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let size = version.size();
+    let positions = get_data_ecc_positions(version);
+    let last = positions.len().saturating_sub(1).max(1) as f64;
+    let mut order = vec![vec![None; size]; size];
+    for (i, &(row, col)) in positions.iter().enumerate() {
+        order[row][col] = Some(i);
+    }
+    let codewords = codeword_map(version);
+
+    let mut img = paint_modules(size, options, |row, col| match (order[row][col], codewords[row][col]) {
+        (_, Some(usize::MAX)) => REMAINDER_COLOR,
+        (Some(i), _) => ramp_color(i as f64 / last),
+        _ => FUNCTION_COLOR,
+    });
+
+    if codeword_boundaries {
+        draw_codeword_boundaries(&mut img, &codewords, options);
+    }
+
+    write_png(&img, options.dpi, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Outline each codeword by drawing the module edges it shares with other codewords
+#[allow(clippy::needless_range_loop)]
+fn draw_codeword_boundaries(img: &mut RgbImage, codewords: &[Vec<Option<usize>>], options: &RenderOptions) {
+    let size = codewords.len();
+    let scale = options.scale;
+    let border = options.border();
+    let width = (scale / 8).max(1);
+    let codeword_at = |row: isize, col: isize| -> Option<usize> {
+        if row < 0 || col < 0 || row as usize >= size || col as usize >= size {
+            return None;
+        }
+        codewords[row as usize][col as usize]
+    };
+
+    for row in 0..size {
+        for col in 0..size {
+            let Some(codeword) = codewords[row][col] else { continue };
+            let (r, c) = (row as isize, col as isize);
+            let left = border + col * scale;
+            let top = border + row * scale;
+            for d in 0..scale {
+                for w in 0..width {
+                    if codeword_at(r - 1, c) != Some(codeword) {
+                        img.put_pixel((left + d) as u32, (top + w) as u32, Rgb(BOUNDARY_COLOR));
+                    }
+                    if codeword_at(r + 1, c) != Some(codeword) {
+                        img.put_pixel((left + d) as u32, (top + scale - 1 - w) as u32, Rgb(BOUNDARY_COLOR));
+                    }
+                    if codeword_at(r, c - 1) != Some(codeword) {
+                        img.put_pixel((left + w) as u32, (top + d) as u32, Rgb(BOUNDARY_COLOR));
+                    }
+                    if codeword_at(r, c + 1) != Some(codeword) {
+                        img.put_pixel((left + scale - 1 - w) as u32, (top + d) as u32, Rgb(BOUNDARY_COLOR));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Role of a module within the symbol
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Finder,
+    Separator,
+    Timing,
+    Alignment,
+    FormatInfo,
+    VersionInfo,
+    DarkModule,
+    Data,
+    Ecc,
+    Remainder,
+    /// Not a function module, but not reached by the placement path either
+    Unmapped,
+}
+
+impl Region {
+    /// Overlay color for light and dark modules of this region
+    fn colors(&self) -> ([u8; 3], [u8; 3]) {
+        match self {
+            Region::Finder => ([255, 190, 190], [190, 0, 0]),
+            Region::Separator => ([255, 230, 230], [150, 60, 60]),
+            Region::Timing => ([255, 220, 170], [210, 110, 0]),
+            Region::Alignment => ([225, 200, 255], [110, 30, 180]),
+            Region::FormatInfo => ([190, 210, 255], [0, 60, 200]),
+            Region::VersionInfo => ([190, 245, 245], [0, 140, 140]),
+            Region::DarkModule => ([220, 200, 180], [100, 60, 20]),
+            Region::Data => ([200, 240, 200], [0, 120, 0]),
+            Region::Ecc => ([250, 245, 180], [150, 130, 0]),
+            Region::Remainder => ([225, 225, 225], [90, 90, 90]),
+            Region::Unmapped => ([255, 180, 255], [200, 0, 200]),
+        }
+    }
+}
+
+/// Classify every module of a symbol. Function patterns come from `module_region`, with the
+/// finders told apart from their separators; data, ECC and remainder modules follow
+/// `get_data_ecc_positions`, so any disagreement between the two shows up as `Unmapped`
+/// modules or a misplaced region.
+/// Without an error correction level, all codewords are reported as `Data`.
+pub fn classify_modules(version: Version, error_correction: Option<ErrorCorrection>) -> Vec<Vec<Region>> {
+    let size = version.size();
+    let mut regions: Vec<Vec<Option<Region>>> = vec![vec![None; size]; size];
+
+    for (row, line) in regions.iter_mut().enumerate() {
+        for (col, region) in line.iter_mut().enumerate() {
+            *region = match module_region(row, col, version) {
+                ModuleRegion::Finder => {
+                    let finder_row = row < 7 || row >= size - 7;
+                    let finder_col = col < 7 || col >= size - 7;
+                    Some(if finder_row && finder_col { Region::Finder } else { Region::Separator })
+                }
+                ModuleRegion::Timing => Some(Region::Timing),
+                ModuleRegion::Alignment => Some(Region::Alignment),
+                ModuleRegion::Format if (row, col) == (size - 8, 8) => Some(Region::DarkModule),
+                ModuleRegion::Format => Some(Region::FormatInfo),
+                ModuleRegion::Version => Some(Region::VersionInfo),
+                ModuleRegion::Data => None,
+            };
+        }
+    }
+
+    let total_codewords = get_total_codewords_in_bytes(version);
+    let data_codewords = match error_correction {
+        Some(ecc) => get_data_capacity_in_bytes(version, ecc),
+        None => total_codewords,
+    };
+    for (i, (row, col)) in get_data_ecc_positions(version).into_iter().enumerate() {
+        if regions[row][col].is_some() {
+            continue;
+        }
+        let codeword = i / 8;
+        regions[row][col] = Some(if codeword < data_codewords {
+            Region::Data
+        } else if codeword < total_codewords {
+            Region::Ecc
+        } else {
+            Region::Remainder
+        });
+    }
+
+    regions
+        .into_iter()
+        .map(|row| row.into_iter().map(|r| r.unwrap_or(Region::Unmapped)).collect())
+        .collect()
+}
+
+/// Render `matrix` with every module tinted by its region: a dark shade for dark modules and
+/// a light one for light modules, so the symbol stays readable underneath the classification.
+pub fn render_region_overlay(matrix: &[Vec<u8>], version: Version, error_correction: Option<ErrorCorrection>, options: &RenderOptions, mut writer: impl Write) -> Result<(), Box<dyn std::error::Error>> {
+    let regions = classify_modules(version, error_correction);
+    if matrix.len() != regions.len() {
+        return Err(format!("matrix is {} modules wide but version {:?} needs {}", matrix.len(), version, regions.len()).into());
+    }
+
+    let img = paint_modules(matrix.len(), options, |row, col| {
+        let (light, dark) = regions[row][col].colors();
+        if matrix[row][col] == 1 { dark } else { light }
+    });
+
+    write_png(&img, options.dpi, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Draw the decoder's sampling grid over the image it was sampled from: a dot at every module
+/// center, red where the module read dark and blue where it read light, and a cross on each
+/// finder and alignment center. Dots that slide off their modules towards one edge show grid
+/// drift; dots centered on modules of the wrong color show damage.
+pub fn draw_sampling_grid(image: &DynamicImage, grid: &SamplingGrid) -> RgbImage {
+    let mut img = image.to_rgb8();
+    let pitch = match grid.centers.first().map(|row| (row.first(), row.get(1))) {
+        Some((Some(&(x0, y0)), Some(&(x1, y1)))) => (x1 - x0).hypot(y1 - y0),
+        _ => 1.0,
+    };
+    let radius = (pitch / 5.0).max(1.0) as i64;
+    let mut plot = |x: i64, y: i64, color: [u8; 3]| {
+        if x >= 0 && y >= 0 && (x as u32) < img.width() && (y as u32) < img.height() {
+            img.put_pixel(x as u32, y as u32, Rgb(color));
+        }
+    };
+
+    for (centers, modules) in grid.centers.iter().zip(&grid.sampled) {
+        for (&(x, y), &module) in centers.iter().zip(modules) {
+            let color = if module == 1 { SAMPLED_DARK_COLOR } else { SAMPLED_LIGHT_COLOR };
+            let (x, y) = (x.floor() as i64, y.floor() as i64);
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    if dx * dx + dy * dy <= radius * radius {
+                        plot(x + dx, y + dy, color);
+                    }
+                }
+            }
+        }
+    }
+    let arm = (pitch * 1.5) as i64;
+    for &(x, y) in grid.finder_patterns.iter().chain(&grid.alignment) {
+        let (x, y) = (x.floor() as i64, y.floor() as i64);
+        for d in -arm..=arm {
+            plot(x + d, y, FINDER_COLOR);
+            plot(x, y + d, FINDER_COLOR);
+        }
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_counts_match_capacity() {
+        for version in [Version::V1, Version::V5] {
+            let regions = classify_modules(version, Some(ErrorCorrection::M));
+            let count = |region: Region| regions.iter().flatten().filter(|&&r| r == region).count();
+            assert_eq!(count(Region::Data), get_data_capacity_in_bytes(version, ErrorCorrection::M) * 8);
+            assert_eq!(count(Region::Data) + count(Region::Ecc), get_total_codewords_in_bytes(version) * 8);
+            assert_eq!(count(Region::Unmapped), 0);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! JavaScript bindings, compiled with the `wasm` feature:
+//!
+//! ```text
+//! cargo build --target wasm32-unknown-unknown --no-default-features --features wasm --lib
+//! wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/debug/qr_tools.wasm
+//! ```
+//!
+//! ```js
+//! import init, { encodeSvg, encodeRgba, decodeRgba } from "./pkg/qr_tools.js";
+//! await init();
+//! document.body.innerHTML = encodeSvg("https://example.com", "M", 8, 4);
+//! const code = encodeRgba("https://example.com", "M", 8, 4);
+//! context.putImageData(new ImageData(code.pixels, code.width), 0, 0);
+//! ```
+//!
+//! Everything works on memory: there is no file system in the browser, so captions (which
+//! load a font file) and logos are not offered here.
+
+use image::{DynamicImage, RgbaImage as RgbaBuffer};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use crate::decoder::decode_pixels;
+use crate::generator::try_generate_qr_matrix;
+use crate::render::{render_image, render_svg, RenderOptions};
+use crate::types::QrConfig;
+
+/// A rendered code as RGBA pixels, row by row, ready for `new ImageData(pixels, width)`
+#[wasm_bindgen]
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl RgbaImage {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pixels(&self) -> Clamped<Vec<u8>> {
+        Clamped(self.pixels.clone())
+    }
+}
+
+/// Encode `text` as an SVG document. `error_correction` is one of L, M, Q and H; `scale` is
+/// the size of a module in pixels and `quiet_zone` the border in modules.
+#[wasm_bindgen(js_name = encodeSvg)]
+pub fn encode_svg(text: &str, error_correction: &str, scale: usize, quiet_zone: usize) -> Result<String, JsError> {
+    let (matrix, options) = encode(text, error_correction, scale, quiet_zone)?;
+    let mut svg = Vec::new();
+    render_svg(&matrix, &options, &mut svg).map_err(|e| JsError::new(&e.to_string()))?;
+    String::from_utf8(svg).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Encode `text` as RGBA pixels, with the same arguments as [`encode_svg`]
+#[wasm_bindgen(js_name = encodeRgba)]
+pub fn encode_rgba(text: &str, error_correction: &str, scale: usize, quiet_zone: usize) -> Result<RgbaImage, JsError> {
+    let (matrix, options) = encode(text, error_correction, scale, quiet_zone)?;
+    let image = render_image(&matrix, &options).map_err(|e| JsError::new(&e.to_string()))?;
+    let (width, height) = image.dimensions();
+    Ok(RgbaImage { width, height, pixels: DynamicImage::ImageRgb8(image).to_rgba8().into_raw() })
+}
+
+/// Decode the code in an RGBA image, such as the `data` of a canvas `ImageData`, and return
+/// its text
+#[wasm_bindgen(js_name = decodeRgba)]
+pub fn decode_rgba(pixels: &[u8], width: u32, height: u32) -> Result<String, JsError> {
+    let image = RgbaBuffer::from_raw(width, height, pixels.to_vec()).ok_or_else(|| JsError::new("the pixels do not match the width and height"))?;
+    let decoded = decode_pixels(&DynamicImage::ImageRgba8(image)).map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(decoded.text)
+}
+
+fn encode(text: &str, error_correction: &str, scale: usize, quiet_zone: usize) -> Result<(Vec<Vec<u8>>, RenderOptions), JsError> {
+    let config = QrConfig { error_correction: error_correction.parse().map_err(|e: String| JsError::new(&e))?, ..QrConfig::default() };
+    if scale == 0 {
+        return Err(JsError::new("the scale must be at least 1"));
+    }
+    let matrix = try_generate_qr_matrix(text.as_bytes(), &config).map_err(|e| JsError::new(&e.to_string()))?;
+    let options = RenderOptions { scale, quiet_zone, ..RenderOptions::default() };
+    Ok((matrix, options))
+}
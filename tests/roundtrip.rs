@@ -0,0 +1,46 @@
+//! Round trips through the `roundtrip` harness. `cargo xtask verify-matrix` runs the full grid
+//! and writes the report; these tests keep the harness itself honest and track the encoder.
+
+use qr_tools::roundtrip::{cases, check, decode_text, report, verify, Case, Reference};
+use qr_tools::types::{DataMode, ErrorCorrection, Version};
+use qrcodegen::{Mask, QrCode, QrCodeEcc, QrSegment};
+
+#[path = "support/rqrr.rs"]
+mod support;
+use support::decode_rqrr;
+
+/// The case encoded by the reference encoder, with the same version, level, mode and mask
+fn reference_matrix(case: &Case, payload: &str) -> Vec<Vec<u8>> {
+    let segment = match case.data_mode {
+        DataMode::Numeric => QrSegment::make_numeric(payload),
+        DataMode::Alphanumeric => QrSegment::make_alphanumeric(payload),
+        DataMode::Byte => QrSegment::make_bytes(payload.as_bytes()),
+    };
+    let ecc = match case.error_correction {
+        ErrorCorrection::L => QrCodeEcc::Low,
+        ErrorCorrection::M => QrCodeEcc::Medium,
+        ErrorCorrection::Q => QrCodeEcc::Quartile,
+        ErrorCorrection::H => QrCodeEcc::High,
+    };
+    let version = qrcodegen::Version::new(case.version as u8);
+    let qr = QrCode::encode_segments_advanced(&[segment], ecc, version, version, Some(Mask::new(case.mask as u8)), false).unwrap();
+    (0..qr.size()).map(|y| (0..qr.size()).map(|x| qr.get_module(x, y) as u8).collect()).collect()
+}
+
+#[test]
+fn reference_symbols_read_back_with_both_decoders() {
+    for case in cases([Version::V1, Version::V2, Version::V7, Version::V14, Version::V27]) {
+        let payload = case.payload();
+        let matrix = reference_matrix(&case, &payload);
+        assert!(check(&matrix, &payload, decode_text).passed(), "our decoder: {}", case);
+        assert!(check(&matrix, &payload, decode_rqrr).passed(), "rqrr: {}", case);
+    }
+}
+
+#[test]
+fn generated_symbols_read_back() {
+    let reference = Reference { name: "rqrr", every: 5, decode: &decode_rqrr };
+    let results = verify(&cases((1..=40).filter_map(Version::from_u8)), Some(&reference));
+    let all_passed = results.iter().all(|r| r.ours.passed() && r.reference.as_ref().is_none_or(|o| o.passed()));
+    assert!(all_passed, "{}", report(&results, Some(reference.name)));
+}
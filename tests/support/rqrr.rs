@@ -0,0 +1,20 @@
+//! rqrr as a second decoder of module matrices, shared by the round trip tests and
+//! `cargo xtask verify-matrix`, which include this file with `#[path]`
+
+/// A module matrix as rqrr's grid, 1 being dark
+struct Modules<'a>(&'a [Vec<u8>]);
+
+impl rqrr::BitGrid for Modules<'_> {
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    fn bit(&self, y: usize, x: usize) -> bool {
+        self.0[y][x] == 1
+    }
+}
+
+/// Decode a module matrix with rqrr, as a `check` callback
+pub fn decode_rqrr(matrix: &[Vec<u8>]) -> Result<String, String> {
+    rqrr::Grid::new(Modules(matrix)).decode().map(|(_, text)| text).map_err(|e| e.to_string())
+}
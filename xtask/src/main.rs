@@ -0,0 +1,72 @@
+//! Development tasks, run as `cargo xtask <task>`:
+//!
+//! ```text
+//! cargo xtask verify-matrix [--versions 1-40] [--sample 7] [--output report.md]
+//! ```
+//!
+//! `verify-matrix` encodes every version × error correction level × data mode × mask, reads
+//! each symbol back with our decoder and every `--sample`th one with rqrr as well, and
+//! prints a Markdown report. It exits with status 1 if any symbol fails to round-trip.
+
+use std::error::Error;
+use std::process::ExitCode;
+use qr_tools::roundtrip::{cases, report, verify, Reference};
+use qr_tools::types::Version;
+
+#[path = "../../tests/support/rqrr.rs"]
+mod support;
+use support::decode_rqrr;
+
+const USAGE: &str = "usage: cargo xtask verify-matrix [--versions FIRST-LAST] [--sample N] [--output FILE]";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Run the task in `args`; `Ok(false)` means it ran and found failures
+fn run(args: Vec<String>) -> Result<bool, Box<dyn Error>> {
+    let mut args = args.into_iter();
+    match args.next().as_deref() {
+        Some("verify-matrix") => verify_matrix(args),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn verify_matrix(mut args: impl Iterator<Item = String>) -> Result<bool, Box<dyn Error>> {
+    let (mut first, mut last, mut sample, mut output) = (1, 40, 7, None);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--versions" => {
+                let range = value()?;
+                let (from, to) = range.split_once('-').unwrap_or((&range, &range));
+                (first, last) = (from.parse()?, to.parse()?);
+            }
+            "--sample" => sample = value()?.parse()?,
+            "--output" => output = Some(value()?),
+            _ => return Err(format!("unexpected argument {:?}\n{}", arg, USAGE).into()),
+        }
+    }
+    let versions: Vec<Version> = (first..=last).map(|v| Version::from_u8(v).ok_or(format!("there is no version {}", v))).collect::<Result<_, _>>()?;
+
+    let cases = cases(versions);
+    eprintln!("Round-tripping {} symbols...", cases.len());
+    let reference = Reference { name: "rqrr", every: sample, decode: &decode_rqrr };
+    let results = verify(&cases, Some(&reference));
+    let report = report(&results, Some(reference.name));
+    match output {
+        Some(path) => {
+            std::fs::write(&path, &report)?;
+            eprintln!("Report written to {}", path);
+        }
+        None => print!("{}", report),
+    }
+    Ok(results.iter().all(|r| r.ours.passed() && r.reference.as_ref().is_none_or(|o| o.passed())))
+}